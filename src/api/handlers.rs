@@ -1,16 +1,23 @@
 use axum::{
-    extract::{Json, Path},
-    http::StatusCode,
+    extract::{Json, Path, Query},
+    http::{header, HeaderMap, StatusCode},
     response::IntoResponse,
     Extension,
 };
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use crate::{
-    models::{User, PriceAlert, AlertType, AlertCondition},
+    models::{User, UserRole, AuditEvent, AlertKind, Plan, RegistrationMode, TradeSide, TradeMode, AlertAction, RiskLimits, Transaction, PriceAlert, AlertType, AlertCondition, DcaPlan, PORTFOLIO_SYMBOL},
+    notify,
+    portfolio::{self, Portfolio, PortfolioError},
+    dca::{DcaError, DcaPlans},
+    backtest::{Backtest, BacktestError, BacktestRequest},
+    quota::{self, QuotaError},
     crypto_api::CryptoAPI,
+    chart::{self, ChartRange, ChartStyle},
 };
 use crate::Auth;
+use crate::auth::AuthError;
 use super::ApiState;
 use super::extractors::BearerAuth;
 
@@ -18,6 +25,8 @@ use super::extractors::BearerAuth;
 pub struct RegisterRequest {
     username: String,
     password: String,
+    /// Obligatorio si el registro es por invitación
+    invite_code: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -31,22 +40,34 @@ pub async fn register(
     Json(payload): Json<RegisterRequest>,
 ) -> impl IntoResponse {
     let auth = Auth::new(state.db.as_ref());
-    match auth.register_user(&payload.username, &payload.password) {
+    match auth.register_user(&payload.username, &payload.password, payload.invite_code.as_deref()) {
         Ok(user) => {
-            // Crear API key para el nuevo usuario
+            // Crear API key para el nuevo usuario; si la cuenta espera
+            // aprobación la key no funciona hasta que se apruebe
             match state.db.create_api_key(user.id) {
                 Ok(api_key) => {
+                    let status = if user.pending_approval {
+                        if let Ok(admins) = state.db.get_admin_chat_ids() {
+                            notify::notify_pending_registration(&state.bot, &admins, &user).await;
+                        }
+                        StatusCode::ACCEPTED
+                    } else {
+                        StatusCode::CREATED
+                    };
                     let response = RegisterResponse {
                         user,
                         api_key: api_key.key,
                     };
-                    (StatusCode::CREATED, Json(response)).into_response()
+                    (status, Json(response)).into_response()
                 }
                 Err(_) => {
                     StatusCode::INTERNAL_SERVER_ERROR.into_response()
                 }
             }
         }
+        Err(e @ (AuthError::InviteRequired | AuthError::InvalidInvite)) => {
+            (StatusCode::FORBIDDEN, Json(json!({ "error": e.to_string() }))).into_response()
+        }
         Err(_) => StatusCode::BAD_REQUEST.into_response(),
     }
 }
@@ -81,6 +102,82 @@ pub async fn login(
     }
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ExchangeCodeRequest {
+    code: String,
+}
+
+pub async fn exchange_api_key_code(
+    Extension(state): Extension<ApiState>,
+    Json(payload): Json<ExchangeCodeRequest>,
+) -> impl IntoResponse {
+    match state.db.consume_api_key_code(&payload.code) {
+        Ok(Some(user_id)) => {
+            match state.db.create_api_key(user_id) {
+                Ok(api_key) => {
+                    let response = json!({
+                        "api_key": api_key.key,
+                        "expires_at": api_key.expires_at,
+                    });
+                    (StatusCode::CREATED, Json(response)).into_response()
+                }
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TelegramLinkResponse {
+    code: String,
+    expires_at: i64,
+    deep_link: Option<String>,
+}
+
+pub async fn create_telegram_link(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            match state.db.create_telegram_link_code(user.id) {
+                Ok((code, expires_at)) => {
+                    let deep_link = std::env::var("TELEGRAM_BOT_USERNAME")
+                        .ok()
+                        .map(|bot| format!("https://t.me/{}?start={}", bot.trim_start_matches('@'), code));
+                    let response = TelegramLinkResponse {
+                        code,
+                        expires_at,
+                        deep_link,
+                    };
+                    (StatusCode::CREATED, Json(response)).into_response()
+                }
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn delete_telegram_link(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            match state.db.unlink_telegram_chat(user.id) {
+                Ok(_) => StatusCode::NO_CONTENT.into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct CreatePriceAlertRequest {
     pub symbol: String,
@@ -104,6 +201,44 @@ pub struct CreatePairAlertRequest {
     pub differential: f64,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CreatePortfolioAlertRequest {
+    Value { threshold: f64, condition: AlertCondition },
+    Drawdown { max_drawdown: f64 },
+    Drift { symbol: String, target_weight: f64, tolerance: f64 },
+}
+
+/// Reproduce el histórico de precios contra una alerta sin guardarla.
+pub async fn backtest_alert(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Json(payload): Json<BacktestRequest>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => match Backtest::new(&state.db).run(Some(user.id), &payload) {
+            Ok(report) => Json(report).into_response(),
+            Err(BacktestError::DatabaseError(_)) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            Err(e) => (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() }))).into_response(),
+        },
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Guarda una alerta nueva si el plan del usuario lo permite.
+fn save_new_alert(state: &ApiState, alert: &PriceAlert) -> axum::response::Response {
+    match quota::check_new_alert(state.db.as_ref(), alert.user_id, alert.kind()) {
+        Ok(()) => {}
+        Err(QuotaError::DatabaseError(_)) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        Err(e) => return (StatusCode::FORBIDDEN, Json(json!({ "error": e.to_string() }))).into_response(),
+    }
+    match state.db.save_alert(alert) {
+        Ok(_) => StatusCode::CREATED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
 pub async fn create_price_alert(
     Extension(state): Extension<ApiState>,
     BearerAuth(token): BearerAuth,
@@ -124,10 +259,7 @@ pub async fn create_price_alert(
                 is_active: true,
             };
 
-            match state.db.save_alert(&alert) {
-                Ok(_) => StatusCode::CREATED.into_response(),
-                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
-            }
+            save_new_alert(&state, &alert)
         }
         Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
         Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
@@ -161,10 +293,7 @@ pub async fn create_depeg_alert(
                 is_active: true,
             };
 
-            match state.db.save_alert(&alert) {
-                Ok(_) => StatusCode::CREATED.into_response(),
-                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
-            }
+            save_new_alert(&state, &alert)
         }
         Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
         Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
@@ -193,10 +322,52 @@ pub async fn create_pair_alert(
                 is_active: true,
             };
 
-            match state.db.save_alert(&alert) {
-                Ok(_) => StatusCode::CREATED.into_response(),
-                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            save_new_alert(&state, &alert)
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn create_portfolio_alert(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Json(payload): Json<CreatePortfolioAlertRequest>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            let mut alert_type = match payload {
+                CreatePortfolioAlertRequest::Value { threshold, condition } => AlertType::PortfolioValue { threshold, condition },
+                CreatePortfolioAlertRequest::Drawdown { max_drawdown } => AlertType::PortfolioDrawdown { max_drawdown, peak: 0.0 },
+                CreatePortfolioAlertRequest::Drift { symbol, target_weight, tolerance } => AlertType::AllocationDrift {
+                    symbol: symbol.to_uppercase(),
+                    target_weight,
+                    tolerance,
+                },
+            };
+            if let Err(e) = portfolio::validate_alert(&alert_type) {
+                return portfolio_error_response(e);
             }
+            let api = CryptoAPI::new(std::env::var("COINGECKO_API_KEY").unwrap_or_default());
+            if let Err(e) = portfolio::seed_peak(&api, state.db.as_ref(), user.id, &mut alert_type).await {
+                return portfolio_error_response(e);
+            }
+
+            let symbol = match &alert_type {
+                AlertType::AllocationDrift { symbol, .. } => symbol.clone(),
+                _ => PORTFOLIO_SYMBOL.to_string(),
+            };
+            let alert = PriceAlert {
+                id: None,
+                user_id: user.id,
+                symbol,
+                alert_type,
+                created_at: chrono::Utc::now().timestamp(),
+                triggered_at: None,
+                is_active: true,
+            };
+
+            save_new_alert(&state, &alert)
         }
         Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
         Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
@@ -243,28 +414,58 @@ pub async fn delete_alert(
     }
 }
 
+/// Reconoce la última notificación de una alerta y detiene su escalado.
+pub async fn acknowledge_alert(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(alert_id): Path<i64>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            match state.db.get_alert(alert_id) {
+                Ok(Some(alert)) if alert.user_id == user.id => {
+                    match state.db.acknowledge_alert(alert_id) {
+                        Ok(acknowledged) => Json(json!({ "acknowledged": acknowledged })).into_response(),
+                        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+                    }
+                }
+                Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+                Ok(None) => StatusCode::NOT_FOUND.into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
 #[derive(Debug, Deserialize)]
-pub struct ResetApiKeyRequest {
-    username: String,
-    password: String,
+pub struct EscalationRequest {
+    interval_minutes: i64,
+    max_attempts: i64,
+    /// Chat de Telegram (usuario, grupo o canal) al que escalar
+    secondary_chat_id: Option<i64>,
+    /// Alternativa a `secondary_chat_id`: otra cuenta con Telegram vinculado
+    secondary_username: Option<String>,
 }
 
-pub async fn reset_api_key(
+pub async fn get_escalation(
     Extension(state): Extension<ApiState>,
-    Json(payload): Json<ResetApiKeyRequest>,
+    BearerAuth(token): BearerAuth,
+    Path(alert_id): Path<i64>,
 ) -> impl IntoResponse {
-    let auth = Auth::new(state.db.as_ref());
-    
-    match auth.login(&payload.username, &payload.password) {
+    match state.db.verify_api_key(&token) {
         Ok(Some(user)) => {
-            match state.db.create_api_key(user.id) {
-                Ok(api_key) => {
-                    let response = json!({
-                        "message": "API key regenerada exitosamente",
-                        "api_key": api_key.key
-                    });
-                    (StatusCode::OK, Json(response)).into_response()
+            match state.db.get_alert(alert_id) {
+                Ok(Some(alert)) if alert.user_id == user.id => {
+                    match state.db.get_escalation_policy(alert_id) {
+                        Ok(Some(policy)) => Json(policy).into_response(),
+                        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+                        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+                    }
                 }
+                Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+                Ok(None) => StatusCode::NOT_FOUND.into_response(),
                 Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
             }
         }
@@ -273,20 +474,1300 @@ pub async fn reset_api_key(
     }
 }
 
-pub async fn get_supported_exchanges(
-    Extension(_state): Extension<ApiState>,
-    BearerAuth(_): BearerAuth,
+pub async fn set_escalation(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(alert_id): Path<i64>,
+    Json(payload): Json<EscalationRequest>,
 ) -> impl IntoResponse {
-    let api = CryptoAPI::new(std::env::var("COINGECKO_API_KEY").unwrap_or_default());
-    Json(api.supported_exchanges())
+    if payload.interval_minutes < 1 || payload.max_attempts < 1 {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "interval_minutes y max_attempts deben ser al menos 1" })),
+        ).into_response();
+    }
+
+    let user = match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => user,
+        Ok(None) => return StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    match state.db.get_alert(alert_id) {
+        Ok(Some(alert)) if alert.user_id == user.id => {}
+        Ok(Some(_)) => return StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+
+    let secondary = match (payload.secondary_chat_id, &payload.secondary_username) {
+        (Some(chat_id), _) => Some(chat_id),
+        (None, Some(username)) => match state.db.get_user_by_username(username) {
+            Ok(Some(User { telegram_chat_id: Some(chat_id), .. })) => Some(chat_id),
+            Ok(_) => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": "El usuario secundario no existe o no tiene Telegram vinculado" })),
+                ).into_response();
+            }
+            Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        },
+        (None, None) => None,
+    };
+
+    match state.db.set_escalation_policy(alert_id, payload.interval_minutes * 60, payload.max_attempts, secondary) {
+        Ok(_) => match state.db.get_escalation_policy(alert_id) {
+            Ok(Some(policy)) => Json(policy).into_response(),
+            _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        },
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
 }
 
-pub async fn get_supported_symbols(
-    Extension(_state): Extension<ApiState>,
-    BearerAuth(_): BearerAuth,
+pub async fn delete_escalation(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(alert_id): Path<i64>,
 ) -> impl IntoResponse {
-    let api = CryptoAPI::new(std::env::var("COINGECKO_API_KEY").unwrap_or_default());
-    Json(api.supported_symbols())
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            match state.db.get_alert(alert_id) {
+                Ok(Some(alert)) if alert.user_id == user.id => {
+                    match state.db.remove_escalation_policy(alert_id) {
+                        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+                        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+                        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+                    }
+                }
+                Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+                Ok(None) => StatusCode::NOT_FOUND.into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AlertActionRequest {
+    side: TradeSide,
+    quantity: f64,
+    #[serde(default = "default_quote_asset")]
+    quote_asset: String,
+    /// Porcentaje sobre el precio de disparo para una orden límite; sin él, a mercado
+    limit_offset: Option<f64>,
+    #[serde(default = "default_trade_mode")]
+    mode: TradeMode,
+    exchange: Option<String>,
+}
+
+fn default_quote_asset() -> String {
+    "USDT".to_string()
+}
+
+fn default_trade_mode() -> TradeMode {
+    TradeMode::DryRun
+}
+
+/// Acción automática de una alerta propia y sus últimas ejecuciones.
+pub async fn get_alert_action(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(alert_id): Path<i64>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            match state.db.get_alert(alert_id) {
+                Ok(Some(alert)) if alert.user_id == user.id => {
+                    match (state.db.get_alert_action(alert_id), state.db.get_action_runs(alert_id, 20)) {
+                        (Ok(Some(action)), Ok(runs)) => Json(json!({ "action": action, "runs": runs })).into_response(),
+                        (Ok(None), _) => StatusCode::NOT_FOUND.into_response(),
+                        _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+                    }
+                }
+                Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+                Ok(None) => StatusCode::NOT_FOUND.into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn set_alert_action(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(alert_id): Path<i64>,
+    Json(payload): Json<AlertActionRequest>,
+) -> impl IntoResponse {
+    let user = match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => user,
+        Ok(None) => return StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let alert = match state.db.get_alert(alert_id) {
+        Ok(Some(alert)) if alert.user_id == user.id => alert,
+        Ok(Some(_)) => return StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    let action = AlertAction {
+        alert_id,
+        side: payload.side,
+        quantity: payload.quantity,
+        quote_asset: payload.quote_asset.trim().to_uppercase(),
+        limit_offset: payload.limit_offset,
+        mode: payload.mode,
+        exchange: payload.exchange.map(|exchange| exchange.trim().to_lowercase()).filter(|exchange| !exchange.is_empty()),
+        created_at: chrono::Utc::now().timestamp(),
+    };
+    if let Err(e) = action.validate(&alert.alert_type) {
+        return (StatusCode::BAD_REQUEST, Json(json!({ "error": e }))).into_response();
+    }
+
+    match state.db.set_alert_action(&action) {
+        Ok(()) => Json(action).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn delete_alert_action(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(alert_id): Path<i64>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            match state.db.get_alert(alert_id) {
+                Ok(Some(alert)) if alert.user_id == user.id => {
+                    match state.db.remove_alert_action(alert_id) {
+                        Ok(true) => StatusCode::NO_CONTENT.into_response(),
+                        Ok(false) => StatusCode::NOT_FOUND.into_response(),
+                        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+                    }
+                }
+                Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+                Ok(None) => StatusCode::NOT_FOUND.into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OrdersQuery {
+    limit: Option<i64>,
 }
 
-// ... continuará con los handlers de alertas ... 
\ No newline at end of file
+/// Órdenes enviadas por las acciones de las alertas o detectadas en los
+/// exchanges del usuario, con su último estado conocido.
+pub async fn get_orders(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Query(query): Query<OrdersQuery>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            let limit = query.limit.unwrap_or(50).clamp(1, 500);
+            match state.db.get_user_orders(user.id, limit) {
+                Ok(orders) => Json(orders).into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[cfg(feature = "exchanges")]
+#[derive(Debug, Deserialize)]
+pub struct ExchangeCredentialRequest {
+    exchange: String,
+    api_key: String,
+    api_secret: String,
+    /// Solo KuCoin
+    passphrase: Option<String>,
+}
+
+/// Credenciales de exchange del usuario, sin secretos.
+#[cfg(feature = "exchanges")]
+pub async fn list_exchange_credentials(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            match state.db.list_exchange_credentials(user.id) {
+                Ok(credentials) => Json(credentials).into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Guarda cifradas las credenciales de un exchange; reemplaza las anteriores.
+#[cfg(feature = "exchanges")]
+pub async fn add_exchange_credential(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Json(payload): Json<ExchangeCredentialRequest>,
+) -> impl IntoResponse {
+    use crate::exchanges::{ExchangeCredentials, VaultError};
+
+    let user = match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => user,
+        Ok(None) => return StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let vault = match &state.vault {
+        Some(vault) => vault,
+        None => {
+            return (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(json!({ "error": "El servidor no tiene configurada VAULT_MASTER_KEY" })),
+            ).into_response();
+        }
+    };
+
+    let credentials = ExchangeCredentials {
+        api_key: payload.api_key.trim().to_string(),
+        api_secret: payload.api_secret.trim().to_string(),
+        passphrase: payload.passphrase.map(|passphrase| passphrase.trim().to_string()),
+    };
+    match vault.store(&state.db, user.id, &payload.exchange, &credentials) {
+        Ok(info) => {
+            audit(&state, &user, "exchange.credentials.add", Some(info.exchange.clone()));
+            (StatusCode::CREATED, Json(info)).into_response()
+        }
+        Err(e @ (VaultError::UnsupportedExchange(_) | VaultError::InvalidCredentials(_))) => {
+            (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() }))).into_response()
+        }
+        Err(e) => {
+            tracing::error!("Error al guardar credenciales de {}: {}", user.id, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[cfg(feature = "exchanges")]
+pub async fn delete_exchange_credential(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(credential_id): Path<i64>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            match state.db.delete_exchange_credential(user.id, credential_id) {
+                Ok(true) => {
+                    audit(&state, &user, "exchange.credentials.delete", Some(credential_id.to_string()));
+                    StatusCode::NO_CONTENT.into_response()
+                }
+                Ok(false) => StatusCode::NOT_FOUND.into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[cfg(feature = "exchanges")]
+#[derive(Debug, Deserialize)]
+pub struct GridStrategyRequest {
+    symbol: String,
+    #[serde(default = "default_quote_asset")]
+    quote_asset: String,
+    #[serde(flatten)]
+    config: crate::strategy::GridConfig,
+    mode: TradeMode,
+    /// Obligatorio en modo live
+    exchange: Option<String>,
+}
+
+#[cfg(feature = "exchanges")]
+pub async fn list_strategies(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => match state.db.get_user_strategies(user.id) {
+            Ok(strategies) => Json(strategies).into_response(),
+            Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        },
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Da de alta una rejilla; el motor coloca sus órdenes en la próxima pasada.
+#[cfg(feature = "exchanges")]
+pub async fn create_grid_strategy(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Json(payload): Json<GridStrategyRequest>,
+) -> impl IntoResponse {
+    let user = match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => user,
+        Ok(None) => return StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let mut strategy = match crate::strategy::new_grid(
+        user.id, payload.mode, payload.exchange, &payload.symbol, &payload.quote_asset, payload.config,
+    ) {
+        Ok(strategy) => strategy,
+        Err(e) => return (StatusCode::BAD_REQUEST, Json(json!({ "error": e }))).into_response(),
+    };
+    match state.db.create_strategy(&strategy) {
+        Ok(id) => {
+            strategy.id = id;
+            audit(&state, &user, "strategy.create", Some(id.to_string()));
+            (StatusCode::CREATED, Json(strategy)).into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Estrategia propia con su estado y su beneficio realizado.
+#[cfg(feature = "exchanges")]
+pub async fn get_strategy(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(strategy_id): Path<i64>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => match state.db.get_strategy(strategy_id) {
+            Ok(Some(strategy)) if strategy.user_id == user.id => Json(strategy).into_response(),
+            Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+            Ok(None) => StatusCode::NOT_FOUND.into_response(),
+            Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        },
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Pide detener una estrategia; el motor cancela sus órdenes abiertas.
+#[cfg(feature = "exchanges")]
+pub async fn stop_strategy(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(strategy_id): Path<i64>,
+) -> impl IntoResponse {
+    let user = match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => user,
+        Ok(None) => return StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    match state.db.get_strategy(strategy_id) {
+        Ok(Some(strategy)) if strategy.user_id == user.id => {}
+        Ok(Some(_)) => return StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+    match state.db.request_strategy_stop(strategy_id) {
+        Ok(true) => {
+            audit(&state, &user, "strategy.stop", Some(strategy_id.to_string()));
+            StatusCode::ACCEPTED.into_response()
+        }
+        Ok(false) => (StatusCode::CONFLICT, Json(json!({ "error": "La estrategia no está en marcha" }))).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ResetApiKeyRequest {
+    username: String,
+    password: String,
+}
+
+pub async fn reset_api_key(
+    Extension(state): Extension<ApiState>,
+    Json(payload): Json<ResetApiKeyRequest>,
+) -> impl IntoResponse {
+    let auth = Auth::new(state.db.as_ref());
+    
+    match auth.login(&payload.username, &payload.password) {
+        Ok(Some(user)) => {
+            match state.db.create_api_key(user.id) {
+                Ok(api_key) => {
+                    let response = json!({
+                        "message": "API key regenerada exitosamente",
+                        "api_key": api_key.key
+                    });
+                    (StatusCode::OK, Json(response)).into_response()
+                }
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn get_supported_exchanges(
+    Extension(_state): Extension<ApiState>,
+    BearerAuth(_): BearerAuth,
+) -> impl IntoResponse {
+    let api = CryptoAPI::new(std::env::var("COINGECKO_API_KEY").unwrap_or_default());
+    Json(api.supported_exchanges())
+}
+
+pub async fn get_supported_symbols(
+    Extension(_state): Extension<ApiState>,
+    BearerAuth(_): BearerAuth,
+) -> impl IntoResponse {
+    let api = CryptoAPI::new(std::env::var("COINGECKO_API_KEY").unwrap_or_default());
+    Json(api.supported_symbols())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChartQuery {
+    pub range: Option<String>,
+    pub style: Option<String>,
+}
+
+pub async fn get_chart(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(file): Path<String>,
+    Query(query): Query<ChartQuery>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(_)) => {}
+        Ok(None) => return StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+
+    let symbol = match file.strip_suffix(".png") {
+        Some(symbol) if !symbol.is_empty() => symbol.to_uppercase(),
+        _ => return StatusCode::NOT_FOUND.into_response(),
+    };
+    let range = match query.range.as_deref().map(ChartRange::parse) {
+        Some(Some(range)) => range,
+        Some(None) => return (StatusCode::BAD_REQUEST, Json(json!({"error": "Rango inválido"}))).into_response(),
+        None => ChartRange::Week,
+    };
+    let style = match query.style.as_deref().map(ChartStyle::parse) {
+        Some(Some(style)) => style,
+        Some(None) => return (StatusCode::BAD_REQUEST, Json(json!({"error": "Estilo inválido"}))).into_response(),
+        None => ChartStyle::Line,
+    };
+
+    let api = CryptoAPI::new(std::env::var("COINGECKO_API_KEY").unwrap_or_default());
+    if !api.supported_symbols().contains(&symbol) {
+        return StatusCode::NOT_FOUND.into_response();
+    }
+
+    match chart::symbol_chart(&api, &symbol, range, style).await {
+        Ok(png) => ([(header::CONTENT_TYPE, "image/png")], png).into_response(),
+        Err(_) => StatusCode::BAD_GATEWAY.into_response(),
+    }
+}
+
+/// Recibe updates de Telegram en modo webhook y los reenvía al dispatcher del bot.
+pub async fn telegram_webhook(
+    Extension(state): Extension<ApiState>,
+    Path(secret): Path<String>,
+    headers: HeaderMap,
+    body: String,
+) -> impl IntoResponse {
+    let webhook = match &state.telegram {
+        Some(webhook) => webhook,
+        None => return StatusCode::NOT_FOUND,
+    };
+
+    let header_secret = headers
+        .get("X-Telegram-Bot-Api-Secret-Token")
+        .map(|value| value.as_bytes())
+        .unwrap_or_default();
+    if !constant_time_eq(secret.as_bytes(), webhook.secret.as_bytes())
+        || !constant_time_eq(header_secret, webhook.secret.as_bytes())
+    {
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    match serde_json::from_str::<teloxide::types::Update>(&body) {
+        Ok(update) => {
+            if webhook.updates.send(Ok(update)).is_err() {
+                return StatusCode::SERVICE_UNAVAILABLE;
+            }
+        }
+        // Responder 200 igualmente para que Telegram no reintente un update que no podemos leer
+        Err(e) => tracing::error!("No se pudo parsear el update de Telegram: {}", e),
+    }
+
+    StatusCode::OK
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+// ... continuará con los handlers de alertas ... 
+fn audit(state: &ApiState, user: &User, action: &str, target: Option<String>) {
+    let event = AuditEvent {
+        id: None,
+        user_id: Some(user.id),
+        chat_id: None,
+        action: action.to_string(),
+        target,
+        created_at: chrono::Utc::now().timestamp(),
+    };
+    if let Err(e) = state.db.record_audit_event(&event) {
+        tracing::error!("No se pudo registrar la acción {}: {}", action, e);
+    }
+}
+
+pub async fn admin_stats(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) if user.role == UserRole::Admin => {
+            match state.db.get_admin_stats() {
+                Ok(stats) => Json(stats).into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UsersQuery {
+    limit: Option<i64>,
+    offset: Option<i64>,
+}
+
+pub async fn admin_list_users(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Query(query): Query<UsersQuery>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) if user.role == UserRole::Admin => {
+            let limit = query.limit.unwrap_or(50).clamp(1, 200);
+            let offset = query.offset.unwrap_or(0).max(0);
+            match (state.db.list_users(limit, offset), state.db.count_users()) {
+                (Ok(users), Ok(total)) => Json(json!({ "users": users, "total": total })).into_response(),
+                _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+async fn admin_set_user_active(state: ApiState, token: String, user_id: i64, active: bool) -> axum::response::Response {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(admin)) if admin.role == UserRole::Admin => {
+            if admin.id == user_id {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": "No puedes cambiar el estado de tu propia cuenta" })),
+                ).into_response();
+            }
+            match state.db.set_user_active(user_id, active) {
+                Ok(true) => {
+                    let action = if active { "admin.user.enable" } else { "admin.user.disable" };
+                    audit(&state, &admin, action, Some(user_id.to_string()));
+                    Json(json!({ "id": user_id, "is_active": active })).into_response()
+                }
+                Ok(false) => match state.db.find_user(&user_id.to_string()) {
+                    Ok(Some(user)) if user.id == user_id => Json(json!({ "id": user_id, "is_active": user.is_active })).into_response(),
+                    Ok(_) => StatusCode::NOT_FOUND.into_response(),
+                    Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+                },
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn admin_disable_user(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(user_id): Path<i64>,
+) -> impl IntoResponse {
+    admin_set_user_active(state, token, user_id, false).await
+}
+
+pub async fn admin_enable_user(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(user_id): Path<i64>,
+) -> impl IntoResponse {
+    admin_set_user_active(state, token, user_id, true).await
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BroadcastRequest {
+    message: String,
+}
+
+pub async fn admin_broadcast(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Json(payload): Json<BroadcastRequest>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(admin)) if admin.role == UserRole::Admin => {
+            let message = payload.message.trim();
+            if message.is_empty() {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": "El mensaje no puede estar vacío" })),
+                ).into_response();
+            }
+            let chat_ids = match state.db.get_broadcast_chat_ids() {
+                Ok(chat_ids) => chat_ids,
+                Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            };
+
+            let announcement = format!("📣 Anuncio\n\n{}", message);
+            let (sent, failed) = notify::broadcast(&state.bot, &chat_ids, &announcement).await;
+            audit(&state, &admin, "admin.broadcast", Some(format!("{} enviados, {} fallidos", sent, failed)));
+            Json(json!({ "sent": sent, "failed": failed })).into_response()
+        }
+        Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn get_quota(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            match (quota::user_plan(state.db.as_ref(), user.id), state.db.count_active_alerts(user.id)) {
+                (Ok(plan), Ok(active_alerts)) => Json(json!({
+                    "plan": plan,
+                    "active_alerts": active_alerts,
+                    "unlimited": user.role == UserRole::Admin,
+                })).into_response(),
+                _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegistrationModeRequest {
+    mode: RegistrationMode,
+}
+
+pub async fn admin_get_registration(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) if user.role == UserRole::Admin => {
+            match state.db.get_registration_mode() {
+                Ok(mode) => Json(json!({ "mode": mode })).into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn admin_set_registration(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Json(payload): Json<RegistrationModeRequest>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(admin)) if admin.role == UserRole::Admin => {
+            match state.db.set_registration_mode(payload.mode) {
+                Ok(()) => {
+                    audit(&state, &admin, "admin.registration", Some(payload.mode.as_str().to_string()));
+                    Json(json!({ "mode": payload.mode })).into_response()
+                }
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Límites de riesgo y estado del interruptor general de la operativa automática.
+pub async fn admin_get_risk(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) if user.role == UserRole::Admin => {
+            match (state.db.get_risk_limits(), state.db.is_trading_halted()) {
+                (Ok(limits), Ok(halted)) => Json(json!({ "halted": halted, "limits": limits })).into_response(),
+                _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn admin_set_risk(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Json(mut limits): Json<RiskLimits>,
+) -> impl IntoResponse {
+    let invalid_amount = [limits.max_order_notional, limits.max_daily_notional, limits.max_position_notional]
+        .iter()
+        .flatten()
+        .any(|limit| !limit.is_finite() || *limit <= 0.0);
+    if invalid_amount || limits.max_orders_per_minute.is_some_and(|rate| rate <= 0) {
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(json!({ "error": "Los límites deben ser positivos; usa null para quitar una regla" })),
+        ).into_response();
+    }
+    limits.allowed_symbols = limits.allowed_symbols.iter()
+        .map(|symbol| symbol.trim().to_uppercase())
+        .filter(|symbol| !symbol.is_empty())
+        .collect();
+
+    match state.db.verify_api_key(&token) {
+        Ok(Some(admin)) if admin.role == UserRole::Admin => {
+            match state.db.set_risk_limits(&limits) {
+                Ok(()) => {
+                    audit(&state, &admin, "admin.trading.risk", serde_json::to_string(&limits).ok());
+                    Json(limits).into_response()
+                }
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KillSwitchRequest {
+    halted: bool,
+}
+
+pub async fn admin_set_kill_switch(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Json(payload): Json<KillSwitchRequest>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(admin)) if admin.role == UserRole::Admin => {
+            match state.db.set_trading_halted(payload.halted) {
+                Ok(()) => {
+                    let action = if payload.halted { "admin.trading.halt" } else { "admin.trading.resume" };
+                    audit(&state, &admin, action, None);
+                    Json(json!({ "halted": payload.halted })).into_response()
+                }
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct InviteRequest {
+    plan: Option<String>,
+}
+
+pub async fn admin_create_invite(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Json(payload): Json<InviteRequest>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(admin)) if admin.role == UserRole::Admin => {
+            let plan = payload.plan.unwrap_or_else(|| Plan::DEFAULT.to_string());
+            match state.db.get_plan(&plan) {
+                Ok(Some(_)) => {}
+                Ok(None) => return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": format!("No existe el plan {}", plan) })),
+                ).into_response(),
+                Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+            match state.db.create_invite_code(admin.id, &plan) {
+                Ok((code, expires_at)) => {
+                    audit(&state, &admin, "admin.invite", Some(plan.clone()));
+                    (StatusCode::CREATED, Json(json!({ "code": code, "plan": plan, "expires_at": expires_at }))).into_response()
+                }
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn admin_pending_users(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) if user.role == UserRole::Admin => {
+            match state.db.list_pending_users() {
+                Ok(users) => Json(users).into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+async fn admin_review_user(state: ApiState, token: String, user_id: i64, approve: bool) -> axum::response::Response {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(admin)) if admin.role == UserRole::Admin => {
+            let user = match state.db.get_user(user_id) {
+                Ok(Some(user)) if user.pending_approval => user,
+                Ok(_) => return StatusCode::NOT_FOUND.into_response(),
+                Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            };
+            let (result, action, notice) = if approve {
+                (state.db.approve_user(user_id), "admin.user.approve", "✅ Tu cuenta fue aprobada. Usa /help para empezar.")
+            } else {
+                (state.db.reject_user(user_id), "admin.user.reject", "❌ Tu solicitud de registro fue rechazada.")
+            };
+            match result {
+                Ok(_) => {
+                    audit(&state, &admin, action, Some(user_id.to_string()));
+                    if let Some(chat_id) = user.telegram_chat_id {
+                        notify::broadcast(&state.bot, &[chat_id], notice).await;
+                    }
+                    Json(json!({ "id": user_id, "approved": approve })).into_response()
+                }
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn admin_approve_user(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(user_id): Path<i64>,
+) -> impl IntoResponse {
+    admin_review_user(state, token, user_id, true).await
+}
+
+pub async fn admin_reject_user(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(user_id): Path<i64>,
+) -> impl IntoResponse {
+    admin_review_user(state, token, user_id, false).await
+}
+
+pub async fn admin_list_plans(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) if user.role == UserRole::Admin => {
+            match state.db.get_plans() {
+                Ok(plans) => Json(plans).into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PlanRequest {
+    max_active_alerts: Option<i64>,
+    #[serde(default)]
+    min_check_interval: i64,
+    allowed_kinds: Vec<AlertKind>,
+}
+
+pub async fn admin_save_plan(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(name): Path<String>,
+    Json(payload): Json<PlanRequest>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(admin)) if admin.role == UserRole::Admin => {
+            if payload.max_active_alerts.is_some_and(|max| max < 0) || payload.min_check_interval < 0 {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": "Los límites no pueden ser negativos" })),
+                ).into_response();
+            }
+            let plan = Plan {
+                name,
+                max_active_alerts: payload.max_active_alerts,
+                min_check_interval: payload.min_check_interval,
+                allowed_kinds: payload.allowed_kinds,
+            };
+            match state.db.save_plan(&plan) {
+                Ok(()) => {
+                    audit(&state, &admin, "admin.plan", Some(plan.name.clone()));
+                    Json(plan).into_response()
+                }
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UserPlanRequest {
+    plan: String,
+}
+
+pub async fn admin_set_user_plan(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(user_id): Path<i64>,
+    Json(payload): Json<UserPlanRequest>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(admin)) if admin.role == UserRole::Admin => {
+            match state.db.get_user(user_id) {
+                Ok(Some(_)) => {}
+                Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+                Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+            match state.db.set_user_plan(user_id, &payload.plan) {
+                Ok(true) => {
+                    audit(&state, &admin, "admin.user.plan", Some(format!("{}:{}", user_id, payload.plan)));
+                    Json(json!({ "id": user_id, "plan": payload.plan })).into_response()
+                }
+                Ok(false) => (
+                    StatusCode::BAD_REQUEST,
+                    Json(json!({ "error": format!("No existe el plan {}", payload.plan) })),
+                ).into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(Some(_)) => StatusCode::FORBIDDEN.into_response(),
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+fn portfolio_error_response(e: PortfolioError) -> axum::response::Response {
+    match e {
+        PortfolioError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        PortfolioError::NotFound => StatusCode::NOT_FOUND.into_response(),
+        e => (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() }))).into_response(),
+    }
+}
+
+pub async fn get_portfolio(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            match state.db.get_holdings(user.id) {
+                Ok(holdings) => {
+                    let api = CryptoAPI::new(std::env::var("COINGECKO_API_KEY").unwrap_or_default());
+                    Json(portfolio::valuation(&api, holdings).await).into_response()
+                }
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn get_holdings(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            match state.db.get_holdings(user.id) {
+                Ok(holdings) => Json(holdings).into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransactionsQuery {
+    symbol: Option<String>,
+}
+
+pub async fn get_transactions(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Query(query): Query<TransactionsQuery>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            let symbol = query.symbol.map(|s| s.to_uppercase());
+            match state.db.get_transactions(user.id, symbol.as_deref()) {
+                Ok(transactions) => Json(transactions).into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TransactionRequest {
+    symbol: String,
+    side: TradeSide,
+    quantity: f64,
+    price: f64,
+    #[serde(default)]
+    fee: f64,
+    /// Por defecto, ahora
+    executed_at: Option<i64>,
+    note: Option<String>,
+}
+
+impl TransactionRequest {
+    fn into_transaction(self, user_id: i64) -> Transaction {
+        Transaction {
+            id: None,
+            user_id,
+            symbol: self.symbol.to_uppercase(),
+            side: self.side,
+            quantity: self.quantity,
+            price: self.price,
+            fee: self.fee,
+            executed_at: self.executed_at.unwrap_or_else(|| chrono::Utc::now().timestamp()),
+            note: self.note,
+        }
+    }
+}
+
+pub async fn create_transaction(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Json(payload): Json<TransactionRequest>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            let transaction = payload.into_transaction(user.id);
+            match Portfolio::new(state.db.as_ref()).record(&transaction) {
+                Ok((id, holding)) => (
+                    StatusCode::CREATED,
+                    Json(json!({ "transaction": Transaction { id: Some(id), ..transaction }, "holding": holding })),
+                ).into_response(),
+                Err(e) => portfolio_error_response(e),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn update_transaction(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(transaction_id): Path<i64>,
+    Json(payload): Json<TransactionRequest>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            let transaction = payload.into_transaction(user.id);
+            match Portfolio::new(state.db.as_ref()).update(user.id, transaction_id, &transaction) {
+                Ok(holding) => Json(json!({
+                    "transaction": Transaction { id: Some(transaction_id), ..transaction },
+                    "holding": holding,
+                })).into_response(),
+                Err(e) => portfolio_error_response(e),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn delete_transaction(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(transaction_id): Path<i64>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            match Portfolio::new(state.db.as_ref()).delete(user.id, transaction_id) {
+                Ok(holding) => Json(json!({ "holding": holding })).into_response(),
+                Err(e) => portfolio_error_response(e),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DcaPlanRequest {
+    symbol: String,
+    /// Importe de cada compra en `quote_asset`
+    amount: f64,
+    #[serde(default = "default_quote_asset")]
+    quote_asset: String,
+    /// Horario cron en UTC, p. ej. `0 9 * * 1` o `@weekly`
+    schedule: String,
+    max_price: Option<f64>,
+    #[serde(default = "default_trade_mode")]
+    mode: TradeMode,
+    exchange: Option<String>,
+}
+
+fn dca_error_response(e: DcaError) -> axum::response::Response {
+    match e {
+        DcaError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        DcaError::NotFound => StatusCode::NOT_FOUND.into_response(),
+        e => (StatusCode::BAD_REQUEST, Json(json!({ "error": e.to_string() }))).into_response(),
+    }
+}
+
+pub async fn get_dca_plans(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => match state.db.get_user_dca_plans(user.id) {
+            Ok(plans) => Json(plans).into_response(),
+            Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        },
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn create_dca_plan(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Json(payload): Json<DcaPlanRequest>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => {
+            let plan = DcaPlan {
+                id: 0,
+                user_id: user.id,
+                symbol: payload.symbol.trim().to_uppercase(),
+                amount: payload.amount,
+                quote_asset: payload.quote_asset.trim().to_uppercase(),
+                schedule: payload.schedule.trim().to_string(),
+                max_price: payload.max_price,
+                mode: payload.mode,
+                exchange: payload.exchange.map(|exchange| exchange.trim().to_lowercase()).filter(|exchange| !exchange.is_empty()),
+                active: true,
+                next_run_at: 0,
+                last_run_at: None,
+                created_at: chrono::Utc::now().timestamp(),
+            };
+            match DcaPlans::new(state.db.as_ref()).create(plan) {
+                Ok(plan) => (StatusCode::CREATED, Json(plan)).into_response(),
+                Err(e) => dca_error_response(e),
+            }
+        }
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Plan DCA propio y sus últimos turnos.
+pub async fn get_dca_plan(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(plan_id): Path<i64>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => match DcaPlans::new(state.db.as_ref()).owned(user.id, plan_id) {
+            Ok(plan) => match state.db.get_dca_runs(plan_id, 20) {
+                Ok(runs) => Json(json!({ "plan": plan, "runs": runs })).into_response(),
+                Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            },
+            Err(e) => dca_error_response(e),
+        },
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn pause_dca_plan(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(plan_id): Path<i64>,
+) -> impl IntoResponse {
+    set_dca_plan_active(state, token, plan_id, false)
+}
+
+pub async fn resume_dca_plan(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(plan_id): Path<i64>,
+) -> impl IntoResponse {
+    set_dca_plan_active(state, token, plan_id, true)
+}
+
+fn set_dca_plan_active(state: ApiState, token: String, plan_id: i64, active: bool) -> axum::response::Response {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => match DcaPlans::new(state.db.as_ref()).set_active(user.id, plan_id, active) {
+            Ok(plan) => Json(plan).into_response(),
+            Err(e) => dca_error_response(e),
+        },
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn delete_dca_plan(
+    Extension(state): Extension<ApiState>,
+    BearerAuth(token): BearerAuth,
+    Path(plan_id): Path<i64>,
+) -> impl IntoResponse {
+    match state.db.verify_api_key(&token) {
+        Ok(Some(user)) => match DcaPlans::new(state.db.as_ref()).delete(user.id, plan_id) {
+            Ok(()) => StatusCode::NO_CONTENT.into_response(),
+            Err(e) => dca_error_response(e),
+        },
+        Ok(None) => StatusCode::UNAUTHORIZED.into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
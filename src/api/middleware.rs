@@ -10,10 +10,15 @@ pub async fn logging(
     next: Next,
 ) -> Response {
     let method = req.method().clone();
-    let uri = req.uri().clone();
-    println!("--> {} {}", method, uri.path());
+    // No registrar el secreto del webhook de Telegram
+    let path = if req.uri().path().starts_with("/telegram/") {
+        "/telegram/***".to_string()
+    } else {
+        req.uri().path().to_string()
+    };
+    println!("--> {} {}", method, path);
     
     let response = next.run(req).await;
-    println!("<-- {} {}", response.status(), uri.path());
+    println!("<-- {} {}", response.status(), path);
     response
 } 
\ No newline at end of file
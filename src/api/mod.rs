@@ -2,7 +2,9 @@ use axum::{Router, Extension};
 use std::sync::Arc;
 use tower_http::cors::CorsLayer;
 use crate::Database;
+use crate::bot::UpdateSender;
 use tokio::net::TcpListener;
+use teloxide::Bot;
 
 mod routes;
 mod handlers;
@@ -14,11 +16,35 @@ pub use middleware::logging;
 #[derive(Clone)]
 pub struct ApiState {
     db: Arc<Database>,
+    telegram: Option<TelegramWebhook>,
+    /// Para los anuncios de `/admin/broadcast`
+    bot: Bot,
+    /// Almacén de credenciales de exchange; sin `VAULT_MASTER_KEY` no se pueden guardar
+    #[cfg(feature = "exchanges")]
+    vault: Option<Arc<crate::exchanges::Vault>>,
 }
 
-pub async fn start_server(db: Arc<Database>, port: u16) -> Result<(), Box<dyn std::error::Error>> {
+/// Destino de los updates recibidos por webhook cuando el bot corre en ese modo.
+#[derive(Clone)]
+pub struct TelegramWebhook {
+    pub secret: String,
+    pub updates: UpdateSender,
+}
+
+pub async fn start_server(
+    db: Arc<Database>,
+    addr: std::net::SocketAddr,
+    telegram: Option<TelegramWebhook>,
+    bot: Bot,
+) -> Result<(), Box<dyn std::error::Error>> {
     // Configurar el estado compartido
-    let state = ApiState { db: db.clone() };
+    let state = ApiState {
+        db: db.clone(),
+        telegram,
+        bot,
+        #[cfg(feature = "exchanges")]
+        vault: crate::exchanges::Vault::from_env()?.map(Arc::new),
+    };
 
     // Configurar CORS
     let cors = CorsLayer::permissive();
@@ -30,7 +56,6 @@ pub async fn start_server(db: Arc<Database>, port: u16) -> Result<(), Box<dyn st
         .layer(cors);
 
     // Iniciar el servidor
-    let addr = std::net::SocketAddr::from(([127, 0, 0, 1], port));
     println!("API escuchando en http://{}", addr);
     
     let listener = TcpListener::bind(addr).await?;
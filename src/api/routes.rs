@@ -1,7 +1,7 @@
 // Por ahora vacío, moveremos las rutas aquí más adelante 
 
 use axum::{
-    routing::{get, post, delete},
+    routing::{get, post, put, delete},
     Router,
 };
 use super::handlers;
@@ -12,12 +12,81 @@ pub fn api_routes() -> Router {
         .route("/auth/register", post(handlers::register))
         .route("/auth/login", post(handlers::login))
         .route("/auth/reset-api-key", post(handlers::reset_api_key))
+        .route("/auth/api-key", post(handlers::exchange_api_key_code))
+        .route("/auth/telegram-link", post(handlers::create_telegram_link).delete(handlers::delete_telegram_link))
         // Rutas de alertas
         .route("/alerts/price", post(handlers::create_price_alert))
         .route("/alerts/depeg", post(handlers::create_depeg_alert))
         .route("/alerts/pair", post(handlers::create_pair_alert))
+        .route("/alerts/portfolio", post(handlers::create_portfolio_alert))
+        .route("/alerts/backtest", post(handlers::backtest_alert))
         .route("/alerts", get(handlers::get_user_alerts))
         .route("/alerts/:id", delete(handlers::delete_alert))
+        .route("/alerts/:id/ack", post(handlers::acknowledge_alert))
+        .route(
+            "/alerts/:id/escalation",
+            get(handlers::get_escalation).put(handlers::set_escalation).delete(handlers::delete_escalation),
+        )
+        .route(
+            "/alerts/:id/action",
+            get(handlers::get_alert_action).put(handlers::set_alert_action).delete(handlers::delete_alert_action),
+        )
+        .route("/orders", get(handlers::get_orders))
         .route("/alerts/exchanges", get(handlers::get_supported_exchanges))
         .route("/alerts/symbols", get(handlers::get_supported_symbols))
+        .route("/alerts/quota", get(handlers::get_quota))
+        // Portafolio
+        .route("/portfolio", get(handlers::get_portfolio))
+        .route("/portfolio/holdings", get(handlers::get_holdings))
+        .route("/portfolio/transactions", get(handlers::get_transactions).post(handlers::create_transaction))
+        .route(
+            "/portfolio/transactions/:id",
+            put(handlers::update_transaction).delete(handlers::delete_transaction),
+        )
+        // Compras periódicas (DCA)
+        .route("/dca", get(handlers::get_dca_plans).post(handlers::create_dca_plan))
+        .route("/dca/:id", get(handlers::get_dca_plan).delete(handlers::delete_dca_plan))
+        .route("/dca/:id/pause", post(handlers::pause_dca_plan))
+        .route("/dca/:id/resume", post(handlers::resume_dca_plan))
+        // Administración
+        .route("/admin/stats", get(handlers::admin_stats))
+        .route("/admin/users", get(handlers::admin_list_users))
+        .route("/admin/users/:id/disable", post(handlers::admin_disable_user))
+        .route("/admin/users/:id/enable", post(handlers::admin_enable_user))
+        .route("/admin/broadcast", post(handlers::admin_broadcast))
+        .route("/admin/registration", get(handlers::admin_get_registration).put(handlers::admin_set_registration))
+        .route("/admin/invites", post(handlers::admin_create_invite))
+        .route("/admin/users/pending", get(handlers::admin_pending_users))
+        .route("/admin/users/:id/approve", post(handlers::admin_approve_user))
+        .route("/admin/users/:id/reject", post(handlers::admin_reject_user))
+        .route("/admin/users/:id/plan", put(handlers::admin_set_user_plan))
+        .route("/admin/plans", get(handlers::admin_list_plans))
+        .route("/admin/plans/:name", put(handlers::admin_save_plan))
+        .route("/admin/risk", get(handlers::admin_get_risk).put(handlers::admin_set_risk))
+        .route("/admin/kill-switch", put(handlers::admin_set_kill_switch))
+        // Webhook de Telegram
+        .route("/telegram/:secret", post(handlers::telegram_webhook))
+        // Gráficos
+        .route("/charts/:file", get(handlers::get_chart))
+        .merge(exchange_routes())
+}
+
+/// Credenciales de exchange y estrategias, solo con la característica `exchanges`.
+#[cfg(feature = "exchanges")]
+fn exchange_routes() -> Router {
+    Router::new()
+        .route(
+            "/exchange-credentials",
+            get(handlers::list_exchange_credentials).post(handlers::add_exchange_credential),
+        )
+        .route("/exchange-credentials/:id", delete(handlers::delete_exchange_credential))
+        .route("/strategies", get(handlers::list_strategies))
+        .route("/strategies/grid", post(handlers::create_grid_strategy))
+        .route("/strategies/:id", get(handlers::get_strategy))
+        .route("/strategies/:id/stop", post(handlers::stop_strategy))
+}
+
+#[cfg(not(feature = "exchanges"))]
+fn exchange_routes() -> Router {
+    Router::new()
 } 
\ No newline at end of file
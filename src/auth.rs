@@ -2,7 +2,7 @@ use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
 };
-use crate::{Database, User};
+use crate::{Database, User, RegistrationMode};
 use argon2::password_hash::Error as ArgonError;
 
 #[derive(Debug)]
@@ -11,6 +11,9 @@ pub enum AuthError {
     UserExists,
     InvalidCredentials,
     HashError(String),
+    /// El registro está limitado a invitaciones y no se indicó código
+    InviteRequired,
+    InvalidInvite,
 }
 
 // Implementar Send y Sync para AuthError
@@ -26,6 +29,8 @@ impl std::fmt::Display for AuthError {
             AuthError::UserExists => write!(f, "El usuario ya existe"),
             AuthError::InvalidCredentials => write!(f, "Credenciales inválidas"),
             AuthError::HashError(e) => write!(f, "Error al hashear contraseña: {}", e),
+            AuthError::InviteRequired => write!(f, "El registro requiere un código de invitación"),
+            AuthError::InvalidInvite => write!(f, "El código de invitación no es válido o ya fue usado"),
         }
     }
 }
@@ -37,6 +42,10 @@ impl From<ArgonError> for AuthError {
     }
 }
 
+/// Marcador de `password_hash` para cuentas registradas desde Telegram sin
+/// contraseña. No es un hash PHC válido, así que nunca verifica.
+pub const NO_PASSWORD: &str = "!telegram";
+
 pub struct Auth<'a> {
     db: &'a Database,
 }
@@ -46,21 +55,47 @@ impl<'a> Auth<'a> {
         Self { db }
     }
 
-    pub fn register_user(&self, username: &str, password: &str) -> Result<User, AuthError> {
+    /// Registra un usuario con contraseña. Según el modo de registro puede
+    /// exigir `invite_code` o dejar la cuenta pendiente de aprobación.
+    pub fn register_user(&self, username: &str, password: &str, invite_code: Option<&str>) -> Result<User, AuthError> {
+        let pending_approval = self.admission(invite_code)?;
         let password_hash = self.hash_password(password)?;
-        let user_id = self.db.create_user(username, &password_hash)
-            .map_err(|e| AuthError::DatabaseError(e))?;
-        
-        Ok(User {
-            id: user_id,
-            username: username.to_string(),
-            password_hash,
-            api_key: None,
-            telegram_chat_id: None,
-            created_at: chrono::Utc::now().timestamp(),
-            last_login: None,
-            is_active: true,
-        })
+        let user_id = self.db.create_user(username, &password_hash, pending_approval, invite_code)
+            .map_err(Self::registration_error)?;
+        self.registered_user(user_id)
+    }
+
+    pub fn register_telegram_user(&self, username: &str, telegram_user_id: i64, chat_id: i64, invite_code: Option<&str>) -> Result<User, AuthError> {
+        let pending_approval = self.admission(invite_code)?;
+        let user_id = self.db.create_telegram_user(username, NO_PASSWORD, telegram_user_id, chat_id, pending_approval, invite_code)
+            .map_err(Self::registration_error)?;
+        self.registered_user(user_id)
+    }
+
+    /// Aplica el modo de registro y devuelve si la cuenta queda pendiente de
+    /// aprobación. Una invitación válida evita la aprobación.
+    fn admission(&self, invite_code: Option<&str>) -> Result<bool, AuthError> {
+        let mode = self.db.get_registration_mode().map_err(AuthError::DatabaseError)?;
+        match (mode, invite_code) {
+            (RegistrationMode::Invite, None) => Err(AuthError::InviteRequired),
+            (RegistrationMode::Approval, None) => Ok(true),
+            _ => Ok(false),
+        }
+    }
+
+    fn registration_error(e: rusqlite::Error) -> AuthError {
+        match e {
+            rusqlite::Error::SqliteFailure(err, _) if err.code == rusqlite::ErrorCode::ConstraintViolation => AuthError::UserExists,
+            // El canje de la invitación no devolvió filas
+            rusqlite::Error::QueryReturnedNoRows => AuthError::InvalidInvite,
+            e => AuthError::DatabaseError(e),
+        }
+    }
+
+    fn registered_user(&self, user_id: i64) -> Result<User, AuthError> {
+        self.db.get_user(user_id)
+            .map_err(AuthError::DatabaseError)?
+            .ok_or(AuthError::DatabaseError(rusqlite::Error::QueryReturnedNoRows))
     }
 
     pub fn login(&self, username: &str, password: &str) -> Result<Option<User>, AuthError> {
@@ -68,6 +103,9 @@ impl<'a> Auth<'a> {
             .map_err(|e| AuthError::DatabaseError(e))?;
 
         if let Some(user) = user {
+            if user.password_hash == NO_PASSWORD {
+                return Ok(None);
+            }
             if self.verify_password(password, &user.password_hash)? {
                 return Ok(Some(user));
             }
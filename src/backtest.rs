@@ -0,0 +1,346 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::db::Database;
+use crate::models::{AlertKind, AlertType, Holding, PriceTick};
+use crate::monitor::{depeg_trigger, pair_trigger, price_triggers};
+use crate::portfolio;
+
+/// Ventana que se prueba con el histórico guardado si no se indica `from`.
+const DEFAULT_WINDOW: i64 = 30 * 24 * 3600;
+
+/// Máximo de cotizaciones que se reproducen en una prueba.
+pub const MAX_TICKS: usize = 500_000;
+
+#[derive(Debug)]
+pub enum BacktestError {
+    DatabaseError(rusqlite::Error),
+    InvalidRequest(String),
+    NoData,
+}
+
+impl std::error::Error for BacktestError {}
+
+impl std::fmt::Display for BacktestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BacktestError::DatabaseError(e) => write!(f, "Error de base de datos: {}", e),
+            BacktestError::InvalidRequest(reason) => write!(f, "Prueba inválida: {}", reason),
+            BacktestError::NoData => write!(f, "No hay precios con los que evaluar la alerta en ese periodo"),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for BacktestError {
+    fn from(err: rusqlite::Error) -> Self {
+        BacktestError::DatabaseError(err)
+    }
+}
+
+/// Alerta a probar y precios con los que probarla.
+#[derive(Debug, Clone, Deserialize)]
+pub struct BacktestRequest {
+    pub alert_type: AlertType,
+    /// Símbolo de las alertas de precio y de depeg
+    #[serde(default)]
+    pub symbol: Option<String>,
+    /// Serie propia. Si falta se usa el histórico guardado entre `from` y `to`
+    #[serde(default)]
+    pub series: Option<Vec<PriceTick>>,
+    #[serde(default)]
+    pub from: Option<i64>,
+    #[serde(default)]
+    pub to: Option<i64>,
+}
+
+/// Un disparo que habría tenido la alerta.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct BacktestTrigger {
+    pub timestamp: i64,
+    /// Precio, ratio, valor o porcentaje que la disparó, como en la notificación
+    pub observed: f64,
+    /// Primer instante en que la condición dejó de cumplirse; `None` si
+    /// seguía cumpliéndose al final de la serie
+    pub cleared_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BacktestReport {
+    pub from: i64,
+    pub to: i64,
+    /// Instantes evaluados; las cotizaciones con el mismo timestamp cuentan una vez
+    pub evaluations: usize,
+    /// Instantes en los que la condición se cumplía
+    pub triggered_evaluations: usize,
+    pub triggers: Vec<BacktestTrigger>,
+}
+
+/// Resultado de evaluar la alerta en un instante.
+enum Evaluation {
+    /// Aún faltan precios para evaluarla
+    Pending,
+    Clear,
+    Triggered(f64),
+}
+
+/// Reproduce `ticks` en orden cronológico contra la alerta, con el mismo
+/// código con el que la evalúa el monitor. El monitor desactiva la alerta al
+/// dispararse; aquí se supone rearmada, de modo que cada vez que la condición
+/// vuelve a cumplirse tras dejar de hacerlo cuenta como un disparo nuevo.
+///
+/// Las alertas de depeg usan los exchanges de la alerta presentes en la
+/// serie; si no aparece ninguno, todas las fuentes del símbolo. Las de
+/// portafolio valoran `holdings` a los precios de cada instante.
+pub fn replay(alert_type: &AlertType, symbol: &str, ticks: &[PriceTick], holdings: &[Holding]) -> BacktestReport {
+    let mut alert_type = alert_type.clone();
+    let symbol = symbol.to_uppercase();
+    let mut ticks: Vec<&PriceTick> = ticks.iter().collect();
+    ticks.sort_by_key(|tick| tick.timestamp);
+
+    let depeg_venues: Option<Vec<String>> = match &alert_type {
+        AlertType::Depeg { exchanges, .. } => {
+            let exchanges: Vec<String> = exchanges.iter().map(|exchange| exchange.to_lowercase()).collect();
+            ticks.iter()
+                .any(|tick| tick.symbol.eq_ignore_ascii_case(&symbol) && exchanges.contains(&tick.venue.to_lowercase()))
+                .then_some(exchanges)
+        }
+        _ => None,
+    };
+
+    let mut report = BacktestReport {
+        from: ticks.first().map(|tick| tick.timestamp).unwrap_or_default(),
+        to: ticks.last().map(|tick| tick.timestamp).unwrap_or_default(),
+        evaluations: 0,
+        triggered_evaluations: 0,
+        triggers: Vec::new(),
+    };
+    // Último precio de cada símbolo, y de cada símbolo en cada fuente
+    let mut latest: HashMap<String, f64> = HashMap::new();
+    let mut venues: HashMap<(String, String), f64> = HashMap::new();
+    let mut active = false;
+
+    for group in ticks.chunk_by(|a, b| a.timestamp == b.timestamp) {
+        for tick in group {
+            latest.insert(tick.symbol.to_uppercase(), tick.price);
+            venues.insert((tick.symbol.to_uppercase(), tick.venue.to_lowercase()), tick.price);
+        }
+        let timestamp = group[0].timestamp;
+
+        let evaluation = match &mut alert_type {
+            AlertType::Price { .. } => match latest.get(&symbol) {
+                Some(price) if price_triggers(&alert_type, *price) => Evaluation::Triggered(*price),
+                Some(_) => Evaluation::Clear,
+                None => Evaluation::Pending,
+            },
+            AlertType::Depeg { target_price, differential, .. } => {
+                let prices: Vec<f64> = venues.iter()
+                    .filter(|((tick_symbol, venue), _)| {
+                        *tick_symbol == symbol && depeg_venues.as_ref().is_none_or(|allowed| allowed.contains(venue))
+                    })
+                    .map(|(_, price)| *price)
+                    .collect();
+                match depeg_trigger(*target_price, *differential, &prices) {
+                    Some(price) => Evaluation::Triggered(price),
+                    None if prices.is_empty() => Evaluation::Pending,
+                    None => Evaluation::Clear,
+                }
+            }
+            AlertType::PairDepeg { token1, token2, expected_ratio, differential } => {
+                match (latest.get(&token1.to_uppercase()), latest.get(&token2.to_uppercase())) {
+                    (Some(price1), Some(price2)) => match pair_trigger(*expected_ratio, *differential, *price1, *price2) {
+                        Some(ratio) => Evaluation::Triggered(ratio),
+                        None => Evaluation::Clear,
+                    },
+                    _ => Evaluation::Pending,
+                }
+            }
+            portfolio_alert => {
+                let valuation = portfolio::value_at(holdings.to_vec(), &latest);
+                if !valuation.is_complete() {
+                    Evaluation::Pending
+                } else {
+                    match portfolio::check_alert(portfolio_alert, &valuation) {
+                        Some(observed) => Evaluation::Triggered(observed),
+                        None => Evaluation::Clear,
+                    }
+                }
+            }
+        };
+
+        match evaluation {
+            Evaluation::Pending => continue,
+            Evaluation::Triggered(observed) => {
+                report.triggered_evaluations += 1;
+                if !active {
+                    report.triggers.push(BacktestTrigger { timestamp, observed, cleared_at: None });
+                }
+                active = true;
+            }
+            Evaluation::Clear => {
+                if active {
+                    if let Some(trigger) = report.triggers.last_mut() {
+                        trigger.cleared_at = Some(timestamp);
+                    }
+                }
+                active = false;
+            }
+        }
+        report.evaluations += 1;
+    }
+    report
+}
+
+/// Símbolos cuyos precios necesita la alerta.
+fn required_symbols(alert_type: &AlertType, symbol: &str, holdings: &[Holding]) -> Vec<String> {
+    match alert_type {
+        AlertType::Price { .. } | AlertType::Depeg { .. } => vec![symbol.to_uppercase()],
+        AlertType::PairDepeg { token1, token2, .. } => vec![token1.to_uppercase(), token2.to_uppercase()],
+        _ => portfolio::open_symbols(holdings),
+    }
+}
+
+/// Comprueba la alerta antes de reproducirla.
+fn validate(alert_type: &AlertType, symbol: &str) -> Result<(), BacktestError> {
+    let invalid = |reason: &str| Err(BacktestError::InvalidRequest(reason.to_string()));
+    match alert_type {
+        AlertType::Price { .. } | AlertType::Depeg { .. } if symbol.trim().is_empty() => invalid("falta el símbolo de la alerta"),
+        AlertType::Depeg { target_price, .. } if !(*target_price > 0.0 && target_price.is_finite()) => invalid("el precio objetivo debe ser mayor que cero"),
+        AlertType::PairDepeg { expected_ratio, .. } if !(*expected_ratio > 0.0 && expected_ratio.is_finite()) => invalid("el ratio esperado debe ser mayor que cero"),
+        AlertType::PortfolioValue { .. } | AlertType::PortfolioDrawdown { .. } | AlertType::AllocationDrift { .. } => {
+            portfolio::validate_alert(alert_type).map_err(|e| BacktestError::InvalidRequest(e.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Pruebas de alertas con el histórico guardado o con una serie propia.
+pub struct Backtest<'a> {
+    db: &'a Database,
+}
+
+impl<'a> Backtest<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        Self { db }
+    }
+
+    /// Las alertas de portafolio se prueban con las posiciones actuales de
+    /// `user_id`, así que sin usuario no se pueden probar.
+    pub fn run(&self, user_id: Option<i64>, request: &BacktestRequest) -> Result<BacktestReport, BacktestError> {
+        let symbol = request.symbol.as_deref().unwrap_or_default().trim().to_uppercase();
+        validate(&request.alert_type, &symbol)?;
+
+        let holdings = match user_id {
+            _ if request.alert_type.kind() != AlertKind::Portfolio => Vec::new(),
+            Some(user_id) => self.db.get_holdings(user_id)?,
+            None => return Err(BacktestError::InvalidRequest("las alertas de portafolio necesitan un usuario".to_string())),
+        };
+        let symbols = required_symbols(&request.alert_type, &symbol, &holdings);
+        if symbols.is_empty() {
+            return Err(BacktestError::InvalidRequest("no hay posiciones abiertas en el portafolio".to_string()));
+        }
+
+        let ticks = match &request.series {
+            Some(series) => series.clone(),
+            None => {
+                let to = request.to.unwrap_or_else(|| chrono::Utc::now().timestamp());
+                let from = request.from.unwrap_or(to - DEFAULT_WINDOW);
+                if from > to {
+                    return Err(BacktestError::InvalidRequest("el inicio es posterior al final".to_string()));
+                }
+                let mut ticks = Vec::new();
+                for symbol in &symbols {
+                    ticks.extend(self.db.get_price_history(symbol, from, to)?);
+                }
+                ticks
+            }
+        };
+        if ticks.len() > MAX_TICKS {
+            return Err(BacktestError::InvalidRequest(format!("la serie supera las {} cotizaciones; acorta el periodo", MAX_TICKS)));
+        }
+
+        let report = replay(&request.alert_type, &symbol, &ticks, &holdings);
+        if report.evaluations == 0 {
+            return Err(BacktestError::NoData);
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::AlertCondition;
+
+    fn tick(timestamp: i64, symbol: &str, venue: &str, price: f64) -> PriceTick {
+        PriceTick { timestamp, symbol: symbol.to_string(), venue: venue.to_string(), price, volume: None }
+    }
+
+    #[test]
+    fn test_price_alert_reports_each_crossing() {
+        let alert = AlertType::Price { target_price: 100.0, condition: AlertCondition::Above };
+        let series = vec![
+            tick(1, "BTC", "binance", 90.0),
+            tick(2, "BTC", "binance", 101.0),
+            tick(3, "BTC", "binance", 105.0),
+            tick(4, "BTC", "binance", 99.0),
+            tick(5, "BTC", "binance", 110.0),
+        ];
+        let report = replay(&alert, "btc", &series, &[]);
+
+        assert_eq!(report.evaluations, 5);
+        assert_eq!(report.triggered_evaluations, 3);
+        assert_eq!(report.triggers, vec![
+            BacktestTrigger { timestamp: 2, observed: 101.0, cleared_at: Some(4) },
+            BacktestTrigger { timestamp: 5, observed: 110.0, cleared_at: None },
+        ]);
+    }
+
+    #[test]
+    fn test_depeg_uses_alert_exchanges() {
+        let alert = AlertType::Depeg { target_price: 1.0, differential: 1.0, exchanges: vec!["binance".to_string()] };
+        let series = vec![
+            tick(1, "USDT", "binance", 1.0),
+            tick(1, "USDT", "kraken", 0.95),
+            tick(2, "USDT", "binance", 1.02),
+        ];
+        let report = replay(&alert, "USDT", &series, &[]);
+
+        assert_eq!(report.evaluations, 2);
+        assert_eq!(report.triggers, vec![BacktestTrigger { timestamp: 2, observed: 1.02, cleared_at: None }]);
+    }
+
+    #[test]
+    fn test_pair_waits_for_both_prices() {
+        let alert = AlertType::PairDepeg {
+            token1: "STETH".to_string(),
+            token2: "ETH".to_string(),
+            expected_ratio: 1.0,
+            differential: 2.0,
+        };
+        let series = vec![
+            tick(1, "STETH", "coingecko", 0.9),
+            tick(2, "ETH", "coingecko", 1.0),
+            tick(3, "STETH", "coingecko", 0.99),
+        ];
+        let report = replay(&alert, "", &series, &[]);
+
+        assert_eq!(report.evaluations, 2);
+        assert_eq!(report.triggers, vec![BacktestTrigger { timestamp: 2, observed: 0.9, cleared_at: Some(3) }]);
+    }
+
+    #[test]
+    fn test_portfolio_value_uses_holdings() {
+        let alert = AlertType::PortfolioValue { threshold: 1000.0, condition: AlertCondition::Below };
+        let holdings = vec![Holding {
+            user_id: 1,
+            symbol: "ETH".to_string(),
+            quantity: 2.0,
+            cost_basis: 2000.0,
+            realized_pnl: 0.0,
+            updated_at: 0,
+        }];
+        let series = vec![tick(1, "ETH", "coingecko", 600.0), tick(2, "ETH", "coingecko", 450.0)];
+        let report = replay(&alert, "", &series, &holdings);
+
+        assert_eq!(report.triggers, vec![BacktestTrigger { timestamp: 2, observed: 900.0, cleared_at: None }]);
+    }
+}
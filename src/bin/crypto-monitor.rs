@@ -1,13 +1,20 @@
 use crypto_monitor::{
     Config, Database,
     start_monitor,
+    api::{self, TelegramWebhook},
+    backtest::{Backtest, BacktestRequest},
+    history::{self, ImportFormat, ImportSummary},
+    CryptoAPI,
     bot::TelegramBot,
 };
 use dotenv::dotenv;
-use tracing::{info, error};
+use teloxide::Bot;
+use tracing::{info, error, warn};
 use tracing_subscriber::FmtSubscriber;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::join;
+use tokio::sync::mpsc;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
@@ -18,19 +25,67 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         .with_env_filter("crypto_monitor=debug")
         .init();
     
+    // Subcomandos que no levantan el servicio
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(command) = args.first() {
+        return match command.as_str() {
+            "backtest" => backtest(&args[1..]),
+            "import" => import(&args[1..]),
+            "backfill" => backfill(&args[1..]).await,
+            _ => Err(format!("Comando desconocido: {}\n{}", command, USAGE).into()),
+        };
+    }
+
     info!("Iniciando Crypto Monitor...");
     
     let config = Config::new()?;
     let db = Arc::new(Database::new(&config.database_url)?);
+
+    if let Some(mode) = config.registration_mode {
+        db.set_registration_mode(mode)?;
+        info!("Modo de registro: {}", mode.as_str());
+    }
+
+    if std::env::var("ADMIN_USERNAMES").is_ok() {
+        warn!("ADMIN_USERNAMES ya no se usa; indica los administradores iniciales con ADMIN_TELEGRAM_IDS");
+    }
+    let promoted = db.bootstrap_admins(&config.admin_telegram_ids)?;
+    if promoted > 0 {
+        info!("{} usuarios promovidos a administrador desde ADMIN_TELEGRAM_IDS", promoted);
+    }
     
     // Verificar token de Telegram
     TelegramBot::verify_bot_token().await?;
     
+    // En modo webhook la API recibe los updates y los pasa al bot por un canal
+    let (telegram_webhook, webhook_updates) = match config.telegram_webhook.clone() {
+        Some(webhook) => {
+            let (tx, rx) = mpsc::unbounded_channel();
+            let state = TelegramWebhook { secret: webhook.secret.clone(), updates: tx };
+            (Some(state), Some((webhook, rx)))
+        }
+        None => (None, None),
+    };
+
+    if let Some(addr) = config.api_addr {
+        let db = db.clone();
+        let bot = Bot::new(config.telegram_token.clone());
+        tokio::spawn(async move {
+            info!("Iniciando API REST...");
+            if let Err(e) = api::start_server(db, addr, telegram_webhook, bot).await {
+                error!("Error en la API: {}", e);
+            }
+        });
+    }
+
     // Crear y ejecutar el bot en un task separado
     let bot = TelegramBot::new(db.clone());
     let bot_handle = tokio::spawn(async move {
         info!("Iniciando bot de Telegram...");
-        bot.run().await;
+        match webhook_updates {
+            Some((webhook, updates)) => bot.run_webhook(webhook, updates).await,
+            None => bot.run().await,
+        }
     });
 
     // Iniciar el monitor de precios en el task principal
@@ -56,4 +111,102 @@ async fn main() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     }
     
     Ok(())
-} 
\ No newline at end of file
+}
+
+const USAGE: &str = "Uso:
+  crypto-monitor-cli backtest <peticion.json> [--user <usuario>]
+  crypto-monitor-cli import <csv|klines|coingecko> <fichero> [--symbol <símbolo>] [--venue <exchange>]
+  crypto-monitor-cli backfill <símbolo> <desde> [--to <hasta>]";
+
+/// Argumentos posicionales y opciones `--nombre valor` de un subcomando.
+fn parse_args<'a>(args: &'a [String], allowed: &[&str]) -> Result<(Vec<&'a str>, HashMap<&'a str, &'a str>), String> {
+    let mut positional = Vec::new();
+    let mut options = HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.strip_prefix("--") {
+            Some(name) if allowed.contains(&name) => {
+                let value = args.next().ok_or(USAGE)?;
+                options.insert(name, value.as_str());
+            }
+            Some(_) => return Err(USAGE.to_string()),
+            None => positional.push(arg.as_str()),
+        }
+    }
+    Ok((positional, options))
+}
+
+fn print_import_summary(summary: &ImportSummary) {
+    println!(
+        "{} cotizaciones leídas: {} nuevas, {} ya estaban guardadas, {} repetidas en la entrada",
+        summary.parsed, summary.inserted, summary.already_stored, summary.duplicates
+    );
+}
+
+/// Prueba una alerta con el histórico guardado, o con la serie que traiga la
+/// petición (el mismo JSON que `POST /alerts/backtest`), e imprime sus disparos.
+/// Las alertas de portafolio usan las posiciones de `--user`.
+fn backtest(args: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (positional, options) = parse_args(args, &["user"])?;
+    let [path] = positional[..] else {
+        return Err(USAGE.into());
+    };
+    let username = options.get("user");
+    let request: BacktestRequest = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+
+    let db = Database::new(&std::env::var("DATABASE_URL")?)?;
+    let user_id = match username {
+        Some(username) => Some(db.get_user_by_username(username)?.ok_or(format!("No existe el usuario {}", username))?.id),
+        None => None,
+    };
+    let report = Backtest::new(&db).run(user_id, &request)?;
+
+    let date = |timestamp: i64| chrono::DateTime::from_timestamp(timestamp, 0)
+        .map(|date| date.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_else(|| timestamp.to_string());
+    println!(
+        "{} evaluaciones entre {} y {} UTC; la condición se cumplía en {}",
+        report.evaluations, date(report.from), date(report.to), report.triggered_evaluations
+    );
+    println!("{} disparos", report.triggers.len());
+    for trigger in &report.triggers {
+        let cleared = trigger.cleared_at.map(date).unwrap_or_else(|| "el final de la serie".to_string());
+        println!("  {}  {:.6}  (hasta {})", date(trigger.timestamp), trigger.observed, cleared);
+    }
+    Ok(())
+}
+
+/// Importa al histórico de precios un fichero en cualquiera de los formatos
+/// soportados. Repetir la importación no duplica nada.
+fn import(args: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (positional, options) = parse_args(args, &["symbol", "venue"])?;
+    let [format, path] = positional[..] else {
+        return Err(USAGE.into());
+    };
+    let format: ImportFormat = format.parse()?;
+    let ticks = history::parse(format, &std::fs::read_to_string(path)?, options.get("symbol").copied(), options.get("venue").copied())?;
+
+    let db = Database::new(&std::env::var("DATABASE_URL")?)?;
+    print_import_summary(&history::import(&db, ticks)?);
+    Ok(())
+}
+
+/// Descarga de CoinGecko el histórico de un símbolo desde `desde` hasta
+/// `--to` (por defecto, ahora) y lo guarda.
+async fn backfill(args: &[String]) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let (positional, options) = parse_args(args, &["to"])?;
+    let [symbol, from] = positional[..] else {
+        return Err(USAGE.into());
+    };
+    let instant = |value: &str| history::parse_timestamp(value).ok_or(format!("Instante inválido: {}", value));
+    let from = instant(from)?;
+    let to = match options.get("to") {
+        Some(to) => instant(to)?,
+        None => chrono::Utc::now().timestamp(),
+    };
+
+    let db = Database::new(&std::env::var("DATABASE_URL")?)?;
+    let api = CryptoAPI::new(std::env::var("COINGECKO_API_KEY")?);
+    print_import_summary(&history::backfill(&api, &db, &symbol.to_uppercase(), from, to).await?);
+    Ok(())
+}
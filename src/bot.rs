@@ -1,4 +1,7 @@
+use std::convert::Infallible;
 use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
 use teloxide::{
     macros::BotCommands,
     prelude::*,
@@ -6,19 +9,34 @@ use teloxide::{
         Message, 
         InlineKeyboardMarkup, 
         InlineKeyboardButton,
+        InputFile,
         ParseMode,
         CallbackQuery,
+        Chat,
+        Recipient,
+        User as TelegramUser,
     },
-    dispatching::{HandlerExt, UpdateFilterExt},
+    dispatching::{HandlerExt, UpdateFilterExt, UpdateHandler},
+    error_handlers::LoggingErrorHandler,
+    stop::{mk_stop_token, StopToken},
+    update_listeners::StatefulListener,
     ApiError,
     RequestError,
     utils::command::BotCommands as TeloxideCommands,
 };
 use crate::db::Database;
 use tracing::{info, error};
-use crate::auth::Auth;
-use crate::models::{User, PriceAlert, AlertCondition, AlertType, UserState, PriceAlertStep, DepegAlertStep, PairAlertStep};
+use crate::auth::{Auth, AuthError};
+use crate::models::{User, UserRole, Plan, TradeSide, TradeMode, AlertAction, DcaPlan, DcaOutcome, Transaction, RegistrationMode, PriceAlert, AlertCondition, AlertType, AlertFilter, AlertKind, AlertStatus, AuditEvent, TargetKind, PORTFOLIO_SYMBOL, UserState, PriceAlertStep, DepegAlertStep, PairAlertStep};
+use crate::callback::{CallbackAction, CallbackCodec, MAX_FILTER_SYMBOL};
 use crate::config::CONFIG;
+use crate::chart::{self, ChartRange, ChartStyle};
+use crate::crypto_api::CryptoAPI;
+use crate::notify;
+use crate::portfolio::{self, Portfolio, PortfolioError};
+use crate::dca::{DcaError, DcaPlans};
+use crate::quota::{self, QuotaError};
+use crate::timer::Timer;
 
 #[derive(BotCommands, Clone, Debug)]
 #[command(
@@ -28,22 +46,56 @@ use crate::config::CONFIG;
 pub enum Command {
     #[command(description = "muestra este mensaje")]
     Help,
-    #[command(description = "inicia el bot")]
-    Start,
-    #[command(description = "registra tu usuario - /register <username> <password>")]
+    #[command(description = "inicia el bot - /start <código> vincula una cuenta existente")]
+    Start { text: String },
+    #[command(description = "registra tu cuenta de Telegram - /register [username] [invite=<código>]")]
     Register { text: String },
+    #[command(description = "genera un código de un solo uso para obtener tu API key")]
+    ApiKey,
     #[command(description = "crea una alerta de precio")]
     Alert,
     #[command(description = "crea alerta de depeg")]
     Depeg,
     #[command(description = "crea alerta de par")]
     PairDepeg,
-    #[command(description = "lista tus alertas activas")]
-    Alerts,
+    #[command(description = "lista y gestiona tus alertas - /alerts [símbolo]")]
+    Alerts { text: String },
     #[command(description = "elimina una alerta")]
     Delete,
     #[command(description = "muestra los símbolos soportados")]
     Symbols,
+    #[command(description = "cancela la operación en curso")]
+    Cancel,
+    #[command(description = "vuelve al paso anterior de la operación en curso")]
+    Back,
+    #[command(description = "desvincula este chat de tu cuenta")]
+    Unlink,
+    #[command(description = "gráfico de precio - /chart <symbol> [1d|7d|14d|30d|90d|180d|1y] [line|candles]")]
+    Chart { text: String },
+    #[command(description = "envía una alerta también a un canal - /share <id> <@canal>")]
+    Share { text: String },
+    #[command(description = "deja de enviar una alerta a un canal - /unshare <id> <@canal>")]
+    Unshare { text: String },
+    #[command(description = "cancela tu suscripción a una alerta - /unsubscribe <id>")]
+    Unsubscribe { text: String },
+    #[command(description = "reenvía una alerta hasta que se reconozca - /escalate <id> <minutos> <intentos> [contacto] | /escalate <id> off")]
+    Escalate { text: String },
+    #[command(description = "orden automática al dispararse una alerta - /action <id> <buy|sell> <cantidad> [limit <±%>] [dry_run|paper|live] [exchange] | /action <id> off")]
+    Action { text: String },
+    #[command(description = "compras periódicas (DCA) - /dca add <símbolo> <importe> <horario> [max <precio>] [dry_run|paper|live] [exchange] | /dca <id> [pause|resume|off]")]
+    Dca { text: String },
+    #[command(description = "muestra tu portafolio valorado a precio de mercado")]
+    Portfolio,
+    #[command(description = "registra una compra - /buy <símbolo> <cantidad> [precio] [comisión]")]
+    Buy { text: String },
+    #[command(description = "registra una venta - /sell <símbolo> <cantidad> [precio] [comisión]")]
+    Sell { text: String },
+    #[command(description = "alerta sobre tu portafolio - /portfolioalert value <above|below> <usd> | drawdown <%> | drift <símbolo> <peso %> <±puntos>")]
+    PortfolioAlert { text: String },
+    #[command(description = "muestra tu plan y cuántas alertas te quedan")]
+    Quota,
+    #[command(description = "administración - /admin sin argumentos muestra los subcomandos")]
+    Admin { text: String },
 }
 
 impl Command {
@@ -51,25 +103,197 @@ impl Command {
         <Command as TeloxideCommands>::descriptions().to_string()
     }
 
+    /// Comandos que manejan credenciales o la cuenta personal y no deben
+    /// usarse en grupos.
+    fn private_only(&self) -> bool {
+        match self {
+            Command::Start { text } => !text.trim().is_empty(),
+            Command::Register { .. }
+            | Command::ApiKey
+            | Command::Unlink
+            | Command::Share { .. }
+            | Command::Unshare { .. }
+            | Command::Unsubscribe { .. }
+            | Command::Admin { .. }
+            | Command::Portfolio
+            | Command::PortfolioAlert { .. }
+            | Command::Action { .. }
+            | Command::Dca { .. }
+            | Command::Buy { .. }
+            | Command::Sell { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// El comando para el log, sin los argumentos que pueden llevar una
+    /// contraseña o un código de un solo uso.
+    fn redacted(&self) -> String {
+        match self {
+            Command::Start { .. } => "Start { .. }".to_string(),
+            Command::Register { .. } => "Register { .. }".to_string(),
+            cmd => format!("{:?}", cmd),
+        }
+    }
+
+    /// Comandos que en un grupo solo pueden usar sus administradores.
+    fn requires_group_admin(&self) -> bool {
+        matches!(
+            self,
+            Command::Alert
+                | Command::Depeg
+                | Command::PairDepeg
+                | Command::Delete
+                | Command::Cancel
+                | Command::Back
+                | Command::Escalate { .. }
+        )
+    }
+
     fn from_str(s: &str) -> Result<Self, &'static str> {
         let lowercase = s.to_lowercase();
         match lowercase.as_str() {
             "/help" | "help" => Ok(Command::Help),
-            "/start" | "start" => Ok(Command::Start),
+            "/start" | "start" => Ok(Command::Start { text: String::new() }),
             // ... otros casos
             _ => Err("Comando no reconocido")
         }
     }
 }
 
+const ALERTS_PER_PAGE: usize = 5;
+const SYMBOLS_PER_PAGE: usize = 10;
+const USERS_PER_PAGE: i64 = 20;
+/// Cuánto pospone una alerta el botón "Posponer" de las notificaciones.
+const SNOOZE_SECS: i64 = 60 * 60;
+
+pub type UpdateSender = mpsc::UnboundedSender<Result<Update, Infallible>>;
+pub type UpdateReceiver = mpsc::UnboundedReceiver<Result<Update, Infallible>>;
+
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// URL pública base con la que Telegram llega a la API (p. ej. https://bot.example.com)
+    pub public_url: String,
+    /// Se usa como segmento de la ruta y como `X-Telegram-Bot-Api-Secret-Token`
+    pub secret: String,
+}
+
+impl WebhookConfig {
+    pub fn webhook_url(&self) -> String {
+        format!("{}/telegram/{}", self.public_url.trim_end_matches('/'), self.secret)
+    }
+}
+
+fn listener_stream<S>(state: &mut (S, StopToken)) -> &mut S {
+    &mut state.0
+}
+
 #[derive(Clone)]
 pub struct TelegramBot {
     db: Arc<Database>,
+    callbacks: CallbackCodec,
 }
 
 impl TelegramBot {
     pub fn new(db: Arc<Database>) -> Self {
-        Self { db }
+        Self { db, callbacks: CallbackCodec::from_env() }
+    }
+
+    /// `callback_data` firmado para un botón de este chat.
+    fn callback_data(&self, chat_id: ChatId, action: CallbackAction) -> String {
+        self.callbacks.encode(chat_id.0, &action)
+    }
+
+    /// Usuario que actúa: en privado el vinculado al chat, en grupos el
+    /// remitente según su ID de Telegram.
+    async fn acting_user(&self, chat: &Chat, from: Option<&TelegramUser>) -> ResponseResult<Option<User>> {
+        if chat.is_private() {
+            return self.get_user_by_chat_id(chat.id.0).await;
+        }
+        match from {
+            Some(from) => Ok(self.db.get_user_by_telegram_user_id(from.id.0 as i64)
+                .map_err(Self::db_error_to_request_error)?
+                .filter(|user| user.is_active)),
+            None => Ok(None),
+        }
+    }
+
+    /// En privado siempre es cierto; en grupos y canales exige ser creador o
+    /// administrador del chat.
+    async fn is_chat_admin(&self, bot: &Bot, chat: &Chat, from: Option<&TelegramUser>) -> ResponseResult<bool> {
+        if chat.is_private() {
+            return Ok(true);
+        }
+        match from {
+            Some(from) => Ok(bot.get_chat_member(chat.id, from.id).await?.is_privileged()),
+            None => Ok(false),
+        }
+    }
+
+    /// El dueño puede gestionar sus alertas desde cualquier chat; en un grupo,
+    /// sus administradores pueden gestionar también las alertas del grupo.
+    async fn can_manage_alert(&self, chat: &Chat, from: &TelegramUser, alert_id: i64) -> ResponseResult<bool> {
+        let alert = match self.db.get_alert(alert_id).map_err(Self::db_error_to_request_error)? {
+            Some(alert) => alert,
+            None => return Ok(false),
+        };
+        if let Some(user) = self.acting_user(chat, Some(from)).await? {
+            if alert.user_id == user.id {
+                return Ok(true);
+            }
+        }
+        if chat.is_private() {
+            return Ok(false);
+        }
+        let targets = self.db.get_alert_targets(alert_id).map_err(Self::db_error_to_request_error)?;
+        Ok(targets.iter().any(|target| target.chat_id == chat.id.0 && target.kind == TargetKind::Group))
+    }
+
+    /// Las alertas creadas desde un grupo notifican al grupo en lugar de al
+    /// chat privado de quien las creó.
+    fn attach_chat_target(&self, chat: &Chat, alert_id: i64, user_id: i64) -> ResponseResult<()> {
+        if chat.is_group() || chat.is_supergroup() {
+            self.db.add_alert_target(alert_id, chat.id.0, TargetKind::Group, user_id)
+                .map_err(Self::db_error_to_request_error)?;
+        }
+        Ok(())
+    }
+
+    async fn audit(&self, chat: &Chat, from: Option<&TelegramUser>, action: &str, target: Option<String>) -> ResponseResult<()> {
+        let user_id = self.acting_user(chat, from).await?.map(|user| user.id);
+        let event = AuditEvent {
+            id: None,
+            user_id,
+            chat_id: Some(chat.id.0),
+            action: action.to_string(),
+            target,
+            created_at: chrono::Utc::now().timestamp(),
+        };
+        self.db.record_audit_event(&event).map_err(Self::db_error_to_request_error)
+    }
+
+    /// `Some(mensaje)` si el plan no permite la operación.
+    fn quota_refusal(result: Result<(), QuotaError>) -> ResponseResult<Option<String>> {
+        match result {
+            Ok(()) => Ok(None),
+            Err(QuotaError::DatabaseError(e)) => Err(Self::db_error_to_request_error(e)),
+            Err(e) => Ok(Some(format!("🚫 {}", e))),
+        }
+    }
+
+    /// Antes de abrir un asistente de creación: si quien escribe ya no puede
+    /// crear alertas de ese tipo se le avisa y no se empieza.
+    async fn within_quota(&self, bot: &Bot, msg: &Message, kind: AlertKind) -> ResponseResult<bool> {
+        let user = match self.acting_user(&msg.chat, msg.from()).await? {
+            Some(user) => user,
+            None => return Ok(true),
+        };
+        match Self::quota_refusal(quota::check_new_alert(&self.db, user.id, kind))? {
+            Some(refusal) => {
+                bot.send_message(msg.chat.id, refusal).await?;
+                Ok(false)
+            }
+            None => Ok(true),
+        }
     }
 
     // Helper para convertir errores de SQLite a RequestError
@@ -78,13 +302,29 @@ impl TelegramBot {
     }
 
     async fn handle_command(&self, bot: Bot, msg: Message, cmd: Command) -> ResponseResult<()> {
-        info!("Manejando comando: {:?}", cmd);
+        info!("Manejando comando: {}", cmd.redacted());
+
+        if !msg.chat.is_private() {
+            if cmd.private_only() {
+                bot.send_message(msg.chat.id, "🔒 Usa este comando en un chat privado con el bot").await?;
+                return Ok(());
+            }
+            if cmd.requires_group_admin() && !self.is_chat_admin(&bot, &msg.chat, msg.from()).await? {
+                bot.send_message(msg.chat.id, "❌ Solo los administradores del grupo pueden gestionar sus alertas").await?;
+                return Ok(());
+            }
+        }
         
         match cmd {
             Command::Help => {
                 bot.send_message(msg.chat.id, Command::descriptions()).await?;
             }
-            Command::Start => {
+            Command::Start { text } => {
+                let code = text.trim();
+                if !code.is_empty() {
+                    self.handle_link(bot, msg, code.to_string()).await?;
+                    return Ok(());
+                }
                 bot.send_message(
                     msg.chat.id,
                     "¡Bienvenido al Bot de Alertas de Criptomonedas!\n\
@@ -92,12 +332,35 @@ impl TelegramBot {
                 ).await?;
             }
             Command::Register { text } => {
-                let parts: Vec<&str> = text.split_whitespace().collect();
-                if parts.len() != 2 {
-                    bot.send_message(msg.chat.id, "Uso: /register <username> <password>").await?;
-                    return Ok(());
+                // /register [username] [invite=<código>]. Cualquier otra
+                // palabra es la contraseña del registro antiguo: ya no se usa
+                let mut username = None;
+                let mut invite_code = None;
+                let mut password = false;
+                for part in text.split_whitespace() {
+                    if let Some(code) = part.strip_prefix("invite=") {
+                        invite_code = Some(code);
+                    } else if username.is_none() {
+                        username = Some(part);
+                    } else {
+                        password = true;
+                    }
+                }
+                if password {
+                    // No dejar la contraseña en el historial
+                    if let Err(e) = bot.delete_message(msg.chat.id, msg.id).await {
+                        error!("No se pudo borrar el mensaje de registro: {}", e);
+                    }
+                    bot.send_message(
+                        msg.chat.id,
+                        "⚠️ Ya no hace falta enviar una contraseña y borré tu mensaje por seguridad.\n\
+                         Tu cuenta queda vinculada a tu usuario de Telegram."
+                    ).await?;
                 }
-                self.handle_register(bot, msg, parts[0].to_string(), parts[1].to_string()).await?;
+                self.handle_register(bot, msg, username.map(str::to_string), invite_code.map(str::to_string)).await?;
+            }
+            Command::ApiKey => {
+                self.handle_api_key_code(bot, msg).await?;
             }
             Command::Alert => {
                 self.handle_alert_creation(bot, msg).await?;
@@ -108,15 +371,64 @@ impl TelegramBot {
             Command::PairDepeg => {
                 self.handle_pair_depeg(bot, msg).await?;
             }
-            Command::Alerts => {
-                self.handle_list_alerts(bot, msg).await?;
+            Command::Alerts { text } => {
+                self.handle_list_alerts(bot, msg, text).await?;
             }
             Command::Delete => {
-                self.handle_delete_alert(bot, msg).await?;
+                // Eliminar es una de las acciones del menú de cada alerta
+                self.handle_list_alerts(bot, msg, String::new()).await?;
             }
             Command::Symbols => {
                 self.handle_symbols(bot, msg).await?;
             }
+            Command::Cancel => {
+                self.handle_cancel(bot, msg).await?;
+            }
+            Command::Back => {
+                self.handle_back(bot, msg).await?;
+            }
+            Command::Unlink => {
+                self.handle_unlink(bot, msg).await?;
+            }
+            Command::Chart { text } => {
+                self.handle_chart(bot, msg, text).await?;
+            }
+            Command::Share { text } => {
+                self.handle_share(bot, msg, text, true).await?;
+            }
+            Command::Unshare { text } => {
+                self.handle_share(bot, msg, text, false).await?;
+            }
+            Command::Unsubscribe { text } => {
+                self.handle_unsubscribe(bot, msg, text).await?;
+            }
+            Command::Escalate { text } => {
+                self.handle_escalate(bot, msg, text).await?;
+            }
+            Command::Action { text } => {
+                self.handle_action(bot, msg, text).await?;
+            }
+            Command::Dca { text } => {
+                self.handle_dca(bot, msg, text).await?;
+            }
+            Command::Admin { text } => {
+                self.handle_admin(bot, msg, text).await?;
+            }
+            Command::Quota => {
+                self.handle_quota(bot, msg).await?;
+            }
+            Command::Portfolio => {
+                self.handle_portfolio(bot, msg).await?;
+            }
+            Command::PortfolioAlert { text } => {
+                self.handle_portfolio_alert(bot, msg, text).await?;
+            }
+            Command::Buy { text } => {
+                self.handle_trade(bot, msg, text, TradeSide::Buy).await?;
+            }
+            Command::Sell { text } => {
+                self.handle_trade(bot, msg, text, TradeSide::Sell).await?;
+            }
         }
         Ok(())
     }
@@ -124,8 +436,78 @@ impl TelegramBot {
     async fn handle_callback(&self, bot: Bot, query: CallbackQuery) -> ResponseResult<()> {
         if let Some(data) = query.data {
             if let Some(message) = query.message {
-                match data.as_str() {
-                    "create_price_alert" => {
+                let action = match self.callbacks.decode(message.chat.id.0, &data) {
+                    Ok(action) => action,
+                    Err(e) => {
+                        info!("Callback rechazado de chat {}: {}", message.chat.id, e);
+                        bot.answer_callback_query(query.id)
+                            .text("❌ Acción inválida o expirada")
+                            .show_alert(true)
+                            .await?;
+                        return Ok(());
+                    }
+                };
+
+                if action.requires_chat_admin() && !self.is_chat_admin(&bot, &message.chat, Some(&query.from)).await? {
+                    bot.answer_callback_query(query.id)
+                        .text("❌ Solo los administradores del grupo pueden hacer esto")
+                        .show_alert(true)
+                        .await?;
+                    return Ok(());
+                }
+
+                if let Some(alert_id) = action.target_alert() {
+                    // El contacto secundario de un escalado puede reconocer la alerta
+                    let escalation_contact = matches!(action, CallbackAction::AcknowledgeAlert(_))
+                        && self.db.get_escalation_policy(alert_id)
+                            .map_err(Self::db_error_to_request_error)?
+                            .and_then(|policy| policy.secondary_chat_id) == Some(message.chat.id.0);
+                    if !escalation_contact && !self.can_manage_alert(&message.chat, &query.from, alert_id).await? {
+                        info!("Chat {} intentó operar sobre la alerta {} sin ser dueño", message.chat.id, alert_id);
+                        bot.answer_callback_query(query.id)
+                            .text("❌ No autorizado")
+                            .show_alert(true)
+                            .await?;
+                        return Ok(());
+                    }
+                }
+
+                // Las cuotas del plan se comprueban antes de crear o reactivar alertas
+                let quota_check = match &action {
+                    CallbackAction::ResumeAlert(alert_id)
+                    | CallbackAction::RearmAlert(alert_id)
+                    | CallbackAction::SnoozeAlert(alert_id) => self.db.get_alert(*alert_id)
+                        .map_err(Self::db_error_to_request_error)?
+                        .map(|alert| quota::check_reactivation(&self.db, &alert)),
+                    CallbackAction::CloneAlert(alert_id) => {
+                        // La copia es de quien la clona, así que tiene que estar registrado
+                        let Some(owner) = self.acting_user(&message.chat, Some(&query.from)).await? else {
+                            bot.answer_callback_query(query.id)
+                                .text("Regístrate primero con /register en un chat privado con el bot")
+                                .show_alert(true)
+                                .await?;
+                            return Ok(());
+                        };
+                        self.db.get_alert(*alert_id).map_err(Self::db_error_to_request_error)?
+                            .map(|original| quota::check_new_alert(&self.db, owner.id, original.kind()))
+                    }
+                    _ => match action.creates_alert() {
+                        Some(kind) => self.acting_user(&message.chat, Some(&query.from)).await?
+                            .map(|user| quota::check_new_alert(&self.db, user.id, kind)),
+                        None => None,
+                    },
+                };
+                if let Some(refusal) = quota_check.map(Self::quota_refusal).transpose()?.flatten() {
+                    bot.answer_callback_query(query.id)
+                        .text(refusal)
+                        .show_alert(true)
+                        .await?;
+                    return Ok(());
+                }
+
+                let audit_name = action.audit_name();
+                match action {
+                    CallbackAction::CreatePriceAlert => {
                         let state = UserState::CreatingPriceAlert {
                             step: PriceAlertStep::SelectSymbol,
                             symbol: None,
@@ -136,7 +518,7 @@ impl TelegramBot {
                             .map_err(Self::db_error_to_request_error)?;
                         self.handle_price_alert_step(&bot, message, &state).await?;
                     }
-                    "create_depeg_alert" => {
+                    CallbackAction::CreateDepegAlert => {
                         let state = UserState::CreatingDepegAlert {
                             step: DepegAlertStep::SelectSymbol,
                             symbol: None,
@@ -148,7 +530,7 @@ impl TelegramBot {
                             .map_err(Self::db_error_to_request_error)?;
                         self.handle_depeg_alert_step(&bot, message, &state).await?;
                     }
-                    "create_pair_alert" => {
+                    CallbackAction::CreatePairAlert => {
                         let state = UserState::CreatingPairAlert {
                             step: PairAlertStep::SelectToken1,
                             token1: None,
@@ -160,8 +542,7 @@ impl TelegramBot {
                             .map_err(Self::db_error_to_request_error)?;
                         self.handle_pair_depeg_step(&bot, message, &state).await?;
                     }
-                    s if s.starts_with("symbol_") => {
-                        let symbol = s.trim_start_matches("symbol_").to_string();
+                    CallbackAction::Symbol(symbol) => {
                         if let Some(state) = self.db.get_user_state(message.chat.id.0)
                             .map_err(Self::db_error_to_request_error)? {
                             match state {
@@ -197,22 +578,13 @@ impl TelegramBot {
                             }
                         }
                     }
-                    s if s.starts_with("condition_") => {
-                        let condition = match s.trim_start_matches("condition_") {
-                            "above" => AlertCondition::Above,
-                            "below" => AlertCondition::Below,
-                            _ => {
-                                info!("Condición no válida");
-                                return Ok(());
-                            }
-                        };
-                        
+                    CallbackAction::Condition(condition) => {
                         if let Some(state) = self.db.get_user_state(message.chat.id.0)
                             .map_err(Self::db_error_to_request_error)? {
                             if let UserState::CreatingPriceAlert { symbol, target_price, .. } = state {
                                 if let (Some(symbol), Some(price)) = (symbol, target_price) {
                                     // Obtener usuario
-                                    let user = match self.get_user_by_chat_id(message.chat.id.0).await? {
+                                    let user = match self.acting_user(&message.chat, Some(&query.from)).await? {
                                         Some(user) => user,
                                         None => {
                                             bot.send_message(message.chat.id, "❌ Debes registrarte primero usando /register").await?;
@@ -236,7 +608,8 @@ impl TelegramBot {
 
                                     // Guardar la alerta
                                     match self.db.save_alert(&alert) {
-                                        Ok(_) => {
+                                        Ok(alert_id) => {
+                                            self.attach_chat_target(&message.chat, alert_id, user.id)?;
                                             bot.send_message(
                                                 message.chat.id,
                                                 format!(
@@ -261,22 +634,12 @@ impl TelegramBot {
                             }
                         }
                     }
-                    s if s.starts_with("delete_") => {
-                        let alert_id = s.trim_start_matches("delete_")
-                            .parse::<i64>()
-                            .map_err(|_| RequestError::Api(ApiError::Unknown("ID inválido".to_string())))?;
-
-                        // Verificar que el usuario es dueño de la alerta
-                        let _user = match self.get_user_by_chat_id(message.chat.id.0).await? {
-                            Some(user) => user,
-                            None => {
-                                bot.send_message(message.chat.id, "❌ Usuario no encontrado").await?;
-                                return Ok(());
-                            }
-                        };
-
+                    CallbackAction::DeleteAlert(alert_id) => {
                         match self.db.delete_alert(alert_id) {
                             Ok(_) => {
+                                if let Some(name) = audit_name {
+                                    self.audit(&message.chat, Some(&query.from), name, Some(alert_id.to_string())).await?;
+                                }
                                 bot.send_message(
                                     message.chat.id,
                                     format!("✅ Alerta #{} eliminada exitosamente", alert_id)
@@ -288,18 +651,206 @@ impl TelegramBot {
                             }
                         }
                     }
-                    s if s.starts_with("pair_") => {
-                        let pair = s.trim_start_matches("pair_").replace("_", "/");
+                    CallbackAction::Subscribe(alert_id) => {
+                        // Solo se puede suscribir a alertas que ya notifican a este chat
+                        let targets = self.db.get_alert_targets(alert_id)
+                            .map_err(Self::db_error_to_request_error)?;
+                        if !targets.iter().any(|target| target.chat_id == message.chat.id.0) {
+                            bot.answer_callback_query(query.id)
+                                .text("❌ Esta alerta ya no está disponible en este chat")
+                                .show_alert(true)
+                                .await?;
+                            return Ok(());
+                        }
+
+                        let subscriber = self.db.get_user_by_telegram_user_id(query.from.id.0 as i64)
+                            .map_err(Self::db_error_to_request_error)?
+                            .filter(|user| user.is_active);
+                        let (user, private_chat) = match subscriber {
+                            Some(User { id, telegram_chat_id: Some(chat_id), .. }) => (id, chat_id),
+                            _ => {
+                                bot.answer_callback_query(query.id)
+                                    .text("Regístrate primero con /register en un chat privado con el bot")
+                                    .show_alert(true)
+                                    .await?;
+                                return Ok(());
+                            }
+                        };
+
+                        match self.db.add_alert_target(alert_id, private_chat, TargetKind::Subscriber, user) {
+                            Ok(added) => {
+                                if added {
+                                    if let Some(name) = audit_name {
+                                        self.audit(&message.chat, Some(&query.from), name, Some(alert_id.to_string())).await?;
+                                    }
+                                }
+                                bot.send_message(
+                                    ChatId(private_chat),
+                                    format!(
+                                        "🔔 Recibirás también en este chat la alerta #{}.\n\
+                                         Usa /unsubscribe {} para dejar de recibirla.",
+                                        alert_id, alert_id
+                                    )
+                                ).await?;
+                            }
+                            Err(e) => {
+                                error!("Error al suscribir a la alerta {}: {}", alert_id, e);
+                                bot.send_message(message.chat.id, "❌ Error al suscribirse a la alerta").await?;
+                            }
+                        }
+                    }
+                    CallbackAction::ListAlerts { page, filter } => {
+                        let alerts = self.visible_alerts(&message.chat, Some(&query.from)).await?.unwrap_or_default();
+                        let (text, markup) = self.alerts_page(message.chat.id, &alerts, page, &filter);
+                        self.edit_menu(&bot, &message, text, markup).await?;
+                    }
+                    CallbackAction::ShowAlert(alert_id) => {
+                        self.show_alert_menu(&bot, &message, &query.from, alert_id).await?;
+                    }
+                    CallbackAction::PauseAlert(alert_id) | CallbackAction::ResumeAlert(alert_id) => {
+                        let result = match action {
+                            CallbackAction::PauseAlert(_) => self.db.pause_alert(alert_id),
+                            _ => self.db.resume_alert(alert_id),
+                        };
+                        match result {
+                            Ok(changed) => {
+                                if let (true, Some(name)) = (changed, audit_name) {
+                                    self.audit(&message.chat, Some(&query.from), name, Some(alert_id.to_string())).await?;
+                                }
+                                self.show_alert_menu(&bot, &message, &query.from, alert_id).await?;
+                            }
+                            Err(e) => {
+                                error!("Error al actualizar la alerta {}: {}", alert_id, e);
+                                bot.send_message(message.chat.id, "❌ Error al actualizar la alerta").await?;
+                            }
+                        }
+                    }
+                    CallbackAction::EditAlert(alert_id) => {
+                        let alert = self.db.get_alert(alert_id)
+                            .map_err(Self::db_error_to_request_error)?;
+                        let prompt = match alert.map(|alert| alert.alert_type) {
+                            Some(AlertType::Price { .. }) => "Envía el nuevo precio objetivo (ejemplo: 45000.50):",
+                            Some(AlertType::Depeg { .. }) => "Envía el nuevo diferencial en % (ejemplo: 0.5):",
+                            Some(AlertType::PairDepeg { .. }) => "Envía el nuevo ratio esperado (ejemplo: 1.0):",
+                            Some(AlertType::PortfolioValue { .. }) => "Envía el nuevo valor total en USD (ejemplo: 25000):",
+                            Some(AlertType::PortfolioDrawdown { .. }) => "Envía la nueva caída máxima en % (ejemplo: 15):",
+                            Some(AlertType::AllocationDrift { .. }) => "Envía la nueva tolerancia en puntos porcentuales (ejemplo: 5):",
+                            None => {
+                                bot.send_message(message.chat.id, "❌ La alerta ya no existe").await?;
+                                return Ok(());
+                            }
+                        };
+                        self.db.save_user_state(message.chat.id.0, &UserState::EditingAlert { alert_id })
+                            .map_err(Self::db_error_to_request_error)?;
+                        bot.send_message(
+                            message.chat.id,
+                            format!("✏️ Alerta #{}\n{}\n\nUsa /cancel para no cambiar nada.", alert_id, prompt)
+                        ).await?;
+                    }
+                    CallbackAction::CloneAlert(alert_id) => {
+                        let original = match self.db.get_alert(alert_id).map_err(Self::db_error_to_request_error)? {
+                            Some(alert) => alert,
+                            None => {
+                                bot.send_message(message.chat.id, "❌ La alerta ya no existe").await?;
+                                return Ok(());
+                            }
+                        };
+                        let Some(owner) = self.acting_user(&message.chat, Some(&query.from)).await?.map(|user| user.id) else {
+                            bot.send_message(message.chat.id, "❌ Debes registrarte primero usando /register").await?;
+                            return Ok(());
+                        };
+                        let copy = PriceAlert {
+                            id: None,
+                            user_id: owner,
+                            is_active: true,
+                            triggered_at: None,
+                            created_at: chrono::Utc::now().timestamp(),
+                            ..original
+                        };
+                        match self.db.save_alert(&copy) {
+                            Ok(new_id) => {
+                                self.attach_chat_target(&message.chat, new_id, owner)?;
+                                if let Some(name) = audit_name {
+                                    self.audit(&message.chat, Some(&query.from), name, Some(format!("{}->{}", alert_id, new_id))).await?;
+                                }
+                                self.show_alert_menu(&bot, &message, &query.from, new_id).await?;
+                            }
+                            Err(e) => {
+                                error!("Error al clonar la alerta {}: {}", alert_id, e);
+                                bot.send_message(message.chat.id, "❌ Error al clonar la alerta").await?;
+                            }
+                        }
+                    }
+                    CallbackAction::Symbols(page) => {
+                        let (text, markup) = self.symbols_page(message.chat.id, page);
+                        self.edit_menu(&bot, &message, text, markup).await?;
+                    }
+                    CallbackAction::SnoozeAlert(alert_id)
+                    | CallbackAction::RearmAlert(alert_id)
+                    | CallbackAction::AcknowledgeAlert(alert_id) => {
+                        let until = chrono::Utc::now().timestamp() + SNOOZE_SECS;
+                        let result = match action {
+                            CallbackAction::SnoozeAlert(_) => self.db.snooze_alert(alert_id, until),
+                            CallbackAction::RearmAlert(_) => self.db.resume_alert(alert_id),
+                            _ => self.db.acknowledge_alert(alert_id),
+                        };
+                        let changed = match result {
+                            Ok(changed) => changed,
+                            Err(e) => {
+                                error!("Error al actualizar la alerta {}: {}", alert_id, e);
+                                bot.send_message(message.chat.id, "❌ Error al actualizar la alerta").await?;
+                                return Ok(());
+                            }
+                        };
+                        if let (true, Some(name)) = (changed, audit_name) {
+                            self.audit(&message.chat, Some(&query.from), name, Some(alert_id.to_string())).await?;
+                        }
+
+                        let note = match action {
+                            CallbackAction::SnoozeAlert(_) => format!(
+                                "😴 Alerta #{} pospuesta hasta las {} UTC",
+                                alert_id,
+                                chrono::DateTime::from_timestamp(until, 0)
+                                    .map(|t| t.format("%H:%M").to_string())
+                                    .unwrap_or_default()
+                            ),
+                            CallbackAction::RearmAlert(_) => format!("🔁 Alerta #{} rearmada", alert_id),
+                            _ if changed => format!("✅ Alerta #{} reconocida por {}", alert_id, query.from.full_name()),
+                            _ => format!("La alerta #{} ya estaba reconocida", alert_id),
+                        };
+                        self.finish_notification(&bot, &message, alert_id, note).await?;
+                    }
+                    CallbackAction::AlertChart(alert_id) => {
+                        let visible = self.visible_alerts(&message.chat, Some(&query.from)).await?.unwrap_or_default();
+                        let alert = match visible.into_iter().find(|alert| alert.id == Some(alert_id)) {
+                            Some(alert) => alert,
+                            None => {
+                                bot.send_message(message.chat.id, format!("La alerta #{} ya no existe", alert_id)).await?;
+                                return Ok(());
+                            }
+                        };
+
+                        let api = CryptoAPI::new(std::env::var("COINGECKO_API_KEY").unwrap_or_default());
+                        match chart::alert_chart(&api, &alert, None).await {
+                            Ok(png) => {
+                                bot.send_photo(message.chat.id, InputFile::memory(png).file_name(format!("alerta-{}.png", alert_id)))
+                                    .caption(Self::alert_summary(&alert))
+                                    .await?;
+                            }
+                            Err(e) => {
+                                error!("Error al generar el gráfico de la alerta {}: {}", alert_id, e);
+                                bot.send_message(message.chat.id, "❌ No se pudo generar el gráfico").await?;
+                            }
+                        }
+                    }
+                    CallbackAction::Pair(token1, token2) => {
                         if let Some(state) = self.db.get_user_state(message.chat.id.0)
                             .map_err(Self::db_error_to_request_error)? {
                             if let UserState::CreatingPairAlert { step: PairAlertStep::SelectToken1, .. } = state {
-                                let (token1, token2) = pair.split_once('/')
-                                    .ok_or_else(|| RequestError::Api(ApiError::Unknown("Par inválido".to_string())))?;
-
                                 let new_state = UserState::CreatingPairAlert {
                                     step: PairAlertStep::EnterRatio,
-                                    token1: Some(token1.to_string()),
-                                    token2: Some(token2.to_string()),
+                                    token1: Some(token1.clone()),
+                                    token2: Some(token2.clone()),
                                     expected_ratio: None,
                                     differential: None,
                                 };
@@ -315,17 +866,13 @@ impl TelegramBot {
                             }
                         }
                     }
-                    s if s.starts_with("pairdiff_") => {
-                        let diff: f64 = s.trim_start_matches("pairdiff_")
-                            .parse()
-                            .map_err(|_| RequestError::Api(ApiError::Unknown("Diferencial inválido".to_string())))?;
-
+                    CallbackAction::PairDifferential(diff) => {
                         if let Some(state) = self.db.get_user_state(message.chat.id.0)
                             .map_err(Self::db_error_to_request_error)? {
                             if let UserState::CreatingPairAlert { token1, token2, expected_ratio, .. } = state {
                                 if let (Some(token1), Some(token2), Some(ratio)) = (token1, token2, expected_ratio) {
                                     // Obtener usuario
-                                    let user = match self.get_user_by_chat_id(message.chat.id.0).await? {
+                                    let user = match self.acting_user(&message.chat, Some(&query.from)).await? {
                                         Some(user) => user,
                                         None => {
                                             bot.send_message(message.chat.id, "❌ Debes registrarte primero usando /register").await?;
@@ -351,7 +898,8 @@ impl TelegramBot {
 
                                     // Guardar la alerta
                                     match self.db.save_alert(&alert) {
-                                        Ok(_) => {
+                                        Ok(alert_id) => {
+                                            self.attach_chat_target(&message.chat, alert_id, user.id)?;
                                             bot.send_message(
                                                 message.chat.id,
                                                 format!(
@@ -376,16 +924,12 @@ impl TelegramBot {
                             }
                         }
                     }
-                    s if s.starts_with("depegdiff_") => {
-                        let diff: f64 = s.trim_start_matches("depegdiff_")
-                            .parse()
-                            .map_err(|_| RequestError::Api(ApiError::Unknown("Diferencial inválido".to_string())))?;
-
+                    CallbackAction::DepegDifferential(diff) => {
                         if let Some(state) = self.db.get_user_state(message.chat.id.0)
                             .map_err(Self::db_error_to_request_error)? {
                             if let UserState::CreatingDepegAlert { symbol, .. } = state {
                                 if let Some(symbol) = symbol {
-                                    let user = match self.get_user_by_chat_id(message.chat.id.0).await? {
+                                    let user = match self.acting_user(&message.chat, Some(&query.from)).await? {
                                         Some(user) => user,
                                         None => {
                                             bot.send_message(message.chat.id, "❌ Debes registrarte primero usando /register").await?;
@@ -408,86 +952,1403 @@ impl TelegramBot {
                                         is_active: true,
                                     };
 
-                                    match self.db.save_alert(&alert) {
-                                        Ok(_) => {
-                                            bot.send_message(
-                                                message.chat.id,
-                                                format!(
-                                                    "✅ Alerta de depeg creada!\n\n\
-                                                     Stablecoin: {}\n\
-                                                     Se alertará si se desvía más de {}% de $1",
-                                                    symbol, diff
-                                                )
-                                            ).await?;
-                                            
-                                            self.db.clear_user_state(message.chat.id.0)
-                                                .map_err(Self::db_error_to_request_error)?;
-                                        }
-                                        Err(e) => {
-                                            error!("Error al crear alerta: {}", e);
-                                            bot.send_message(message.chat.id, "❌ Error al crear la alerta").await?;
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    _ => {
-                        info!("Callback no manejado: {}", data);
-                    }
-                }
-                
-                // Responder al callback query para quitar el estado de "loading"
-                bot.answer_callback_query(query.id).await?;
+                                    match self.db.save_alert(&alert) {
+                                        Ok(alert_id) => {
+                                            self.attach_chat_target(&message.chat, alert_id, user.id)?;
+                                            bot.send_message(
+                                                message.chat.id,
+                                                format!(
+                                                    "✅ Alerta de depeg creada!\n\n\
+                                                     Stablecoin: {}\n\
+                                                     Se alertará si se desvía más de {}% de $1",
+                                                    symbol, diff
+                                                )
+                                            ).await?;
+                                            
+                                            self.db.clear_user_state(message.chat.id.0)
+                                                .map_err(Self::db_error_to_request_error)?;
+                                        }
+                                        Err(e) => {
+                                            error!("Error al crear alerta: {}", e);
+                                            bot.send_message(message.chat.id, "❌ Error al crear la alerta").await?;
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                }
+                
+                // Responder al callback query para quitar el estado de "loading"
+                bot.answer_callback_query(query.id).await?;
+            }
+        } 
+        Ok(())
+    }
+
+    async fn handle_register(&self, bot: Bot, msg: Message, username: Option<String>, invite_code: Option<String>) -> ResponseResult<()> {
+        let chat_id = msg.chat.id.0;
+        let telegram_user = match msg.from() {
+            Some(user) => user,
+            None => {
+                bot.send_message(msg.chat.id, "❌ No se pudo identificar tu usuario de Telegram").await?;
+                return Ok(());
+            }
+        };
+        let telegram_user_id = telegram_user.id.0 as i64;
+        info!("Intento de registro desde Telegram: chat_id={}", chat_id);
+
+        let existing = match self.db.get_user_by_telegram_user_id(telegram_user_id) {
+            Ok(Some(user)) => Some(user),
+            Ok(None) => self.db.get_user_by_telegram_id(chat_id).map_err(Self::db_error_to_request_error)?,
+            Err(e) => return Err(Self::db_error_to_request_error(e)),
+        };
+        if let Some(user) = existing {
+            if user.pending_approval {
+                bot.send_message(msg.chat.id, "⏳ Tu registro está pendiente de aprobación. Te avisaré por aquí.").await?;
+                return Ok(());
+            }
+            if !user.is_active {
+                bot.send_message(msg.chat.id, "⛔ Tu cuenta está desactivada. Contacta a un administrador.").await?;
+                return Ok(());
+            }
+            bot.send_message(
+                msg.chat.id,
+                format!("Ya estás registrado como {}.\nUsa /help para ver los comandos disponibles.", user.username)
+            ).await?;
+            return Ok(());
+        }
+
+        let username = username
+            .or_else(|| telegram_user.username.clone())
+            .unwrap_or_else(|| format!("tg_{}", telegram_user_id));
+
+        let auth = Auth::new(self.db.as_ref());
+        match auth.register_telegram_user(&username, telegram_user_id, chat_id, invite_code.as_deref()) {
+            Ok(user) if user.pending_approval => {
+                info!("Usuario {} registrado desde Telegram, pendiente de aprobación", user.id);
+                let admins = self.db.get_admin_chat_ids().map_err(Self::db_error_to_request_error)?;
+                notify::notify_pending_registration(&bot, &admins, &user).await;
+                bot.send_message(
+                    msg.chat.id,
+                    "⏳ Solicitud enviada. Un administrador tiene que aprobar tu cuenta; te avisaré por aquí."
+                ).await?;
+            }
+            Ok(user) => {
+                info!("Usuario {} registrado desde Telegram", user.id);
+                bot.send_message(msg.chat.id, format!(
+                    "✅ Registro exitoso!\n\n\
+                     Usuario: {}\n\n\
+                     Para usar la API REST genera un código con /apikey.\n\
+                     Usa /help para ver los comandos disponibles.",
+                    user.username
+                )).await?;
+            }
+            Err(AuthError::UserExists) => {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Ese nombre de usuario ya existe.\n\
+                     Intenta con otro: /register <username>"
+                ).await?;
+            }
+            Err(AuthError::InviteRequired) => {
+                bot.send_message(
+                    msg.chat.id,
+                    "🔒 El registro es solo por invitación.\n\
+                     Usa /register <username> invite=<código>"
+                ).await?;
+            }
+            Err(AuthError::InvalidInvite) => {
+                bot.send_message(msg.chat.id, "❌ El código de invitación no es válido, expiró o ya fue usado").await?;
+            }
+            Err(e) => {
+                error!("Error al registrar usuario de Telegram: {}", e);
+                bot.send_message(msg.chat.id, "❌ Error al registrar usuario").await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_api_key_code(&self, bot: Bot, msg: Message) -> ResponseResult<()> {
+        let user = match self.get_user_by_chat_id(msg.chat.id.0).await? {
+            Some(user) => user,
+            None => {
+                bot.send_message(msg.chat.id, "❌ Debes registrarte primero usando /register").await?;
+                return Ok(());
+            }
+        };
+
+        match self.db.create_api_key_code(user.id) {
+            Ok((code, _)) => {
+                bot.send_message(msg.chat.id, format!(
+                    "🔑 Código de un solo uso: {}\n\n\
+                     Canjéalo en los próximos 10 minutos con:\n\
+                     POST /auth/api-key {{\"code\": \"{}\"}}\n\n\
+                     La API key solo se mostrará en esa respuesta.",
+                    code, code
+                )).await?;
+            }
+            Err(e) => {
+                error!("Error al generar código de API key: {}", e);
+                bot.send_message(msg.chat.id, "❌ Error al generar el código").await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_link(&self, bot: Bot, msg: Message, code: String) -> ResponseResult<()> {
+        let chat_id = msg.chat.id.0;
+        info!("Intento de vinculación de Telegram: chat_id={}", chat_id);
+
+        let user_id = match self.db.consume_telegram_link_code(&code) {
+            Ok(Some(user_id)) => user_id,
+            Ok(None) => {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Código de vinculación inválido o expirado.\n\
+                     Genera uno nuevo con POST /auth/telegram-link"
+                ).await?;
+                return Ok(());
+            }
+            Err(e) => {
+                error!("Error al validar código de vinculación: {}", e);
+                bot.send_message(msg.chat.id, "❌ Error al vincular la cuenta").await?;
+                return Ok(());
+            }
+        };
+
+        match self.db.link_telegram_chat(user_id, chat_id) {
+            Ok(_) => {
+                info!("Usuario {} vinculado al chat {}", user_id, chat_id);
+                bot.send_message(
+                    msg.chat.id,
+                    "✅ Cuenta vinculada exitosamente!\n\
+                     A partir de ahora recibirás tus alertas en este chat."
+                ).await?;
+            }
+            Err(e) => {
+                error!("Error al vincular chat {}: {}", chat_id, e);
+                bot.send_message(msg.chat.id, "❌ Error al vincular la cuenta").await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn handle_cancel(&self, bot: Bot, msg: Message) -> ResponseResult<()> {
+        let state = self.db.get_user_state(msg.chat.id.0)
+            .map_err(Self::db_error_to_request_error)?;
+
+        match state {
+            Some(UserState::Idle) | None => {
+                bot.send_message(msg.chat.id, "No hay ninguna operación en curso").await?;
+            }
+            Some(_) => {
+                self.db.clear_user_state(msg.chat.id.0)
+                    .map_err(Self::db_error_to_request_error)?;
+                bot.send_message(msg.chat.id, "❌ Operación cancelada").await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_back(&self, bot: Bot, msg: Message) -> ResponseResult<()> {
+        let state = match self.db.get_user_state(msg.chat.id.0)
+            .map_err(Self::db_error_to_request_error)? {
+            Some(UserState::Idle) | None => {
+                bot.send_message(msg.chat.id, "No hay ninguna operación en curso").await?;
+                return Ok(());
+            }
+            Some(state) => state,
+        };
+
+        let previous = match state.back() {
+            Some(previous) => previous,
+            None => {
+                bot.send_message(
+                    msg.chat.id,
+                    "Ya estás en el primer paso. Usa /cancel para cancelar la operación."
+                ).await?;
+                return Ok(());
+            }
+        };
+
+        self.db.save_user_state(msg.chat.id.0, &previous)
+            .map_err(Self::db_error_to_request_error)?;
+        bot.send_message(msg.chat.id, format!("↩️ {}", previous.describe())).await?;
+
+        match &previous {
+            UserState::CreatingPriceAlert { .. } => self.handle_price_alert_step(&bot, msg, &previous).await?,
+            UserState::CreatingDepegAlert { .. } => self.handle_depeg_alert_step(&bot, msg, &previous).await?,
+            UserState::CreatingPairAlert { .. } => self.handle_pair_depeg_step(&bot, msg, &previous).await?,
+            UserState::Idle | UserState::EditingAlert { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// `/share` y `/unshare`: agrega o quita un canal como destino de una
+    /// alerta propia. Para compartir hay que ser administrador del canal.
+    async fn handle_share(&self, bot: Bot, msg: Message, text: String, share: bool) -> ResponseResult<()> {
+        let command = if share { "/share" } else { "/unshare" };
+        let mut parts = text.split_whitespace();
+        let (alert_id, channel) = match (parts.next().and_then(|id| id.parse::<i64>().ok()), parts.next()) {
+            (Some(alert_id), Some(channel)) => (alert_id, channel.to_string()),
+            _ => {
+                bot.send_message(msg.chat.id, format!("Uso: {} <id de alerta> <@canal o ID del canal>", command)).await?;
+                return Ok(());
+            }
+        };
+
+        let user = match self.get_user_by_chat_id(msg.chat.id.0).await? {
+            Some(user) => user,
+            None => {
+                bot.send_message(msg.chat.id, "❌ Debes registrarte primero usando /register").await?;
+                return Ok(());
+            }
+        };
+        let owned = self.db.get_alert(alert_id)
+            .map_err(Self::db_error_to_request_error)?
+            .is_some_and(|alert| alert.user_id == user.id);
+        if !owned {
+            bot.send_message(msg.chat.id, format!("❌ No tienes ninguna alerta #{}", alert_id)).await?;
+            return Ok(());
+        }
+
+        let recipient = match channel.parse::<i64>() {
+            Ok(id) => Recipient::Id(ChatId(id)),
+            Err(_) => Recipient::ChannelUsername(channel.clone()),
+        };
+        let chat = match bot.get_chat(recipient).await {
+            Ok(chat) if chat.is_channel() => chat,
+            Ok(_) => {
+                bot.send_message(
+                    msg.chat.id,
+                    "❌ Solo se pueden compartir alertas con canales.\n\
+                     Para un grupo, crea la alerta desde el propio grupo."
+                ).await?;
+                return Ok(());
+            }
+            Err(e) => {
+                info!("No se pudo resolver el canal {}: {}", channel, e);
+                bot.send_message(
+                    msg.chat.id,
+                    format!("❌ No encuentro el canal {}. Agrega el bot como administrador del canal primero.", channel)
+                ).await?;
+                return Ok(());
+            }
+        };
+
+        if share {
+            let is_admin = self.is_chat_admin(&bot, &chat, msg.from()).await.unwrap_or(false);
+            if !is_admin {
+                bot.send_message(msg.chat.id, "❌ Debes ser administrador del canal para enviarle alertas").await?;
+                return Ok(());
+            }
+        }
+
+        let result = if share {
+            self.db.add_alert_target(alert_id, chat.id.0, TargetKind::Channel, user.id)
+        } else {
+            self.db.remove_alert_target(alert_id, chat.id.0)
+        };
+        match result {
+            Ok(changed) => {
+                if changed {
+                    let action = if share { "alert.share" } else { "alert.unshare" };
+                    self.audit(&msg.chat, msg.from(), action, Some(format!("{}:{}", alert_id, chat.id))).await?;
+                }
+                let reply = match (share, changed) {
+                    (true, true) => format!("✅ La alerta #{} también se enviará a {}", alert_id, channel),
+                    (true, false) => format!("La alerta #{} ya se enviaba a {}", alert_id, channel),
+                    (false, true) => format!("✅ La alerta #{} ya no se enviará a {}", alert_id, channel),
+                    (false, false) => format!("La alerta #{} no se enviaba a {}", alert_id, channel),
+                };
+                bot.send_message(msg.chat.id, reply).await?;
+            }
+            Err(e) => {
+                error!("Error al actualizar destinos de la alerta {}: {}", alert_id, e);
+                bot.send_message(msg.chat.id, "❌ Error al actualizar la alerta").await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// `/escalate`: configura o quita la política de escalado de una alerta.
+    /// El contacto secundario puede ser un @canal, un ID de chat o el nombre
+    /// de usuario de otra cuenta con Telegram vinculado.
+    async fn handle_escalate(&self, bot: Bot, msg: Message, text: String) -> ResponseResult<()> {
+        const USAGE: &str = "Uso: /escalate <id> <minutos> <intentos> [@canal | ID de chat | usuario]\n\
+                             Para quitarla: /escalate <id> off";
+
+        let parts: Vec<&str> = text.split_whitespace().collect();
+        let alert_id = match parts.first().and_then(|id| id.parse::<i64>().ok()) {
+            Some(alert_id) => alert_id,
+            None => {
+                bot.send_message(msg.chat.id, USAGE).await?;
+                return Ok(());
+            }
+        };
+
+        let from = match msg.from() {
+            Some(from) => from,
+            None => return Ok(()),
+        };
+        if !self.can_manage_alert(&msg.chat, from, alert_id).await? {
+            bot.send_message(msg.chat.id, format!("❌ No puedes gestionar la alerta #{}", alert_id)).await?;
+            return Ok(());
+        }
+
+        if parts.get(1) == Some(&"off") {
+            let removed = self.db.remove_escalation_policy(alert_id).map_err(Self::db_error_to_request_error)?;
+            if removed {
+                self.audit(&msg.chat, Some(from), "alert.escalation.remove", Some(alert_id.to_string())).await?;
+            }
+            let reply = if removed {
+                format!("✅ La alerta #{} ya no se escalará", alert_id)
+            } else {
+                format!("La alerta #{} no tenía escalado", alert_id)
+            };
+            bot.send_message(msg.chat.id, reply).await?;
+            return Ok(());
+        }
+
+        let (minutes, attempts) = match (
+            parts.get(1).and_then(|m| m.parse::<i64>().ok()),
+            parts.get(2).and_then(|a| a.parse::<i64>().ok()),
+        ) {
+            (Some(minutes), Some(attempts)) if minutes >= 1 && attempts >= 1 => (minutes, attempts),
+            _ => {
+                bot.send_message(msg.chat.id, USAGE).await?;
+                return Ok(());
+            }
+        };
+
+        let secondary = match parts.get(3) {
+            None => None,
+            Some(contact) => match self.resolve_contact(&bot, contact).await? {
+                Some(chat_id) => Some(chat_id),
+                None => {
+                    bot.send_message(
+                        msg.chat.id,
+                        format!("❌ No encuentro el contacto {}. Debe haber iniciado el bot o tenerlo como administrador del canal.", contact)
+                    ).await?;
+                    return Ok(());
+                }
+            },
+        };
+
+        match self.db.set_escalation_policy(alert_id, minutes * 60, attempts, secondary) {
+            Ok(_) => {
+                self.audit(&msg.chat, Some(from), "alert.escalation.set", Some(alert_id.to_string())).await?;
+                let mut reply = format!(
+                    "🚨 Al dispararse, la alerta #{} se reenviará cada {} min hasta que se reconozca.",
+                    alert_id, minutes
+                );
+                if let (Some(contact), Some(_)) = (parts.get(3), secondary) {
+                    reply.push_str(&format!("\nTras {} avisos sin respuesta se avisará también a {}.", attempts, contact));
+                }
+                bot.send_message(msg.chat.id, reply).await?;
+            }
+            Err(e) => {
+                error!("Error al guardar el escalado de la alerta {}: {}", alert_id, e);
+                bot.send_message(msg.chat.id, "❌ Error al guardar el escalado").await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `/action`: asocia a una alerta propia la orden que se envía al
+    /// dispararse, la quita o, solo con el ID, muestra la actual y sus
+    /// últimas ejecuciones. Por defecto el modo es dry_run.
+    async fn handle_action(&self, bot: Bot, msg: Message, text: String) -> ResponseResult<()> {
+        const USAGE: &str = "Uso: /action <id> <buy|sell> <cantidad> [limit <±%>] [dry_run|paper|live] [exchange]\n\
+                             Ej.: /action 12 sell 0.5 paper - vende 0.5 a mercado en la cuenta simulada\n\
+                             Ej.: /action 12 buy 1 limit -1 live binance - compra límite 1 % bajo el disparo\n\
+                             Ver: /action <id> · Quitar: /action <id> off";
+
+        let user = match self.get_user_by_chat_id(msg.chat.id.0).await? {
+            Some(user) => user,
+            None => {
+                bot.send_message(msg.chat.id, "❌ Debes registrarte primero usando /register").await?;
+                return Ok(());
+            }
+        };
+
+        let parts: Vec<&str> = text.split_whitespace().collect();
+        let alert_id = match parts.first().and_then(|id| id.parse::<i64>().ok()) {
+            Some(alert_id) => alert_id,
+            None => {
+                bot.send_message(msg.chat.id, USAGE).await?;
+                return Ok(());
+            }
+        };
+        let alert = match self.db.get_alert(alert_id).map_err(Self::db_error_to_request_error)? {
+            Some(alert) if alert.user_id == user.id => alert,
+            _ => {
+                bot.send_message(msg.chat.id, format!("❌ No tienes ninguna alerta #{}", alert_id)).await?;
+                return Ok(());
+            }
+        };
+
+        match parts.get(1).map(|p| p.to_lowercase()).as_deref() {
+            None => {
+                let action = self.db.get_alert_action(alert_id).map_err(Self::db_error_to_request_error)?;
+                let reply = match action {
+                    None => format!("La alerta #{} no tiene acción automática", alert_id),
+                    Some(action) => {
+                        let runs = self.db.get_action_runs(alert_id, 5).map_err(Self::db_error_to_request_error)?;
+                        let mut reply = format!("🤖 Al dispararse, la alerta #{} va a {}", alert_id, action.describe(&alert.symbol));
+                        for run in runs {
+                            let when = chrono::DateTime::from_timestamp(run.executed_at, 0)
+                                .map(|date| date.format("%Y-%m-%d %H:%M").to_string())
+                                .unwrap_or_default();
+                            let icon = if run.success { "✅" } else { "❌" };
+                            reply.push_str(&format!("\n\n{} {} ({})\n{}", icon, when, run.mode.as_str(), run.summary));
+                        }
+                        reply
+                    }
+                };
+                bot.send_message(msg.chat.id, reply).await?;
+                return Ok(());
+            }
+            Some("off") => {
+                let removed = self.db.remove_alert_action(alert_id).map_err(Self::db_error_to_request_error)?;
+                let reply = if removed {
+                    self.audit(&msg.chat, msg.from(), "alert.action.remove", Some(alert_id.to_string())).await?;
+                    format!("✅ La alerta #{} ya no enviará órdenes", alert_id)
+                } else {
+                    format!("La alerta #{} no tenía acción automática", alert_id)
+                };
+                bot.send_message(msg.chat.id, reply).await?;
+                return Ok(());
+            }
+            _ => {}
+        }
+
+        let side = match parts.get(1).map(|p| p.to_lowercase()).as_deref() {
+            Some("buy") => TradeSide::Buy,
+            Some("sell") => TradeSide::Sell,
+            _ => {
+                bot.send_message(msg.chat.id, USAGE).await?;
+                return Ok(());
+            }
+        };
+        let quantity = match parts.get(2).and_then(|q| q.parse::<f64>().ok()) {
+            Some(quantity) => quantity,
+            None => {
+                bot.send_message(msg.chat.id, USAGE).await?;
+                return Ok(());
+            }
+        };
+
+        let mut limit_offset = None;
+        let mut mode = TradeMode::DryRun;
+        let mut exchange = None;
+        let mut options = parts.iter().skip(3);
+        while let Some(option) = options.next() {
+            match option.to_lowercase().as_str() {
+                "market" => limit_offset = None,
+                "limit" => match options.next().and_then(|o| o.trim_end_matches('%').parse::<f64>().ok()) {
+                    Some(offset) => limit_offset = Some(offset),
+                    None => {
+                        bot.send_message(msg.chat.id, USAGE).await?;
+                        return Ok(());
+                    }
+                },
+                other => match other.parse::<TradeMode>() {
+                    Ok(parsed) => mode = parsed,
+                    Err(_) => exchange = Some(other.to_string()),
+                },
+            }
+        }
+
+        let action = AlertAction {
+            alert_id,
+            side,
+            quantity,
+            quote_asset: "USDT".to_string(),
+            limit_offset,
+            mode,
+            exchange,
+            created_at: chrono::Utc::now().timestamp(),
+        };
+        if let Err(e) = action.validate(&alert.alert_type) {
+            bot.send_message(msg.chat.id, format!("❌ {}", e)).await?;
+            return Ok(());
+        }
+
+        match self.db.set_alert_action(&action) {
+            Ok(()) => {
+                self.audit(&msg.chat, msg.from(), "alert.action.set", Some(alert_id.to_string())).await?;
+                bot.send_message(
+                    msg.chat.id,
+                    format!("🤖 Al dispararse, la alerta #{} va a {}", alert_id, action.describe(&alert.symbol))
+                ).await?;
+            }
+            Err(e) => {
+                error!("Error al guardar la acción de la alerta {}: {}", alert_id, e);
+                bot.send_message(msg.chat.id, "❌ Error al guardar la acción").await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// `/dca`: sin argumentos lista los planes de compra periódica; `add` crea
+    /// uno y, con un ID, muestra sus últimos turnos o lo pausa, reanuda o
+    /// borra. Por defecto el modo es dry_run.
+    async fn handle_dca(&self, bot: Bot, msg: Message, text: String) -> ResponseResult<()> {
+        const USAGE: &str = "Uso: /dca add <símbolo> <importe> <horario> [max <precio>] [dry_run|paper|live] [exchange]\n\
+                             El horario es cron en UTC (minuto hora día mes día-semana) o @hourly, @daily, @weekly, @monthly.\n\
+                             Ej.: /dca add BTC 50 0 9 * * 1 live binance - 50 USDT de BTC los lunes a las 9:00\n\
+                             Ej.: /dca add ETH 20 @daily max 4000 paper - salta los días en que ETH supere $4000\n\
+                             Ver: /dca <id> · Pausar: /dca <id> pause · Reanudar: /dca <id> resume · Borrar: /dca <id> off";
+
+        let user = match self.get_user_by_chat_id(msg.chat.id.0).await? {
+            Some(user) => user,
+            None => {
+                bot.send_message(msg.chat.id, "❌ Debes registrarte primero usando /register").await?;
+                return Ok(());
+            }
+        };
+        let plans = DcaPlans::new(&self.db);
+        let format_time = |timestamp: i64| chrono::DateTime::from_timestamp(timestamp, 0)
+            .map(|date| date.format("%Y-%m-%d %H:%M UTC").to_string())
+            .unwrap_or_default();
+
+        let parts: Vec<&str> = text.split_whitespace().collect();
+        match parts.first().map(|p| p.to_lowercase()).as_deref() {
+            None => {
+                let user_plans = self.db.get_user_dca_plans(user.id).map_err(Self::db_error_to_request_error)?;
+                if user_plans.is_empty() {
+                    bot.send_message(msg.chat.id, format!("No tienes planes de compra periódica.\n\n{}", USAGE)).await?;
+                    return Ok(());
+                }
+                let mut reply = "🗓 Tus planes DCA:".to_string();
+                for plan in user_plans {
+                    let status = if plan.active {
+                        format!("próxima: {}", format_time(plan.next_run_at))
+                    } else {
+                        "pausado".to_string()
+                    };
+                    reply.push_str(&format!("\n\n#{}: {}\n{}", plan.id, plan.describe(), status));
+                }
+                bot.send_message(msg.chat.id, reply).await?;
+                return Ok(());
+            }
+            Some("add") => {}
+            Some(id) => {
+                let Ok(plan_id) = id.parse::<i64>() else {
+                    bot.send_message(msg.chat.id, USAGE).await?;
+                    return Ok(());
+                };
+                let result = match parts.get(1).map(|p| p.to_lowercase()).as_deref() {
+                    None => plans.owned(user.id, plan_id).map(|plan| {
+                        let mut reply = format!("🗓 Plan DCA #{}: {}", plan.id, plan.describe());
+                        if plan.active {
+                            reply.push_str(&format!("\nPróxima compra: {}", format_time(plan.next_run_at)));
+                        } else {
+                            reply.push_str("\nEn pausa");
+                        }
+                        reply
+                    }),
+                    Some("pause") => plans.set_active(user.id, plan_id, false)
+                        .map(|_| format!("⏸ Plan DCA #{} en pausa", plan_id)),
+                    Some("resume") => plans.set_active(user.id, plan_id, true)
+                        .map(|plan| format!("▶️ Plan DCA #{} reanudado. Próxima compra: {}", plan_id, format_time(plan.next_run_at))),
+                    Some("off") => plans.delete(user.id, plan_id)
+                        .map(|_| format!("✅ Plan DCA #{} eliminado", plan_id)),
+                    _ => {
+                        bot.send_message(msg.chat.id, USAGE).await?;
+                        return Ok(());
+                    }
+                };
+                let mut reply = match result {
+                    Ok(reply) => reply,
+                    Err(DcaError::DatabaseError(e)) => return Err(Self::db_error_to_request_error(e)),
+                    Err(DcaError::NotFound) => {
+                        bot.send_message(msg.chat.id, format!("❌ No tienes ningún plan DCA #{}", plan_id)).await?;
+                        return Ok(());
+                    }
+                    Err(e) => {
+                        bot.send_message(msg.chat.id, format!("❌ {}", e)).await?;
+                        return Ok(());
+                    }
+                };
+                if parts.len() == 1 {
+                    let runs = self.db.get_dca_runs(plan_id, 5).map_err(Self::db_error_to_request_error)?;
+                    for run in runs {
+                        let icon = match run.outcome {
+                            DcaOutcome::Executed => "✅",
+                            DcaOutcome::Skipped => "⏭️",
+                            DcaOutcome::Failed => "❌",
+                        };
+                        reply.push_str(&format!("\n\n{} {} ({})\n{}", icon, format_time(run.executed_at), run.mode.as_str(), run.summary));
+                    }
+                } else {
+                    let action = format!("dca.{}", parts[1].to_lowercase());
+                    self.audit(&msg.chat, msg.from(), &action, Some(plan_id.to_string())).await?;
+                }
+                bot.send_message(msg.chat.id, reply).await?;
+                return Ok(());
+            }
+        }
+
+        let (Some(symbol), Some(amount)) = (parts.get(1), parts.get(2).and_then(|a| a.parse::<f64>().ok())) else {
+            bot.send_message(msg.chat.id, USAGE).await?;
+            return Ok(());
+        };
+        // `@daily` y similares son un solo campo; cron son cinco
+        let schedule_len = if parts.get(3).is_some_and(|p| p.starts_with('@')) { 1 } else { 5 };
+        if parts.len() < 3 + schedule_len {
+            bot.send_message(msg.chat.id, USAGE).await?;
+            return Ok(());
+        }
+        let schedule = parts[3..3 + schedule_len].join(" ");
+
+        let mut max_price = None;
+        let mut mode = TradeMode::DryRun;
+        let mut exchange = None;
+        let mut options = parts.iter().skip(3 + schedule_len);
+        while let Some(option) = options.next() {
+            match option.to_lowercase().as_str() {
+                "max" => match options.next().and_then(|o| o.trim_start_matches('$').parse::<f64>().ok()) {
+                    Some(price) => max_price = Some(price),
+                    None => {
+                        bot.send_message(msg.chat.id, USAGE).await?;
+                        return Ok(());
+                    }
+                },
+                other => match other.parse::<TradeMode>() {
+                    Ok(parsed) => mode = parsed,
+                    Err(_) => exchange = Some(other.to_string()),
+                },
+            }
+        }
+
+        let plan = DcaPlan {
+            id: 0,
+            user_id: user.id,
+            symbol: symbol.to_uppercase(),
+            amount,
+            quote_asset: "USDT".to_string(),
+            schedule,
+            max_price,
+            mode,
+            exchange,
+            active: true,
+            next_run_at: 0,
+            last_run_at: None,
+            created_at: chrono::Utc::now().timestamp(),
+        };
+        match plans.create(plan) {
+            Ok(plan) => {
+                self.audit(&msg.chat, msg.from(), "dca.create", Some(plan.id.to_string())).await?;
+                bot.send_message(
+                    msg.chat.id,
+                    format!("🗓 Plan DCA #{} creado: {}\nPrimera compra: {}", plan.id, plan.describe(), format_time(plan.next_run_at))
+                ).await?;
+            }
+            Err(DcaError::DatabaseError(e)) => {
+                error!("Error al guardar el plan DCA de {}: {}", user.id, e);
+                bot.send_message(msg.chat.id, "❌ Error al guardar el plan").await?;
+            }
+            Err(e) => {
+                bot.send_message(msg.chat.id, format!("❌ {}", e)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Resuelve un contacto de escalado a un ID de chat.
+    async fn resolve_contact(&self, bot: &Bot, contact: &str) -> ResponseResult<Option<i64>> {
+        if let Ok(chat_id) = contact.parse::<i64>() {
+            return Ok(Some(chat_id));
+        }
+        if contact.starts_with('@') {
+            return Ok(bot.get_chat(Recipient::ChannelUsername(contact.to_string())).await
+                .ok()
+                .map(|chat| chat.id.0));
+        }
+        Ok(self.db.get_user_by_username(contact)
+            .map_err(Self::db_error_to_request_error)?
+            .and_then(|user| user.telegram_chat_id))
+    }
+
+    async fn handle_admin(&self, bot: Bot, msg: Message, text: String) -> ResponseResult<()> {
+        const USAGE: &str = "Uso:\n\
+                             /admin stats\n\
+                             /admin users [página]\n\
+                             /admin disable <usuario | #id>\n\
+                             /admin enable <usuario | #id>\n\
+                             /admin broadcast <texto>\n\
+                             /admin registration [open | invite | approval]\n\
+                             /admin invite [plan]\n\
+                             /admin pending\n\
+                             /admin approve <usuario | #id>\n\
+                             /admin reject <usuario | #id>\n\
+                             /admin plans\n\
+                             /admin plan <nombre> <máx. alertas | -> <intervalo seg.> <tipos: price,depeg,pair_depeg,portfolio>\n\
+                             /admin setplan <usuario | #id> <plan>\n\
+                             /admin risk [order | daily | position | rate | symbols] [valor | off]\n\
+                             /admin halt - detiene toda la operativa automática\n\
+                             /admin resume";
+
+        let admin = match self.get_user_by_chat_id(msg.chat.id.0).await? {
+            Some(user) if user.role == UserRole::Admin => user,
+            _ => {
+                bot.send_message(msg.chat.id, "❌ Este comando es solo para administradores").await?;
+                return Ok(());
+            }
+        };
+
+        let text = text.trim();
+        let (subcommand, args) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let args = args.trim();
+
+        match subcommand {
+            "stats" => {
+                let stats = self.db.get_admin_stats().map_err(Self::db_error_to_request_error)?;
+                bot.send_message(msg.chat.id, format!(
+                    "📊 Estadísticas\n\n\
+                     Usuarios: {} ({} activos, {} administradores)\n\
+                     Chats vinculados: {}\n\
+                     Alertas: {} ({} activas)\n\
+                     Disparadas en 24h: {}\n\
+                     Destinos adicionales: {}",
+                    stats.users, stats.active_users, stats.admins,
+                    stats.linked_chats,
+                    stats.alerts, stats.active_alerts,
+                    stats.triggered_last_24h,
+                    stats.alert_targets,
+                )).await?;
+            }
+            "users" => {
+                let total = self.db.count_users().map_err(Self::db_error_to_request_error)?;
+                let pages = ((total + USERS_PER_PAGE - 1) / USERS_PER_PAGE).max(1);
+                let page = args.parse::<i64>().unwrap_or(1).clamp(1, pages);
+                let users = self.db.list_users(USERS_PER_PAGE, (page - 1) * USERS_PER_PAGE)
+                    .map_err(Self::db_error_to_request_error)?;
+
+                let mut reply = format!("👥 Usuarios ({} en total, página {}/{})\n\n", total, page, pages);
+                for user in &users {
+                    reply.push_str(&format!("#{} {}", user.id, user.username));
+                    if user.role == UserRole::Admin {
+                        reply.push_str(" 👑");
+                    }
+                    if user.telegram_chat_id.is_some() {
+                        reply.push_str(" 📱");
+                    }
+                    if user.pending_approval {
+                        reply.push_str(" ⏳ pendiente");
+                    } else if !user.is_active {
+                        reply.push_str(" ⛔ desactivado");
+                    }
+                    reply.push('\n');
+                }
+                if page < pages {
+                    reply.push_str(&format!("\nSiguiente página: /admin users {}", page + 1));
+                }
+                bot.send_message(msg.chat.id, reply).await?;
+            }
+            "disable" | "enable" => {
+                let active = subcommand == "enable";
+                let user = match self.db.find_user(args).map_err(Self::db_error_to_request_error)? {
+                    Some(user) if !args.is_empty() => user,
+                    _ => {
+                        bot.send_message(msg.chat.id, format!("❌ No encuentro al usuario {}", args)).await?;
+                        return Ok(());
+                    }
+                };
+                if user.id == admin.id {
+                    bot.send_message(msg.chat.id, "❌ No puedes cambiar el estado de tu propia cuenta").await?;
+                    return Ok(());
+                }
+
+                let changed = self.db.set_user_active(user.id, active).map_err(Self::db_error_to_request_error)?;
+                if changed {
+                    let action = if active { "admin.user.enable" } else { "admin.user.disable" };
+                    self.audit(&msg.chat, msg.from(), action, Some(user.id.to_string())).await?;
+                }
+                let reply = match (changed, active) {
+                    (true, true) => format!("✅ Cuenta de {} reactivada", user.username),
+                    (true, false) => format!("⛔ Cuenta de {} desactivada. Sus alertas dejan de evaluarse.", user.username),
+                    (false, true) => format!("La cuenta de {} ya estaba activa", user.username),
+                    (false, false) => format!("La cuenta de {} ya estaba desactivada", user.username),
+                };
+                bot.send_message(msg.chat.id, reply).await?;
+            }
+            "broadcast" if !args.is_empty() => {
+                let chat_ids = self.db.get_broadcast_chat_ids().map_err(Self::db_error_to_request_error)?;
+                bot.send_message(msg.chat.id, format!("📣 Enviando anuncio a {} chats...", chat_ids.len())).await?;
+
+                let announcement = format!("📣 Anuncio\n\n{}", args);
+                let (sent, failed) = notify::broadcast(&bot, &chat_ids, &announcement).await;
+                self.audit(&msg.chat, msg.from(), "admin.broadcast", Some(format!("{} enviados, {} fallidos", sent, failed))).await?;
+                bot.send_message(msg.chat.id, format!("✅ Anuncio enviado a {} chats ({} fallidos)", sent, failed)).await?;
+            }
+            "registration" => {
+                if !args.is_empty() {
+                    let mode = match args.parse::<RegistrationMode>() {
+                        Ok(mode) => mode,
+                        Err(e) => {
+                            bot.send_message(msg.chat.id, format!("❌ {}", e)).await?;
+                            return Ok(());
+                        }
+                    };
+                    self.db.set_registration_mode(mode).map_err(Self::db_error_to_request_error)?;
+                    self.audit(&msg.chat, msg.from(), "admin.registration", Some(mode.as_str().to_string())).await?;
+                }
+                let mode = self.db.get_registration_mode().map_err(Self::db_error_to_request_error)?;
+                let description = match mode {
+                    RegistrationMode::Open => "cualquiera puede registrarse",
+                    RegistrationMode::Invite => "solo con código de invitación",
+                    RegistrationMode::Approval => "las cuentas nuevas esperan aprobación",
+                };
+                bot.send_message(msg.chat.id, format!("📝 Modo de registro: {} ({})", mode.as_str(), description)).await?;
+            }
+            "invite" => {
+                let plan = if args.is_empty() { Plan::DEFAULT } else { args };
+                if self.db.get_plan(plan).map_err(Self::db_error_to_request_error)?.is_none() {
+                    bot.send_message(msg.chat.id, format!("❌ No existe el plan {}", plan)).await?;
+                    return Ok(());
+                }
+                let (code, _) = self.db.create_invite_code(admin.id, plan).map_err(Self::db_error_to_request_error)?;
+                self.audit(&msg.chat, msg.from(), "admin.invite", Some(plan.to_string())).await?;
+                bot.send_message(msg.chat.id, format!(
+                    "🎟 Código de invitación (plan {}, válido 7 días, un solo uso):\n\n\
+                     <code>/register invite={}</code>",
+                    plan, code
+                ))
+                .parse_mode(ParseMode::Html)
+                .await?;
+            }
+            "pending" => {
+                let pending = self.db.list_pending_users().map_err(Self::db_error_to_request_error)?;
+                let reply = if pending.is_empty() {
+                    "No hay solicitudes de registro pendientes".to_string()
+                } else {
+                    pending.iter().fold("⏳ Solicitudes pendientes\n\n".to_string(), |mut reply, user| {
+                        reply.push_str(&format!("#{} {} - /admin approve #{}\n", user.id, user.username, user.id));
+                        reply
+                    })
+                };
+                bot.send_message(msg.chat.id, reply).await?;
+            }
+            "approve" | "reject" => {
+                let user = match self.db.find_user(args).map_err(Self::db_error_to_request_error)? {
+                    Some(user) if !args.is_empty() && user.pending_approval => user,
+                    _ => {
+                        bot.send_message(msg.chat.id, format!("❌ No hay una solicitud pendiente de {}", args)).await?;
+                        return Ok(());
+                    }
+                };
+
+                if subcommand == "approve" {
+                    self.db.approve_user(user.id).map_err(Self::db_error_to_request_error)?;
+                    self.audit(&msg.chat, msg.from(), "admin.user.approve", Some(user.id.to_string())).await?;
+                    if let Some(chat_id) = user.telegram_chat_id {
+                        if let Err(e) = bot.send_message(ChatId(chat_id), "✅ Tu cuenta fue aprobada. Usa /help para empezar.").await {
+                            error!("No se pudo avisar a {} de su aprobación: {}", user.username, e);
+                        }
+                    }
+                    bot.send_message(msg.chat.id, format!("✅ Cuenta de {} aprobada", user.username)).await?;
+                } else {
+                    self.db.reject_user(user.id).map_err(Self::db_error_to_request_error)?;
+                    self.audit(&msg.chat, msg.from(), "admin.user.reject", Some(user.username.clone())).await?;
+                    if let Some(chat_id) = user.telegram_chat_id {
+                        if let Err(e) = bot.send_message(ChatId(chat_id), "❌ Tu solicitud de registro fue rechazada.").await {
+                            error!("No se pudo avisar a {} del rechazo: {}", user.username, e);
+                        }
+                    }
+                    bot.send_message(msg.chat.id, format!("🗑 Solicitud de {} rechazada", user.username)).await?;
+                }
+            }
+            "plans" => {
+                let plans = self.db.get_plans().map_err(Self::db_error_to_request_error)?;
+                let reply = plans.iter().fold("📦 Planes\n\n".to_string(), |mut reply, plan| {
+                    reply.push_str(&Self::format_plan(plan));
+                    reply.push_str("\n\n");
+                    reply
+                });
+                bot.send_message(msg.chat.id, reply).await?;
+            }
+            "plan" => {
+                let parts: Vec<&str> = args.split_whitespace().collect();
+                let plan = match parts.as_slice() {
+                    [name, max, interval, kinds] => {
+                        let max_active_alerts = match *max {
+                            "-" => Some(None),
+                            max => max.parse::<i64>().ok().filter(|max| *max >= 0).map(Some),
+                        };
+                        let allowed_kinds = kinds.split(',')
+                            .map(|kind| kind.parse())
+                            .collect::<Result<Vec<AlertKind>, _>>();
+                        match (max_active_alerts, interval.parse::<i64>(), allowed_kinds) {
+                            (Some(max_active_alerts), Ok(min_check_interval), Ok(allowed_kinds)) if min_check_interval >= 0 => Some(Plan {
+                                name: name.to_string(),
+                                max_active_alerts,
+                                min_check_interval,
+                                allowed_kinds,
+                            }),
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                };
+                let plan = match plan {
+                    Some(plan) => plan,
+                    None => {
+                        bot.send_message(
+                            msg.chat.id,
+                            "Uso: /admin plan <nombre> <máx. alertas | -> <intervalo seg.> <tipos>\n\
+                             Ejemplo: /admin plan pro 50 60 price,depeg,pair_depeg,portfolio"
+                        ).await?;
+                        return Ok(());
+                    }
+                };
+                self.db.save_plan(&plan).map_err(Self::db_error_to_request_error)?;
+                self.audit(&msg.chat, msg.from(), "admin.plan", Some(plan.name.clone())).await?;
+                bot.send_message(msg.chat.id, format!("✅ Plan guardado\n\n{}", Self::format_plan(&plan))).await?;
+            }
+            "setplan" => {
+                let (ident, plan) = match args.rsplit_once(char::is_whitespace) {
+                    Some((ident, plan)) => (ident.trim(), plan),
+                    None => {
+                        bot.send_message(msg.chat.id, "Uso: /admin setplan <usuario | #id> <plan>").await?;
+                        return Ok(());
+                    }
+                };
+                let user = match self.db.find_user(ident).map_err(Self::db_error_to_request_error)? {
+                    Some(user) => user,
+                    None => {
+                        bot.send_message(msg.chat.id, format!("❌ No encuentro al usuario {}", ident)).await?;
+                        return Ok(());
+                    }
+                };
+                if !self.db.set_user_plan(user.id, plan).map_err(Self::db_error_to_request_error)? {
+                    bot.send_message(msg.chat.id, format!("❌ No existe el plan {}", plan)).await?;
+                    return Ok(());
+                }
+                self.audit(&msg.chat, msg.from(), "admin.user.plan", Some(format!("{}:{}", user.id, plan))).await?;
+                bot.send_message(msg.chat.id, format!("✅ {} ahora está en el plan {}", user.username, plan)).await?;
+            }
+            "halt" | "resume" => {
+                let halted = subcommand == "halt";
+                self.db.set_trading_halted(halted).map_err(Self::db_error_to_request_error)?;
+                self.audit(&msg.chat, msg.from(), if halted { "admin.trading.halt" } else { "admin.trading.resume" }, None).await?;
+                let reply = if halted {
+                    "🛑 Operativa automática detenida. Ninguna orden saldrá hasta /admin resume."
+                } else {
+                    "✅ Operativa automática reanudada"
+                };
+                bot.send_message(msg.chat.id, reply).await?;
+            }
+            "risk" => {
+                let mut limits = self.db.get_risk_limits().map_err(Self::db_error_to_request_error)?;
+                if !args.is_empty() {
+                    let (rule, value) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+                    let value = value.trim();
+                    let off = value.eq_ignore_ascii_case("off");
+                    // Some(None) quita la regla
+                    let amount = if off { Some(None) } else { value.parse::<f64>().ok().filter(|amount| *amount > 0.0).map(Some) };
+                    let rate = if off { Some(None) } else { value.parse::<i64>().ok().filter(|rate| *rate > 0).map(Some) };
+                    let valid = match (rule, amount, rate) {
+                        ("order", Some(amount), _) => {
+                            limits.max_order_notional = amount;
+                            true
+                        }
+                        ("daily", Some(amount), _) => {
+                            limits.max_daily_notional = amount;
+                            true
+                        }
+                        ("position", Some(amount), _) => {
+                            limits.max_position_notional = amount;
+                            true
+                        }
+                        ("rate", _, Some(rate)) => {
+                            limits.max_orders_per_minute = rate;
+                            true
+                        }
+                        ("symbols", _, _) if !value.is_empty() => {
+                            limits.allowed_symbols = if off {
+                                Vec::new()
+                            } else {
+                                value.split(',').map(|s| s.trim().to_uppercase()).filter(|s| !s.is_empty()).collect()
+                            };
+                            true
+                        }
+                        _ => false,
+                    };
+                    if !valid {
+                        bot.send_message(
+                            msg.chat.id,
+                            "Uso: /admin risk <order | daily | position> <usd | off>\n\
+                             /admin risk rate <órdenes por minuto | off>\n\
+                             /admin risk symbols <BTC,ETH,... | off>"
+                        ).await?;
+                        return Ok(());
+                    }
+                    self.db.set_risk_limits(&limits).map_err(Self::db_error_to_request_error)?;
+                    self.audit(&msg.chat, msg.from(), "admin.trading.risk", Some(args.to_string())).await?;
+                }
+
+                let halted = self.db.is_trading_halted().map_err(Self::db_error_to_request_error)?;
+                let usd = |limit: Option<f64>| limit.map_or("sin límite".to_string(), |limit| format!("${:.2}", limit));
+                let symbols = if limits.allowed_symbols.is_empty() {
+                    "todos".to_string()
+                } else {
+                    limits.allowed_symbols.join(", ")
+                };
+                bot.send_message(msg.chat.id, format!(
+                    "🛡 Límites de riesgo (por usuario y modo)\n\n\
+                     Estado: {}\n\
+                     Máximo por orden: {}\n\
+                     Máximo diario: {}\n\
+                     Posición máxima por símbolo: {}\n\
+                     Órdenes por minuto: {}\n\
+                     Símbolos: {}",
+                    if halted { "🛑 detenida" } else { "✅ activa" },
+                    usd(limits.max_order_notional),
+                    usd(limits.max_daily_notional),
+                    usd(limits.max_position_notional),
+                    limits.max_orders_per_minute.map_or("sin límite".to_string(), |rate| rate.to_string()),
+                    symbols,
+                )).await?;
+            }
+            _ => {
+                bot.send_message(msg.chat.id, USAGE).await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn format_plan(plan: &Plan) -> String {
+        let max = plan.max_active_alerts.map_or("sin límite".to_string(), |max| max.to_string());
+        let interval = match plan.min_check_interval {
+            0 => "el global".to_string(),
+            secs => format!("{} s", secs),
+        };
+        let kinds = plan.allowed_kinds.iter().map(|kind| kind.as_str()).collect::<Vec<_>>().join(", ");
+        format!(
+            "{}\nAlertas activas: {}\nIntervalo mínimo: {}\nTipos: {}",
+            plan.name, max, interval, kinds
+        )
+    }
+
+    async fn handle_quota(&self, bot: Bot, msg: Message) -> ResponseResult<()> {
+        let user = match self.acting_user(&msg.chat, msg.from()).await? {
+            Some(user) => user,
+            None => {
+                bot.send_message(msg.chat.id, "❌ Debes registrarte primero usando /register").await?;
+                return Ok(());
+            }
+        };
+        if user.role == UserRole::Admin {
+            bot.send_message(msg.chat.id, "👑 Eres administrador: no tienes límites").await?;
+            return Ok(());
+        }
+
+        let plan = match quota::user_plan(&self.db, user.id) {
+            Ok(Some(plan)) => plan,
+            Ok(None) => {
+                bot.send_message(msg.chat.id, "Tu cuenta no tiene límites").await?;
+                return Ok(());
+            }
+            Err(e) => {
+                error!("Error al obtener el plan de {}: {}", user.id, e);
+                bot.send_message(msg.chat.id, "❌ No se pudo obtener tu plan").await?;
+                return Ok(());
+            }
+        };
+        let active = self.db.count_active_alerts(user.id).map_err(Self::db_error_to_request_error)?;
+        bot.send_message(msg.chat.id, format!(
+            "📦 Plan {}\n\nEn uso: {} alertas activas",
+            Self::format_plan(&plan), active
+        )).await?;
+        Ok(())
+    }
+
+    async fn handle_trade(&self, bot: Bot, msg: Message, text: String, side: TradeSide) -> ResponseResult<()> {
+        let usage = match side {
+            TradeSide::Buy => "Uso: /buy <símbolo> <cantidad> [precio] [comisión]\nSin precio se usa la cotización actual.",
+            TradeSide::Sell => "Uso: /sell <símbolo> <cantidad> [precio] [comisión]\nSin precio se usa la cotización actual.",
+        };
+
+        let user = match self.get_user_by_chat_id(msg.chat.id.0).await? {
+            Some(user) => user,
+            None => {
+                bot.send_message(msg.chat.id, "❌ Debes registrarte primero usando /register").await?;
+                return Ok(());
+            }
+        };
+
+        let parts: Vec<&str> = text.split_whitespace().collect();
+        let numbers: Option<Vec<f64>> = parts.iter().skip(1).map(|p| p.parse::<f64>().ok()).collect();
+        let (symbol, quantity, price, fee) = match (parts.first(), numbers.as_deref()) {
+            (Some(symbol), Some([quantity])) => (symbol.to_uppercase(), *quantity, None, 0.0),
+            (Some(symbol), Some([quantity, price])) => (symbol.to_uppercase(), *quantity, Some(*price), 0.0),
+            (Some(symbol), Some([quantity, price, fee])) => (symbol.to_uppercase(), *quantity, Some(*price), *fee),
+            _ => {
+                bot.send_message(msg.chat.id, usage).await?;
+                return Ok(());
+            }
+        };
+
+        let price = match price {
+            Some(price) => price,
+            None => {
+                let api = CryptoAPI::new(std::env::var("COINGECKO_API_KEY").unwrap_or_default());
+                match api.get_price(&symbol).await {
+                    Ok(quote) => quote.price,
+                    Err(e) => {
+                        error!("No se pudo cotizar {}: {}", symbol, e);
+                        bot.send_message(msg.chat.id, format!("❌ No pude obtener el precio de {}. Indícalo a mano.\n\n{}", symbol, usage)).await?;
+                        return Ok(());
+                    }
+                }
+            }
+        };
+
+        let transaction = Transaction {
+            id: None,
+            user_id: user.id,
+            symbol: symbol.clone(),
+            side,
+            quantity,
+            price,
+            fee,
+            executed_at: chrono::Utc::now().timestamp(),
+            note: None,
+        };
+        match Portfolio::new(&self.db).record(&transaction) {
+            Ok((id, holding)) => {
+                let label = match side {
+                    TradeSide::Buy => "Compra",
+                    TradeSide::Sell => "Venta",
+                };
+                let mut reply = format!(
+                    "✅ {} registrada (#{})\n\n{} {} a ${:.2}\n\nPosición: {} {}",
+                    label, id, quantity, symbol, price, holding.quantity, symbol
+                );
+                if let Some(average) = holding.average_cost() {
+                    reply.push_str(&format!("\nCosto promedio: ${:.2}", average));
+                }
+                if side == TradeSide::Sell {
+                    reply.push_str(&format!("\nP&L realizado en {}: {}", symbol, Self::format_pnl(holding.realized_pnl)));
+                }
+                bot.send_message(msg.chat.id, reply).await?;
+            }
+            Err(PortfolioError::DatabaseError(e)) => return Err(Self::db_error_to_request_error(e)),
+            Err(e) => {
+                bot.send_message(msg.chat.id, format!("❌ {}", e)).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn handle_portfolio(&self, bot: Bot, msg: Message) -> ResponseResult<()> {
+        let user = match self.get_user_by_chat_id(msg.chat.id.0).await? {
+            Some(user) => user,
+            None => {
+                bot.send_message(msg.chat.id, "❌ Debes registrarte primero usando /register").await?;
+                return Ok(());
+            }
+        };
+
+        let holdings = self.db.get_holdings(user.id).map_err(Self::db_error_to_request_error)?;
+        if holdings.is_empty() {
+            bot.send_message(
+                msg.chat.id,
+                "Tu portafolio está vacío.\nRegistra una compra con /buy <símbolo> <cantidad> [precio]"
+            ).await?;
+            return Ok(());
+        }
+
+        let api = CryptoAPI::new(std::env::var("COINGECKO_API_KEY").unwrap_or_default());
+        let valuation = portfolio::valuation(&api, holdings).await;
+
+        let mut reply = String::from("💼 Portafolio\n");
+        for position in &valuation.positions {
+            reply.push_str(&format!("\n{}: {}", position.symbol, position.quantity));
+            if let Some(average) = position.average_cost {
+                reply.push_str(&format!(" · costo prom. ${:.2}", average));
+            }
+            match (position.price, position.market_value, position.unrealized_pnl) {
+                (Some(price), Some(value), Some(unrealized)) => {
+                    let percent = if position.cost_basis > 0.0 { unrealized / position.cost_basis * 100.0 } else { 0.0 };
+                    reply.push_str(&format!(
+                        "\n  Precio ${:.2} · valor ${:.2} · no realizado {} ({:+.1}%)",
+                        price, value, Self::format_pnl(unrealized), percent
+                    ));
+                }
+                _ if position.quantity > 0.0 => reply.push_str("\n  Precio no disponible"),
+                _ => {}
+            }
+            if position.realized_pnl != 0.0 {
+                reply.push_str(&format!("\n  Realizado {}", Self::format_pnl(position.realized_pnl)));
+            }
+            reply.push('\n');
+        }
+        reply.push_str(&format!(
+            "\nValor total: ${:.2}\nCosto: ${:.2}\nNo realizado: {}\nRealizado: {}",
+            valuation.total_value,
+            valuation.total_cost,
+            Self::format_pnl(valuation.unrealized_pnl),
+            Self::format_pnl(valuation.realized_pnl),
+        ));
+        bot.send_message(msg.chat.id, reply).await?;
+        Ok(())
+    }
+
+    async fn handle_portfolio_alert(&self, bot: Bot, msg: Message, text: String) -> ResponseResult<()> {
+        const USAGE: &str = "Uso:\n\
+            /portfolioalert value <above|below> <usd> - valor total del portafolio\n\
+            /portfolioalert drawdown <%> - caída desde el máximo\n\
+            /portfolioalert drift <símbolo> <peso %> <±puntos> - desvío de la asignación objetivo";
+
+        let user = match self.get_user_by_chat_id(msg.chat.id.0).await? {
+            Some(user) => user,
+            None => {
+                bot.send_message(msg.chat.id, "❌ Debes registrarte primero usando /register").await?;
+                return Ok(());
+            }
+        };
+
+        let parts: Vec<&str> = text.split_whitespace().collect();
+        let number = |index: usize| parts.get(index).and_then(|p| p.trim_end_matches('%').parse::<f64>().ok());
+        let alert_type = match parts.first().map(|p| p.to_lowercase()).as_deref() {
+            Some("value") if parts.len() == 3 => {
+                let condition = match parts[1].to_lowercase().as_str() {
+                    "above" => Some(AlertCondition::Above),
+                    "below" => Some(AlertCondition::Below),
+                    _ => None,
+                };
+                condition.zip(number(2)).map(|(condition, threshold)| AlertType::PortfolioValue { threshold, condition })
+            }
+            Some("drawdown") if parts.len() == 2 => {
+                number(1).map(|max_drawdown| AlertType::PortfolioDrawdown { max_drawdown, peak: 0.0 })
+            }
+            Some("drift") if parts.len() == 4 => {
+                number(2).zip(number(3)).map(|(target_weight, tolerance)| AlertType::AllocationDrift {
+                    symbol: parts[1].to_uppercase(),
+                    target_weight,
+                    tolerance: tolerance.abs(),
+                })
+            }
+            _ => None,
+        };
+        let mut alert_type = match alert_type {
+            Some(alert_type) => alert_type,
+            None => {
+                bot.send_message(msg.chat.id, USAGE).await?;
+                return Ok(());
+            }
+        };
+        if let Err(e) = portfolio::validate_alert(&alert_type) {
+            bot.send_message(msg.chat.id, format!("❌ {}\n\n{}", e, USAGE)).await?;
+            return Ok(());
+        }
+        if let Some(refusal) = Self::quota_refusal(quota::check_new_alert(&self.db, user.id, AlertKind::Portfolio))? {
+            bot.send_message(msg.chat.id, refusal).await?;
+            return Ok(());
+        }
+
+        let api = CryptoAPI::new(std::env::var("COINGECKO_API_KEY").unwrap_or_default());
+        match portfolio::seed_peak(&api, &self.db, user.id, &mut alert_type).await {
+            Ok(()) => {}
+            Err(PortfolioError::DatabaseError(e)) => return Err(Self::db_error_to_request_error(e)),
+            Err(e) => error!("No se pudo valorar el portafolio de {}: {}", user.id, e),
+        }
+
+        let symbol = match &alert_type {
+            AlertType::AllocationDrift { symbol, .. } => symbol.clone(),
+            _ => PORTFOLIO_SYMBOL.to_string(),
+        };
+        let alert = PriceAlert {
+            id: None,
+            user_id: user.id,
+            symbol,
+            alert_type,
+            created_at: chrono::Utc::now().timestamp(),
+            triggered_at: None,
+            is_active: true,
+        };
+        match self.db.save_alert(&alert) {
+            Ok(alert_id) => {
+                let created = PriceAlert { id: Some(alert_id), ..alert };
+                bot.send_message(
+                    msg.chat.id,
+                    format!("✅ Alerta creada exitosamente!\n\n{}", Self::format_alert_details(&created))
+                ).await?;
+            }
+            Err(e) => {
+                error!("Error al crear alerta de portafolio: {}", e);
+                bot.send_message(msg.chat.id, "❌ Error al crear la alerta").await?;
+            }
+        }
+        Ok(())
+    }
+
+    fn format_pnl(value: f64) -> String {
+        if value < 0.0 {
+            format!("-${:.2}", -value)
+        } else {
+            format!("+${:.2}", value)
+        }
+    }
+
+    async fn handle_unsubscribe(&self, bot: Bot, msg: Message, text: String) -> ResponseResult<()> {
+        let alert_id = match text.trim().parse::<i64>() {
+            Ok(alert_id) => alert_id,
+            Err(_) => {
+                bot.send_message(msg.chat.id, "Uso: /unsubscribe <id de alerta>").await?;
+                return Ok(());
+            }
+        };
+
+        match self.db.remove_alert_target(alert_id, msg.chat.id.0) {
+            Ok(true) => {
+                self.audit(&msg.chat, msg.from(), "alert.unsubscribe", Some(alert_id.to_string())).await?;
+                bot.send_message(msg.chat.id, format!("🔕 Ya no recibirás la alerta #{}", alert_id)).await?;
             }
-        } 
+            Ok(false) => {
+                bot.send_message(msg.chat.id, format!("No estás suscrito a la alerta #{}", alert_id)).await?;
+            }
+            Err(e) => {
+                error!("Error al cancelar la suscripción a la alerta {}: {}", alert_id, e);
+                bot.send_message(msg.chat.id, "❌ Error al cancelar la suscripción").await?;
+            }
+        }
+
         Ok(())
     }
 
-    async fn handle_register(&self, bot: Bot, msg: Message, username: String, password: String) -> ResponseResult<()> {
-        let chat_id = msg.chat.id.0;
-        info!("Intento de registro: username={}, chat_id={}", username, chat_id);
-        
-        let auth = Auth::new(self.db.as_ref());
-        
-        match auth.register_user(&username, &password) {
-            Ok(user) => {
-                if let Err(e) = self.db.update_user_telegram_chat_id(user.id, chat_id) {
-                    let error_msg = format!("Error al actualizar chat_id: {}", e);
-                    error!("{}", error_msg);
-                    bot.send_message(msg.chat.id, "Error al vincular cuenta con Telegram").await?;
-                    return Ok(());
-                }
+    async fn handle_unlink(&self, bot: Bot, msg: Message) -> ResponseResult<()> {
+        let user = match self.get_user_by_chat_id(msg.chat.id.0).await? {
+            Some(user) => user,
+            None => {
+                bot.send_message(msg.chat.id, "Este chat no está vinculado a ninguna cuenta").await?;
+                return Ok(());
+            }
+        };
 
-                match self.db.create_api_key(user.id) {
-                    Ok(api_key) => {
-                        bot.send_message(msg.chat.id, format!(
-                            "✅ Registro exitoso!\n\n\
-                             Tu API key es: {}\n\n\
-                             Guárdala en un lugar seguro.\n\
-                             Usa /help para ver los comandos disponibles.",
-                            api_key.key
-                        )).await?;
-                    }
-                    Err(e) => {
-                        let error_msg = format!("Error al generar API key: {}", e);
-                        error!("{}", error_msg);
-                        bot.send_message(msg.chat.id, error_msg).await?;
-                    }
-                }
+        match self.db.unlink_telegram_chat(user.id) {
+            Ok(_) => {
+                bot.send_message(
+                    msg.chat.id,
+                    "✅ Chat desvinculado. Ya no recibirás alertas aquí.\n\
+                     Puedes volver a vincularlo con un nuevo código."
+                ).await?;
             }
             Err(e) => {
-                let error_msg = format!(
-                    "❌ Error al registrar usuario: {}\n\
-                     El nombre de usuario ya existe o es inválido.\n\
-                     Intenta con otro nombre de usuario.",
-                    e
-                );
-                error!("{}", error_msg);
-                bot.send_message(msg.chat.id, error_msg).await?;
+                error!("Error al desvincular usuario {}: {}", user.id, e);
+                bot.send_message(msg.chat.id, "❌ Error al desvincular la cuenta").await?;
             }
         }
-        
+
         Ok(())
     }
 
@@ -549,6 +2410,9 @@ impl TelegramBot {
     }
 
     async fn handle_depeg(&self, bot: Bot, msg: Message) -> ResponseResult<()> {
+        if !self.within_quota(&bot, &msg, AlertKind::Depeg).await? {
+            return Ok(());
+        }
         let state = UserState::CreatingDepegAlert {
             step: DepegAlertStep::SelectSymbol,
             symbol: None,
@@ -563,6 +2427,9 @@ impl TelegramBot {
     }
 
     async fn handle_pair_depeg(&self, bot: Bot, msg: Message) -> ResponseResult<()> {
+        if !self.within_quota(&bot, &msg, AlertKind::PairDepeg).await? {
+            return Ok(());
+        }
         let state = UserState::CreatingPairAlert {
             step: PairAlertStep::SelectToken1,
             token1: None,
@@ -590,7 +2457,9 @@ impl TelegramBot {
                     let markup = InlineKeyboardMarkup::new(
                         pairs.iter().map(|row| {
                             row.iter().map(|&pair| {
-                                InlineKeyboardButton::callback(pair, format!("pair_{}", pair.replace("/", "_")))
+                                let (token1, token2) = pair.split_once('/').unwrap_or((pair, ""));
+                                let action = CallbackAction::Pair(token1.to_string(), token2.to_string());
+                                InlineKeyboardButton::callback(pair, self.callback_data(msg.chat.id, action))
                             }).collect::<Vec<_>>()
                         })
                     );
@@ -611,10 +2480,10 @@ impl TelegramBot {
                 },
                 PairAlertStep::EnterDifferential => {
                     let markup = InlineKeyboardMarkup::new([[
-                        InlineKeyboardButton::callback("0.5%", "pairdiff_0.5"),
-                        InlineKeyboardButton::callback("1%", "pairdiff_1"),
-                        InlineKeyboardButton::callback("2%", "pairdiff_2"),
-                        InlineKeyboardButton::callback("5%", "pairdiff_5"),
+                        InlineKeyboardButton::callback("0.5%", self.callback_data(msg.chat.id, CallbackAction::PairDifferential(0.5))),
+                        InlineKeyboardButton::callback("1%", self.callback_data(msg.chat.id, CallbackAction::PairDifferential(1.0))),
+                        InlineKeyboardButton::callback("2%", self.callback_data(msg.chat.id, CallbackAction::PairDifferential(2.0))),
+                        InlineKeyboardButton::callback("5%", self.callback_data(msg.chat.id, CallbackAction::PairDifferential(5.0))),
                     ]]);
 
                     bot.send_message(
@@ -630,163 +2499,495 @@ impl TelegramBot {
         Ok(())
     }
 
-    // Método auxiliar para obtener usuario por chat_id
+    // Método auxiliar para obtener usuario por chat_id. Las cuentas
+    // desactivadas se tratan como si no estuvieran registradas.
     async fn get_user_by_chat_id(&self, chat_id: i64) -> ResponseResult<Option<User>> {
         self.db.get_user_by_telegram_id(chat_id)
+            .map(|user| user.filter(|user| user.is_active))
             .map_err(|e| RequestError::Api(ApiError::Unknown(e.to_string())))
     }
 
-    async fn handle_list_alerts(&self, bot: Bot, msg: Message) -> ResponseResult<()> {
-        let chat_id = msg.chat.id.0;
-        info!("Listando alertas para chat_id={}", chat_id);
+    fn format_alert_details(alert: &PriceAlert) -> String {
+        let status = match alert.status() {
+            AlertStatus::Active => "🟢 Activa",
+            AlertStatus::Paused => "⏸ Pausada",
+            AlertStatus::Triggered => "🔴 Disparada",
+        };
 
-        // Obtener usuario por chat_id
-        let user = match self.get_user_by_chat_id(chat_id).await? {
+        match &alert.alert_type {
+            AlertType::Price { target_price, condition } => {
+                format!(
+                    "ID: {}\n\
+                     Tipo: Precio\n\
+                     Símbolo: {}\n\
+                     Precio objetivo: ${:.2}\n\
+                     Condición: {:?}\n\
+                     Estado: {}\n",
+                    alert.id.unwrap_or(-1),
+                    alert.symbol,
+                    target_price,
+                    condition,
+                    status
+                )
+            },
+            AlertType::Depeg { target_price, differential, exchanges } => {
+                format!(
+                    "ID: {}\n\
+                     Tipo: Depeg\n\
+                     Símbolo: {}\n\
+                     Precio objetivo: ${:.2}\n\
+                     Diferencial: {:.2}%\n\
+                     Exchanges: {}\n\
+                     Estado: {}\n",
+                    alert.id.unwrap_or(-1),
+                    alert.symbol,
+                    target_price,
+                    differential,
+                    exchanges.join(", "),
+                    status
+                )
+            },
+            AlertType::PairDepeg { token1, token2, expected_ratio, differential } => {
+                format!(
+                    "ID: {}\n\
+                     Tipo: Depeg de Par\n\
+                     Par: {}/{}\n\
+                     Ratio esperado: {:.4}\n\
+                     Diferencial: {:.2}%\n\
+                     Estado: {}\n",
+                    alert.id.unwrap_or(-1),
+                    token1, token2,
+                    expected_ratio,
+                    differential,
+                    status
+                )
+            },
+            AlertType::PortfolioValue { threshold, condition } => {
+                format!(
+                    "ID: {}\n\
+                     Tipo: Valor del portafolio\n\
+                     Valor objetivo: ${:.2}\n\
+                     Condición: {:?}\n\
+                     Estado: {}\n",
+                    alert.id.unwrap_or(-1),
+                    threshold,
+                    condition,
+                    status
+                )
+            },
+            AlertType::PortfolioDrawdown { max_drawdown, peak } => {
+                format!(
+                    "ID: {}\n\
+                     Tipo: Caída del portafolio\n\
+                     Caída máxima: {:.2}%\n\
+                     Máximo registrado: ${:.2}\n\
+                     Estado: {}\n",
+                    alert.id.unwrap_or(-1),
+                    max_drawdown,
+                    peak,
+                    status
+                )
+            },
+            AlertType::AllocationDrift { symbol, target_weight, tolerance } => {
+                format!(
+                    "ID: {}\n\
+                     Tipo: Desvío de asignación\n\
+                     Símbolo: {}\n\
+                     Peso objetivo: {:.2}%\n\
+                     Tolerancia: ±{:.2} puntos\n\
+                     Estado: {}\n",
+                    alert.id.unwrap_or(-1),
+                    symbol,
+                    target_weight,
+                    tolerance,
+                    status
+                )
+            }
+        }
+    }
+
+    /// Línea corta para listas y botones.
+    fn alert_summary(alert: &PriceAlert) -> String {
+        let status = match alert.status() {
+            AlertStatus::Active => "🟢",
+            AlertStatus::Paused => "⏸",
+            AlertStatus::Triggered => "🔴",
+        };
+        let description = match &alert.alert_type {
+            AlertType::Price { target_price, condition } => {
+                format!("{} ${} {:?}", alert.symbol, target_price, condition)
+            },
+            AlertType::Depeg { target_price, differential, .. } => {
+                format!("{} ${} (±{}%)", alert.symbol, target_price, differential)
+            },
+            AlertType::PairDepeg { token1, token2, expected_ratio, differential } => {
+                format!("{}/{} ratio {} (±{}%)", token1, token2, expected_ratio, differential)
+            },
+            AlertType::PortfolioValue { threshold, condition } => {
+                format!("Portafolio ${} {:?}", threshold, condition)
+            },
+            AlertType::PortfolioDrawdown { max_drawdown, .. } => {
+                format!("Portafolio caída >{}%", max_drawdown)
+            },
+            AlertType::AllocationDrift { symbol, target_weight, tolerance } => {
+                format!("{} peso {}% (±{})", symbol, target_weight, tolerance)
+            }
+        };
+        format!("{} #{} {}", status, alert.id.unwrap_or(-1), description)
+    }
+
+    /// Alertas que se pueden ver desde el chat: en privado las propias y las
+    /// suscripciones, en grupos y canales las del chat. `None` si el usuario
+    /// no está registrado.
+    async fn visible_alerts(&self, chat: &Chat, from: Option<&TelegramUser>) -> ResponseResult<Option<Vec<PriceAlert>>> {
+        if !chat.is_private() {
+            return self.db.get_chat_alerts(chat.id.0)
+                .map(Some)
+                .map_err(Self::db_error_to_request_error);
+        }
+
+        let user = match self.acting_user(chat, from).await? {
             Some(user) => user,
-            None => {
-                bot.send_message(msg.chat.id, "❌ Debes registrarte primero usando /register").await?;
+            None => return Ok(None),
+        };
+        let mut alerts = self.db.get_user_alerts(user.id).map_err(Self::db_error_to_request_error)?;
+        for alert in self.db.get_chat_alerts(chat.id.0).map_err(Self::db_error_to_request_error)? {
+            if !alerts.iter().any(|own| own.id == alert.id) {
+                alerts.push(alert);
+            }
+        }
+        Ok(Some(alerts))
+    }
+
+    fn alerts_page(&self, chat_id: ChatId, alerts: &[PriceAlert], page: u32, filter: &AlertFilter) -> (String, InlineKeyboardMarkup) {
+        let filtered: Vec<&PriceAlert> = alerts.iter().filter(|alert| filter.matches(alert)).collect();
+        let pages = filtered.len().div_ceil(ALERTS_PER_PAGE).max(1) as u32;
+        let page = page.min(pages - 1);
+
+        let kind_label = match filter.kind {
+            None => "Todos",
+            Some(AlertKind::Price) => "Precio",
+            Some(AlertKind::Depeg) => "Depeg",
+            Some(AlertKind::PairDepeg) => "Par",
+            Some(AlertKind::Portfolio) => "Portafolio",
+        };
+        let status_label = match filter.status {
+            None => "Todas",
+            Some(AlertStatus::Active) => "Activas",
+            Some(AlertStatus::Paused) => "Pausadas",
+            Some(AlertStatus::Triggered) => "Disparadas",
+        };
+
+        let mut text = format!(
+            "📊 Alertas ({} en total) - página {}/{}\nTipo: {} · Estado: {}",
+            filtered.len(), page + 1, pages, kind_label, status_label
+        );
+        if let Some(symbol) = &filter.symbol {
+            text.push_str(&format!(" · Símbolo: {}", symbol));
+        }
+        text.push_str("\n\n");
+
+        let start = page as usize * ALERTS_PER_PAGE;
+        let on_page = &filtered[start.min(filtered.len())..(start + ALERTS_PER_PAGE).min(filtered.len())];
+        if on_page.is_empty() {
+            text.push_str("No hay alertas que coincidan con el filtro.");
+        }
+
+        let list = |page: u32, filter: AlertFilter| {
+            self.callback_data(chat_id, CallbackAction::ListAlerts { page, filter })
+        };
+
+        let mut keyboard: Vec<Vec<InlineKeyboardButton>> = on_page.iter()
+            .filter_map(|alert| alert.id.map(|id| (id, alert)))
+            .map(|(id, alert)| vec![InlineKeyboardButton::callback(
+                Self::alert_summary(alert),
+                self.callback_data(chat_id, CallbackAction::ShowAlert(id)),
+            )])
+            .collect();
+
+        // Los filtros rotan entre sus valores y vuelven a la primera página
+        let next_kind = match filter.kind {
+            None => Some(AlertKind::Price),
+            Some(AlertKind::Price) => Some(AlertKind::Depeg),
+            Some(AlertKind::Depeg) => Some(AlertKind::PairDepeg),
+            Some(AlertKind::PairDepeg) => Some(AlertKind::Portfolio),
+            Some(AlertKind::Portfolio) => None,
+        };
+        let next_status = match filter.status {
+            None => Some(AlertStatus::Active),
+            Some(AlertStatus::Active) => Some(AlertStatus::Paused),
+            Some(AlertStatus::Paused) => Some(AlertStatus::Triggered),
+            Some(AlertStatus::Triggered) => None,
+        };
+        keyboard.push(vec![
+            InlineKeyboardButton::callback(
+                format!("Tipo: {}", kind_label),
+                list(0, AlertFilter { kind: next_kind, ..filter.clone() }),
+            ),
+            InlineKeyboardButton::callback(
+                format!("Estado: {}", status_label),
+                list(0, AlertFilter { status: next_status, ..filter.clone() }),
+            ),
+        ]);
+
+        let mut nav = Vec::new();
+        if page > 0 {
+            nav.push(InlineKeyboardButton::callback("⬅️ Anterior", list(page - 1, filter.clone())));
+        }
+        if page + 1 < pages {
+            nav.push(InlineKeyboardButton::callback("Siguiente ➡️", list(page + 1, filter.clone())));
+        }
+        if !nav.is_empty() {
+            keyboard.push(nav);
+        }
+
+        (text, InlineKeyboardMarkup::new(keyboard))
+    }
+
+    /// Tras actuar sobre una notificación deja solo el botón del gráfico, para
+    /// que no se repita la acción, y confirma lo hecho.
+    async fn finish_notification(&self, bot: &Bot, message: &Message, alert_id: i64, note: String) -> ResponseResult<()> {
+        let markup = InlineKeyboardMarkup::new([[InlineKeyboardButton::callback(
+            "📈 Ver gráfico",
+            self.callback_data(message.chat.id, CallbackAction::AlertChart(alert_id)),
+        )]]);
+        match bot.edit_message_reply_markup(message.chat.id, message.id).reply_markup(markup).await {
+            Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => {}
+            Err(e) => return Err(e),
+        }
+        bot.send_message(message.chat.id, note).reply_to_message_id(message.id).await?;
+        Ok(())
+    }
+
+    /// Reemplaza el contenido de un mensaje con botones. Telegram devuelve
+    /// error si nada cambió, lo que aquí no es un problema.
+    async fn edit_menu(&self, bot: &Bot, message: &Message, text: String, markup: InlineKeyboardMarkup) -> ResponseResult<()> {
+        match bot.edit_message_text(message.chat.id, message.id, text).reply_markup(markup).await {
+            Ok(_) | Err(RequestError::Api(ApiError::MessageNotModified)) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Aplica el nuevo umbral enviado tras pulsar "Editar" en el menú de una alerta.
+    async fn handle_alert_edit(&self, bot: &Bot, msg: &Message, alert_id: i64, text: &str) -> ResponseResult<()> {
+        let value = match text.trim().trim_end_matches('%').parse::<f64>() {
+            Ok(value) if value > 0.0 => value,
+            _ => {
+                bot.send_message(msg.chat.id, "❌ Valor inválido. Envía un número positivo o usa /cancel").await?;
                 return Ok(());
             }
         };
 
-        // Obtener alertas del usuario
-        match self.db.get_user_alerts(user.id) {
-            Ok(alerts) => {
-                if alerts.is_empty() {
-                    bot.send_message(msg.chat.id, "No tienes alertas configuradas").await?;
-                    return Ok(());
-                }
+        // Los permisos pueden haber cambiado desde que se pulsó el botón
+        let from = match msg.from() {
+            Some(from) => from,
+            None => return Ok(()),
+        };
+        let alert = match self.db.get_alert(alert_id).map_err(Self::db_error_to_request_error)? {
+            Some(alert) if self.can_manage_alert(&msg.chat, from, alert_id).await? => alert,
+            _ => {
+                self.db.clear_user_state(msg.chat.id.0).map_err(Self::db_error_to_request_error)?;
+                bot.send_message(msg.chat.id, "❌ La alerta ya no existe o no puedes editarla").await?;
+                return Ok(());
+            }
+        };
 
-                let mut response = String::from("📊 Tus alertas:\n\n");
-                for alert in alerts {
-                    let status = if alert.is_active { "🟢 Activa" } else { "🔴 Disparada" };
-                    
-                    let alert_details = match &alert.alert_type {
-                        AlertType::Price { target_price, condition } => {
-                            format!(
-                                "ID: {}\n\
-                                 Tipo: Precio\n\
-                                 Símbolo: {}\n\
-                                 Precio objetivo: ${:.2}\n\
-                                 Condición: {:?}\n\
-                                 Estado: {}\n",
-                                alert.id.unwrap_or(-1),
-                                alert.symbol,
-                                target_price,
-                                condition,
-                                status
-                            )
-                        },
-                        AlertType::Depeg { target_price, differential, exchanges } => {
-                            format!(
-                                "ID: {}\n\
-                                 Tipo: Depeg\n\
-                                 Símbolo: {}\n\
-                                 Precio objetivo: ${:.2}\n\
-                                 Diferencial: {:.2}%\n\
-                                 Exchanges: {}\n\
-                                 Estado: {}\n",
-                                alert.id.unwrap_or(-1),
-                                alert.symbol,
-                                target_price,
-                                differential,
-                                exchanges.join(", "),
-                                status
-                            )
-                        },
-                        AlertType::PairDepeg { token1, token2, expected_ratio, differential } => {
-                            format!(
-                                "ID: {}\n\
-                                 Tipo: Depeg de Par\n\
-                                 Par: {}/{}\n\
-                                 Ratio esperado: {:.4}\n\
-                                 Diferencial: {:.2}%\n\
-                                 Estado: {}\n",
-                                alert.id.unwrap_or(-1),
-                                token1, token2,
-                                expected_ratio,
-                                differential,
-                                status
-                            )
-                        }
-                    };
-                    
-                    response.push_str(&alert_details);
-                    response.push_str("\n");
-                }
-                bot.send_message(msg.chat.id, response).await?;
+        let alert_type = match alert.alert_type {
+            AlertType::Price { condition, .. } => AlertType::Price { target_price: value, condition },
+            AlertType::Depeg { target_price, exchanges, .. } => AlertType::Depeg { target_price, differential: value, exchanges },
+            AlertType::PairDepeg { token1, token2, differential, .. } => AlertType::PairDepeg { token1, token2, expected_ratio: value, differential },
+            AlertType::PortfolioValue { condition, .. } => AlertType::PortfolioValue { threshold: value, condition },
+            AlertType::PortfolioDrawdown { peak, .. } => AlertType::PortfolioDrawdown { max_drawdown: value, peak },
+            AlertType::AllocationDrift { symbol, target_weight, .. } => AlertType::AllocationDrift { symbol, target_weight, tolerance: value },
+        };
+
+        match self.db.update_alert_type(alert_id, &alert_type) {
+            Ok(_) => {
+                self.db.clear_user_state(msg.chat.id.0).map_err(Self::db_error_to_request_error)?;
+                self.audit(&msg.chat, Some(from), "alert.edit", Some(alert_id.to_string())).await?;
+                let updated = PriceAlert { alert_type, ..alert };
+                bot.send_message(
+                    msg.chat.id,
+                    format!("✅ Alerta actualizada\n\n{}", Self::format_alert_details(&updated))
+                ).await?;
             }
             Err(e) => {
-                error!("Error al obtener alertas: {}", e);
-                bot.send_message(msg.chat.id, "❌ Error al obtener las alertas").await?;
+                error!("Error al editar la alerta {}: {}", alert_id, e);
+                bot.send_message(msg.chat.id, "❌ Error al editar la alerta").await?;
             }
         }
-
         Ok(())
     }
 
-    async fn handle_delete_alert(&self, bot: Bot, msg: Message) -> ResponseResult<()> {
-        let chat_id = msg.chat.id.0;
-        
-        // Obtener usuario
-        let user = match self.get_user_by_chat_id(chat_id).await? {
-            Some(user) => user,
+    async fn handle_list_alerts(&self, bot: Bot, msg: Message, text: String) -> ResponseResult<()> {
+        info!("Listando alertas para chat_id={}", msg.chat.id);
+
+        let alerts = match self.visible_alerts(&msg.chat, msg.from()).await? {
+            Some(alerts) => alerts,
             None => {
                 bot.send_message(msg.chat.id, "❌ Debes registrarte primero usando /register").await?;
                 return Ok(());
             }
         };
+        if alerts.is_empty() {
+            let reply = if msg.chat.is_private() {
+                "No tienes alertas configuradas"
+            } else {
+                "Este chat no tiene alertas. Un administrador puede crearlas con /alert, /depeg o /pairdepeg"
+            };
+            bot.send_message(msg.chat.id, reply).await?;
+            return Ok(());
+        }
 
-        // Obtener alertas activas
-        match self.db.get_user_alerts(user.id) {
-            Ok(alerts) if !alerts.is_empty() => {
-                let keyboard: Vec<Vec<InlineKeyboardButton>> = alerts.iter().map(|alert| {
-                    let description = match &alert.alert_type {
-                        AlertType::Price { target_price, condition } => {
-                            format!("ID {}: {} ${} {:?}", alert.id.unwrap_or(-1), alert.symbol, target_price, condition)
-                        },
-                        AlertType::Depeg { target_price, differential, .. } => {
-                            format!("ID {}: {} ${} (±{}%)", alert.id.unwrap_or(-1), alert.symbol, target_price, differential)
-                        },
-                        AlertType::PairDepeg { token1, token2, expected_ratio, differential } => {
-                            format!("ID {}: {}/{} ratio {} (±{}%)", 
-                                alert.id.unwrap_or(-1), token1, token2, expected_ratio, differential)
-                        }
-                    };
-                    vec![InlineKeyboardButton::callback(description, format!("delete_{}", alert.id.unwrap_or(-1)))]
-                }).collect();
+        // El filtro viaja en el callback_data (máx. 64 bytes), así que se recorta
+        let symbol = text.split_whitespace().next()
+            .map(|symbol| symbol.chars()
+                .filter(char::is_ascii_alphanumeric)
+                .take(MAX_FILTER_SYMBOL)
+                .collect::<String>()
+                .to_uppercase())
+            .filter(|symbol| !symbol.is_empty());
+        let filter = AlertFilter { symbol, ..AlertFilter::default() };
+        let (text, markup) = self.alerts_page(msg.chat.id, &alerts, 0, &filter);
+        bot.send_message(msg.chat.id, text).reply_markup(markup).await?;
+        Ok(())
+    }
 
-                let markup = InlineKeyboardMarkup::new(keyboard);
-                bot.send_message(msg.chat.id, "Selecciona la alerta que deseas eliminar:")
-                    .reply_markup(markup)
-                    .await?;
-            },
-            Ok(_) => {
-                bot.send_message(msg.chat.id, "No tienes alertas activas.").await?;
-            },
-            Err(e) => {
-                error!("Error al obtener alertas: {}", e);
-                bot.send_message(msg.chat.id, "❌ Error al obtener las alertas").await?;
+    /// Detalle de una alerta con los botones de las acciones permitidas al usuario.
+    async fn show_alert_menu(&self, bot: &Bot, message: &Message, from: &TelegramUser, alert_id: i64) -> ResponseResult<()> {
+        let visible = self.visible_alerts(&message.chat, Some(from)).await?.unwrap_or_default();
+        let alert = match visible.into_iter().find(|alert| alert.id == Some(alert_id)) {
+            Some(alert) => alert,
+            None => {
+                let markup = InlineKeyboardMarkup::new([[InlineKeyboardButton::callback(
+                    "⬅️ Volver",
+                    self.callback_data(message.chat.id, CallbackAction::ListAlerts { page: 0, filter: AlertFilter::default() }),
+                )]]);
+                return self.edit_menu(bot, message, format!("La alerta #{} ya no existe", alert_id), markup).await;
             }
+        };
+
+        let can_manage = self.is_chat_admin(bot, &message.chat, Some(from)).await?
+            && self.can_manage_alert(&message.chat, from, alert_id).await?;
+        let button = |label: &str, action: CallbackAction| {
+            InlineKeyboardButton::callback(label.to_string(), self.callback_data(message.chat.id, action))
+        };
+
+        let mut keyboard = Vec::new();
+        if can_manage {
+            keyboard.push(vec![
+                match alert.status() {
+                    AlertStatus::Active => button("⏸ Pausar", CallbackAction::PauseAlert(alert_id)),
+                    _ => button("▶️ Reanudar", CallbackAction::ResumeAlert(alert_id)),
+                },
+                button("✏️ Editar", CallbackAction::EditAlert(alert_id)),
+            ]);
+            keyboard.push(vec![
+                button("📄 Clonar", CallbackAction::CloneAlert(alert_id)),
+                button("🗑 Eliminar", CallbackAction::DeleteAlert(alert_id)),
+            ]);
         }
-        Ok(())
+        if !message.chat.is_private() {
+            keyboard.push(vec![button("🔔 Suscribirme", CallbackAction::Subscribe(alert_id))]);
+        }
+        keyboard.push(vec![button(
+            "⬅️ Volver",
+            CallbackAction::ListAlerts { page: 0, filter: AlertFilter::default() },
+        )]);
+
+        self.edit_menu(bot, message, Self::format_alert_details(&alert), InlineKeyboardMarkup::new(keyboard)).await
     }
 
-    async fn handle_symbols(&self, bot: Bot, msg: Message) -> ResponseResult<()> {
-        info!("Mostrando símbolos soportados");
-        
-        let symbols: Vec<String> = CONFIG.cryptocurrencies.iter()
+    fn symbols_page(&self, chat_id: ChatId, page: u32) -> (String, InlineKeyboardMarkup) {
+        let mut symbols: Vec<String> = CONFIG.cryptocurrencies.iter()
             .map(|(symbol, info)| format!("{} ({})", symbol, info.name))
             .collect();
+        symbols.sort();
 
-        let response = format!(
-            "🪙 Símbolos soportados:\n\n{}\n\n\
-             Uso: /alert <symbol> <price> <above|below>\n\
-             Ejemplo: /alert BTC 42000 above",
-            symbols.join("\n")
+        let pages = symbols.len().div_ceil(SYMBOLS_PER_PAGE).max(1) as u32;
+        let page = page.min(pages - 1);
+        let start = page as usize * SYMBOLS_PER_PAGE;
+        let on_page = &symbols[start.min(symbols.len())..(start + SYMBOLS_PER_PAGE).min(symbols.len())];
+
+        let text = format!(
+            "🪙 Símbolos soportados (página {}/{}):\n\n{}\n\n\
+             Crea una alerta con /alert o consulta un gráfico con /chart <symbol>",
+            page + 1, pages, on_page.join("\n")
         );
 
-        bot.send_message(msg.chat.id, response).await?;
+        let mut nav = Vec::new();
+        if page > 0 {
+            nav.push(InlineKeyboardButton::callback("⬅️ Anterior", self.callback_data(chat_id, CallbackAction::Symbols(page - 1))));
+        }
+        if page + 1 < pages {
+            nav.push(InlineKeyboardButton::callback("Siguiente ➡️", self.callback_data(chat_id, CallbackAction::Symbols(page + 1))));
+        }
+        (text, InlineKeyboardMarkup::new([nav]))
+    }
+
+    async fn handle_symbols(&self, bot: Bot, msg: Message) -> ResponseResult<()> {
+        info!("Mostrando símbolos soportados");
+
+        let (text, markup) = self.symbols_page(msg.chat.id, 0);
+        bot.send_message(msg.chat.id, text).reply_markup(markup).await?;
+        Ok(())
+    }
+
+    async fn handle_chart(&self, bot: Bot, msg: Message, text: String) -> ResponseResult<()> {
+        let parts: Vec<&str> = text.split_whitespace().collect();
+        if parts.is_empty() || parts.len() > 3 {
+            bot.send_message(
+                msg.chat.id,
+                format!(
+                    "Uso: /chart <symbol> [{}] [line|candles]\n\
+                     Ejemplo: /chart BTC 7d candles",
+                    ChartRange::choices("|")
+                )
+            ).await?;
+            return Ok(());
+        }
+
+        let symbol = parts[0].to_uppercase();
+        let range = match parts.get(1) {
+            Some(r) => match ChartRange::parse(r) {
+                Some(range) => range,
+                None => {
+                    bot.send_message(msg.chat.id, format!("❌ Rango inválido. Usa {}", ChartRange::choices(", "))).await?;
+                    return Ok(());
+                }
+            },
+            None => ChartRange::Week,
+        };
+        let style = match parts.get(2) {
+            Some(s) => match ChartStyle::parse(s) {
+                Some(style) => style,
+                None => {
+                    bot.send_message(msg.chat.id, "❌ Estilo inválido. Usa 'line' o 'candles'").await?;
+                    return Ok(());
+                }
+            },
+            None => ChartStyle::Line,
+        };
+
+        if !CONFIG.cryptocurrencies.contains_key(&symbol) {
+            bot.send_message(msg.chat.id, format!("❌ Símbolo no soportado: {}\nUsa /symbols para ver la lista", symbol)).await?;
+            return Ok(());
+        }
+
+        info!("Generando gráfico de {} ({})", symbol, range.label());
+        let api = CryptoAPI::new(std::env::var("COINGECKO_API_KEY").unwrap_or_default());
+        match chart::symbol_chart(&api, &symbol, range, style).await {
+            Ok(png) => {
+                bot.send_photo(msg.chat.id, InputFile::memory(png).file_name(format!("{}.png", symbol)))
+                    .caption(format!("📈 {} · {}", CONFIG.get_symbol_display(&symbol), range.label()))
+                    .await?;
+            }
+            Err(e) => {
+                error!("Error al generar gráfico de {}: {}", symbol, e);
+                bot.send_message(msg.chat.id, "❌ No se pudo generar el gráfico").await?;
+            }
+        }
+
         Ok(())
     }
 
@@ -794,9 +2995,9 @@ impl TelegramBot {
         info!("Iniciando creación de alerta");
         
         let markup = InlineKeyboardMarkup::new([[
-            InlineKeyboardButton::callback("💰 Precio", "create_price_alert"),
-            InlineKeyboardButton::callback("🎯 Depeg", "create_depeg_alert"),
-            InlineKeyboardButton::callback("⚖️ Par de Tokens", "create_pair_alert"),
+            InlineKeyboardButton::callback("💰 Precio", self.callback_data(msg.chat.id, CallbackAction::CreatePriceAlert)),
+            InlineKeyboardButton::callback("🎯 Depeg", self.callback_data(msg.chat.id, CallbackAction::CreateDepegAlert)),
+            InlineKeyboardButton::callback("⚖️ Par de Tokens", self.callback_data(msg.chat.id, CallbackAction::CreatePairAlert)),
         ]]);
 
         bot.send_message(
@@ -823,7 +3024,7 @@ impl TelegramBot {
                     let markup = InlineKeyboardMarkup::new(
                         symbols.iter().map(|row| {
                             row.iter().map(|symbol| {
-                                InlineKeyboardButton::callback(symbol, format!("symbol_{}", symbol))
+                                InlineKeyboardButton::callback(symbol, self.callback_data(msg.chat.id, CallbackAction::Symbol(symbol.to_string())))
                             }).collect::<Vec<_>>()
                         })
                     );
@@ -843,8 +3044,8 @@ impl TelegramBot {
                 },
                 PriceAlertStep::SelectCondition => {
                     let markup = InlineKeyboardMarkup::new([[
-                        InlineKeyboardButton::callback("⬆️ Por encima", "condition_above"),
-                        InlineKeyboardButton::callback("⬇️ Por debajo", "condition_below"),
+                        InlineKeyboardButton::callback("⬆️ Por encima", self.callback_data(msg.chat.id, CallbackAction::Condition(AlertCondition::Above))),
+                        InlineKeyboardButton::callback("⬇️ Por debajo", self.callback_data(msg.chat.id, CallbackAction::Condition(AlertCondition::Below))),
                     ]]);
 
                     bot.send_message(
@@ -876,7 +3077,7 @@ impl TelegramBot {
                     let markup = InlineKeyboardMarkup::new(
                         stablecoins.iter().map(|row| {
                             row.iter().map(|&coin| {
-                                InlineKeyboardButton::callback(coin, format!("symbol_{}", coin))
+                                InlineKeyboardButton::callback(coin, self.callback_data(msg.chat.id, CallbackAction::Symbol(coin.to_string())))
                             }).collect::<Vec<_>>()
                         })
                     );
@@ -891,10 +3092,10 @@ impl TelegramBot {
                 },
                 DepegAlertStep::EnterDifferential => {
                     let markup = InlineKeyboardMarkup::new([[
-                        InlineKeyboardButton::callback("0.5%", "depegdiff_0.5"),
-                        InlineKeyboardButton::callback("1%", "depegdiff_1"),
-                        InlineKeyboardButton::callback("2%", "depegdiff_2"),
-                        InlineKeyboardButton::callback("5%", "depegdiff_5"),
+                        InlineKeyboardButton::callback("0.5%", self.callback_data(msg.chat.id, CallbackAction::DepegDifferential(0.5))),
+                        InlineKeyboardButton::callback("1%", self.callback_data(msg.chat.id, CallbackAction::DepegDifferential(1.0))),
+                        InlineKeyboardButton::callback("2%", self.callback_data(msg.chat.id, CallbackAction::DepegDifferential(2.0))),
+                        InlineKeyboardButton::callback("5%", self.callback_data(msg.chat.id, CallbackAction::DepegDifferential(5.0))),
                     ]]);
 
                     bot.send_message(
@@ -951,6 +3152,10 @@ Para crear una alerta, usa /alert y sigue las instrucciones\."#;
         if let Some(text) = msg.text() {
             if let Some(state) = self.db.get_user_state(msg.chat.id.0)
                 .map_err(Self::db_error_to_request_error)? {
+                // En grupos el asistente es del chat; solo avanzan los administradores
+                if !self.is_chat_admin(&bot, &msg.chat, msg.from()).await? {
+                    return Ok(());
+                }
                 match state {
                     UserState::CreatingPriceAlert { step: PriceAlertStep::EnterPrice, symbol, .. } => {
                         match text.parse::<f64>() {
@@ -987,10 +3192,10 @@ Para crear una alerta, usa /alert y sigue las instrucciones\."#;
                                     .map_err(Self::db_error_to_request_error)?;
 
                                 let markup = InlineKeyboardMarkup::new([[
-                                    InlineKeyboardButton::callback("0.5%", "pairdiff_0.5"),
-                                    InlineKeyboardButton::callback("1%", "pairdiff_1"),
-                                    InlineKeyboardButton::callback("2%", "pairdiff_2"),
-                                    InlineKeyboardButton::callback("5%", "pairdiff_5"),
+                                    InlineKeyboardButton::callback("0.5%", self.callback_data(msg.chat.id, CallbackAction::PairDifferential(0.5))),
+                                    InlineKeyboardButton::callback("1%", self.callback_data(msg.chat.id, CallbackAction::PairDifferential(1.0))),
+                                    InlineKeyboardButton::callback("2%", self.callback_data(msg.chat.id, CallbackAction::PairDifferential(2.0))),
+                                    InlineKeyboardButton::callback("5%", self.callback_data(msg.chat.id, CallbackAction::PairDifferential(5.0))),
                                 ]]);
 
                                 bot.send_message(
@@ -1008,22 +3213,32 @@ Para crear una alerta, usa /alert y sigue las instrucciones\."#;
                             }
                         }
                     }
-                    // Manejar otros estados que requieren entrada de texto
-                    _ => {}
+                    UserState::EditingAlert { alert_id } => {
+                        self.handle_alert_edit(&bot, &msg, alert_id, text).await?;
+                    }
+                    // El paso actual no espera texto: recordar dónde quedó el asistente
+                    UserState::Idle => {}
+                    state => {
+                        bot.send_message(
+                            msg.chat.id,
+                            format!(
+                                "📝 Tienes una operación en curso:\n\n{}\n\n\
+                                 Usa los botones para continuar, /back para volver o /cancel para cancelar.",
+                                state.describe()
+                            )
+                        ).await?;
+                    }
                 }
             }
         }
         Ok(())
     }
 
-    pub async fn run(self) {
-        let bot = Bot::new(std::env::var("TELEGRAM_BOT_TOKEN").expect("TELEGRAM_BOT_TOKEN must be set"));
-        
-        info!("Starting Telegram bot...");
-        
+    /// Árbol de handlers compartido por los modos polling y webhook.
+    fn schema(self) -> UpdateHandler<RequestError> {
         let bot_handler = Arc::new(self);
-        
-        let handler = {
+
+        {
             let bot_handler1 = bot_handler.clone();
             let bot_handler2 = bot_handler.clone();
             let bot_handler3 = bot_handler.clone();
@@ -1069,15 +3284,81 @@ Para crear una alerta, usa /alert y sigue las instrucciones\."#;
                             }
                         })
                 )
-        };
+        }
+    }
+
+    /// Descarta periódicamente los asistentes abandonados y avisa al usuario.
+    fn spawn_state_sweeper(&self, bot: Bot) {
+        let db = self.db.clone();
+        tokio::spawn(async move {
+            Timer::new(60).start(|| {
+                let db = db.clone();
+                let bot = bot.clone();
+                async move {
+                    match db.delete_expired_user_states() {
+                        Ok(chat_ids) => {
+                            for chat_id in chat_ids {
+                                let _ = bot.send_message(
+                                    ChatId(chat_id),
+                                    "⌛ Tu operación en curso expiró por inactividad."
+                                ).await;
+                            }
+                        }
+                        Err(e) => error!("Error al limpiar estados expirados: {}", e),
+                    }
+                }
+            }).await;
+        });
+    }
+
+    pub async fn run(self) {
+        let bot = Bot::new(std::env::var("TELEGRAM_BOT_TOKEN").expect("TELEGRAM_BOT_TOKEN must be set"));
+        
+        info!("Starting Telegram bot...");
+        self.spawn_state_sweeper(bot.clone());
 
-        Dispatcher::builder(bot, handler)
+        Dispatcher::builder(bot, self.schema())
             .enable_ctrlc_handler()
             .build()
             .dispatch()
             .await;
     }
 
+    /// Modo webhook: registra el webhook en Telegram y procesa los updates que
+    /// el servidor de la API recibe en `/telegram/<secret>`.
+    pub async fn run_webhook(self, config: WebhookConfig, updates: UpdateReceiver) {
+        let bot = Bot::new(std::env::var("TELEGRAM_BOT_TOKEN").expect("TELEGRAM_BOT_TOKEN must be set"));
+
+        info!("Starting Telegram bot en modo webhook...");
+
+        let url = match reqwest::Url::parse(&config.webhook_url()) {
+            Ok(url) => url,
+            Err(e) => {
+                error!("URL de webhook inválida: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = bot.set_webhook(url).secret_token(config.secret.clone()).await {
+            error!("Error al registrar el webhook en Telegram: {}", e);
+            return;
+        }
+
+        self.spawn_state_sweeper(bot.clone());
+
+        let (stop_token, _stop_flag) = mk_stop_token();
+        let listener = StatefulListener::new(
+            (UnboundedReceiverStream::new(updates), stop_token),
+            listener_stream,
+            |state: &mut (UnboundedReceiverStream<Result<Update, Infallible>>, StopToken)| state.1.clone(),
+        );
+
+        Dispatcher::builder(bot, self.schema())
+            .enable_ctrlc_handler()
+            .build()
+            .dispatch_with_listener(listener, LoggingErrorHandler::with_custom_text("Error en el listener del webhook"))
+            .await;
+    }
+
     pub async fn verify_bot_token() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         let token = std::env::var("TELEGRAM_BOT_TOKEN")?;
         let bot = Bot::new(token);
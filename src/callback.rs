@@ -0,0 +1,331 @@
+use crate::models::{AlertCondition, AlertFilter, AlertKind, AlertStatus};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Bytes del HMAC que se agregan al payload. Telegram limita `callback_data`
+/// a 64 bytes, así que la etiqueta se trunca.
+const TAG_BYTES: usize = 8;
+
+/// Límite de Telegram para `callback_data`.
+const MAX_CALLBACK_DATA: usize = 64;
+
+/// Largo máximo (ASCII) del símbolo con que se filtra la lista de alertas,
+/// para que `ListAlerts` quepa en `callback_data` junto con la etiqueta.
+pub const MAX_FILTER_SYMBOL: usize = 12;
+
+/// Acciones que pueden llegar en el `callback_data` de los botones del bot.
+#[derive(Debug, Clone, PartialEq)]
+pub enum CallbackAction {
+    CreatePriceAlert,
+    CreateDepegAlert,
+    CreatePairAlert,
+    Symbol(String),
+    Condition(AlertCondition),
+    Pair(String, String),
+    PairDifferential(f64),
+    DepegDifferential(f64),
+    DeleteAlert(i64),
+    Subscribe(i64),
+    ListAlerts { page: u32, filter: AlertFilter },
+    ShowAlert(i64),
+    PauseAlert(i64),
+    ResumeAlert(i64),
+    EditAlert(i64),
+    CloneAlert(i64),
+    Symbols(u32),
+    SnoozeAlert(i64),
+    RearmAlert(i64),
+    AcknowledgeAlert(i64),
+    AlertChart(i64),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum CallbackError {
+    Malformed,
+    InvalidSignature,
+}
+
+impl std::fmt::Display for CallbackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CallbackError::Malformed => write!(f, "Callback mal formado"),
+            CallbackError::InvalidSignature => write!(f, "Firma de callback inválida"),
+        }
+    }
+}
+
+impl std::error::Error for CallbackError {}
+
+impl CallbackAction {
+    pub fn encode(&self) -> String {
+        match self {
+            CallbackAction::CreatePriceAlert => "np".to_string(),
+            CallbackAction::CreateDepegAlert => "nd".to_string(),
+            CallbackAction::CreatePairAlert => "nr".to_string(),
+            CallbackAction::Symbol(symbol) => format!("s:{}", symbol),
+            CallbackAction::Condition(AlertCondition::Above) => "c:a".to_string(),
+            CallbackAction::Condition(AlertCondition::Below) => "c:b".to_string(),
+            CallbackAction::Pair(token1, token2) => format!("p:{}:{}", token1, token2),
+            CallbackAction::PairDifferential(diff) => format!("pd:{}", diff),
+            CallbackAction::DepegDifferential(diff) => format!("dd:{}", diff),
+            CallbackAction::DeleteAlert(id) => format!("d:{}", id),
+            CallbackAction::Subscribe(id) => format!("sub:{}", id),
+            CallbackAction::ListAlerts { page, filter } => {
+                let kind = match filter.kind {
+                    None => "",
+                    Some(AlertKind::Price) => "p",
+                    Some(AlertKind::Depeg) => "d",
+                    Some(AlertKind::PairDepeg) => "r",
+                    Some(AlertKind::Portfolio) => "f",
+                };
+                let status = match filter.status {
+                    None => "",
+                    Some(AlertStatus::Active) => "a",
+                    Some(AlertStatus::Paused) => "p",
+                    Some(AlertStatus::Triggered) => "t",
+                };
+                let symbol = filter.symbol.as_deref().unwrap_or("");
+                let symbol = symbol.get(..MAX_FILTER_SYMBOL).unwrap_or(symbol);
+                format!("l:{}:{}:{}:{}", page, kind, status, symbol)
+            }
+            CallbackAction::ShowAlert(id) => format!("a:{}", id),
+            CallbackAction::PauseAlert(id) => format!("pa:{}", id),
+            CallbackAction::ResumeAlert(id) => format!("re:{}", id),
+            CallbackAction::EditAlert(id) => format!("e:{}", id),
+            CallbackAction::CloneAlert(id) => format!("cl:{}", id),
+            CallbackAction::Symbols(page) => format!("sy:{}", page),
+            CallbackAction::SnoozeAlert(id) => format!("sn:{}", id),
+            CallbackAction::RearmAlert(id) => format!("ra:{}", id),
+            CallbackAction::AcknowledgeAlert(id) => format!("ak:{}", id),
+            CallbackAction::AlertChart(id) => format!("ch:{}", id),
+        }
+    }
+
+    pub fn decode(payload: &str) -> Option<Self> {
+        let (kind, args) = payload.split_once(':').unwrap_or((payload, ""));
+        match (kind, args) {
+            ("np", "") => Some(CallbackAction::CreatePriceAlert),
+            ("nd", "") => Some(CallbackAction::CreateDepegAlert),
+            ("nr", "") => Some(CallbackAction::CreatePairAlert),
+            ("s", symbol) if !symbol.is_empty() => Some(CallbackAction::Symbol(symbol.to_string())),
+            ("c", "a") => Some(CallbackAction::Condition(AlertCondition::Above)),
+            ("c", "b") => Some(CallbackAction::Condition(AlertCondition::Below)),
+            ("p", pair) => {
+                let (token1, token2) = pair.split_once(':')?;
+                if token1.is_empty() || token2.is_empty() {
+                    return None;
+                }
+                Some(CallbackAction::Pair(token1.to_string(), token2.to_string()))
+            }
+            ("pd", diff) => diff.parse().ok().map(CallbackAction::PairDifferential),
+            ("dd", diff) => diff.parse().ok().map(CallbackAction::DepegDifferential),
+            ("d", id) => id.parse().ok().map(CallbackAction::DeleteAlert),
+            ("sub", id) => id.parse().ok().map(CallbackAction::Subscribe),
+            ("l", args) => {
+                let mut parts = args.splitn(4, ':');
+                let page = parts.next()?.parse().ok()?;
+                let kind = match parts.next()? {
+                    "" => None,
+                    "p" => Some(AlertKind::Price),
+                    "d" => Some(AlertKind::Depeg),
+                    "r" => Some(AlertKind::PairDepeg),
+                    "f" => Some(AlertKind::Portfolio),
+                    _ => return None,
+                };
+                let status = match parts.next()? {
+                    "" => None,
+                    "a" => Some(AlertStatus::Active),
+                    "p" => Some(AlertStatus::Paused),
+                    "t" => Some(AlertStatus::Triggered),
+                    _ => return None,
+                };
+                let symbol = Some(parts.next()?).filter(|s| !s.is_empty()).map(str::to_string);
+                Some(CallbackAction::ListAlerts { page, filter: AlertFilter { kind, status, symbol } })
+            }
+            ("a", id) => id.parse().ok().map(CallbackAction::ShowAlert),
+            ("pa", id) => id.parse().ok().map(CallbackAction::PauseAlert),
+            ("re", id) => id.parse().ok().map(CallbackAction::ResumeAlert),
+            ("e", id) => id.parse().ok().map(CallbackAction::EditAlert),
+            ("cl", id) => id.parse().ok().map(CallbackAction::CloneAlert),
+            ("sy", page) => page.parse().ok().map(CallbackAction::Symbols),
+            ("sn", id) => id.parse().ok().map(CallbackAction::SnoozeAlert),
+            ("ra", id) => id.parse().ok().map(CallbackAction::RearmAlert),
+            ("ak", id) => id.parse().ok().map(CallbackAction::AcknowledgeAlert),
+            ("ch", id) => id.parse().ok().map(CallbackAction::AlertChart),
+            _ => None,
+        }
+    }
+
+    /// Alerta que el callback modifica, si la hay. Antes de ejecutar la acción
+    /// hay que verificar que el usuario puede gestionarla. `Subscribe` no
+    /// cuenta: solo exige que la alerta notifique al chat del botón.
+    pub fn target_alert(&self) -> Option<i64> {
+        match self {
+            CallbackAction::DeleteAlert(id)
+            | CallbackAction::PauseAlert(id)
+            | CallbackAction::ResumeAlert(id)
+            | CallbackAction::EditAlert(id)
+            | CallbackAction::CloneAlert(id)
+            | CallbackAction::SnoozeAlert(id)
+            | CallbackAction::RearmAlert(id)
+            | CallbackAction::AcknowledgeAlert(id) => Some(*id),
+            _ => None,
+        }
+    }
+
+    /// Tipo de alerta que el callback empieza a crear o guarda, para
+    /// comprobar la cuota del plan antes de seguir.
+    pub fn creates_alert(&self) -> Option<AlertKind> {
+        match self {
+            CallbackAction::CreatePriceAlert | CallbackAction::Condition(_) => Some(AlertKind::Price),
+            CallbackAction::CreateDepegAlert | CallbackAction::DepegDifferential(_) => Some(AlertKind::Depeg),
+            CallbackAction::CreatePairAlert | CallbackAction::PairDifferential(_) => Some(AlertKind::PairDepeg),
+            _ => None,
+        }
+    }
+
+    /// En grupos, todo lo que no sea consultar o suscribirse queda reservado
+    /// a los administradores del chat.
+    pub fn requires_chat_admin(&self) -> bool {
+        !matches!(
+            self,
+            CallbackAction::Subscribe(_)
+                | CallbackAction::ListAlerts { .. }
+                | CallbackAction::ShowAlert(_)
+                | CallbackAction::Symbols(_)
+                | CallbackAction::AlertChart(_)
+        )
+    }
+
+    /// Nombre con el que se registra la acción en el log de auditoría.
+    pub fn audit_name(&self) -> Option<&'static str> {
+        match self {
+            CallbackAction::DeleteAlert(_) => Some("alert.delete"),
+            CallbackAction::Subscribe(_) => Some("alert.subscribe"),
+            CallbackAction::PauseAlert(_) => Some("alert.pause"),
+            CallbackAction::ResumeAlert(_) => Some("alert.resume"),
+            CallbackAction::CloneAlert(_) => Some("alert.clone"),
+            CallbackAction::SnoozeAlert(_) => Some("alert.snooze"),
+            CallbackAction::RearmAlert(_) => Some("alert.rearm"),
+            CallbackAction::AcknowledgeAlert(_) => Some("alert.ack"),
+            _ => None,
+        }
+    }
+}
+
+/// Codifica y verifica `callback_data`. Con clave configurada, cada payload
+/// lleva una etiqueta HMAC ligada al chat, así que no puede falsificarse ni
+/// reutilizarse desde otro chat.
+#[derive(Clone)]
+pub struct CallbackCodec {
+    key: Option<Vec<u8>>,
+}
+
+impl CallbackCodec {
+    pub fn new(key: Option<Vec<u8>>) -> Self {
+        Self { key }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(
+            std::env::var("CALLBACK_HMAC_KEY")
+                .ok()
+                .filter(|key| !key.is_empty())
+                .map(String::into_bytes),
+        )
+    }
+
+    fn mac(key: &[u8], chat_id: i64, payload: &str) -> HmacSha256 {
+        let mut mac = HmacSha256::new_from_slice(key).expect("HMAC acepta claves de cualquier tamaño");
+        mac.update(chat_id.to_string().as_bytes());
+        mac.update(b"|");
+        mac.update(payload.as_bytes());
+        mac
+    }
+
+    pub fn encode(&self, chat_id: i64, action: &CallbackAction) -> String {
+        let payload = action.encode();
+        let data = match &self.key {
+            Some(key) => {
+                let tag = Self::mac(key, chat_id, &payload).finalize().into_bytes();
+                format!("{}|{}", payload, hex::encode(&tag[..TAG_BYTES]))
+            }
+            None => payload,
+        };
+        assert!(data.len() <= MAX_CALLBACK_DATA, "callback_data de {} bytes: {}", data.len(), data);
+        data
+    }
+
+    pub fn decode(&self, chat_id: i64, data: &str) -> Result<CallbackAction, CallbackError> {
+        let payload = match &self.key {
+            Some(key) => {
+                let (payload, tag) = data.rsplit_once('|').ok_or(CallbackError::InvalidSignature)?;
+                let tag = hex::decode(tag).map_err(|_| CallbackError::InvalidSignature)?;
+                if tag.len() != TAG_BYTES {
+                    return Err(CallbackError::InvalidSignature);
+                }
+                Self::mac(key, chat_id, payload)
+                    .verify_truncated_left(&tag)
+                    .map_err(|_| CallbackError::InvalidSignature)?;
+                payload
+            }
+            None => data,
+        };
+
+        CallbackAction::decode(payload).ok_or(CallbackError::Malformed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roundtrip_and_tamper_detection() {
+        let codec = CallbackCodec::new(Some(b"secreto".to_vec()));
+        let data = codec.encode(42, &CallbackAction::DeleteAlert(7));
+        assert!(data.len() <= 64);
+        assert_eq!(codec.decode(42, &data), Ok(CallbackAction::DeleteAlert(7)));
+
+        // Mismo payload desde otro chat o con el ID cambiado
+        assert_eq!(codec.decode(43, &data), Err(CallbackError::InvalidSignature));
+        let forged = data.replacen("d:7", "d:8", 1);
+        assert_eq!(codec.decode(42, &forged), Err(CallbackError::InvalidSignature));
+        assert_eq!(codec.decode(42, "d:8"), Err(CallbackError::InvalidSignature));
+    }
+
+    #[test]
+    fn test_decode_without_key() {
+        let codec = CallbackCodec::new(None);
+        let pair = CallbackAction::Pair("ETH".to_string(), "stETH".to_string());
+        assert_eq!(codec.decode(1, &codec.encode(1, &pair)), Ok(pair));
+        assert_eq!(codec.decode(1, "pd:0.5"), Ok(CallbackAction::PairDifferential(0.5)));
+        assert_eq!(codec.decode(1, "sub:3"), Ok(CallbackAction::Subscribe(3)));
+
+        let list = CallbackAction::ListAlerts {
+            page: 2,
+            filter: AlertFilter { kind: Some(AlertKind::Depeg), status: None, symbol: Some("USDC".to_string()) },
+        };
+        assert_eq!(list.encode(), "l:2:d::USDC");
+        assert_eq!(codec.decode(1, &list.encode()), Ok(list));
+        assert_eq!(codec.decode(1, "delete_5"), Err(CallbackError::Malformed));
+    }
+
+    #[test]
+    fn test_longest_list_fits_callback_data() {
+        let codec = CallbackCodec::new(Some(b"secreto".to_vec()));
+        let list = CallbackAction::ListAlerts {
+            page: u32::MAX,
+            filter: AlertFilter {
+                kind: Some(AlertKind::Portfolio),
+                status: Some(AlertStatus::Triggered),
+                symbol: Some("A".repeat(MAX_FILTER_SYMBOL)),
+            },
+        };
+        let data = codec.encode(-1_001_234_567_890, &list);
+        assert!(data.len() <= MAX_CALLBACK_DATA);
+        assert_eq!(codec.decode(-1_001_234_567_890, &data), Ok(list));
+    }
+}
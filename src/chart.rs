@@ -0,0 +1,435 @@
+use crate::crypto_api::CryptoAPI;
+use crate::models::{AlertType, Candle, CryptoPrice, PriceAlert, PricePoint};
+use plotters::prelude::*;
+use plotters::style::{register_font, FontStyle};
+use std::error::Error;
+use std::sync::Once;
+
+const WIDTH: u32 = 1000;
+const HEIGHT: u32 = 560;
+
+static FONT_INIT: Once = Once::new();
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChartStyle {
+    Line,
+    Candles,
+}
+
+impl ChartStyle {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "line" | "linea" => Some(ChartStyle::Line),
+            "candles" | "velas" => Some(ChartStyle::Candles),
+            _ => None,
+        }
+    }
+}
+
+/// Rangos aceptados por el endpoint OHLC de CoinGecko.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ChartRange {
+    Day,
+    Week,
+    TwoWeeks,
+    Month,
+    Quarter,
+    HalfYear,
+    Year,
+}
+
+impl ChartRange {
+    pub const ALL: [ChartRange; 7] = [
+        ChartRange::Day,
+        ChartRange::Week,
+        ChartRange::TwoWeeks,
+        ChartRange::Month,
+        ChartRange::Quarter,
+        ChartRange::HalfYear,
+        ChartRange::Year,
+    ];
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "1d" | "24h" => Some(ChartRange::Day),
+            "7d" | "1w" => Some(ChartRange::Week),
+            "14d" | "2w" => Some(ChartRange::TwoWeeks),
+            "30d" | "1m" => Some(ChartRange::Month),
+            "90d" | "3m" => Some(ChartRange::Quarter),
+            "180d" | "6m" => Some(ChartRange::HalfYear),
+            "365d" | "1y" => Some(ChartRange::Year),
+            _ => None,
+        }
+    }
+
+    pub fn days(&self) -> u32 {
+        match self {
+            ChartRange::Day => 1,
+            ChartRange::Week => 7,
+            ChartRange::TwoWeeks => 14,
+            ChartRange::Month => 30,
+            ChartRange::Quarter => 90,
+            ChartRange::HalfYear => 180,
+            ChartRange::Year => 365,
+        }
+    }
+
+    /// Forma canónica que acepta `parse`, la que se muestra en las ayudas.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ChartRange::Day => "1d",
+            ChartRange::Week => "7d",
+            ChartRange::TwoWeeks => "14d",
+            ChartRange::Month => "30d",
+            ChartRange::Quarter => "90d",
+            ChartRange::HalfYear => "180d",
+            ChartRange::Year => "1y",
+        }
+    }
+
+    /// Todos los rangos aceptados, separados por `sep`.
+    pub fn choices(sep: &str) -> String {
+        Self::ALL.iter().map(|range| range.code()).collect::<Vec<_>>().join(sep)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ChartRange::Day => "24h",
+            ChartRange::Week => "7d",
+            ChartRange::TwoWeeks => "14d",
+            ChartRange::Month => "30d",
+            ChartRange::Quarter => "90d",
+            ChartRange::HalfYear => "180d",
+            ChartRange::Year => "1y",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ChartSeries {
+    Line(Vec<PricePoint>),
+    Candles(Vec<Candle>),
+}
+
+#[derive(Debug, Clone)]
+pub struct ChartSpec {
+    pub title: String,
+    pub series: ChartSeries,
+    pub target_lines: Vec<f64>,
+    pub peg_band: Option<(f64, f64)>,
+    pub marker: Option<PricePoint>,
+}
+
+impl ChartSpec {
+    pub fn new(title: impl Into<String>, series: ChartSeries) -> Self {
+        Self {
+            title: title.into(),
+            series,
+            target_lines: Vec::new(),
+            peg_band: None,
+            marker: None,
+        }
+    }
+
+    /// Agrega las líneas de umbral de la alerta: el objetivo para alertas de precio
+    /// y la banda de tolerancia alrededor del peg para depeg y pares.
+    pub fn with_alert_overlay(mut self, alert: &PriceAlert) -> Self {
+        match &alert.alert_type {
+            AlertType::Price { target_price, .. } => {
+                self.target_lines.push(*target_price);
+            }
+            AlertType::Depeg { target_price, differential, .. } => {
+                let delta = target_price * differential / 100.0;
+                self.target_lines.push(*target_price);
+                self.peg_band = Some((target_price - delta, target_price + delta));
+            }
+            AlertType::PairDepeg { expected_ratio, differential, .. } => {
+                let delta = expected_ratio * differential / 100.0;
+                self.target_lines.push(*expected_ratio);
+                self.peg_band = Some((expected_ratio - delta, expected_ratio + delta));
+            }
+            // Los umbrales de portafolio no son precios del activo
+            AlertType::PortfolioValue { .. }
+            | AlertType::PortfolioDrawdown { .. }
+            | AlertType::AllocationDrift { .. } => {}
+        }
+        self
+    }
+
+    pub fn with_marker(mut self, point: PricePoint) -> Self {
+        self.marker = Some(point);
+        self
+    }
+
+    fn time_bounds(&self) -> Option<(i64, i64)> {
+        let timestamps: Vec<i64> = match &self.series {
+            ChartSeries::Line(points) => points.iter().map(|p| p.timestamp).collect(),
+            ChartSeries::Candles(candles) => candles.iter().map(|c| c.timestamp).collect(),
+        };
+        let mut min = *timestamps.iter().min()?;
+        let mut max = *timestamps.iter().max()?;
+        if let Some(marker) = &self.marker {
+            min = min.min(marker.timestamp);
+            max = max.max(marker.timestamp);
+        }
+        if min == max {
+            max = min + 60;
+        }
+        Some((min, max))
+    }
+
+    fn price_bounds(&self) -> Option<(f64, f64)> {
+        let mut values: Vec<f64> = match &self.series {
+            ChartSeries::Line(points) => points.iter().map(|p| p.price).collect(),
+            ChartSeries::Candles(candles) => candles
+                .iter()
+                .flat_map(|c| [c.high, c.low])
+                .collect(),
+        };
+        values.extend(self.target_lines.iter().copied());
+        if let Some((low, high)) = self.peg_band {
+            values.push(low);
+            values.push(high);
+        }
+        if let Some(marker) = &self.marker {
+            values.push(marker.price);
+        }
+
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        if !min.is_finite() || !max.is_finite() {
+            return None;
+        }
+
+        let padding = ((max - min) * 0.05).max(max.abs() * 0.001).max(f64::EPSILON);
+        Some((min - padding, max + padding))
+    }
+}
+
+fn ensure_font() {
+    FONT_INIT.call_once(|| {
+        if register_font("sans-serif", FontStyle::Normal, notosans::REGULAR_TTF).is_err() {
+            tracing::error!("No se pudo registrar la fuente para los gráficos");
+        }
+    });
+}
+
+fn format_timestamp(ts: i64, span: i64) -> String {
+    let format = if span <= 2 * 24 * 60 * 60 { "%H:%M" } else { "%d/%m" };
+    chrono::DateTime::from_timestamp(ts, 0)
+        .map(|dt| dt.format(format).to_string())
+        .unwrap_or_default()
+}
+
+fn format_price(price: f64) -> String {
+    if price.abs() >= 1000.0 {
+        format!("{:.0}", price)
+    } else if price.abs() >= 10.0 {
+        format!("{:.2}", price)
+    } else {
+        format!("{:.4}", price)
+    }
+}
+
+/// Renderiza el gráfico como PNG en memoria.
+pub fn render_png(spec: &ChartSpec) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    ensure_font();
+
+    let (x_min, x_max) = spec.time_bounds().ok_or("No hay datos para graficar")?;
+    let (y_min, y_max) = spec.price_bounds().ok_or("No hay datos para graficar")?;
+    let span = x_max - x_min;
+
+    let mut buffer = vec![0u8; (WIDTH * HEIGHT * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut buffer, (WIDTH, HEIGHT)).into_drawing_area();
+        root.fill(&WHITE)?;
+
+        let mut chart = ChartBuilder::on(&root)
+            .caption(&spec.title, ("sans-serif", 24))
+            .margin(12)
+            .x_label_area_size(36)
+            .y_label_area_size(72)
+            .build_cartesian_2d(x_min..x_max, y_min..y_max)?;
+
+        chart
+            .configure_mesh()
+            .light_line_style(WHITE.mix(0.0))
+            .x_labels(8)
+            .y_labels(8)
+            .x_label_formatter(&|ts| format_timestamp(*ts, span))
+            .y_label_formatter(&|price| format_price(*price))
+            .draw()?;
+
+        if let Some((low, high)) = spec.peg_band {
+            chart.draw_series(std::iter::once(Rectangle::new(
+                [(x_min, low), (x_max, high)],
+                GREEN.mix(0.12).filled(),
+            )))?;
+        }
+
+        for target in &spec.target_lines {
+            chart.draw_series(LineSeries::new(
+                vec![(x_min, *target), (x_max, *target)],
+                RED.stroke_width(2),
+            ))?;
+        }
+
+        match &spec.series {
+            ChartSeries::Line(points) => {
+                chart.draw_series(LineSeries::new(
+                    points.iter().map(|p| (p.timestamp, p.price)),
+                    BLUE.stroke_width(2),
+                ))?;
+            }
+            ChartSeries::Candles(candles) => {
+                let width = (((WIDTH - 100) as usize / candles.len().max(1)) as u32)
+                    .saturating_sub(2)
+                    .clamp(1, 15);
+                chart.draw_series(candles.iter().map(|c| {
+                    CandleStick::new(
+                        c.timestamp,
+                        c.open,
+                        c.high,
+                        c.low,
+                        c.close,
+                        GREEN.filled(),
+                        RED.filled(),
+                        width,
+                    )
+                }))?;
+            }
+        }
+
+        if let Some(marker) = &spec.marker {
+            chart.draw_series(std::iter::once(Circle::new(
+                (marker.timestamp, marker.price),
+                6,
+                MAGENTA.filled(),
+            )))?;
+        }
+
+        root.present()?;
+    }
+
+    let mut png_bytes = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png_bytes, WIDTH, HEIGHT);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&buffer)?;
+    }
+
+    Ok(png_bytes)
+}
+
+/// Calcula la serie de ratios token1/token2 emparejando cada punto con el
+/// punto más cercano en el tiempo de la segunda serie.
+pub fn ratio_series(base: &[PricePoint], quote: &[PricePoint]) -> Vec<PricePoint> {
+    base.iter()
+        .filter_map(|p| {
+            let nearest = quote
+                .iter()
+                .min_by_key(|q| (q.timestamp - p.timestamp).abs())?;
+            if nearest.price == 0.0 {
+                return None;
+            }
+            Some(PricePoint {
+                timestamp: p.timestamp,
+                price: p.price / nearest.price,
+            })
+        })
+        .collect()
+}
+
+/// Descarga el histórico de un símbolo y lo renderiza.
+pub async fn symbol_chart(
+    api: &CryptoAPI,
+    symbol: &str,
+    range: ChartRange,
+    style: ChartStyle,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let symbol = symbol.to_uppercase();
+    let series = match style {
+        ChartStyle::Line => ChartSeries::Line(api.get_market_chart(&symbol, range.days()).await?),
+        ChartStyle::Candles => ChartSeries::Candles(api.get_ohlc(&symbol, range.days()).await?),
+    };
+    let spec = ChartSpec::new(format!("{} · {}", symbol, range.label()), series);
+    render_png(&spec)
+}
+
+/// Gráfico de las últimas 24h de una alerta, con el umbral dibujado y, si se
+/// indica, el punto de cruce marcado.
+pub async fn alert_chart(
+    api: &CryptoAPI,
+    alert: &PriceAlert,
+    price: Option<&CryptoPrice>,
+) -> Result<Vec<u8>, Box<dyn Error + Send + Sync>> {
+    let points = match &alert.alert_type {
+        AlertType::PairDepeg { token1, token2, .. } => {
+            let base = api.get_market_chart(token1, 1).await?;
+            let quote = api.get_market_chart(token2, 1).await?;
+            ratio_series(&base, &quote)
+        }
+        AlertType::PortfolioValue { .. } | AlertType::PortfolioDrawdown { .. } => {
+            return Err("Las alertas sobre el valor del portafolio no tienen gráfico".into());
+        }
+        _ => api.get_market_chart(&alert.symbol, 1).await?,
+    };
+
+    let mut spec = ChartSpec::new(format!("{} · 24h", alert.symbol), ChartSeries::Line(points))
+        .with_alert_overlay(alert);
+    if let Some(price) = price {
+        spec = spec.with_marker(PricePoint {
+            timestamp: price.timestamp,
+            price: price.price,
+        });
+    }
+    render_png(&spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::AlertCondition;
+
+    fn sample_points() -> Vec<PricePoint> {
+        (0..48)
+            .map(|i| PricePoint {
+                timestamp: 1_700_000_000 + i * 1800,
+                price: 100.0 + (i as f64).sin() * 5.0,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_render_line_chart_with_target() {
+        let alert = PriceAlert {
+            id: Some(1),
+            user_id: 1,
+            symbol: "BTC".to_string(),
+            alert_type: AlertType::Price {
+                target_price: 104.0,
+                condition: AlertCondition::Above,
+            },
+            created_at: 0,
+            triggered_at: None,
+            is_active: true,
+        };
+        let spec = ChartSpec::new("BTC 24h", ChartSeries::Line(sample_points()))
+            .with_alert_overlay(&alert);
+
+        let png = render_png(&spec).expect("el gráfico debería renderizarse");
+        assert_eq!(&png[..8], b"\x89PNG\r\n\x1a\n");
+    }
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(ChartRange::parse("7d"), Some(ChartRange::Week));
+        assert_eq!(ChartRange::parse("1Y").map(|r| r.days()), Some(365));
+        assert_eq!(ChartRange::parse("2h"), None);
+        assert!(ChartRange::ALL.iter().all(|range| ChartRange::parse(range.code()) == Some(*range)));
+        // La ayuda de /chart en bot.rs repite esta lista a mano
+        assert_eq!(ChartRange::choices("|"), "1d|7d|14d|30d|90d|180d|1y");
+    }
+}
@@ -1,16 +1,33 @@
-use crate::models::CryptoPrice;
+use crate::models::{Candle, CryptoPrice, PricePoint};
 use reqwest::Client;
-use std::{error::Error, time::Duration, collections::HashMap};
+use std::{error::Error, time::Duration, collections::HashMap, sync::{Arc, RwLock}};
 use serde::Deserialize;
 use tokio::time::sleep;
 use tracing::{info, error};
 use crate::config::CONFIG;
+use crate::history::MarketChart;
 
 pub struct CryptoAPI {
     client: Client,
     api_key: String,
     symbol_to_id: HashMap<String, String>,
     supported_exchanges: Vec<String>,
+    cache: PriceCache,
+}
+
+/// Última cotización conocida de cada símbolo. Se comparte entre clones, de
+/// modo que quien tenga una copia ve los precios que va obteniendo el monitor.
+#[derive(Debug, Clone, Default)]
+pub struct PriceCache(Arc<RwLock<HashMap<String, CryptoPrice>>>);
+
+impl PriceCache {
+    pub fn get(&self, symbol: &str) -> Option<CryptoPrice> {
+        self.0.read().unwrap().get(&symbol.to_uppercase()).cloned()
+    }
+
+    pub fn set(&self, price: CryptoPrice) {
+        self.0.write().unwrap().insert(price.symbol.to_uppercase(), price);
+    }
 }
 
 #[derive(Deserialize)]
@@ -29,6 +46,11 @@ struct ExchangePrices {
     market_cap: Option<f64>,
 }
 
+#[derive(Deserialize)]
+struct MarketChartResponse {
+    prices: Vec<(f64, f64)>,
+}
+
 #[derive(Debug)]
 pub struct ExchangePrice {
     pub exchange: String,
@@ -50,12 +72,27 @@ impl CryptoAPI {
             api_key,
             symbol_to_id,
             supported_exchanges: CONFIG.exchanges.supported.clone(),
+            cache: PriceCache::default(),
         }
     }
 
+    /// Usa una caché ya existente, p. ej. la del monitor, para que ambos vean
+    /// los precios que obtiene el otro.
+    pub fn with_cache(mut self, cache: PriceCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    /// Caché que se actualiza con cada precio obtenido por `get_price` y `get_prices`.
+    pub fn price_cache(&self) -> PriceCache {
+        self.cache.clone()
+    }
+
     pub async fn get_price(&self, symbol: &str) -> Result<CryptoPrice, Box<dyn Error + Send + Sync>> {
         // Obtener precio del exchange por defecto (binance)
-        self.get_price_from_exchange(symbol, "binance").await
+        let price = self.get_price_from_exchange(symbol, "binance").await?;
+        self.cache.set(price.clone());
+        Ok(price)
     }
 
     pub async fn get_price_from_exchange(&self, symbol: &str, exchange: &str) -> Result<CryptoPrice, Box<dyn Error + Send + Sync>> {
@@ -146,6 +183,154 @@ impl CryptoAPI {
         prices
     }
 
+    /// Precios (USD) de varios símbolos en una sola consulta. Los símbolos no
+    /// soportados o sin cotización no aparecen en el resultado.
+    pub async fn get_prices(&self, symbols: &[String]) -> Result<HashMap<String, f64>, Box<dyn Error + Send + Sync>> {
+        let ids: HashMap<&String, String> = symbols.iter()
+            .filter_map(|symbol| self.symbol_to_id.get(&symbol.to_uppercase()).map(|id| (id, symbol.to_uppercase())))
+            .collect();
+        if ids.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let joined: Vec<&str> = ids.keys().map(|id| id.as_str()).collect();
+        let url = format!(
+            "https://api.coingecko.com/api/v3/simple/price?ids={}&vs_currencies=usd&x_cg_demo_api_key={}",
+            joined.join(","),
+            self.api_key
+        );
+
+        info!("Consultando precios de {} símbolos", ids.len());
+        let response = self.client
+            .get(&url)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            error!("Error de API al obtener precios: {} - {}", status, error_text);
+            return Err(format!("Error de API: {}", status).into());
+        }
+
+        let data = response.json::<CoinGeckoResponse>().await?;
+        let timestamp = chrono::Utc::now().timestamp();
+        let prices: HashMap<String, f64> = data.prices
+            .into_iter()
+            .filter_map(|(id, prices)| ids.get(&id).map(|symbol| (symbol.clone(), prices.price)))
+            .collect();
+        for (symbol, price) in &prices {
+            self.cache.set(CryptoPrice {
+                symbol: symbol.clone(),
+                price: *price,
+                exchange: "coingecko".to_string(),
+                timestamp,
+            });
+        }
+        Ok(prices)
+    }
+
+    fn coin_id(&self, symbol: &str) -> Result<&String, Box<dyn Error + Send + Sync>> {
+        self.symbol_to_id
+            .get(&symbol.to_uppercase())
+            .ok_or_else(|| format!("Símbolo no soportado: {}", symbol).into())
+    }
+
+    /// Serie histórica de precios (USD) de los últimos `days` días.
+    pub async fn get_market_chart(&self, symbol: &str, days: u32) -> Result<Vec<PricePoint>, Box<dyn Error + Send + Sync>> {
+        let coin_id = self.coin_id(symbol)?;
+        let url = format!(
+            "https://api.coingecko.com/api/v3/coins/{}/market_chart?vs_currency=usd&days={}&x_cg_demo_api_key={}",
+            coin_id, days, self.api_key
+        );
+
+        info!("Consultando histórico de {} ({} días)", symbol, days);
+        let response = self.client
+            .get(&url)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            error!("Error de API al obtener histórico de {}: {} - {}", symbol, status, error_text);
+            return Err(format!("Error de API: {}", status).into());
+        }
+
+        let data = response.json::<MarketChartResponse>().await?;
+        Ok(data.prices
+            .into_iter()
+            .map(|(ms, price)| PricePoint {
+                timestamp: (ms / 1000.0) as i64,
+                price,
+            })
+            .collect())
+    }
+
+    /// Serie histórica (USD) entre dos instantes, con su volumen. CoinGecko
+    /// decide la granularidad según la longitud del tramo.
+    pub async fn get_market_chart_range(&self, symbol: &str, from: i64, to: i64) -> Result<MarketChart, Box<dyn Error + Send + Sync>> {
+        let coin_id = self.coin_id(symbol)?;
+        let url = format!(
+            "https://api.coingecko.com/api/v3/coins/{}/market_chart/range?vs_currency=usd&from={}&to={}&x_cg_demo_api_key={}",
+            coin_id, from, to, self.api_key
+        );
+
+        info!("Consultando histórico de {} entre {} y {}", symbol, from, to);
+        let response = self.client
+            .get(&url)
+            .timeout(Duration::from_secs(30))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            error!("Error de API al obtener histórico de {}: {} - {}", symbol, status, error_text);
+            return Err(format!("Error de API: {}", status).into());
+        }
+
+        Ok(response.json::<MarketChart>().await?)
+    }
+
+    /// Velas OHLC (USD) de los últimos `days` días. CoinGecko solo acepta
+    /// 1, 7, 14, 30, 90, 180 y 365.
+    pub async fn get_ohlc(&self, symbol: &str, days: u32) -> Result<Vec<Candle>, Box<dyn Error + Send + Sync>> {
+        let coin_id = self.coin_id(symbol)?;
+        let url = format!(
+            "https://api.coingecko.com/api/v3/coins/{}/ohlc?vs_currency=usd&days={}&x_cg_demo_api_key={}",
+            coin_id, days, self.api_key
+        );
+
+        info!("Consultando velas de {} ({} días)", symbol, days);
+        let response = self.client
+            .get(&url)
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let error_text = response.text().await.unwrap_or_default();
+            error!("Error de API al obtener velas de {}: {} - {}", symbol, status, error_text);
+            return Err(format!("Error de API: {}", status).into());
+        }
+
+        let data = response.json::<Vec<(f64, f64, f64, f64, f64)>>().await?;
+        Ok(data
+            .into_iter()
+            .map(|(ms, open, high, low, close)| Candle {
+                timestamp: (ms / 1000.0) as i64,
+                open,
+                high,
+                low,
+                close,
+            })
+            .collect())
+    }
+
     pub fn supported_symbols(&self) -> Vec<String> {
         self.symbol_to_id.keys().cloned().collect()
     }
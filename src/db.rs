@@ -3,11 +3,17 @@ use std::error::Error;
 use std::fs;
 use std::path::Path;
 use chrono::Utc;
-use crate::models::{User, PriceAlert, ApiKey, AlertType, UserState};
+use crate::models::{User, UserRole, AdminStats, Transaction, Holding, AlertKind, Plan, RegistrationMode, PriceAlert, ApiKey, AlertType, UserState, AuditEvent, AlertTarget, TargetKind, EscalationPolicy, AlertAction, ActionRun, TradeMode, RiskLimits, ExchangeCredentialInfo, SealedCredential, OrderRecord, DcaPlan, DcaRun, DcaOutcome, StrategyRecord, StrategyStatus, PriceTick};
+use crate::risk::{ProposedOrder, TradingActivity};
+use std::collections::HashMap;
 use std::sync::Mutex;
 use tracing::info;
 use serde_json;
 
+/// Tiempo máximo que un asistente del bot puede quedar sin actividad antes de
+/// descartarse (usa la columna `updated_at` de `user_states`).
+pub const USER_STATE_TTL_SECS: i64 = 15 * 60;
+
 pub struct Database {
     conn: Mutex<Connection>,
 }
@@ -91,14 +97,308 @@ impl Database {
             [],
         )?;
 
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS telegram_link_codes (
+                code TEXT PRIMARY KEY,
+                user_id INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL,
+                used_at INTEGER,
+                FOREIGN KEY(user_id) REFERENCES users(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS api_key_codes (
+                code TEXT PRIMARY KEY,
+                user_id INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL,
+                used_at INTEGER,
+                FOREIGN KEY(user_id) REFERENCES users(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS audit_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER,
+                chat_id INTEGER,
+                action TEXT NOT NULL,
+                target TEXT,
+                created_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS alert_targets (
+                alert_id INTEGER NOT NULL,
+                chat_id INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                added_by INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                PRIMARY KEY(alert_id, chat_id),
+                FOREIGN KEY(alert_id) REFERENCES price_alerts(id) ON DELETE CASCADE,
+                FOREIGN KEY(added_by) REFERENCES users(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS alert_escalations (
+                alert_id INTEGER PRIMARY KEY,
+                interval_secs INTEGER NOT NULL,
+                max_attempts INTEGER NOT NULL,
+                secondary_chat_id INTEGER,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                last_notified_at INTEGER,
+                escalated_at INTEGER,
+                FOREIGN KEY(alert_id) REFERENCES price_alerts(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS alert_actions (
+                alert_id INTEGER PRIMARY KEY,
+                side TEXT NOT NULL,
+                quantity REAL NOT NULL,
+                quote_asset TEXT NOT NULL,
+                limit_offset REAL,
+                mode TEXT NOT NULL,
+                exchange TEXT,
+                created_at INTEGER NOT NULL,
+                FOREIGN KEY(alert_id) REFERENCES price_alerts(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS alert_action_runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                alert_id INTEGER NOT NULL,
+                mode TEXT NOT NULL,
+                success BOOLEAN NOT NULL,
+                summary TEXT NOT NULL,
+                executed_at INTEGER NOT NULL,
+                FOREIGN KEY(alert_id) REFERENCES price_alerts(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS trade_ledger (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                mode TEXT NOT NULL,
+                symbol TEXT NOT NULL,
+                side TEXT NOT NULL,
+                quantity REAL NOT NULL,
+                notional REAL NOT NULL,
+                created_at INTEGER NOT NULL,
+                order_id INTEGER,
+                FOREIGN KEY(user_id) REFERENCES users(id),
+                FOREIGN KEY(order_id) REFERENCES orders(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS orders (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                mode TEXT NOT NULL,
+                exchange TEXT NOT NULL,
+                exchange_order_id TEXT NOT NULL,
+                symbol TEXT NOT NULL,
+                side TEXT NOT NULL,
+                order_type TEXT NOT NULL,
+                price REAL,
+                quantity REAL NOT NULL,
+                filled_quantity REAL NOT NULL DEFAULT 0,
+                average_price REAL,
+                fee REAL NOT NULL DEFAULT 0,
+                status TEXT NOT NULL,
+                source TEXT NOT NULL,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL,
+                UNIQUE(user_id, mode, exchange, exchange_order_id),
+                FOREIGN KEY(user_id) REFERENCES users(id)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_orders_status ON orders(status)",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS dca_plans (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                symbol TEXT NOT NULL,
+                amount REAL NOT NULL,
+                quote_asset TEXT NOT NULL,
+                schedule TEXT NOT NULL,
+                max_price REAL,
+                mode TEXT NOT NULL,
+                exchange TEXT,
+                active BOOLEAN NOT NULL DEFAULT 1,
+                next_run_at INTEGER NOT NULL,
+                last_run_at INTEGER,
+                created_at INTEGER NOT NULL,
+                FOREIGN KEY(user_id) REFERENCES users(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS dca_runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                plan_id INTEGER NOT NULL,
+                mode TEXT NOT NULL,
+                outcome TEXT NOT NULL,
+                price REAL,
+                quantity REAL,
+                summary TEXT NOT NULL,
+                executed_at INTEGER NOT NULL,
+                FOREIGN KEY(plan_id) REFERENCES dca_plans(id) ON DELETE CASCADE
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS strategies (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                kind TEXT NOT NULL,
+                mode TEXT NOT NULL,
+                exchange TEXT NOT NULL,
+                symbol TEXT NOT NULL,
+                quote_asset TEXT NOT NULL,
+                config TEXT NOT NULL,
+                state TEXT NOT NULL,
+                status TEXT NOT NULL,
+                realized_profit REAL NOT NULL DEFAULT 0,
+                last_error TEXT,
+                created_at INTEGER NOT NULL,
+                updated_at INTEGER NOT NULL,
+                FOREIGN KEY(user_id) REFERENCES users(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS exchange_credentials (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                exchange TEXT NOT NULL,
+                api_key_hint TEXT NOT NULL,
+                key_id TEXT NOT NULL,
+                nonce BLOB NOT NULL,
+                ciphertext BLOB NOT NULL,
+                created_at INTEGER NOT NULL,
+                UNIQUE(user_id, exchange),
+                FOREIGN KEY(user_id) REFERENCES users(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS settings (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS plans (
+                name TEXT PRIMARY KEY,
+                max_active_alerts INTEGER,
+                min_check_interval INTEGER NOT NULL DEFAULT 0,
+                allowed_kinds TEXT NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "INSERT OR IGNORE INTO plans (name, max_active_alerts, min_check_interval, allowed_kinds)
+             VALUES (?, 10, 0, 'price,depeg,pair_depeg,portfolio')",
+            [Plan::DEFAULT],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS invite_codes (
+                code TEXT PRIMARY KEY,
+                plan TEXT NOT NULL,
+                created_by INTEGER NOT NULL,
+                created_at INTEGER NOT NULL,
+                expires_at INTEGER NOT NULL,
+                used_by INTEGER,
+                used_at INTEGER,
+                FOREIGN KEY(created_by) REFERENCES users(id),
+                FOREIGN KEY(plan) REFERENCES plans(name)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS transactions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id INTEGER NOT NULL,
+                symbol TEXT NOT NULL,
+                side TEXT NOT NULL,
+                quantity REAL NOT NULL,
+                price REAL NOT NULL,
+                fee REAL NOT NULL DEFAULT 0,
+                executed_at INTEGER NOT NULL,
+                note TEXT,
+                FOREIGN KEY(user_id) REFERENCES users(id)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_transactions_user_symbol ON transactions(user_id, symbol, executed_at)",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS holdings (
+                user_id INTEGER NOT NULL,
+                symbol TEXT NOT NULL,
+                quantity REAL NOT NULL,
+                cost_basis REAL NOT NULL,
+                realized_pnl REAL NOT NULL DEFAULT 0,
+                updated_at INTEGER NOT NULL,
+                PRIMARY KEY(user_id, symbol),
+                FOREIGN KEY(user_id) REFERENCES users(id)
+            )",
+            [],
+        )?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS price_history (
+                symbol TEXT NOT NULL,
+                venue TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                price REAL NOT NULL,
+                volume REAL,
+                PRIMARY KEY(symbol, venue, timestamp)
+            )",
+            [],
+        )?;
+
         // Verificar que las tablas se crearon antes de mover conn
         let table_count: i64 = conn.query_row(
-            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name IN ('users', 'price_alerts', 'api_keys', 'user_states')",
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name IN ('users', 'price_alerts', 'api_keys', 'user_states', 'telegram_link_codes', 'api_key_codes', 'audit_log', 'alert_targets', 'alert_escalations', 'alert_actions', 'alert_action_runs', 'trade_ledger', 'orders', 'dca_plans', 'dca_runs', 'strategies', 'exchange_credentials', 'settings', 'plans', 'invite_codes', 'transactions', 'holdings', 'price_history')",
             [],
             |row| row.get(0),
         )?;
 
-        if table_count != 4 {
+        if table_count != 23 {
             return Err("No se pudieron crear todas las tablas".into());
         }
 
@@ -107,6 +407,9 @@ impl Database {
         
         // Migrar datos existentes si es necesario
         Database::migrate_alerts_table(&db.conn.lock().unwrap())?;
+        Database::migrate_users_table(&db.conn.lock().unwrap())?;
+        Database::migrate_alert_state_columns(&db.conn.lock().unwrap())?;
+        Database::migrate_trade_ledger(&db.conn.lock().unwrap())?;
 
         println!("Tablas creadas correctamente");
         Ok(db)
@@ -173,31 +476,132 @@ impl Database {
         Ok(())
     }
 
-    pub fn create_user(&self, username: &str, password_hash: &str) -> SqliteResult<i64> {
-        let conn = self.conn.lock().unwrap();
-        let now = Utc::now().timestamp();
+    fn migrate_users_table(conn: &Connection) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let has_telegram_user_id = conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('users') WHERE name = 'telegram_user_id'",
+            [],
+            |row| row.get::<_, i32>(0),
+        )? > 0;
+
+        if !has_telegram_user_id {
+            info!("Agregando columna telegram_user_id a la tabla users");
+            conn.execute("ALTER TABLE users ADD COLUMN telegram_user_id INTEGER", [])?;
+        }
+
+        let columns = [
+            ("role", "TEXT NOT NULL DEFAULT 'user'"),
+            ("pending_approval", "BOOLEAN NOT NULL DEFAULT 0"),
+            ("plan", "TEXT NOT NULL DEFAULT 'free'"),
+        ];
+        for (column, definition) in columns {
+            let exists = conn.query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('users') WHERE name = ?",
+                [column],
+                |row| row.get::<_, i32>(0),
+            )? > 0;
+
+            if !exists {
+                info!("Agregando columna {} a la tabla users", column);
+                conn.execute(&format!("ALTER TABLE users ADD COLUMN {} {}", column, definition), [])?;
+            }
+        }
         conn.execute(
-            "INSERT INTO users (username, password_hash, created_at) VALUES (?, ?, ?)",
-            params![username, password_hash, now],
+            "CREATE UNIQUE INDEX IF NOT EXISTS idx_users_telegram_user_id ON users(telegram_user_id)",
+            [],
         )?;
-        Ok(conn.last_insert_rowid())
+
+        Ok(())
+    }
+
+    /// Columnas del ciclo de vida de una alerta disparada: hasta cuándo está
+    /// pospuesta y cuándo se reconoció la última notificación.
+    fn migrate_alert_state_columns(conn: &Connection) -> Result<(), Box<dyn Error + Send + Sync>> {
+        for column in ["snoozed_until", "acknowledged_at"] {
+            let exists = conn.query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('price_alerts') WHERE name = ?",
+                [column],
+                |row| row.get::<_, i32>(0),
+            )? > 0;
+
+            if !exists {
+                info!("Agregando columna {} a la tabla price_alerts", column);
+                conn.execute(&format!("ALTER TABLE price_alerts ADD COLUMN {} INTEGER", column), [])?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Cada apunte del registro de riesgo queda ligado a su orden para
+    /// ajustarlo a lo ejecutado cuando se cierra.
+    fn migrate_trade_ledger(conn: &Connection) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let exists = conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('trade_ledger') WHERE name = 'order_id'",
+            [],
+            |row| row.get::<_, i32>(0),
+        )? > 0;
+
+        if !exists {
+            info!("Agregando columna order_id a la tabla trade_ledger");
+            conn.execute("ALTER TABLE trade_ledger ADD COLUMN order_id INTEGER REFERENCES orders(id)", [])?;
+        }
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_trade_ledger_order ON trade_ledger(order_id)",
+            [],
+        )?;
+
+        Ok(())
+    }
+
+    /// Crea un usuario. Si trae código de invitación lo canjea en la misma
+    /// transacción y el usuario recibe el plan de la invitación; un código
+    /// inválido o usado devuelve `QueryReturnedNoRows`.
+    pub fn create_user(&self, username: &str, password_hash: &str, pending_approval: bool, invite_code: Option<&str>) -> SqliteResult<i64> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let id = Self::insert_user(&tx, username, password_hash, None, pending_approval, invite_code)?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    fn insert_user(
+        tx: &rusqlite::Transaction<'_>,
+        username: &str,
+        password_hash: &str,
+        telegram: Option<(i64, i64)>,
+        pending_approval: bool,
+        invite_code: Option<&str>,
+    ) -> SqliteResult<i64> {
+        let now = Utc::now().timestamp();
+        let plan: String = match invite_code {
+            Some(code) => tx.query_row(
+                "UPDATE invite_codes SET used_at = ?1
+                 WHERE code = ?2 AND used_at IS NULL AND expires_at > ?1
+                 RETURNING plan",
+                params![now, code],
+                |row| row.get(0),
+            )?,
+            None => Plan::DEFAULT.to_string(),
+        };
+
+        let (telegram_user_id, chat_id) = telegram.unzip();
+        tx.execute(
+            "INSERT INTO users (username, password_hash, telegram_chat_id, telegram_user_id, created_at, is_active, pending_approval, plan)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            params![username, password_hash, chat_id, telegram_user_id, now, !pending_approval, pending_approval, plan],
+        )?;
+        let id = tx.last_insert_rowid();
+
+        if let Some(code) = invite_code {
+            tx.execute("UPDATE invite_codes SET used_by = ? WHERE code = ?", params![id, code])?;
+        }
+        Ok(id)
     }
 
     pub fn verify_user(&self, username: &str, password_hash: &str) -> SqliteResult<Option<User>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare("SELECT * FROM users WHERE username = ? AND password_hash = ? AND is_active = 1")?;
-        let mut rows = stmt.query_map(params![username, password_hash], |row| {
-            Ok(User {
-                id: row.get(0)?,
-                username: row.get(1)?,
-                password_hash: row.get(2)?,
-                api_key: row.get(3)?,
-                telegram_chat_id: row.get(4)?,
-                created_at: row.get(5)?,
-                last_login: row.get(6)?,
-                is_active: row.get(7)?,
-            })
-        })?;
+        let mut rows = stmt.query_map(params![username, password_hash], Self::row_to_user)?;
 
         match rows.next() {
             Some(result) => Ok(Some(result?)),
@@ -205,7 +609,7 @@ impl Database {
         }
     }
 
-    pub fn save_alert(&self, alert: &PriceAlert) -> SqliteResult<()> {
+    pub fn save_alert(&self, alert: &PriceAlert) -> SqliteResult<i64> {
         let conn = self.conn.lock().unwrap();
         let now = Utc::now().timestamp();
 
@@ -225,19 +629,21 @@ impl Database {
                 true
             ],
         )?;
-        Ok(())
+        Ok(conn.last_insert_rowid())
     }
 
     pub fn get_active_alerts(&self) -> SqliteResult<Vec<PriceAlert>> {
         let conn = self.conn.lock().unwrap();
         info!("Consultando alertas activas de la base de datos");
         let mut stmt = conn.prepare(
-            "SELECT id, user_id, symbol, alert_type, created_at, triggered_at, is_active 
-             FROM price_alerts 
-             WHERE is_active = 1 AND triggered_at IS NULL"
+            "SELECT a.id, a.user_id, a.symbol, a.alert_type, a.created_at, a.triggered_at, a.is_active
+             FROM price_alerts a
+             JOIN users u ON u.id = a.user_id
+             WHERE a.is_active = 1 AND a.triggered_at IS NULL AND u.is_active = 1
+               AND (a.snoozed_until IS NULL OR a.snoozed_until <= ?)"
         )?;
 
-        let alerts = stmt.query_map([], |row| {
+        let alerts = stmt.query_map([Utc::now().timestamp()], |row| {
             let alert_type_json: String = row.get(3)?;
             let alert_type: AlertType = serde_json::from_str(&alert_type_json)
                 .map_err(|e| rusqlite::Error::InvalidParameterName(e.to_string()))?;
@@ -259,13 +665,20 @@ impl Database {
     }
 
     pub fn mark_alert_triggered(&self, alert_id: i64) -> SqliteResult<()> {
-        let conn = self.conn.lock().unwrap();
+        let mut conn = self.conn.lock().unwrap();
         let now = Utc::now().timestamp();
-        conn.execute(
-            "UPDATE price_alerts SET triggered_at = ?, is_active = 0 WHERE id = ?",
+        let tx = conn.transaction()?;
+        tx.execute(
+            "UPDATE price_alerts SET triggered_at = ?, is_active = 0, acknowledged_at = NULL WHERE id = ?",
             params![now, alert_id],
         )?;
-        Ok(())
+        // La notificación del disparo cuenta como el primer aviso
+        tx.execute(
+            "UPDATE alert_escalations SET attempts = 1, last_notified_at = ?, escalated_at = NULL
+             WHERE alert_id = ?",
+            params![now, alert_id],
+        )?;
+        tx.commit()
     }
 
     pub fn create_api_key(&self, user_id: i64) -> SqliteResult<ApiKey> {
@@ -308,18 +721,7 @@ impl Database {
             "#
         )?;
         
-        let mut rows = stmt.query_map(params![api_key, now], |row| {
-            Ok(User {
-                id: row.get(0)?,
-                username: row.get(1)?,
-                password_hash: row.get(2)?,
-                api_key: row.get(3)?,
-                telegram_chat_id: row.get(4)?,
-                created_at: row.get(5)?,
-                last_login: row.get(6)?,
-                is_active: row.get(7)?,
-            })
-        })?;
+        let mut rows = stmt.query_map(params![api_key, now], Self::row_to_user)?;
 
         match rows.next() {
             Some(result) => {
@@ -375,21 +777,26 @@ impl Database {
         Ok(alerts)
     }
 
+    fn row_to_user(row: &rusqlite::Row<'_>) -> SqliteResult<User> {
+        Ok(User {
+            id: row.get("id")?,
+            username: row.get("username")?,
+            password_hash: row.get("password_hash")?,
+            api_key: row.get("api_key")?,
+            telegram_chat_id: row.get("telegram_chat_id")?,
+            created_at: row.get("created_at")?,
+            last_login: row.get("last_login")?,
+            is_active: row.get("is_active")?,
+            role: row.get("role")?,
+            pending_approval: row.get("pending_approval")?,
+            plan: row.get("plan")?,
+        })
+    }
+
     pub fn get_user_by_username(&self, username: &str) -> SqliteResult<Option<User>> {
         let conn = self.conn.lock().unwrap();
         let mut stmt = conn.prepare("SELECT * FROM users WHERE username = ? AND is_active = 1")?;
-        let mut rows = stmt.query_map(params![username], |row| {
-            Ok(User {
-                id: row.get(0)?,
-                username: row.get(1)?,
-                password_hash: row.get(2)?,
-                api_key: row.get(3)?,
-                telegram_chat_id: row.get(4)?,
-                created_at: row.get(5)?,
-                last_login: row.get(6)?,
-                is_active: row.get(7)?,
-            })
-        })?;
+        let mut rows = stmt.query_map(params![username], Self::row_to_user)?;
 
         match rows.next() {
             Some(result) => Ok(Some(result?)),
@@ -436,85 +843,460 @@ impl Database {
         Ok(())
     }
 
-    pub fn update_user_telegram_chat_id(&self, user_id: i64, chat_id: i64) -> SqliteResult<()> {
+    /// Pausa una alerta activa. Las alertas ya disparadas no cambian.
+    pub fn pause_alert(&self, alert_id: i64) -> SqliteResult<bool> {
         let conn = self.conn.lock().unwrap();
-        conn.execute(
-            "UPDATE users SET telegram_chat_id = ? WHERE id = ?",
-            params![chat_id, user_id],
+        let updated = conn.execute(
+            "UPDATE price_alerts SET is_active = 0 WHERE id = ? AND is_active = 1",
+            params![alert_id],
         )?;
-        Ok(())
+        Ok(updated > 0)
     }
 
-    pub fn get_user_api_key(&self, user_id: i64) -> SqliteResult<Option<String>> {
+    /// Reactiva una alerta pausada o disparada para que el monitor vuelva a evaluarla.
+    pub fn resume_alert(&self, alert_id: i64) -> SqliteResult<bool> {
         let conn = self.conn.lock().unwrap();
-        let mut stmt = conn.prepare(
-            "SELECT key FROM api_keys 
-             WHERE user_id = ? AND is_active = 1 
-             ORDER BY created_at DESC LIMIT 1"
+        let updated = conn.execute(
+            "UPDATE price_alerts
+             SET is_active = 1, triggered_at = NULL, snoozed_until = NULL, acknowledged_at = NULL
+             WHERE id = ?",
+            params![alert_id],
         )?;
-        
-        let mut rows = stmt.query_map([user_id], |row| row.get(0))?;
-        
-        match rows.next() {
-            Some(result) => Ok(Some(result?)),
-            None => Ok(None),
-        }
+        Ok(updated > 0)
     }
 
-    pub fn get_user_telegram_chat_id(&self, user_id: i64) -> SqliteResult<Option<i64>> {
+    /// Rearma la alerta pero el monitor la ignora hasta `until`. Posponer
+    /// cuenta como haber visto la notificación.
+    pub fn snooze_alert(&self, alert_id: i64, until: i64) -> SqliteResult<bool> {
         let conn = self.conn.lock().unwrap();
-        conn.query_row(
-            "SELECT telegram_chat_id FROM users WHERE id = ?",
-            [user_id],
-            |row| row.get(0)
-        ).optional()
+        let updated = conn.execute(
+            "UPDATE price_alerts
+             SET is_active = 1, triggered_at = NULL, snoozed_until = ?, acknowledged_at = ?
+             WHERE id = ?",
+            params![until, Utc::now().timestamp(), alert_id],
+        )?;
+        Ok(updated > 0)
     }
 
-    pub fn get_user_by_telegram_id(&self, chat_id: i64) -> SqliteResult<Option<User>> {
+    /// Marca como vista la última notificación. Devuelve `false` si ya lo estaba.
+    pub fn acknowledge_alert(&self, alert_id: i64) -> SqliteResult<bool> {
         let conn = self.conn.lock().unwrap();
-        conn.query_row(
-            "SELECT * FROM users WHERE telegram_chat_id = ?",
-            [chat_id],
-            |row| {
-                Ok(User {
-                    id: row.get(0)?,
-                    username: row.get(1)?,
-                    password_hash: row.get(2)?,
-                    api_key: row.get(3)?,
-                    telegram_chat_id: row.get(4)?,
-                    created_at: row.get(5)?,
-                    last_login: row.get(6)?,
-                    is_active: row.get(7)?,
-                })
-            }
-        ).optional()
+        let updated = conn.execute(
+            "UPDATE price_alerts SET acknowledged_at = ? WHERE id = ? AND acknowledged_at IS NULL",
+            params![Utc::now().timestamp(), alert_id],
+        )?;
+        Ok(updated > 0)
     }
 
-    pub fn save_user_state(&self, chat_id: i64, state: &UserState) -> SqliteResult<()> {
+    /// Crea o reemplaza la política de escalado, reiniciando su estado.
+    pub fn set_escalation_policy(&self, alert_id: i64, interval_secs: i64, max_attempts: i64, secondary_chat_id: Option<i64>) -> SqliteResult<()> {
         let conn = self.conn.lock().unwrap();
-        let now = chrono::Utc::now().timestamp();
-        let state_json = serde_json::to_string(state)
-            .map_err(|e| rusqlite::Error::InvalidParameterName(e.to_string()))?;
-
         conn.execute(
-            "INSERT INTO user_states (chat_id, state, created_at, updated_at)
-             VALUES (?1, ?2, ?3, ?3)
-             ON CONFLICT(chat_id) DO UPDATE SET
-             state = ?2,
-             updated_at = ?3",
-            params![chat_id, state_json, now],
+            "INSERT OR REPLACE INTO alert_escalations (alert_id, interval_secs, max_attempts, secondary_chat_id, attempts)
+             VALUES (?, ?, ?, ?, 0)",
+            params![alert_id, interval_secs, max_attempts, secondary_chat_id],
         )?;
-
+        Ok(())
+    }
+
+    pub fn remove_escalation_policy(&self, alert_id: i64) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let deleted = conn.execute("DELETE FROM alert_escalations WHERE alert_id = ?", params![alert_id])?;
+        Ok(deleted > 0)
+    }
+
+    pub fn get_escalation_policy(&self, alert_id: i64) -> SqliteResult<Option<EscalationPolicy>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT alert_id, interval_secs, max_attempts, secondary_chat_id, attempts, last_notified_at, escalated_at
+             FROM alert_escalations WHERE alert_id = ?",
+            [alert_id],
+            Self::row_to_escalation,
+        )
+        .optional()
+    }
+
+    /// Políticas de alertas disparadas, sin reconocer, cuyo próximo aviso ya venció.
+    pub fn get_due_escalations(&self, now: i64) -> SqliteResult<Vec<EscalationPolicy>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT e.alert_id, e.interval_secs, e.max_attempts, e.secondary_chat_id, e.attempts, e.last_notified_at, e.escalated_at
+             FROM alert_escalations e
+             JOIN price_alerts a ON a.id = e.alert_id
+             JOIN users u ON u.id = a.user_id
+             WHERE a.triggered_at IS NOT NULL
+               AND u.is_active = 1
+               AND a.acknowledged_at IS NULL
+               AND e.last_notified_at IS NOT NULL
+               AND e.last_notified_at + e.interval_secs <= ?"
+        )?;
+        let policies = stmt.query_map([now], Self::row_to_escalation)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(policies)
+    }
+
+    pub fn record_escalation_attempt(&self, alert_id: i64, escalated: bool) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        let now = Utc::now().timestamp();
+        conn.execute(
+            "UPDATE alert_escalations
+             SET attempts = attempts + 1,
+                 last_notified_at = ?,
+                 escalated_at = CASE WHEN ? AND escalated_at IS NULL THEN ? ELSE escalated_at END
+             WHERE alert_id = ?",
+            params![now, escalated, now, alert_id],
+        )?;
+        Ok(())
+    }
+
+    fn row_to_escalation(row: &rusqlite::Row<'_>) -> SqliteResult<EscalationPolicy> {
+        Ok(EscalationPolicy {
+            alert_id: row.get(0)?,
+            interval_secs: row.get(1)?,
+            max_attempts: row.get(2)?,
+            secondary_chat_id: row.get(3)?,
+            attempts: row.get(4)?,
+            last_notified_at: row.get(5)?,
+            escalated_at: row.get(6)?,
+        })
+    }
+
+    /// Crea o reemplaza la acción automática de una alerta.
+    pub fn set_alert_action(&self, action: &AlertAction) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO alert_actions (alert_id, side, quantity, quote_asset, limit_offset, mode, exchange, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                action.alert_id,
+                action.side,
+                action.quantity,
+                action.quote_asset,
+                action.limit_offset,
+                action.mode,
+                action.exchange,
+                action.created_at,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn remove_alert_action(&self, alert_id: i64) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let deleted = conn.execute("DELETE FROM alert_actions WHERE alert_id = ?", params![alert_id])?;
+        Ok(deleted > 0)
+    }
+
+    pub fn get_alert_action(&self, alert_id: i64) -> SqliteResult<Option<AlertAction>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT alert_id, side, quantity, quote_asset, limit_offset, mode, exchange, created_at
+             FROM alert_actions WHERE alert_id = ?",
+            [alert_id],
+            |row| Ok(AlertAction {
+                alert_id: row.get(0)?,
+                side: row.get(1)?,
+                quantity: row.get(2)?,
+                quote_asset: row.get(3)?,
+                limit_offset: row.get(4)?,
+                mode: row.get(5)?,
+                exchange: row.get(6)?,
+                created_at: row.get(7)?,
+            }),
+        )
+        .optional()
+    }
+
+    pub fn record_action_run(&self, alert_id: i64, mode: TradeMode, success: bool, summary: &str) -> SqliteResult<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO alert_action_runs (alert_id, mode, success, summary, executed_at) VALUES (?, ?, ?, ?, ?)",
+            params![alert_id, mode, success, summary, Utc::now().timestamp()],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Últimas ejecuciones de la acción de una alerta, de la más reciente a la más antigua.
+    pub fn get_action_runs(&self, alert_id: i64, limit: i64) -> SqliteResult<Vec<ActionRun>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, alert_id, mode, success, summary, executed_at
+             FROM alert_action_runs WHERE alert_id = ?
+             ORDER BY executed_at DESC, id DESC LIMIT ?"
+        )?;
+        let runs = stmt.query_map(params![alert_id, limit], |row| Ok(ActionRun {
+            id: row.get(0)?,
+            alert_id: row.get(1)?,
+            mode: row.get(2)?,
+            success: row.get(3)?,
+            summary: row.get(4)?,
+            executed_at: row.get(5)?,
+        }))?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(runs)
+    }
+
+    pub fn update_alert_type(&self, alert_id: i64, alert_type: &AlertType) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        let alert_type_json = serde_json::to_string(alert_type)
+            .map_err(|e| rusqlite::Error::InvalidParameterName(e.to_string()))?;
+        conn.execute(
+            "UPDATE price_alerts SET alert_type = ? WHERE id = ?",
+            params![alert_type_json, alert_id],
+        )?;
+        Ok(())
+    }
+
+    /// Agrega un chat como destino de la alerta. Si ya lo era no hace nada.
+    pub fn add_alert_target(&self, alert_id: i64, chat_id: i64, kind: TargetKind, added_by: i64) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let inserted = conn.execute(
+            "INSERT OR IGNORE INTO alert_targets (alert_id, chat_id, kind, added_by, created_at)
+             VALUES (?, ?, ?, ?, ?)",
+            params![alert_id, chat_id, kind, added_by, Utc::now().timestamp()],
+        )?;
+        Ok(inserted > 0)
+    }
+
+    pub fn remove_alert_target(&self, alert_id: i64, chat_id: i64) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let deleted = conn.execute(
+            "DELETE FROM alert_targets WHERE alert_id = ? AND chat_id = ?",
+            params![alert_id, chat_id],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    pub fn get_alert_targets(&self, alert_id: i64) -> SqliteResult<Vec<AlertTarget>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT alert_id, chat_id, kind, added_by, created_at
+             FROM alert_targets
+             WHERE alert_id = ?
+             ORDER BY created_at"
+        )?;
+        let targets = stmt.query_map([alert_id], |row| {
+            Ok(AlertTarget {
+                alert_id: row.get(0)?,
+                chat_id: row.get(1)?,
+                kind: row.get(2)?,
+                added_by: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })?
+        .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(targets)
+    }
+
+    /// Alertas que notifican a un chat sin ser del usuario vinculado a él:
+    /// las de un grupo o canal, o las suscripciones de un chat privado.
+    pub fn get_chat_alerts(&self, chat_id: i64) -> SqliteResult<Vec<PriceAlert>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT a.id, a.user_id, a.symbol, a.alert_type, a.created_at, a.triggered_at, a.is_active
+             FROM price_alerts a
+             JOIN alert_targets t ON t.alert_id = a.id
+             WHERE t.chat_id = ?
+             ORDER BY a.created_at DESC"
+        )?;
+        let alerts = stmt.query_map([chat_id], |row| {
+            let alert_type_json: String = row.get(3)?;
+            let alert_type: AlertType = serde_json::from_str(&alert_type_json)
+                .map_err(|e| rusqlite::Error::InvalidParameterName(e.to_string()))?;
+
+            Ok(PriceAlert {
+                id: Some(row.get(0)?),
+                user_id: row.get(1)?,
+                symbol: row.get(2)?,
+                alert_type,
+                created_at: row.get(4)?,
+                triggered_at: row.get(5)?,
+                is_active: row.get(6)?,
+            })
+        })?
+        .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(alerts)
+    }
+
+    pub fn update_user_telegram_chat_id(&self, user_id: i64, chat_id: i64) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE users SET telegram_chat_id = ? WHERE id = ?",
+            params![chat_id, user_id],
+        )?;
+        Ok(())
+    }
+
+    /// Crea un usuario registrado desde Telegram. Estas cuentas no tienen
+    /// contraseña: `password_hash` guarda un marcador que nunca verifica.
+    pub fn create_telegram_user(
+        &self,
+        username: &str,
+        password_hash: &str,
+        telegram_user_id: i64,
+        chat_id: i64,
+        pending_approval: bool,
+        invite_code: Option<&str>,
+    ) -> SqliteResult<i64> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "UPDATE users SET telegram_chat_id = NULL WHERE telegram_chat_id = ?",
+            params![chat_id],
+        )?;
+        let id = Self::insert_user(&tx, username, password_hash, Some((telegram_user_id, chat_id)), pending_approval, invite_code)?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    pub fn get_user_by_telegram_user_id(&self, telegram_user_id: i64) -> SqliteResult<Option<User>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT * FROM users WHERE telegram_user_id = ?",
+            [telegram_user_id],
+            Self::row_to_user
+        ).optional()
+    }
+
+    /// Vincula un chat de Telegram a un usuario. Un chat solo puede estar
+    /// vinculado a una cuenta, así que se desvincula de cualquier otra.
+    pub fn link_telegram_chat(&self, user_id: i64, chat_id: i64) -> SqliteResult<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "UPDATE users SET telegram_chat_id = NULL WHERE telegram_chat_id = ? AND id != ?",
+            params![chat_id, user_id],
+        )?;
+        tx.execute(
+            "UPDATE users SET telegram_chat_id = ? WHERE id = ?",
+            params![chat_id, user_id],
+        )?;
+        tx.commit()
+    }
+
+    pub fn unlink_telegram_chat(&self, user_id: i64) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE users SET telegram_chat_id = NULL WHERE id = ?",
+            params![user_id],
+        )?;
+        Ok(())
+    }
+
+    /// Genera un código de vinculación de un solo uso válido por 10 minutos.
+    /// Los códigos anteriores sin usar del mismo usuario quedan invalidados.
+    pub fn create_telegram_link_code(&self, user_id: i64) -> SqliteResult<(String, i64)> {
+        self.create_one_time_code("telegram_link_codes", user_id)
+    }
+
+    /// Marca el código como usado y devuelve el usuario al que pertenece, si
+    /// el código existe, no fue usado y no expiró.
+    pub fn consume_telegram_link_code(&self, code: &str) -> SqliteResult<Option<i64>> {
+        self.consume_one_time_code("telegram_link_codes", code)
+    }
+
+    /// Código de un solo uso para obtener una API key desde la API REST
+    /// sin que la key pase por el chat de Telegram.
+    pub fn create_api_key_code(&self, user_id: i64) -> SqliteResult<(String, i64)> {
+        self.create_one_time_code("api_key_codes", user_id)
+    }
+
+    pub fn consume_api_key_code(&self, code: &str) -> SqliteResult<Option<i64>> {
+        self.consume_one_time_code("api_key_codes", code)
+    }
+
+    fn create_one_time_code(&self, table: &str, user_id: i64) -> SqliteResult<(String, i64)> {
+        let conn = self.conn.lock().unwrap();
+        let now = Utc::now().timestamp();
+        let code = generate_code(24);
+        let expires_at = now + 10 * 60;
+
+        conn.execute(
+            &format!("DELETE FROM {} WHERE user_id = ? AND used_at IS NULL", table),
+            params![user_id],
+        )?;
+        conn.execute(
+            &format!("INSERT INTO {} (code, user_id, created_at, expires_at) VALUES (?, ?, ?, ?)", table),
+            params![code, user_id, now, expires_at],
+        )?;
+        Ok((code, expires_at))
+    }
+
+    fn consume_one_time_code(&self, table: &str, code: &str) -> SqliteResult<Option<i64>> {
+        let conn = self.conn.lock().unwrap();
+        let now = Utc::now().timestamp();
+        conn.query_row(
+            &format!(
+                "UPDATE {} SET used_at = ?1
+                 WHERE code = ?2 AND used_at IS NULL AND expires_at > ?1
+                 RETURNING user_id",
+                table
+            ),
+            params![now, code],
+            |row| row.get(0),
+        ).optional()
+    }
+
+    pub fn get_user_api_key(&self, user_id: i64) -> SqliteResult<Option<String>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT key FROM api_keys 
+             WHERE user_id = ? AND is_active = 1 
+             ORDER BY created_at DESC LIMIT 1"
+        )?;
+        
+        let mut rows = stmt.query_map([user_id], |row| row.get(0))?;
+        
+        match rows.next() {
+            Some(result) => Ok(Some(result?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn get_user_telegram_chat_id(&self, user_id: i64) -> SqliteResult<Option<i64>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT telegram_chat_id FROM users WHERE id = ?",
+            [user_id],
+            |row| row.get(0)
+        ).optional()
+    }
+
+    pub fn get_user_by_telegram_id(&self, chat_id: i64) -> SqliteResult<Option<User>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT * FROM users WHERE telegram_chat_id = ?",
+            [chat_id],
+            Self::row_to_user
+        ).optional()
+    }
+
+    pub fn save_user_state(&self, chat_id: i64, state: &UserState) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        let now = chrono::Utc::now().timestamp();
+        let state_json = serde_json::to_string(state)
+            .map_err(|e| rusqlite::Error::InvalidParameterName(e.to_string()))?;
+
+        conn.execute(
+            "INSERT INTO user_states (chat_id, state, created_at, updated_at)
+             VALUES (?1, ?2, ?3, ?3)
+             ON CONFLICT(chat_id) DO UPDATE SET
+             state = ?2,
+             updated_at = ?3",
+            params![chat_id, state_json, now],
+        )?;
+
         Ok(())
     }
 
     pub fn get_user_state(&self, chat_id: i64) -> SqliteResult<Option<UserState>> {
         let conn = self.conn.lock().unwrap();
+        let expires_before = chrono::Utc::now().timestamp() - USER_STATE_TTL_SECS;
         let mut stmt = conn.prepare(
-            "SELECT state FROM user_states WHERE chat_id = ?"
+            "SELECT state FROM user_states WHERE chat_id = ? AND updated_at > ?"
         )?;
 
-        let state = stmt.query_row([chat_id], |row| {
+        let state = stmt.query_row(params![chat_id, expires_before], |row| {
             let state_json: String = row.get(0)?;
             let state: UserState = serde_json::from_str(&state_json)
                 .map_err(|e| rusqlite::Error::InvalidParameterName(e.to_string()))?;
@@ -532,15 +1314,1106 @@ impl Database {
         )?;
         Ok(())
     }
-}
 
-fn generate_api_key() -> String {
-    use rand::{thread_rng, Rng};
-    use rand::distributions::Alphanumeric;
+    /// Busca por ID (`#12` o `12`) o por nombre, incluyendo cuentas desactivadas.
+    pub fn find_user(&self, ident: &str) -> SqliteResult<Option<User>> {
+        let conn = self.conn.lock().unwrap();
+        let id = ident.trim_start_matches('#').parse::<i64>().ok();
+        conn.query_row(
+            "SELECT * FROM users WHERE id = ? OR username = ? ORDER BY id = ? DESC LIMIT 1",
+            params![id, ident, id],
+            Self::row_to_user,
+        ).optional()
+    }
 
-    thread_rng()
-        .sample_iter(&Alphanumeric)
-        .take(32)
-        .map(char::from)
-        .collect()
-} 
\ No newline at end of file
+    pub fn list_users(&self, limit: i64, offset: i64) -> SqliteResult<Vec<User>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT * FROM users ORDER BY id LIMIT ? OFFSET ?")?;
+        let users = stmt.query_map(params![limit, offset], Self::row_to_user)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(users)
+    }
+
+    pub fn count_users(&self) -> SqliteResult<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row("SELECT COUNT(*) FROM users", [], |row| row.get(0))
+    }
+
+    /// Activa o desactiva una cuenta. Una cuenta desactivada no puede usar la
+    /// API ni el bot y sus alertas dejan de evaluarse.
+    pub fn set_user_active(&self, user_id: i64, active: bool) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE users SET is_active = ?1, pending_approval = pending_approval AND NOT ?1
+             WHERE id = ?2 AND is_active != ?1",
+            params![active, user_id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    pub fn get_user(&self, user_id: i64) -> SqliteResult<Option<User>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row("SELECT * FROM users WHERE id = ?", [user_id], Self::row_to_user).optional()
+    }
+
+    pub fn list_pending_users(&self) -> SqliteResult<Vec<User>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT * FROM users WHERE pending_approval = 1 ORDER BY created_at")?;
+        let users = stmt.query_map([], Self::row_to_user)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(users)
+    }
+
+    pub fn approve_user(&self, user_id: i64) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE users SET is_active = 1, pending_approval = 0 WHERE id = ? AND pending_approval = 1",
+            [user_id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Borra una solicitud de registro pendiente junto con sus claves y códigos.
+    pub fn reject_user(&self, user_id: i64) -> SqliteResult<bool> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let pending = tx.query_row(
+            "SELECT COUNT(*) FROM users WHERE id = ? AND pending_approval = 1",
+            [user_id],
+            |row| row.get::<_, i64>(0),
+        )? > 0;
+        if !pending {
+            return Ok(false);
+        }
+        for table in ["api_keys", "api_key_codes", "telegram_link_codes"] {
+            tx.execute(&format!("DELETE FROM {} WHERE user_id = ?", table), [user_id])?;
+        }
+        tx.execute("DELETE FROM users WHERE id = ?", [user_id])?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    /// Chats privados de los administradores activos, para avisarles de
+    /// solicitudes de registro.
+    pub fn get_admin_chat_ids(&self) -> SqliteResult<Vec<i64>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT telegram_chat_id FROM users
+             WHERE role = 'admin' AND is_active = 1 AND telegram_chat_id IS NOT NULL"
+        )?;
+        let chat_ids = stmt.query_map([], |row| row.get(0))?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(chat_ids)
+    }
+
+    pub fn get_registration_mode(&self) -> SqliteResult<RegistrationMode> {
+        let conn = self.conn.lock().unwrap();
+        let value: Option<String> = conn.query_row(
+            "SELECT value FROM settings WHERE key = 'registration_mode'",
+            [],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(value.and_then(|v| v.parse().ok()).unwrap_or_default())
+    }
+
+    pub fn set_registration_mode(&self, mode: RegistrationMode) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('registration_mode', ?)",
+            [mode.as_str()],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_risk_limits(&self) -> SqliteResult<RiskLimits> {
+        let conn = self.conn.lock().unwrap();
+        let value: Option<String> = conn.query_row(
+            "SELECT value FROM settings WHERE key = 'risk_limits'",
+            [],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(value.and_then(|v| serde_json::from_str(&v).ok()).unwrap_or_default())
+    }
+
+    pub fn set_risk_limits(&self, limits: &RiskLimits) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        let value = serde_json::to_string(limits)
+            .map_err(|e| rusqlite::Error::InvalidParameterName(e.to_string()))?;
+        conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('risk_limits', ?)",
+            [value],
+        )?;
+        Ok(())
+    }
+
+    /// Interruptor general: con la operativa detenida no sale ninguna orden automática.
+    pub fn is_trading_halted(&self) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let value: Option<String> = conn.query_row(
+            "SELECT value FROM settings WHERE key = 'trading_halted'",
+            [],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(value.as_deref() == Some("1"))
+    }
+
+    pub fn set_trading_halted(&self, halted: bool) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO settings (key, value) VALUES ('trading_halted', ?)",
+            [if halted { "1" } else { "0" }],
+        )?;
+        Ok(())
+    }
+
+    /// Comprueba `order` con `check` y, si pasa, la aparta en el registro de
+    /// riesgo en la misma transacción, para que dos envíos simultáneos no
+    /// pasen ambos la comprobación. Devuelve el id del apunte, que se cierra
+    /// con `settle_trade_reservation` cuando se sabe qué se colocó.
+    pub fn reserve_trade<E: From<rusqlite::Error>>(
+        &self,
+        user_id: i64,
+        mode: TradeMode,
+        order: &ProposedOrder,
+        check: impl FnOnce(&TradingActivity) -> Result<(), E>,
+    ) -> Result<i64, E> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let activity = Self::trading_activity(&tx, user_id, mode, &order.symbol)?;
+        check(&activity)?;
+        tx.execute(
+            "INSERT INTO trade_ledger (user_id, mode, symbol, side, quantity, notional, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![user_id, mode, order.symbol, order.side, order.quantity, order.notional(), Utc::now().timestamp()],
+        )?;
+        let id = tx.last_insert_rowid();
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// Sustituye el apunte reservado por las órdenes que realmente se
+    /// colocaron: `(id de la orden, cantidad, nominal)`. Sin órdenes, la
+    /// reserva simplemente se libera.
+    pub fn settle_trade_reservation(&self, reservation_id: i64, orders: &[(i64, f64, f64)]) -> SqliteResult<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for (order_id, quantity, notional) in orders {
+            tx.execute(
+                "INSERT INTO trade_ledger (user_id, mode, symbol, side, quantity, notional, created_at, order_id)
+                 SELECT user_id, mode, symbol, side, ?, ?, created_at, ? FROM trade_ledger WHERE id = ?",
+                params![quantity, notional, order_id, reservation_id],
+            )?;
+        }
+        tx.execute("DELETE FROM trade_ledger WHERE id = ?", [reservation_id])?;
+        tx.commit()
+    }
+
+    /// Deja en el registro de riesgo solo lo ejecutado de una orden que se
+    /// cerró (cancelada, caducada o rechazada con parte ejecutada o nada).
+    pub fn settle_trade(&self, order_id: i64, filled_quantity: f64) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        Self::settle_trade_in(&conn, order_id, filled_quantity)
+    }
+
+    fn settle_trade_in(conn: &Connection, order_id: i64, filled_quantity: f64) -> SqliteResult<()> {
+        conn.execute(
+            "UPDATE trade_ledger SET
+                notional = CASE WHEN quantity > 0 THEN notional * ?1 / quantity ELSE 0.0 END,
+                quantity = ?1
+             WHERE order_id = ?2 AND quantity > ?1",
+            params![filled_quantity, order_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn get_trading_activity(&self, user_id: i64, mode: TradeMode, symbol: &str) -> SqliteResult<TradingActivity> {
+        let conn = self.conn.lock().unwrap();
+        Self::trading_activity(&conn, user_id, mode, symbol)
+    }
+
+    fn trading_activity(conn: &Connection, user_id: i64, mode: TradeMode, symbol: &str) -> SqliteResult<TradingActivity> {
+        let now = Utc::now();
+        let day_start = now.date_naive().and_hms_opt(0, 0, 0)
+            .map(|start| start.and_utc().timestamp())
+            .unwrap_or_default();
+        conn.query_row(
+            "SELECT
+                COALESCE(SUM(CASE WHEN created_at >= ? THEN notional END), 0.0),
+                COUNT(CASE WHEN created_at >= ? THEN 1 END),
+                COALESCE(SUM(CASE WHEN symbol = ? THEN
+                    CASE side WHEN 'buy' THEN quantity ELSE -quantity END
+                END), 0.0)
+             FROM trade_ledger WHERE user_id = ? AND mode = ?",
+            params![day_start, now.timestamp() - 60, symbol, user_id, mode],
+            |row| Ok(TradingActivity {
+                daily_notional: row.get(0)?,
+                orders_last_minute: row.get(1)?,
+                net_position: row.get(2)?,
+            }),
+        )
+    }
+
+    /// Guarda una orden o, si ya existe, su estado actual. Devuelve su id.
+    pub fn save_order(&self, order: &OrderRecord) -> SqliteResult<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "INSERT INTO orders (user_id, mode, exchange, exchange_order_id, symbol, side, order_type, price,
+                quantity, filled_quantity, average_price, fee, status, source, created_at, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(user_id, mode, exchange, exchange_order_id) DO UPDATE SET
+                filled_quantity = excluded.filled_quantity,
+                average_price = excluded.average_price,
+                fee = excluded.fee,
+                status = excluded.status,
+                updated_at = excluded.updated_at,
+                -- El sondeo puede verla antes de que se guarde quién la envió
+                source = CASE WHEN excluded.source = 'external' THEN orders.source ELSE excluded.source END
+             RETURNING id",
+            params![
+                order.user_id, order.mode, order.exchange, order.exchange_order_id, order.symbol,
+                order.side, order.order_type, order.price, order.quantity, order.filled_quantity,
+                order.average_price, order.fee, order.status, order.source, order.created_at, order.updated_at,
+            ],
+            |row| row.get(0),
+        )
+    }
+
+    pub fn order_exists(&self, user_id: i64, mode: TradeMode, exchange: &str, exchange_order_id: &str) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT COUNT(*) FROM orders WHERE user_id = ? AND mode = ? AND exchange = ? AND exchange_order_id = ?",
+            params![user_id, mode, exchange, exchange_order_id],
+            |row| row.get::<_, i64>(0),
+        ).map(|count| count > 0)
+    }
+
+    /// Órdenes de todos los usuarios que siguen abiertas según el último sondeo.
+    pub fn get_open_orders(&self) -> SqliteResult<Vec<OrderRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, user_id, mode, exchange, exchange_order_id, symbol, side, order_type, price,
+                quantity, filled_quantity, average_price, fee, status, source, created_at, updated_at
+             FROM orders WHERE status IN ('new', 'partially_filled')
+             ORDER BY user_id, mode, exchange, id"
+        )?;
+        let orders = stmt.query_map([], Self::row_to_order)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(orders)
+    }
+
+    /// Órdenes de un usuario, de la más reciente a la más antigua.
+    pub fn get_user_orders(&self, user_id: i64, limit: i64) -> SqliteResult<Vec<OrderRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, user_id, mode, exchange, exchange_order_id, symbol, side, order_type, price,
+                quantity, filled_quantity, average_price, fee, status, source, created_at, updated_at
+             FROM orders WHERE user_id = ?
+             ORDER BY created_at DESC, id DESC LIMIT ?"
+        )?;
+        let orders = stmt.query_map(params![user_id, limit], Self::row_to_order)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(orders)
+    }
+
+    /// Cuentas y pares en los que se ha operado en un modo: (usuario,
+    /// exchange, símbolo). Son los que se vigilan en busca de órdenes ajenas.
+    pub fn get_traded_markets(&self, mode: TradeMode) -> SqliteResult<Vec<(i64, String, String)>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT user_id, exchange, symbol FROM orders WHERE mode = ? ORDER BY user_id, exchange, symbol"
+        )?;
+        let markets = stmt.query_map([mode], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(markets)
+    }
+
+    /// Da por caducadas las órdenes abiertas de un modo y deja en el registro
+    /// de riesgo solo lo que llegaron a ejecutar. Devuelve cuántas eran.
+    pub fn expire_open_orders(&self, mode: TradeMode) -> SqliteResult<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let open: Vec<(i64, f64)> = {
+            let mut stmt = tx.prepare(
+                "SELECT id, filled_quantity FROM orders WHERE mode = ? AND status IN ('new', 'partially_filled')"
+            )?;
+            let open = stmt.query_map([mode], |row| Ok((row.get(0)?, row.get(1)?)))?
+                .collect::<SqliteResult<Vec<_>>>()?;
+            open
+        };
+        for (order_id, filled_quantity) in &open {
+            tx.execute(
+                "UPDATE orders SET status = 'expired', updated_at = ? WHERE id = ?",
+                params![Utc::now().timestamp(), order_id],
+            )?;
+            Self::settle_trade_in(&tx, *order_id, *filled_quantity)?;
+        }
+        tx.commit()?;
+        Ok(open.len())
+    }
+
+    fn row_to_order(row: &rusqlite::Row<'_>) -> SqliteResult<OrderRecord> {
+        Ok(OrderRecord {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            mode: row.get(2)?,
+            exchange: row.get(3)?,
+            exchange_order_id: row.get(4)?,
+            symbol: row.get(5)?,
+            side: row.get(6)?,
+            order_type: row.get(7)?,
+            price: row.get(8)?,
+            quantity: row.get(9)?,
+            filled_quantity: row.get(10)?,
+            average_price: row.get(11)?,
+            fee: row.get(12)?,
+            status: row.get(13)?,
+            source: row.get(14)?,
+            created_at: row.get(15)?,
+            updated_at: row.get(16)?,
+        })
+    }
+
+    /// Guarda un plan DCA nuevo y devuelve su id.
+    pub fn create_dca_plan(&self, plan: &DcaPlan) -> SqliteResult<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO dca_plans (user_id, symbol, amount, quote_asset, schedule, max_price, mode, exchange,
+                active, next_run_at, last_run_at, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                plan.user_id, plan.symbol, plan.amount, plan.quote_asset, plan.schedule, plan.max_price,
+                plan.mode, plan.exchange, plan.active, plan.next_run_at, plan.last_run_at, plan.created_at,
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    pub fn get_dca_plan(&self, plan_id: i64) -> SqliteResult<Option<DcaPlan>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT id, user_id, symbol, amount, quote_asset, schedule, max_price, mode, exchange,
+                active, next_run_at, last_run_at, created_at
+             FROM dca_plans WHERE id = ?",
+            [plan_id],
+            Self::row_to_dca_plan,
+        ).optional()
+    }
+
+    pub fn get_user_dca_plans(&self, user_id: i64) -> SqliteResult<Vec<DcaPlan>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, user_id, symbol, amount, quote_asset, schedule, max_price, mode, exchange,
+                active, next_run_at, last_run_at, created_at
+             FROM dca_plans WHERE user_id = ? ORDER BY id"
+        )?;
+        let plans = stmt.query_map([user_id], Self::row_to_dca_plan)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(plans)
+    }
+
+    /// Planes activos de usuarios activos cuyo turno ya llegó.
+    pub fn get_due_dca_plans(&self, now: i64) -> SqliteResult<Vec<DcaPlan>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT p.id, p.user_id, p.symbol, p.amount, p.quote_asset, p.schedule, p.max_price, p.mode, p.exchange,
+                p.active, p.next_run_at, p.last_run_at, p.created_at
+             FROM dca_plans p JOIN users u ON u.id = p.user_id
+             WHERE p.active = 1 AND u.is_active = 1 AND p.next_run_at <= ?
+             ORDER BY p.next_run_at, p.id"
+        )?;
+        let plans = stmt.query_map([now], Self::row_to_dca_plan)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(plans)
+    }
+
+    /// Activa o pausa un plan; al reactivarlo, `next_run_at` es su próximo turno.
+    pub fn set_dca_plan_active(&self, plan_id: i64, active: bool, next_run_at: i64) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE dca_plans SET active = ?, next_run_at = ? WHERE id = ?",
+            params![active, next_run_at, plan_id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    pub fn delete_dca_plan(&self, plan_id: i64) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute("DELETE FROM dca_runs WHERE plan_id = ?", [plan_id])?;
+        let deleted = conn.execute("DELETE FROM dca_plans WHERE id = ?", [plan_id])?;
+        Ok(deleted > 0)
+    }
+
+    /// Anota el turno de un plan y programa el siguiente. Sin siguiente
+    /// turno, el plan queda pausado.
+    pub fn record_dca_run(&self, run: &DcaRun, next_run_at: Option<i64>) -> SqliteResult<i64> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute(
+            "INSERT INTO dca_runs (plan_id, mode, outcome, price, quantity, summary, executed_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+            params![run.plan_id, run.mode, run.outcome, run.price, run.quantity, run.summary, run.executed_at],
+        )?;
+        let id = tx.last_insert_rowid();
+        tx.execute(
+            "UPDATE dca_plans SET last_run_at = ?, next_run_at = COALESCE(?, next_run_at),
+                active = CASE WHEN ? IS NULL THEN 0 ELSE active END
+             WHERE id = ?",
+            params![run.executed_at, next_run_at, next_run_at, run.plan_id],
+        )?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    /// Últimos turnos de un plan, del más reciente al más antiguo.
+    pub fn get_dca_runs(&self, plan_id: i64, limit: i64) -> SqliteResult<Vec<DcaRun>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, plan_id, mode, outcome, price, quantity, summary, executed_at
+             FROM dca_runs WHERE plan_id = ?
+             ORDER BY executed_at DESC, id DESC LIMIT ?"
+        )?;
+        let runs = stmt.query_map(params![plan_id, limit], |row| Ok(DcaRun {
+            id: row.get(0)?,
+            plan_id: row.get(1)?,
+            mode: row.get(2)?,
+            outcome: row.get::<_, DcaOutcome>(3)?,
+            price: row.get(4)?,
+            quantity: row.get(5)?,
+            summary: row.get(6)?,
+            executed_at: row.get(7)?,
+        }))?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(runs)
+    }
+
+    fn row_to_dca_plan(row: &rusqlite::Row<'_>) -> SqliteResult<DcaPlan> {
+        Ok(DcaPlan {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            symbol: row.get(2)?,
+            amount: row.get(3)?,
+            quote_asset: row.get(4)?,
+            schedule: row.get(5)?,
+            max_price: row.get(6)?,
+            mode: row.get(7)?,
+            exchange: row.get(8)?,
+            active: row.get(9)?,
+            next_run_at: row.get(10)?,
+            last_run_at: row.get(11)?,
+            created_at: row.get(12)?,
+        })
+    }
+
+    /// Guarda una estrategia nueva y devuelve su id.
+    pub fn create_strategy(&self, strategy: &StrategyRecord) -> SqliteResult<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO strategies (user_id, kind, mode, exchange, symbol, quote_asset, config, state, status,
+                realized_profit, last_error, created_at, updated_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            params![
+                strategy.user_id, strategy.kind, strategy.mode, strategy.exchange, strategy.symbol,
+                strategy.quote_asset, strategy.config.to_string(), strategy.state.to_string(), strategy.status,
+                strategy.realized_profit, strategy.last_error, strategy.created_at, strategy.updated_at,
+            ],
+        )?;
+        Ok(conn.last_insert_rowid())
+    }
+
+    /// Guarda el estado de una estrategia tras una pasada del motor.
+    pub fn update_strategy(&self, strategy: &StrategyRecord) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE strategies SET state = ?, realized_profit = ?, last_error = ?, updated_at = ?,
+                -- Una parada pedida mientras corría la pasada no se pisa
+                status = CASE WHEN status = 'stopping' AND ? = 'running' THEN status ELSE ? END
+             WHERE id = ?",
+            params![
+                strategy.state.to_string(), strategy.realized_profit, strategy.last_error,
+                Utc::now().timestamp(), strategy.status, strategy.status, strategy.id,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Pide detener una estrategia en marcha. Devuelve false si ya no lo estaba.
+    pub fn request_strategy_stop(&self, strategy_id: i64) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE strategies SET status = ?, updated_at = ? WHERE id = ? AND status = ?",
+            params![StrategyStatus::Stopping, Utc::now().timestamp(), strategy_id, StrategyStatus::Running],
+        )?;
+        Ok(updated > 0)
+    }
+
+    pub fn get_strategy(&self, strategy_id: i64) -> SqliteResult<Option<StrategyRecord>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT id, user_id, kind, mode, exchange, symbol, quote_asset, config, state, status,
+                realized_profit, last_error, created_at, updated_at
+             FROM strategies WHERE id = ?",
+            [strategy_id],
+            Self::row_to_strategy,
+        ).optional()
+    }
+
+    pub fn get_user_strategies(&self, user_id: i64) -> SqliteResult<Vec<StrategyRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, user_id, kind, mode, exchange, symbol, quote_asset, config, state, status,
+                realized_profit, last_error, created_at, updated_at
+             FROM strategies WHERE user_id = ? ORDER BY id"
+        )?;
+        let strategies = stmt.query_map([user_id], Self::row_to_strategy)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(strategies)
+    }
+
+    /// Estrategias que el motor tiene que atender: las que corren y las que
+    /// esperan a que se cancelen sus órdenes.
+    pub fn get_active_strategies(&self) -> SqliteResult<Vec<StrategyRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, user_id, kind, mode, exchange, symbol, quote_asset, config, state, status,
+                realized_profit, last_error, created_at, updated_at
+             FROM strategies WHERE status IN ('running', 'stopping') ORDER BY id"
+        )?;
+        let strategies = stmt.query_map([], Self::row_to_strategy)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(strategies)
+    }
+
+    fn row_to_strategy(row: &rusqlite::Row<'_>) -> SqliteResult<StrategyRecord> {
+        let json = |index: usize| -> SqliteResult<serde_json::Value> {
+            let text: String = row.get(index)?;
+            serde_json::from_str(&text).map_err(|e| rusqlite::Error::InvalidParameterName(e.to_string()))
+        };
+        Ok(StrategyRecord {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            kind: row.get(2)?,
+            mode: row.get(3)?,
+            exchange: row.get(4)?,
+            symbol: row.get(5)?,
+            quote_asset: row.get(6)?,
+            config: json(7)?,
+            state: json(8)?,
+            status: row.get(9)?,
+            realized_profit: row.get(10)?,
+            last_error: row.get(11)?,
+            created_at: row.get(12)?,
+            updated_at: row.get(13)?,
+        })
+    }
+
+    /// Añade cotizaciones al histórico. Las que ya estaban (mismo símbolo,
+    /// fuente e instante) se ignoran; devuelve cuántas se añadieron.
+    pub fn save_price_ticks(&self, ticks: &[PriceTick]) -> SqliteResult<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut inserted = 0;
+        {
+            let mut stmt = tx.prepare(
+                "INSERT OR IGNORE INTO price_history (symbol, venue, timestamp, price, volume) VALUES (?, ?, ?, ?, ?)"
+            )?;
+            for tick in ticks {
+                inserted += stmt.execute(params![tick.symbol, tick.venue, tick.timestamp, tick.price, tick.volume])?;
+            }
+        }
+        tx.commit()?;
+        Ok(inserted)
+    }
+
+    /// Deja un solo precio (el primero) por cada tramo de `bucket` segundos en
+    /// lo anterior a `downsample_before` y borra lo anterior a `delete_before`.
+    /// Devuelve cuántas filas se borraron.
+    pub fn prune_price_history(&self, downsample_before: i64, bucket: i64, delete_before: Option<i64>) -> SqliteResult<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let mut deleted = 0;
+        if let Some(delete_before) = delete_before {
+            deleted += tx.execute("DELETE FROM price_history WHERE timestamp < ?", [delete_before])?;
+        }
+        deleted += tx.execute(
+            "DELETE FROM price_history WHERE timestamp < ?1 AND EXISTS (
+                SELECT 1 FROM price_history earlier
+                WHERE earlier.symbol = price_history.symbol
+                  AND earlier.venue = price_history.venue
+                  AND earlier.timestamp / ?2 = price_history.timestamp / ?2
+                  AND earlier.timestamp < price_history.timestamp
+             )",
+            params![downsample_before, bucket],
+        )?;
+        tx.commit()?;
+        Ok(deleted)
+    }
+
+    /// Histórico de `symbol` entre `from` y `to` (inclusive), en orden cronológico.
+    pub fn get_price_history(&self, symbol: &str, from: i64, to: i64) -> SqliteResult<Vec<PriceTick>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, symbol, venue, price, volume FROM price_history
+             WHERE symbol = ? AND timestamp BETWEEN ? AND ?
+             ORDER BY timestamp, venue"
+        )?;
+        let ticks = stmt.query_map(params![symbol, from, to], |row| Ok(PriceTick {
+            timestamp: row.get(0)?,
+            symbol: row.get(1)?,
+            venue: row.get(2)?,
+            price: row.get(3)?,
+            volume: row.get(4)?,
+        }))?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(ticks)
+    }
+
+    /// Guarda credenciales ya cifradas; reemplaza las que el usuario tuviera
+    /// para ese exchange.
+    pub fn save_exchange_credential(&self, user_id: i64, exchange: &str, api_key_hint: &str, key_id: &str, nonce: &[u8], ciphertext: &[u8]) -> SqliteResult<ExchangeCredentialInfo> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "INSERT INTO exchange_credentials (user_id, exchange, api_key_hint, key_id, nonce, ciphertext, created_at)
+             VALUES (?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(user_id, exchange) DO UPDATE SET
+                api_key_hint = excluded.api_key_hint,
+                key_id = excluded.key_id,
+                nonce = excluded.nonce,
+                ciphertext = excluded.ciphertext,
+                created_at = excluded.created_at
+             RETURNING id, user_id, exchange, api_key_hint, key_id, created_at",
+            params![user_id, exchange, api_key_hint, key_id, nonce, ciphertext, Utc::now().timestamp()],
+            Self::row_to_credential_info,
+        )
+    }
+
+    pub fn list_exchange_credentials(&self, user_id: i64) -> SqliteResult<Vec<ExchangeCredentialInfo>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, user_id, exchange, api_key_hint, key_id, created_at
+             FROM exchange_credentials WHERE user_id = ? ORDER BY exchange"
+        )?;
+        let credentials = stmt.query_map([user_id], Self::row_to_credential_info)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(credentials)
+    }
+
+    pub fn delete_exchange_credential(&self, user_id: i64, credential_id: i64) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let deleted = conn.execute(
+            "DELETE FROM exchange_credentials WHERE id = ? AND user_id = ?",
+            params![credential_id, user_id],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    pub fn get_sealed_credentials(&self, user_id: i64) -> SqliteResult<Vec<SealedCredential>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, user_id, exchange, api_key_hint, key_id, created_at, nonce, ciphertext
+             FROM exchange_credentials WHERE user_id = ? ORDER BY exchange"
+        )?;
+        let credentials = stmt.query_map([user_id], Self::row_to_sealed_credential)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(credentials)
+    }
+
+    /// Credenciales cifradas con una clave distinta de `key_id`, para rotarlas.
+    pub fn get_credentials_to_rotate(&self, key_id: &str) -> SqliteResult<Vec<SealedCredential>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, user_id, exchange, api_key_hint, key_id, created_at, nonce, ciphertext
+             FROM exchange_credentials WHERE key_id != ?"
+        )?;
+        let credentials = stmt.query_map([key_id], Self::row_to_sealed_credential)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(credentials)
+    }
+
+    pub fn reseal_exchange_credential(&self, credential_id: i64, key_id: &str, nonce: &[u8], ciphertext: &[u8]) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "UPDATE exchange_credentials SET key_id = ?, nonce = ?, ciphertext = ? WHERE id = ?",
+            params![key_id, nonce, ciphertext, credential_id],
+        )?;
+        Ok(())
+    }
+
+    fn row_to_credential_info(row: &rusqlite::Row<'_>) -> SqliteResult<ExchangeCredentialInfo> {
+        Ok(ExchangeCredentialInfo {
+            id: row.get(0)?,
+            user_id: row.get(1)?,
+            exchange: row.get(2)?,
+            api_key_hint: row.get(3)?,
+            key_id: row.get(4)?,
+            created_at: row.get(5)?,
+        })
+    }
+
+    fn row_to_sealed_credential(row: &rusqlite::Row<'_>) -> SqliteResult<SealedCredential> {
+        Ok(SealedCredential {
+            info: Self::row_to_credential_info(row)?,
+            nonce: row.get(6)?,
+            ciphertext: row.get(7)?,
+        })
+    }
+
+    /// Código de invitación de un solo uso válido por 7 días. Quien se
+    /// registre con él queda en `plan`.
+    pub fn create_invite_code(&self, created_by: i64, plan: &str) -> SqliteResult<(String, i64)> {
+        let conn = self.conn.lock().unwrap();
+        let now = Utc::now().timestamp();
+        let code = generate_code(16);
+        let expires_at = now + 7 * 24 * 60 * 60;
+        conn.execute(
+            "INSERT INTO invite_codes (code, plan, created_by, created_at, expires_at) VALUES (?, ?, ?, ?, ?)",
+            params![code, plan, created_by, now, expires_at],
+        )?;
+        Ok((code, expires_at))
+    }
+
+    fn row_to_plan(row: &rusqlite::Row<'_>) -> SqliteResult<Plan> {
+        let kinds: String = row.get(3)?;
+        Ok(Plan {
+            name: row.get(0)?,
+            max_active_alerts: row.get(1)?,
+            min_check_interval: row.get(2)?,
+            allowed_kinds: kinds.split(',').filter_map(|kind| kind.parse::<AlertKind>().ok()).collect(),
+        })
+    }
+
+    pub fn get_plans(&self) -> SqliteResult<Vec<Plan>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT name, max_active_alerts, min_check_interval, allowed_kinds FROM plans ORDER BY name"
+        )?;
+        let plans = stmt.query_map([], Self::row_to_plan)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(plans)
+    }
+
+    pub fn get_plan(&self, name: &str) -> SqliteResult<Option<Plan>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT name, max_active_alerts, min_check_interval, allowed_kinds FROM plans WHERE name = ?",
+            [name],
+            Self::row_to_plan,
+        ).optional()
+    }
+
+    /// Crea el plan o reemplaza sus límites.
+    pub fn save_plan(&self, plan: &Plan) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        let kinds = plan.allowed_kinds.iter().map(|kind| kind.as_str()).collect::<Vec<_>>().join(",");
+        conn.execute(
+            "INSERT OR REPLACE INTO plans (name, max_active_alerts, min_check_interval, allowed_kinds)
+             VALUES (?, ?, ?, ?)",
+            params![plan.name, plan.max_active_alerts, plan.min_check_interval, kinds],
+        )?;
+        Ok(())
+    }
+
+    /// Asigna un plan existente al usuario.
+    pub fn set_user_plan(&self, user_id: i64, plan: &str) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE users SET plan = ?1 WHERE id = ?2 AND EXISTS (SELECT 1 FROM plans WHERE name = ?1)",
+            params![plan, user_id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    pub fn get_user_plan(&self, user_id: i64) -> SqliteResult<Option<Plan>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT p.name, p.max_active_alerts, p.min_check_interval, p.allowed_kinds
+             FROM plans p JOIN users u ON u.plan = p.name
+             WHERE u.id = ?",
+            [user_id],
+            Self::row_to_plan,
+        ).optional()
+    }
+
+    pub fn count_active_alerts(&self, user_id: i64) -> SqliteResult<i64> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT COUNT(*) FROM price_alerts WHERE user_id = ? AND is_active = 1",
+            [user_id],
+            |row| row.get(0),
+        )
+    }
+
+    /// Intervalo mínimo de evaluación por usuario, solo para los planes que
+    /// lo limitan.
+    pub fn get_user_check_intervals(&self) -> SqliteResult<HashMap<i64, i64>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT u.id, p.min_check_interval FROM users u
+             JOIN plans p ON p.name = u.plan
+             WHERE p.min_check_interval > 0"
+        )?;
+        let intervals = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<SqliteResult<HashMap<_, _>>>()?;
+        Ok(intervals)
+    }
+
+    pub fn set_user_role(&self, user_id: i64, role: UserRole) -> SqliteResult<bool> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE users SET role = ? WHERE id = ?",
+            params![role, user_id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Mientras no haya ningún administrador, promueve a las cuentas
+    /// vinculadas a los usuarios de Telegram indicados. Se identifican por su
+    /// id de Telegram, que nadie más puede reclamar, y no por el username,
+    /// que elige cada uno al registrarse. Devuelve cuántos cambiaron de rol.
+    pub fn bootstrap_admins(&self, telegram_user_ids: &[i64]) -> SqliteResult<usize> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let admins: i64 = tx.query_row(
+            "SELECT COUNT(*) FROM users WHERE role = ?",
+            params![UserRole::Admin],
+            |row| row.get(0),
+        )?;
+        if admins > 0 {
+            return Ok(0);
+        }
+        let mut promoted = 0;
+        for telegram_user_id in telegram_user_ids {
+            promoted += tx.execute(
+                "UPDATE users SET role = ? WHERE telegram_user_id = ?",
+                params![UserRole::Admin, telegram_user_id],
+            )?;
+        }
+        tx.commit()?;
+        Ok(promoted)
+    }
+
+    /// Chats privados vinculados de cuentas activas, destino de los anuncios.
+    pub fn get_broadcast_chat_ids(&self) -> SqliteResult<Vec<i64>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT telegram_chat_id FROM users WHERE is_active = 1 AND telegram_chat_id IS NOT NULL"
+        )?;
+        let chat_ids = stmt.query_map([], |row| row.get(0))?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(chat_ids)
+    }
+
+    pub fn get_admin_stats(&self) -> SqliteResult<AdminStats> {
+        let conn = self.conn.lock().unwrap();
+        let since = Utc::now().timestamp() - 24 * 60 * 60;
+        conn.query_row(
+            "SELECT
+                (SELECT COUNT(*) FROM users),
+                (SELECT COUNT(*) FROM users WHERE is_active = 1),
+                (SELECT COUNT(*) FROM users WHERE role = 'admin'),
+                (SELECT COUNT(*) FROM users WHERE telegram_chat_id IS NOT NULL),
+                (SELECT COUNT(*) FROM price_alerts),
+                (SELECT COUNT(*) FROM price_alerts WHERE is_active = 1 AND triggered_at IS NULL),
+                (SELECT COUNT(*) FROM price_alerts WHERE triggered_at >= ?),
+                (SELECT COUNT(*) FROM alert_targets)",
+            [since],
+            |row| Ok(AdminStats {
+                users: row.get(0)?,
+                active_users: row.get(1)?,
+                admins: row.get(2)?,
+                linked_chats: row.get(3)?,
+                alerts: row.get(4)?,
+                active_alerts: row.get(5)?,
+                triggered_last_24h: row.get(6)?,
+                alert_targets: row.get(7)?,
+            }),
+        )
+    }
+
+    fn row_to_transaction(row: &rusqlite::Row<'_>) -> SqliteResult<Transaction> {
+        Ok(Transaction {
+            id: Some(row.get(0)?),
+            user_id: row.get(1)?,
+            symbol: row.get(2)?,
+            side: row.get(3)?,
+            quantity: row.get(4)?,
+            price: row.get(5)?,
+            fee: row.get(6)?,
+            executed_at: row.get(7)?,
+            note: row.get(8)?,
+        })
+    }
+
+    /// Transacciones del usuario en orden cronológico, opcionalmente de un solo símbolo.
+    pub fn get_transactions(&self, user_id: i64, symbol: Option<&str>) -> SqliteResult<Vec<Transaction>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, user_id, symbol, side, quantity, price, fee, executed_at, note
+             FROM transactions
+             WHERE user_id = ?1 AND (?2 IS NULL OR symbol = ?2)
+             ORDER BY executed_at, id"
+        )?;
+        let transactions = stmt.query_map(params![user_id, symbol], Self::row_to_transaction)?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(transactions)
+    }
+
+    pub fn get_transaction(&self, transaction_id: i64) -> SqliteResult<Option<Transaction>> {
+        let conn = self.conn.lock().unwrap();
+        conn.query_row(
+            "SELECT id, user_id, symbol, side, quantity, price, fee, executed_at, note
+             FROM transactions WHERE id = ?",
+            [transaction_id],
+            Self::row_to_transaction,
+        ).optional()
+    }
+
+    /// Inserta la transacción (o la reemplaza si trae `id`) y guarda la
+    /// posición recalculada en la misma transacción SQL.
+    pub fn record_transaction(&self, transaction: &Transaction, holding: &Holding) -> SqliteResult<i64> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        let id = match transaction.id {
+            Some(id) => {
+                tx.execute(
+                    "UPDATE transactions
+                     SET side = ?, quantity = ?, price = ?, fee = ?, executed_at = ?, note = ?
+                     WHERE id = ?",
+                    params![transaction.side, transaction.quantity, transaction.price, transaction.fee,
+                            transaction.executed_at, transaction.note, id],
+                )?;
+                id
+            }
+            None => {
+                tx.execute(
+                    "INSERT INTO transactions (user_id, symbol, side, quantity, price, fee, executed_at, note)
+                     VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+                    params![transaction.user_id, transaction.symbol, transaction.side, transaction.quantity,
+                            transaction.price, transaction.fee, transaction.executed_at, transaction.note],
+                )?;
+                tx.last_insert_rowid()
+            }
+        };
+        Self::store_holding(&tx, holding)?;
+        tx.commit()?;
+        Ok(id)
+    }
+
+    pub fn delete_transaction(&self, transaction_id: i64, holding: &Holding) -> SqliteResult<()> {
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        tx.execute("DELETE FROM transactions WHERE id = ?", [transaction_id])?;
+        Self::store_holding(&tx, holding)?;
+        tx.commit()
+    }
+
+    /// Una posición cerrada se conserva mientras tenga P&L realizado que mostrar.
+    fn store_holding(tx: &rusqlite::Transaction<'_>, holding: &Holding) -> SqliteResult<()> {
+        if holding.quantity == 0.0 && holding.realized_pnl == 0.0 {
+            tx.execute(
+                "DELETE FROM holdings WHERE user_id = ? AND symbol = ?",
+                params![holding.user_id, holding.symbol],
+            )?;
+        } else {
+            tx.execute(
+                "INSERT OR REPLACE INTO holdings (user_id, symbol, quantity, cost_basis, realized_pnl, updated_at)
+                 VALUES (?, ?, ?, ?, ?, ?)",
+                params![holding.user_id, holding.symbol, holding.quantity, holding.cost_basis,
+                        holding.realized_pnl, holding.updated_at],
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn get_holdings(&self, user_id: i64) -> SqliteResult<Vec<Holding>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT user_id, symbol, quantity, cost_basis, realized_pnl, updated_at
+             FROM holdings WHERE user_id = ? ORDER BY symbol"
+        )?;
+        let holdings = stmt.query_map([user_id], |row| Ok(Holding {
+            user_id: row.get(0)?,
+            symbol: row.get(1)?,
+            quantity: row.get(2)?,
+            cost_basis: row.get(3)?,
+            realized_pnl: row.get(4)?,
+            updated_at: row.get(5)?,
+        }))?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(holdings)
+    }
+
+    pub fn record_audit_event(&self, event: &AuditEvent) -> SqliteResult<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO audit_log (user_id, chat_id, action, target, created_at)
+             VALUES (?, ?, ?, ?, ?)",
+            params![event.user_id, event.chat_id, event.action, event.target, event.created_at],
+        )?;
+        Ok(())
+    }
+
+    /// Borra los estados inactivos por más de `USER_STATE_TTL_SECS` y
+    /// devuelve los chats afectados.
+    pub fn delete_expired_user_states(&self) -> SqliteResult<Vec<i64>> {
+        let conn = self.conn.lock().unwrap();
+        let expires_before = chrono::Utc::now().timestamp() - USER_STATE_TTL_SECS;
+        let mut stmt = conn.prepare(
+            "DELETE FROM user_states WHERE updated_at <= ? RETURNING chat_id"
+        )?;
+        let chat_ids = stmt.query_map([expires_before], |row| row.get(0))?
+            .collect::<SqliteResult<Vec<i64>>>()?;
+        Ok(chat_ids)
+    }
+}
+
+fn generate_api_key() -> String {
+    generate_code(32)
+}
+
+fn generate_code(len: usize) -> String {
+    use rand::{thread_rng, Rng};
+    use rand::distributions::Alphanumeric;
+
+    thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(len)
+        .map(char::from)
+        .collect()
+} 
+/// Base de datos en un fichero temporal para las pruebas; el fichero se borra
+/// al soltarla.
+#[cfg(test)]
+pub(crate) struct TempDatabase {
+    db: std::sync::Arc<Database>,
+    path: std::path::PathBuf,
+}
+
+#[cfg(test)]
+impl TempDatabase {
+    /// `name` distingue el fichero de las demás pruebas que corren en paralelo.
+    pub(crate) fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("{}-{}.db", name, std::process::id()));
+        let db = Database::new(path.to_str().unwrap()).unwrap();
+        Self { db: std::sync::Arc::new(db), path }
+    }
+
+    pub(crate) fn shared(&self) -> std::sync::Arc<Database> {
+        self.db.clone()
+    }
+}
+
+#[cfg(test)]
+impl std::ops::Deref for TempDatabase {
+    type Target = Database;
+
+    fn deref(&self) -> &Database {
+        &self.db
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempDatabase {
+    fn drop(&mut self) {
+        fs::remove_file(&self.path).ok();
+    }
+}
@@ -0,0 +1,293 @@
+use std::error::Error;
+use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use tracing::{error, info, warn};
+use crate::config::CONFIG;
+use crate::crypto_api::CryptoAPI;
+use crate::db::Database;
+use crate::models::{DcaOutcome, DcaPlan, DcaRun, TradeMode, TradeSide, Transaction};
+use crate::notify::NotificationService;
+use crate::portfolio::Portfolio;
+use crate::timer::{Schedule, Timer};
+#[cfg(feature = "exchanges")]
+use rust_decimal::prelude::ToPrimitive;
+#[cfg(feature = "exchanges")]
+use crate::trading::{self, TradeExecutor};
+
+/// Segundos entre revisiones de los planes cuyo turno ha llegado.
+const CHECK_INTERVAL: u64 = 60;
+
+#[derive(Debug)]
+pub enum DcaError {
+    DatabaseError(rusqlite::Error),
+    UnsupportedSymbol(String),
+    InvalidPlan(String),
+    NotFound,
+}
+
+impl std::error::Error for DcaError {}
+
+impl std::fmt::Display for DcaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DcaError::DatabaseError(e) => write!(f, "Error de base de datos: {}", e),
+            DcaError::UnsupportedSymbol(symbol) => write!(f, "Símbolo no soportado: {}", symbol),
+            DcaError::InvalidPlan(reason) => write!(f, "Plan inválido: {}", reason),
+            DcaError::NotFound => write!(f, "El plan no existe"),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for DcaError {
+    fn from(err: rusqlite::Error) -> Self {
+        DcaError::DatabaseError(err)
+    }
+}
+
+/// Comprueba un plan y devuelve su primer turno después de `now`.
+pub fn validate(plan: &DcaPlan, now: DateTime<Utc>) -> Result<i64, DcaError> {
+    if !CONFIG.cryptocurrencies.contains_key(&plan.symbol) {
+        return Err(DcaError::UnsupportedSymbol(plan.symbol.clone()));
+    }
+    if !(plan.amount > 0.0 && plan.amount.is_finite()) {
+        return Err(DcaError::InvalidPlan("el importe debe ser mayor que cero".to_string()));
+    }
+    if plan.max_price.is_some_and(|price| !(price > 0.0 && price.is_finite())) {
+        return Err(DcaError::InvalidPlan("el precio máximo debe ser mayor que cero".to_string()));
+    }
+    if plan.quote_asset.trim().is_empty() {
+        return Err(DcaError::InvalidPlan("falta el activo de cotización".to_string()));
+    }
+    if plan.mode != TradeMode::DryRun && !cfg!(feature = "exchanges") {
+        return Err(DcaError::InvalidPlan(
+            "este servidor no tiene soporte de exchanges; solo está disponible el modo dry_run".to_string(),
+        ));
+    }
+    let schedule: Schedule = plan.schedule.parse().map_err(DcaError::InvalidPlan)?;
+    schedule.next_after(now)
+        .map(|next| next.timestamp())
+        .ok_or_else(|| DcaError::InvalidPlan("el horario nunca se cumple".to_string()))
+}
+
+/// Alta, pausa y baja de los planes DCA de un usuario.
+pub struct DcaPlans<'a> {
+    db: &'a Database,
+}
+
+impl<'a> DcaPlans<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        Self { db }
+    }
+
+    /// Guarda un plan nuevo, activo y con su primer turno ya programado.
+    pub fn create(&self, plan: DcaPlan) -> Result<DcaPlan, DcaError> {
+        let next_run_at = validate(&plan, Utc::now())?;
+        let mut plan = DcaPlan { active: true, next_run_at, last_run_at: None, ..plan };
+        plan.id = self.db.create_dca_plan(&plan)?;
+        Ok(plan)
+    }
+
+    /// Pausa o reanuda un plan. Al reanudarlo no se recuperan los turnos
+    /// perdidos: el siguiente es el próximo que marque el horario.
+    pub fn set_active(&self, user_id: i64, plan_id: i64, active: bool) -> Result<DcaPlan, DcaError> {
+        let plan = self.owned(user_id, plan_id)?;
+        let next_run_at = if active { validate(&plan, Utc::now())? } else { plan.next_run_at };
+        self.db.set_dca_plan_active(plan_id, active, next_run_at)?;
+        Ok(DcaPlan { active, next_run_at, ..plan })
+    }
+
+    pub fn delete(&self, user_id: i64, plan_id: i64) -> Result<(), DcaError> {
+        self.owned(user_id, plan_id)?;
+        self.db.delete_dca_plan(plan_id)?;
+        Ok(())
+    }
+
+    pub fn owned(&self, user_id: i64, plan_id: i64) -> Result<DcaPlan, DcaError> {
+        match self.db.get_dca_plan(plan_id)? {
+            Some(plan) if plan.user_id == user_id => Ok(plan),
+            _ => Err(DcaError::NotFound),
+        }
+    }
+}
+
+/// Lo que se compró en un turno.
+struct Purchase {
+    quantity: f64,
+    price: f64,
+    fee: f64,
+    summary: String,
+}
+
+/// Ejecuta en segundo plano los turnos de los planes DCA, anota cada compra
+/// real en el portafolio y avisa al dueño del plan por Telegram.
+pub struct DcaScheduler {
+    api: CryptoAPI,
+    notification_service: NotificationService,
+    db: Arc<Database>,
+    #[cfg(feature = "exchanges")]
+    trader: Option<Arc<TradeExecutor>>,
+}
+
+impl DcaScheduler {
+    pub fn new(api: CryptoAPI, notification_service: NotificationService, db: Arc<Database>) -> Self {
+        Self {
+            api,
+            notification_service,
+            db,
+            #[cfg(feature = "exchanges")]
+            trader: None,
+        }
+    }
+
+    /// Habilita los planes en modo `paper` y `live`.
+    #[cfg(feature = "exchanges")]
+    pub fn with_trader(mut self, trader: Arc<TradeExecutor>) -> Self {
+        self.trader = Some(trader);
+        self
+    }
+
+    pub async fn start(&self) {
+        info!("Revisando los planes DCA cada {} segundos", CHECK_INTERVAL);
+        Timer::new(CHECK_INTERVAL).start(|| self.run_due()).await;
+    }
+
+    async fn run_due(&self) {
+        let now = Utc::now();
+        let plans = match self.db.get_due_dca_plans(now.timestamp()) {
+            Ok(plans) => plans,
+            Err(e) => {
+                error!("Error al leer los planes DCA pendientes: {}", e);
+                return;
+            }
+        };
+        for plan in plans {
+            let run = self.run(&plan).await;
+            // Los turnos perdidos con el servidor parado no se recuperan
+            let next_run_at = plan.schedule.parse::<Schedule>().ok()
+                .and_then(|schedule| schedule.next_after(now))
+                .map(|next| next.timestamp());
+            if next_run_at.is_none() {
+                warn!("El plan DCA {} no tiene más turnos; queda pausado", plan.id);
+            }
+            if let Err(e) = self.db.record_dca_run(&run, next_run_at) {
+                error!("Error al guardar el turno del plan DCA {}: {}", plan.id, e);
+            }
+            self.notify(&plan, &run).await;
+        }
+    }
+
+    async fn run(&self, plan: &DcaPlan) -> DcaRun {
+        let mut run = DcaRun {
+            id: 0,
+            plan_id: plan.id,
+            mode: plan.mode,
+            outcome: DcaOutcome::Failed,
+            price: None,
+            quantity: None,
+            summary: String::new(),
+            executed_at: Utc::now().timestamp(),
+        };
+
+        let price = match self.api.get_price(&plan.symbol).await {
+            Ok(price) => price.price,
+            Err(e) => {
+                error!("Plan DCA {}: no se pudo obtener el precio de {}: {}", plan.id, plan.symbol, e);
+                run.summary = format!("No se pudo obtener el precio de {}: {}", plan.symbol, e);
+                return run;
+            }
+        };
+        run.price = Some(price);
+
+        if let Some(max_price) = plan.max_price.filter(|max_price| price > *max_price) {
+            run.outcome = DcaOutcome::Skipped;
+            run.summary = format!("{} cotiza a ${:.4}, por encima del techo de ${}; no se compra", plan.symbol, price, max_price);
+            return run;
+        }
+
+        if plan.mode == TradeMode::DryRun {
+            let quantity = plan.amount / price;
+            run.outcome = DcaOutcome::Executed;
+            run.quantity = Some(quantity);
+            run.summary = format!(
+                "Se habrían comprado {:.8} {} a ${:.4} por {} {}",
+                quantity, plan.symbol, price, plan.amount, plan.quote_asset
+            );
+            return run;
+        }
+
+        let purchase = match self.submit(plan, price).await {
+            Ok(purchase) if purchase.quantity > 0.0 => purchase,
+            Ok(purchase) => {
+                run.summary = purchase.summary;
+                return run;
+            }
+            Err(e) => {
+                error!("Falló el turno del plan DCA {}: {}", plan.id, e);
+                run.summary = e.to_string();
+                return run;
+            }
+        };
+        run.outcome = DcaOutcome::Executed;
+        run.price = Some(purchase.price);
+        run.quantity = Some(purchase.quantity);
+        run.summary = purchase.summary;
+
+        // Las compras en papel no son del usuario: no entran en su portafolio
+        if plan.mode != TradeMode::Live {
+            return run;
+        }
+        let transaction = Transaction {
+            id: None,
+            user_id: plan.user_id,
+            symbol: plan.symbol.clone(),
+            side: TradeSide::Buy,
+            quantity: purchase.quantity,
+            price: purchase.price,
+            fee: purchase.fee,
+            executed_at: run.executed_at,
+            note: Some(format!("DCA #{}", plan.id)),
+        };
+        if let Err(e) = Portfolio::new(&self.db).record(&transaction) {
+            error!("No se pudo anotar en el portafolio la compra del plan DCA {}: {}", plan.id, e);
+            run.summary.push_str(&format!("\n⚠️ No se pudo anotar en el portafolio: {}", e));
+        }
+        run
+    }
+
+    #[cfg(feature = "exchanges")]
+    async fn submit(&self, plan: &DcaPlan, price: f64) -> Result<Purchase, Box<dyn Error + Send + Sync>> {
+        let trader = self.trader.as_ref().ok_or("la operativa automática no está configurada")?;
+        let report = trader.execute_dca(plan, price).await?;
+        Ok(Purchase {
+            quantity: report.filled_quantity.to_f64().unwrap_or_default(),
+            price: report.average_price.and_then(|price| price.to_f64()).unwrap_or(price),
+            fee: report.total_fee.to_f64().unwrap_or_default(),
+            summary: trading::summarize(&report),
+        })
+    }
+
+    #[cfg(not(feature = "exchanges"))]
+    async fn submit(&self, _plan: &DcaPlan, _price: f64) -> Result<Purchase, Box<dyn Error + Send + Sync>> {
+        Err("este servidor se compiló sin soporte de exchanges".into())
+    }
+
+    async fn notify(&self, plan: &DcaPlan, run: &DcaRun) {
+        let chat_id = match self.db.get_user_telegram_chat_id(plan.user_id) {
+            Ok(Some(chat_id)) => chat_id,
+            Ok(None) => return,
+            Err(e) => {
+                error!("Error al buscar el chat de {}: {}", plan.user_id, e);
+                return;
+            }
+        };
+        let icon = match run.outcome {
+            DcaOutcome::Executed => "🛒",
+            DcaOutcome::Skipped => "⏭️",
+            DcaOutcome::Failed => "⚠️",
+        };
+        let message = format!("{} Plan DCA #{} de {} ({}):\n{}", icon, plan.id, plan.symbol, plan.mode.as_str(), run.summary);
+        if let Err(e) = self.notification_service.send_alert(chat_id, &message, None).await {
+            error!("No se pudo avisar a {} del turno del plan DCA {}: {}", plan.user_id, plan.id, e);
+        }
+    }
+}
@@ -1,41 +1,372 @@
-use super::{types::*, errors::*};
+use super::{types::*, errors::*, from_millis, hmac_sha256, parse_decimal};
 use rust_decimal::Decimal;
-use binance::{api::*, config::*, market::*, account::*};
+use reqwest::{Client, Method};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::collections::HashMap;
+use std::sync::{Mutex, atomic::{AtomicBool, AtomicI64, Ordering}};
+use std::time::Duration;
+use tracing::info;
+
+const DEFAULT_BASE_URL: &str = "https://api.binance.com";
+const RECV_WINDOW_MS: i64 = 5000;
+/// Código de Binance para una marca de tiempo fuera de `recvWindow`
+const TIMESTAMP_OUT_OF_WINDOW: i64 = -1021;
+/// Binance devuelve -2013 al consultar una orden que no existe o ya purgó
+const ORDER_NOT_FOUND: i64 = -2013;
+/// y -2011 al cancelarla
+const CANCEL_REJECTED: i64 = -2011;
 
 pub struct BinanceExchange {
-    account: Account,
-    market: Market,
+    client: Client,
+    base_url: String,
+    credentials: ExchangeCredentials,
+    /// Diferencia en ms entre el reloj del servidor y el local
+    time_offset: AtomicI64,
+    time_synced: AtomicBool,
+    rules: Mutex<HashMap<String, SymbolRules>>,
+}
+
+#[derive(Deserialize)]
+struct ApiErrorBody {
+    code: i64,
+    msg: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ServerTime {
+    server_time: i64,
+}
+
+#[derive(Deserialize)]
+struct AccountInfo {
+    balances: Vec<AccountBalance>,
+}
+
+#[derive(Deserialize)]
+struct AccountBalance {
+    asset: String,
+    free: String,
+    locked: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BookTicker {
+    bid_price: String,
+    ask_price: String,
+}
+
+#[derive(Deserialize)]
+struct ExchangeInfo {
+    symbols: Vec<SymbolInfo>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SymbolInfo {
+    symbol: String,
+    base_asset: String,
+    quote_asset: String,
+    filters: Vec<SymbolFilter>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "filterType")]
+enum SymbolFilter {
+    #[serde(rename = "PRICE_FILTER", rename_all = "camelCase")]
+    Price { tick_size: String },
+    #[serde(rename = "LOT_SIZE", rename_all = "camelCase")]
+    LotSize { step_size: String, min_qty: String },
+    #[serde(rename = "NOTIONAL", alias = "MIN_NOTIONAL", rename_all = "camelCase")]
+    Notional { min_notional: String },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BinanceOrder {
+    symbol: String,
+    order_id: i64,
+    price: String,
+    orig_qty: String,
+    executed_qty: String,
+    cummulative_quote_qty: String,
+    status: String,
+    #[serde(rename = "type")]
+    order_type: String,
+    side: String,
+    #[serde(default)]
+    stop_price: Option<String>,
+    #[serde(alias = "transactTime")]
+    time: Option<i64>,
+    update_time: Option<i64>,
+    #[serde(default)]
+    fills: Vec<BinanceFill>,
+}
+
+/// Ejecución de una orden, tal como viene en `fills` o en `/api/v3/myTrades`.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BinanceFill {
+    price: String,
+    commission: String,
+    commission_asset: String,
+}
+
+fn order_status(status: &str) -> OrderStatus {
+    match status {
+        "NEW" | "PENDING_NEW" => OrderStatus::New,
+        "PARTIALLY_FILLED" => OrderStatus::PartiallyFilled,
+        "FILLED" => OrderStatus::Filled,
+        "CANCELED" | "PENDING_CANCEL" => OrderStatus::Canceled,
+        "EXPIRED" | "EXPIRED_IN_MATCH" => OrderStatus::Expired,
+        _ => OrderStatus::Rejected,
+    }
+}
+
+fn order_type_name(order_type: OrderType) -> &'static str {
+    match order_type {
+        OrderType::Market => "MARKET",
+        OrderType::Limit => "LIMIT",
+        OrderType::StopLoss => "STOP_LOSS",
+        OrderType::StopLossLimit => "STOP_LOSS_LIMIT",
+        OrderType::TakeProfit => "TAKE_PROFIT",
+        OrderType::TakeProfitLimit => "TAKE_PROFIT_LIMIT",
+    }
+}
+
+fn parse_order_type(name: &str) -> OrderType {
+    match name {
+        "STOP_LOSS" => OrderType::StopLoss,
+        "STOP_LOSS_LIMIT" => OrderType::StopLossLimit,
+        "TAKE_PROFIT" => OrderType::TakeProfit,
+        "TAKE_PROFIT_LIMIT" => OrderType::TakeProfitLimit,
+        "MARKET" => OrderType::Market,
+        _ => OrderType::Limit,
+    }
+}
+
+/// Binance usa los pares sin separador: BTC/USDT y BTC-USDT pasan a BTCUSDT.
+fn market_symbol(symbol: &str) -> String {
+    symbol.replace(['/', '-'], "").to_uppercase()
 }
 
 impl BinanceExchange {
     pub fn new(credentials: ExchangeCredentials) -> Result<Self, ExchangeError> {
-        let config = Config::default()
-            .api_key(Some(credentials.api_key))
-            .secret_key(Some(credentials.api_secret));
-            
-        let account = Account::new(Some(config.clone()));
-        let market = Market::new(Some(config));
-        
-        Ok(Self { account, market })
+        Self::with_base_url(credentials, DEFAULT_BASE_URL)
+    }
+
+    /// Igual que `new` pero contra otra URL, p. ej. la testnet o un servidor de pruebas.
+    pub fn with_base_url(credentials: ExchangeCredentials, base_url: &str) -> Result<Self, ExchangeError> {
+        if credentials.api_key.is_empty() || credentials.api_secret.is_empty() {
+            return Err(ExchangeError::MissingCredentials("Binance requiere API key y secret".into()));
+        }
+        Ok(Self {
+            client: Client::builder().timeout(Duration::from_secs(10)).build()?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            credentials,
+            time_offset: AtomicI64::new(0),
+            time_synced: AtomicBool::new(false),
+            rules: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn sync_time(&self) -> Result<(), ExchangeError> {
+        let before = chrono::Utc::now().timestamp_millis();
+        let time: ServerTime = self.public(&format!("{}/api/v3/time", self.base_url)).await?;
+        let after = chrono::Utc::now().timestamp_millis();
+        self.time_offset.store(time.server_time - (before + after) / 2, Ordering::Relaxed);
+        self.time_synced.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn public<T: DeserializeOwned>(&self, url: &str) -> Result<T, ExchangeError> {
+        let response = self.client.get(url).send().await?;
+        Self::parse(response).await.map_err(|(_, e)| e)
+    }
+
+    async fn parse<T: DeserializeOwned>(response: reqwest::Response) -> Result<T, (Option<i64>, ExchangeError)> {
+        let status = response.status();
+        let body = response.text().await.map_err(|e| (None, e.into()))?;
+        if status.is_success() {
+            return serde_json::from_str(&body)
+                .map_err(|e| (None, ExchangeError::Api(format!("respuesta inesperada: {}", e))));
+        }
+        match serde_json::from_str::<ApiErrorBody>(&body) {
+            Ok(error) => Err((Some(error.code), ExchangeError::Api(format!("{} ({})", error.msg, error.code)))),
+            Err(_) => Err((None, ExchangeError::Api(format!("HTTP {}: {}", status, body)))),
+        }
+    }
+
+    /// Petición firmada con HMAC-SHA256. Si Binance rechaza la marca de tiempo
+    /// se vuelve a sincronizar el reloj y se reintenta una vez.
+    async fn signed<T: DeserializeOwned>(&self, method: Method, path: &str, params: &[(&str, String)]) -> Result<T, ExchangeError> {
+        self.signed_with_code(method, path, params).await.map_err(|(_, e)| e)
+    }
+
+    /// Como `signed`, conservando el código de error de Binance.
+    async fn signed_with_code<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        params: &[(&str, String)],
+    ) -> Result<T, (Option<i64>, ExchangeError)> {
+        if !self.time_synced.load(Ordering::Relaxed) {
+            self.sync_time().await.map_err(|e| (None, e))?;
+        }
+
+        let mut retried = false;
+        loop {
+            let timestamp = chrono::Utc::now().timestamp_millis() + self.time_offset.load(Ordering::Relaxed);
+            let mut query: Vec<String> = params.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+            query.push(format!("recvWindow={}", RECV_WINDOW_MS));
+            query.push(format!("timestamp={}", timestamp));
+            let query = query.join("&");
+            let signature = hex::encode(hmac_sha256(&self.credentials.api_secret, &query));
+
+            let response = self.client
+                .request(method.clone(), format!("{}{}?{}&signature={}", self.base_url, path, query, signature))
+                .header("X-MBX-APIKEY", &self.credentials.api_key)
+                .send()
+                .await
+                .map_err(|e| (None, e.into()))?;
+            match Self::parse(response).await {
+                Err((Some(TIMESTAMP_OUT_OF_WINDOW), _)) if !retried => {
+                    info!("Binance rechazó la marca de tiempo; sincronizando el reloj");
+                    retried = true;
+                    self.sync_time().await.map_err(|e| (None, e))?;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Precisión y mínimos del par, consultados una vez y guardados.
+    async fn symbol_rules(&self, symbol: &str) -> Result<SymbolRules, ExchangeError> {
+        if let Some(rules) = self.rules.lock().unwrap().get(symbol) {
+            return Ok(rules.clone());
+        }
+
+        let info: ExchangeInfo = self.public(&format!("{}/api/v3/exchangeInfo?symbol={}", self.base_url, symbol)).await?;
+        let info = info.symbols.into_iter()
+            .find(|info| info.symbol == symbol)
+            .ok_or_else(|| ExchangeError::UnsupportedSymbol(symbol.to_string()))?;
+
+        let mut rules = SymbolRules {
+            symbol: info.symbol,
+            base_asset: info.base_asset,
+            quote_asset: info.quote_asset,
+            step_size: Decimal::ZERO,
+            tick_size: Decimal::ZERO,
+            min_quantity: Decimal::ZERO,
+            min_notional: Decimal::ZERO,
+        };
+        for filter in info.filters {
+            match filter {
+                SymbolFilter::Price { tick_size } => rules.tick_size = parse_decimal(&tick_size)?,
+                SymbolFilter::LotSize { step_size, min_qty } => {
+                    rules.step_size = parse_decimal(&step_size)?;
+                    rules.min_quantity = parse_decimal(&min_qty)?;
+                }
+                SymbolFilter::Notional { min_notional } => rules.min_notional = parse_decimal(&min_notional)?,
+                SymbolFilter::Other => {}
+            }
+        }
+        self.rules.lock().unwrap().insert(symbol.to_string(), rules.clone());
+        Ok(rules)
+    }
+
+    /// Las consultas de órdenes no traen sus ejecuciones y sin ellas no se
+    /// conoce la comisión: se piden aparte si la orden ejecutó algo.
+    async fn load_fills(&self, order: &mut BinanceOrder) -> Result<(), ExchangeError> {
+        if order.fills.is_empty() && parse_decimal(&order.executed_qty)? > Decimal::ZERO {
+            let params = [("symbol", order.symbol.clone()), ("orderId", order.order_id.to_string())];
+            order.fills = self.signed(Method::GET, "/api/v3/myTrades", &params).await?;
+        }
+        Ok(())
+    }
+
+    fn to_order(order: BinanceOrder, rules: &SymbolRules) -> Result<Order, ExchangeError> {
+        let executed = parse_decimal(&order.executed_qty)?;
+        let quote_quantity = parse_decimal(&order.cummulative_quote_qty)?;
+        let price = parse_decimal(&order.price)?;
+        let stop_price = parse_decimal(order.stop_price.as_deref().unwrap_or_default())?;
+        // Binance cobra las compras en el activo base (o en BNB si el usuario
+        // lo eligió): la base se descuenta de lo recibido y se valora al
+        // precio de la ejecución; otros activos no se pueden valorar aquí
+        let mut fee = Decimal::ZERO;
+        let mut base_fee = Decimal::ZERO;
+        let mut fee_complete = executed.is_zero() || !order.fills.is_empty();
+        for fill in &order.fills {
+            let commission = parse_decimal(&fill.commission)?;
+            if fill.commission_asset == rules.quote_asset {
+                fee += commission;
+            } else if fill.commission_asset == rules.base_asset {
+                fee += commission * parse_decimal(&fill.price)?;
+                base_fee += commission;
+            } else if commission > Decimal::ZERO {
+                fee_complete = false;
+            }
+        }
+        let side = if order.side == "SELL" { OrderSide::Sell } else { OrderSide::Buy };
+        let received = match side {
+            OrderSide::Buy => executed - base_fee,
+            OrderSide::Sell => executed,
+        };
+        let created_at = from_millis(order.time.or(order.update_time).unwrap_or_default());
+
+        Ok(Order {
+            id: order.order_id.to_string(),
+            symbol: order.symbol,
+            order_type: parse_order_type(&order.order_type),
+            side,
+            price: [price, stop_price].into_iter().find(|price| *price > Decimal::ZERO),
+            quantity: parse_decimal(&order.orig_qty)?,
+            filled_quantity: received,
+            average_price: (executed > Decimal::ZERO).then(|| quote_quantity / executed),
+            fee,
+            fee_complete,
+            status: order_status(&order.status),
+            created_at,
+            updated_at: order.update_time.map(from_millis).unwrap_or(created_at),
+        })
     }
 }
 
 #[async_trait::async_trait]
 impl Exchange for BinanceExchange {
+    fn name(&self) -> &str {
+        "binance"
+    }
+
     async fn get_balance(&self, asset: &str) -> Result<Balance, ExchangeError> {
-        let account = self.account.get_account()?;
+        let account: AccountInfo = self.signed(Method::GET, "/api/v3/account", &[]).await?;
         let balance = account.balances
             .iter()
-            .find(|b| b.asset == asset)
+            .find(|b| b.asset.eq_ignore_ascii_case(asset))
             .ok_or(ExchangeError::AssetNotFound(asset.to_string()))?;
-            
+
         Ok(Balance {
             asset: balance.asset.clone(),
-            free: Decimal::from_str(&balance.free)?,
-            locked: Decimal::from_str(&balance.locked)?,
+            free: parse_decimal(&balance.free)?,
+            locked: parse_decimal(&balance.locked)?,
         })
     }
-    
+
+    async fn get_quote(&self, symbol: &str) -> Result<Quote, ExchangeError> {
+        let ticker: BookTicker = self.public(&format!(
+            "{}/api/v3/ticker/bookTicker?symbol={}",
+            self.base_url,
+            market_symbol(symbol)
+        )).await?;
+        Ok(Quote {
+            bid: parse_decimal(&ticker.bid_price)?,
+            ask: parse_decimal(&ticker.ask_price)?,
+        })
+    }
+
     async fn place_order(
         &self,
         symbol: &str,
@@ -44,9 +375,173 @@ impl Exchange for BinanceExchange {
         quantity: Decimal,
         price: Option<Decimal>,
     ) -> Result<Order, ExchangeError> {
-        // Implementar lógica de orden
-        todo!()
+        let symbol = market_symbol(symbol);
+        let rules = self.symbol_rules(&symbol).await?;
+        let (quantity, price) = rules.apply(quantity, price)?;
+
+        let mut params = vec![
+            ("symbol", symbol.clone()),
+            ("side", if side == OrderSide::Buy { "BUY" } else { "SELL" }.to_string()),
+            ("type", order_type_name(order_type).to_string()),
+            ("quantity", quantity.to_string()),
+            ("newOrderRespType", "FULL".to_string()),
+        ];
+        // Con un solo precio, las órdenes de activación lo usan como disparo
+        // y, si llevan límite, también como límite
+        if order_type != OrderType::Market {
+            let price = price.ok_or_else(|| ExchangeError::InvalidOrder("esta orden necesita precio".to_string()))?;
+            if matches!(order_type, OrderType::Limit | OrderType::StopLossLimit | OrderType::TakeProfitLimit) {
+                params.push(("price", price.to_string()));
+                params.push(("timeInForce", "GTC".to_string()));
+            }
+            if order_type != OrderType::Limit {
+                params.push(("stopPrice", price.to_string()));
+            }
+        }
+
+        let order: BinanceOrder = self.signed(Method::POST, "/api/v3/order", &params).await?;
+        Self::to_order(order, &rules)
+    }
+
+    async fn cancel_order(&self, symbol: &str, order_id: &str) -> Result<(), ExchangeError> {
+        let params = [("symbol", market_symbol(symbol)), ("orderId", order_id.to_string())];
+        match self.signed_with_code::<BinanceOrder>(Method::DELETE, "/api/v3/order", &params).await {
+            Ok(_) => Ok(()),
+            Err((Some(ORDER_NOT_FOUND | CANCEL_REJECTED), _)) => Err(ExchangeError::OrderNotFound(order_id.to_string())),
+            Err((_, e)) => Err(e),
+        }
+    }
+
+    async fn get_order(&self, symbol: &str, order_id: &str) -> Result<Order, ExchangeError> {
+        let symbol = market_symbol(symbol);
+        let rules = self.symbol_rules(&symbol).await?;
+        let params = [("symbol", symbol), ("orderId", order_id.to_string())];
+        match self.signed_with_code(Method::GET, "/api/v3/order", &params).await {
+            Ok(mut order) => {
+                self.load_fills(&mut order).await?;
+                Self::to_order(order, &rules)
+            }
+            Err((Some(ORDER_NOT_FOUND), _)) => Err(ExchangeError::OrderNotFound(order_id.to_string())),
+            Err((_, e)) => Err(e),
+        }
+    }
+
+    async fn get_open_orders(&self, symbol: &str) -> Result<Vec<Order>, ExchangeError> {
+        let symbol = market_symbol(symbol);
+        let rules = self.symbol_rules(&symbol).await?;
+        let params = [("symbol", symbol)];
+        let orders: Vec<BinanceOrder> = self.signed(Method::GET, "/api/v3/openOrders", &params).await?;
+        let mut open = Vec::with_capacity(orders.len());
+        for mut order in orders {
+            self.load_fills(&mut order).await?;
+            open.push(Self::to_order(order, &rules)?);
+        }
+        Ok(open)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::RawQuery, routing::get, Json, Router};
+    use serde_json::json;
+
+    /// Exchange de pruebas que solo acepta órdenes con firma válida.
+    async fn mock_exchange() -> String {
+        let app = Router::new()
+            .route("/api/v3/time", get(|| async { Json(json!({ "serverTime": chrono::Utc::now().timestamp_millis() })) }))
+            .route("/api/v3/exchangeInfo", get(|| async {
+                Json(json!({ "symbols": [{
+                    "symbol": "BTCUSDT", "baseAsset": "BTC", "quoteAsset": "USDT",
+                    "filters": [
+                        { "filterType": "PRICE_FILTER", "tickSize": "0.01000000" },
+                        { "filterType": "LOT_SIZE", "stepSize": "0.00100000", "minQty": "0.00100000" },
+                        { "filterType": "NOTIONAL", "minNotional": "5.00000000" },
+                        { "filterType": "ICEBERG_PARTS", "limit": 10 }
+                    ]
+                }] }))
+            }))
+            .route("/api/v3/myTrades", get(|RawQuery(query): RawQuery| async move {
+                let asset = if query.unwrap_or_default().contains("orderId=43&") { "BTC" } else { "BNB" };
+                Json(json!([{ "price": "100", "qty": "0.5", "commission": "0.0005", "commissionAsset": asset }]))
+            }))
+            .route("/api/v3/order", get(|RawQuery(query): RawQuery| async move {
+                let query = query.unwrap_or_default();
+                let Some(id) = ["43", "44"].into_iter().find(|id| query.contains(&format!("orderId={}&", id))) else {
+                    return unknown_order(-2013, "Order does not exist.");
+                };
+                (axum::http::StatusCode::OK, Json(json!({
+                    "symbol": "BTCUSDT", "orderId": id.parse::<i64>().unwrap(), "time": 1700000000000i64,
+                    "price": "100", "origQty": "0.5", "executedQty": "0.5",
+                    "cummulativeQuoteQty": "50", "status": "FILLED", "type": "LIMIT", "side": "BUY"
+                })))
+            })
+                .delete(|| async { unknown_order(-2011, "Unknown order sent.") })
+                .post(|RawQuery(query): RawQuery| async move {
+                let query = query.unwrap_or_default();
+                let (payload, signature) = query.rsplit_once("&signature=").unwrap();
+                assert_eq!(hex::encode(hmac_sha256("secret", payload)), signature);
+                assert!(payload.contains("quantity=0.123&"));
+                assert!(payload.contains("price=100.01&"));
+                Json(json!({
+                    "symbol": "BTCUSDT", "orderId": 42, "transactTime": 1700000000000i64,
+                    "price": "100.01", "origQty": "0.123", "executedQty": "0.123",
+                    "cummulativeQuoteQty": "12.30123", "status": "FILLED",
+                    "type": "LIMIT", "side": "BUY",
+                    "fills": [{ "price": "100.01", "qty": "0.123", "commission": "0.0123", "commissionAsset": "USDT" }]
+                }))
+            }));
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    fn unknown_order(code: i64, msg: &str) -> (axum::http::StatusCode, Json<serde_json::Value>) {
+        (axum::http::StatusCode::BAD_REQUEST, Json(json!({ "code": code, "msg": msg })))
     }
-    
-    // Implementar resto de métodos...
-} 
\ No newline at end of file
+
+    #[tokio::test]
+    async fn test_signed_order_against_mock_exchange() {
+        let credentials = ExchangeCredentials {
+            api_key: "key".to_string(),
+            api_secret: "secret".to_string(),
+            passphrase: None,
+        };
+        let exchange = BinanceExchange::with_base_url(credentials, &mock_exchange().await).unwrap();
+
+        // La cantidad se trunca al stepSize y el precio se ajusta al tickSize
+        let order = exchange.place_order(
+            "BTC/USDT",
+            OrderSide::Buy,
+            OrderType::Limit,
+            Decimal::new(12345, 5),
+            Some(Decimal::new(1000051, 4)),
+        ).await.unwrap();
+        assert_eq!(order.id, "42");
+        assert_eq!(order.status, OrderStatus::Filled);
+        assert_eq!(order.average_price, Some(Decimal::new(10001, 2)));
+        assert_eq!(order.fee, Decimal::new(123, 4));
+
+        let too_small = exchange.place_order("BTCUSDT", OrderSide::Buy, OrderType::Limit, Decimal::new(1, 3), Some(Decimal::ONE)).await;
+        assert!(matches!(too_small, Err(ExchangeError::InvalidOrder(_))));
+
+        // Las órdenes purgadas por Binance se reportan como inexistentes
+        let missing = exchange.get_order("BTCUSDT", "7").await;
+        assert!(matches!(missing, Err(ExchangeError::OrderNotFound(id)) if id == "7"));
+        let cancel = exchange.cancel_order("BTCUSDT", "7").await;
+        assert!(matches!(cancel, Err(ExchangeError::OrderNotFound(_))));
+
+        // La comisión en BTC de una orden consultada sale de sus ejecuciones
+        let queried = exchange.get_order("BTCUSDT", "43").await.unwrap();
+        assert_eq!(queried.filled_quantity, Decimal::new(4995, 4));
+        assert_eq!(queried.fee, Decimal::new(5, 2));
+        assert!(queried.fee_complete);
+
+        // En BNB no se puede valorar y queda marcada
+        let in_bnb = exchange.get_order("BTCUSDT", "44").await.unwrap();
+        assert_eq!((in_bnb.filled_quantity, in_bnb.fee), (Decimal::new(5, 1), Decimal::ZERO));
+        assert!(!in_bnb.fee_complete);
+    }
+}
@@ -0,0 +1,53 @@
+use rust_decimal::Decimal;
+
+#[derive(Debug)]
+pub enum ExchangeError {
+    Http(reqwest::Error),
+    /// Error devuelto por el exchange
+    Api(String),
+    MissingCredentials(String),
+    AssetNotFound(String),
+    UnsupportedSymbol(String),
+    PriceUnavailable(String),
+    InvalidOrder(String),
+    InsufficientBalance { asset: String, available: Decimal, required: Decimal },
+    OrderNotFound(String),
+    ExchangeNotFound(String),
+    Decimal(rust_decimal::Error),
+}
+
+impl std::error::Error for ExchangeError {}
+
+impl std::fmt::Display for ExchangeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExchangeError::Http(e) => write!(f, "Error de conexión con el exchange: {}", e),
+            ExchangeError::Api(message) => write!(f, "Error del exchange: {}", message),
+            ExchangeError::MissingCredentials(message) => write!(f, "Faltan credenciales: {}", message),
+            ExchangeError::AssetNotFound(asset) => write!(f, "Activo no encontrado: {}", asset),
+            ExchangeError::UnsupportedSymbol(symbol) => write!(f, "Símbolo no soportado: {}", symbol),
+            ExchangeError::PriceUnavailable(symbol) => write!(f, "No hay precio reciente para {}", symbol),
+            ExchangeError::InvalidOrder(reason) => write!(f, "Orden inválida: {}", reason),
+            ExchangeError::InsufficientBalance { asset, available, required } => write!(
+                f,
+                "Saldo insuficiente de {}: disponible {}, necesario {}",
+                asset, available, required
+            ),
+            ExchangeError::OrderNotFound(id) => write!(f, "La orden {} no existe", id),
+            ExchangeError::ExchangeNotFound(name) => write!(f, "El exchange {} no está configurado", name),
+            ExchangeError::Decimal(e) => write!(f, "Número inválido: {}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for ExchangeError {
+    fn from(err: reqwest::Error) -> Self {
+        ExchangeError::Http(err)
+    }
+}
+
+impl From<rust_decimal::Error> for ExchangeError {
+    fn from(err: rust_decimal::Error) -> Self {
+        ExchangeError::Decimal(err)
+    }
+}
@@ -1,37 +1,552 @@
-use super::{types::*, errors::*};
+use super::{types::*, errors::*, from_millis, hmac_sha256, parse_decimal};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use rand::RngCore;
 use rust_decimal::Decimal;
-use kucoin_rs::{client::*, trade::*};
+use reqwest::{Client, Method};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Mutex, atomic::{AtomicBool, AtomicI64, Ordering}};
+use std::time::Duration;
+use tracing::info;
+
+const DEFAULT_BASE_URL: &str = "https://api.kucoin.com";
+const SUCCESS: &str = "200000";
+/// Código de KuCoin para una marca de tiempo fuera de margen
+const INVALID_TIMESTAMP: &str = "400002";
+/// Código de KuCoin para una orden inexistente
+const ORDER_NOT_FOUND: &str = "400100";
 
 pub struct KuCoinExchange {
-    client: KucoinClient,
+    client: Client,
+    base_url: String,
+    api_key: String,
+    api_secret: String,
+    /// Frase de paso firmada, como exige la versión 2 de las API keys
+    passphrase: String,
+    time_offset: AtomicI64,
+    time_synced: AtomicBool,
+    rules: Mutex<HashMap<String, SymbolRules>>,
+}
+
+#[derive(Deserialize)]
+struct KucoinResponse<T> {
+    code: String,
+    data: Option<T>,
+    msg: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct KucoinAccount {
+    currency: String,
+    available: String,
+    holds: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KucoinSymbol {
+    symbol: String,
+    base_currency: String,
+    quote_currency: String,
+    base_increment: String,
+    price_increment: String,
+    base_min_size: String,
+    #[serde(default)]
+    min_funds: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Level1 {
+    best_bid: String,
+    best_ask: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlacedOrder {
+    order_id: String,
+}
+
+#[derive(Deserialize)]
+struct OrderPage {
+    items: Vec<KucoinOrder>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct KucoinOrder {
+    id: String,
+    symbol: String,
+    #[serde(rename = "type")]
+    order_type: String,
+    side: String,
+    #[serde(default)]
+    price: Option<String>,
+    #[serde(default)]
+    size: Option<String>,
+    #[serde(default)]
+    deal_size: Option<String>,
+    #[serde(default)]
+    deal_funds: Option<String>,
+    #[serde(default)]
+    fee: Option<String>,
+    #[serde(default)]
+    is_active: Option<bool>,
+    #[serde(default)]
+    cancel_exist: Option<bool>,
+    /// "loss" o "entry" en las órdenes de activación
+    #[serde(default)]
+    stop: Option<String>,
+    #[serde(default)]
+    stop_price: Option<String>,
+    /// Solo en las órdenes de activación sin disparar: NEW o TRIGGERED
+    #[serde(default)]
+    status: Option<String>,
+    created_at: i64,
+}
+
+/// KuCoin separa los pares con guion: BTC/USDT pasa a BTC-USDT.
+fn market_symbol(symbol: &str) -> String {
+    symbol.replace('/', "-").to_uppercase()
+}
+
+/// En KuCoin "loss" se dispara cuando el precio baja hasta el de activación y
+/// "entry" cuando sube. Un stop-loss de venta es "loss"; de compra, "entry".
+fn stop_direction(order_type: OrderType, side: OrderSide) -> Option<&'static str> {
+    let stop_loss = match order_type {
+        OrderType::StopLoss | OrderType::StopLossLimit => true,
+        OrderType::TakeProfit | OrderType::TakeProfitLimit => false,
+        OrderType::Market | OrderType::Limit => return None,
+    };
+    Some(if stop_loss == (side == OrderSide::Sell) { "loss" } else { "entry" })
+}
+
+fn parse_order_type(order: &KucoinOrder, side: OrderSide) -> OrderType {
+    let limit = order.order_type == "limit";
+    match order.stop.as_deref().filter(|stop| !stop.is_empty()) {
+        None if limit => OrderType::Limit,
+        None => OrderType::Market,
+        Some(stop) => match ((stop == "loss") == (side == OrderSide::Sell), limit) {
+            (true, false) => OrderType::StopLoss,
+            (true, true) => OrderType::StopLossLimit,
+            (false, false) => OrderType::TakeProfit,
+            (false, true) => OrderType::TakeProfitLimit,
+        },
+    }
+}
+
+fn order_status(order: &KucoinOrder, quantity: Decimal, filled: Decimal) -> OrderStatus {
+    if let Some(status) = order.status.as_deref() {
+        // Orden de activación todavía en espera
+        if status == "NEW" {
+            return OrderStatus::New;
+        }
+    }
+    match (order.is_active.unwrap_or(false), order.cancel_exist.unwrap_or(false)) {
+        (true, _) if filled > Decimal::ZERO => OrderStatus::PartiallyFilled,
+        (true, _) => OrderStatus::New,
+        (false, true) => OrderStatus::Canceled,
+        (false, false) if quantity > Decimal::ZERO && filled < quantity => OrderStatus::Expired,
+        (false, false) => OrderStatus::Filled,
+    }
 }
 
 impl KuCoinExchange {
     pub fn new(credentials: ExchangeCredentials) -> Result<Self, ExchangeError> {
+        Self::with_base_url(credentials, DEFAULT_BASE_URL)
+    }
+
+    /// Igual que `new` pero contra otra URL, p. ej. el sandbox o un servidor de pruebas.
+    pub fn with_base_url(credentials: ExchangeCredentials, base_url: &str) -> Result<Self, ExchangeError> {
         let passphrase = credentials.passphrase
             .ok_or(ExchangeError::MissingCredentials("KuCoin requires passphrase".into()))?;
-            
-        let client = KucoinClient::new(
-            credentials.api_key,
-            credentials.api_secret,
-            passphrase,
-        )?;
-        
-        Ok(Self { client })
+        if credentials.api_key.is_empty() || credentials.api_secret.is_empty() {
+            return Err(ExchangeError::MissingCredentials("KuCoin requiere API key y secret".into()));
+        }
+
+        Ok(Self {
+            client: Client::builder().timeout(Duration::from_secs(10)).build()?,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            passphrase: BASE64.encode(hmac_sha256(&credentials.api_secret, &passphrase)),
+            api_key: credentials.api_key,
+            api_secret: credentials.api_secret,
+            time_offset: AtomicI64::new(0),
+            time_synced: AtomicBool::new(false),
+            rules: Mutex::new(HashMap::new()),
+        })
+    }
+
+    async fn sync_time(&self) -> Result<(), ExchangeError> {
+        let before = chrono::Utc::now().timestamp_millis();
+        let response: KucoinResponse<i64> = self.client
+            .get(format!("{}/api/v1/timestamp", self.base_url))
+            .send()
+            .await?
+            .json()
+            .await?;
+        let server_time = response.data
+            .ok_or_else(|| ExchangeError::Api(format!("no se pudo obtener la hora del servidor ({})", response.code)))?;
+        let after = chrono::Utc::now().timestamp_millis();
+        self.time_offset.store(server_time - (before + after) / 2, Ordering::Relaxed);
+        self.time_synced.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Firma: base64(HMAC-SHA256(secret, marca + método + ruta con query + cuerpo)).
+    fn sign(&self, timestamp: i64, method: &Method, endpoint: &str, body: &str) -> String {
+        BASE64.encode(hmac_sha256(&self.api_secret, &format!("{}{}{}{}", timestamp, method.as_str(), endpoint, body)))
+    }
+
+    async fn request<T: DeserializeOwned>(&self, method: Method, endpoint: &str, body: Option<serde_json::Value>, signed: bool) -> Result<T, ExchangeError> {
+        self.request_with_code(method, endpoint, body, signed).await.map_err(|(_, e)| e)
+    }
+
+    /// Como `request`, pero conserva el código de error de KuCoin. Las
+    /// peticiones firmadas rechazadas por la marca de tiempo se reintentan
+    /// una vez tras sincronizar el reloj.
+    async fn request_with_code<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        endpoint: &str,
+        body: Option<serde_json::Value>,
+        signed: bool,
+    ) -> Result<T, (Option<String>, ExchangeError)> {
+        if signed && !self.time_synced.load(Ordering::Relaxed) {
+            self.sync_time().await.map_err(|e| (None, e))?;
+        }
+        let body = body.map(|body| body.to_string()).unwrap_or_default();
+
+        let mut retried = false;
+        loop {
+            let mut request = self.client.request(method.clone(), format!("{}{}", self.base_url, endpoint));
+            if signed {
+                let timestamp = chrono::Utc::now().timestamp_millis() + self.time_offset.load(Ordering::Relaxed);
+                request = request
+                    .header("KC-API-KEY", &self.api_key)
+                    .header("KC-API-SIGN", self.sign(timestamp, &method, endpoint, &body))
+                    .header("KC-API-TIMESTAMP", timestamp.to_string())
+                    .header("KC-API-PASSPHRASE", &self.passphrase)
+                    .header("KC-API-KEY-VERSION", "2");
+            }
+            if !body.is_empty() {
+                request = request.header("Content-Type", "application/json").body(body.clone());
+            }
+
+            let response = request.send().await.map_err(|e| (None, e.into()))?;
+            let status = response.status();
+            let text = response.text().await.map_err(|e| (None, e.into()))?;
+            let parsed: KucoinResponse<T> = match serde_json::from_str(&text) {
+                Ok(parsed) => parsed,
+                Err(_) => return Err((None, ExchangeError::Api(format!("HTTP {}: {}", status, text)))),
+            };
+
+            if parsed.code == SUCCESS {
+                return parsed.data.ok_or_else(|| (None, ExchangeError::Api("respuesta sin datos".to_string())));
+            }
+            if signed && parsed.code == INVALID_TIMESTAMP && !retried {
+                info!("KuCoin rechazó la marca de tiempo; sincronizando el reloj");
+                retried = true;
+                self.sync_time().await.map_err(|e| (None, e))?;
+                continue;
+            }
+            let message = format!("{} ({})", parsed.msg.unwrap_or_default(), parsed.code);
+            return Err((Some(parsed.code), ExchangeError::Api(message)));
+        }
+    }
+
+    async fn symbol_rules(&self, symbol: &str) -> Result<SymbolRules, ExchangeError> {
+        if let Some(rules) = self.rules.lock().unwrap().get(symbol) {
+            return Ok(rules.clone());
+        }
+
+        let info: KucoinSymbol = self.request(Method::GET, &format!("/api/v2/symbols/{}", symbol), None, false).await?;
+        let rules = SymbolRules {
+            symbol: info.symbol,
+            base_asset: info.base_currency,
+            quote_asset: info.quote_currency,
+            step_size: parse_decimal(&info.base_increment)?,
+            tick_size: parse_decimal(&info.price_increment)?,
+            min_quantity: parse_decimal(&info.base_min_size)?,
+            min_notional: parse_decimal(info.min_funds.as_deref().unwrap_or_default())?,
+        };
+        self.rules.lock().unwrap().insert(symbol.to_string(), rules.clone());
+        Ok(rules)
+    }
+
+    fn to_order(order: KucoinOrder) -> Result<Order, ExchangeError> {
+        let side = if order.side == "sell" { OrderSide::Sell } else { OrderSide::Buy };
+        let quantity = parse_decimal(order.size.as_deref().unwrap_or_default())?;
+        let filled = parse_decimal(order.deal_size.as_deref().unwrap_or_default())?;
+        let funds = parse_decimal(order.deal_funds.as_deref().unwrap_or_default())?;
+        let price = parse_decimal(order.price.as_deref().unwrap_or_default())?;
+        let stop_price = parse_decimal(order.stop_price.as_deref().unwrap_or_default())?;
+        let created_at = from_millis(order.created_at);
+
+        Ok(Order {
+            order_type: parse_order_type(&order, side),
+            status: order_status(&order, quantity, filled),
+            id: order.id,
+            symbol: order.symbol,
+            side,
+            price: [price, stop_price].into_iter().find(|price| *price > Decimal::ZERO),
+            quantity,
+            filled_quantity: filled,
+            average_price: (filled > Decimal::ZERO).then(|| funds / filled),
+            fee: parse_decimal(order.fee.as_deref().unwrap_or_default())?,
+            fee_complete: true,
+            created_at,
+            updated_at: created_at,
+        })
+    }
+
+    /// Las órdenes de activación viven en otro recurso hasta que se disparan.
+    async fn find_order(&self, order_id: &str) -> Result<(KucoinOrder, bool), ExchangeError> {
+        match self.request_with_code(Method::GET, &format!("/api/v1/orders/{}", order_id), None, true).await {
+            Ok(order) => Ok((order, false)),
+            Err((Some(code), _)) if code == ORDER_NOT_FOUND => {
+                match self.request_with_code(Method::GET, &format!("/api/v1/stop-order/{}", order_id), None, true).await {
+                    Ok(order) => Ok((order, true)),
+                    Err((Some(code), _)) if code == ORDER_NOT_FOUND => Err(ExchangeError::OrderNotFound(order_id.to_string())),
+                    Err((_, e)) => Err(e),
+                }
+            }
+            Err((_, e)) => Err(e),
+        }
     }
 }
 
 #[async_trait::async_trait]
 impl Exchange for KuCoinExchange {
+    fn name(&self) -> &str {
+        "kucoin"
+    }
+
     async fn get_balance(&self, asset: &str) -> Result<Balance, ExchangeError> {
-        let account = self.client.get_account(asset)?;
-        
+        let asset = asset.to_uppercase();
+        let accounts: Vec<KucoinAccount> = self.request(
+            Method::GET,
+            &format!("/api/v1/accounts?currency={}&type=trade", asset),
+            None,
+            true,
+        ).await?;
+        let account = accounts.into_iter()
+            .next()
+            .ok_or(ExchangeError::AssetNotFound(asset))?;
+
         Ok(Balance {
             asset: account.currency,
-            free: Decimal::from_str(&account.available)?,
-            locked: Decimal::from_str(&account.holds)?,
+            free: parse_decimal(&account.available)?,
+            locked: parse_decimal(&account.holds)?,
+        })
+    }
+
+    async fn get_quote(&self, symbol: &str) -> Result<Quote, ExchangeError> {
+        let level1: Level1 = self.request(
+            Method::GET,
+            &format!("/api/v1/market/orderbook/level1?symbol={}", market_symbol(symbol)),
+            None,
+            false,
+        ).await?;
+        Ok(Quote {
+            bid: parse_decimal(&level1.best_bid)?,
+            ask: parse_decimal(&level1.best_ask)?,
         })
     }
-    
-    // Implementar resto de métodos...
-} 
\ No newline at end of file
+
+    async fn place_order(
+        &self,
+        symbol: &str,
+        side: OrderSide,
+        order_type: OrderType,
+        quantity: Decimal,
+        price: Option<Decimal>,
+    ) -> Result<Order, ExchangeError> {
+        let symbol = market_symbol(symbol);
+        let rules = self.symbol_rules(&symbol).await?;
+        let (quantity, price) = rules.apply(quantity, price)?;
+
+        let mut client_oid = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut client_oid);
+        let limit = matches!(order_type, OrderType::Limit | OrderType::StopLossLimit | OrderType::TakeProfitLimit);
+        let mut body = json!({
+            "clientOid": hex::encode(client_oid),
+            "side": if side == OrderSide::Buy { "buy" } else { "sell" },
+            "symbol": symbol,
+            "type": if limit { "limit" } else { "market" },
+            "size": quantity.to_string(),
+        });
+
+        let stop = stop_direction(order_type, side);
+        if order_type != OrderType::Market {
+            let price = price.ok_or_else(|| ExchangeError::InvalidOrder("esta orden necesita precio".to_string()))?;
+            if limit {
+                body["price"] = json!(price.to_string());
+            }
+            if let Some(stop) = stop {
+                body["stop"] = json!(stop);
+                body["stopPrice"] = json!(price.to_string());
+            }
+        }
+
+        let endpoint = if stop.is_some() { "/api/v1/stop-order" } else { "/api/v1/orders" };
+        let placed: PlacedOrder = self.request(Method::POST, endpoint, Some(body), true).await?;
+        let (order, _) = self.find_order(&placed.order_id).await?;
+        Self::to_order(order)
+    }
+
+    async fn cancel_order(&self, _symbol: &str, order_id: &str) -> Result<(), ExchangeError> {
+        let (_, is_stop) = self.find_order(order_id).await?;
+        let endpoint = if is_stop {
+            format!("/api/v1/stop-order/{}", order_id)
+        } else {
+            format!("/api/v1/orders/{}", order_id)
+        };
+        let _: serde_json::Value = self.request(Method::DELETE, &endpoint, None, true).await?;
+        Ok(())
+    }
+
+    async fn get_order(&self, _symbol: &str, order_id: &str) -> Result<Order, ExchangeError> {
+        let (order, _) = self.find_order(order_id).await?;
+        Self::to_order(order)
+    }
+
+    async fn get_open_orders(&self, symbol: &str) -> Result<Vec<Order>, ExchangeError> {
+        let symbol = market_symbol(symbol);
+        let active: OrderPage = self.request(
+            Method::GET,
+            &format!("/api/v1/orders?status=active&symbol={}", symbol),
+            None,
+            true,
+        ).await?;
+        let stops: OrderPage = self.request(
+            Method::GET,
+            &format!("/api/v1/stop-order?symbol={}", symbol),
+            None,
+            true,
+        ).await?;
+        active.items.into_iter()
+            .chain(stops.items)
+            .map(Self::to_order)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{extract::State, http::{HeaderMap, Uri}, Json, Router};
+    use std::sync::{Arc, atomic::AtomicUsize};
+
+    #[derive(Default)]
+    struct MockState {
+        /// Consultas de la hora del servidor
+        syncs: AtomicUsize,
+        /// Si ya se rechazó la primera petición firmada por la marca de tiempo
+        rejected: AtomicBool,
+    }
+
+    /// Exchange de pruebas que comprueba la firma v2 de cada petición privada
+    /// y solo conoce la orden de activación `s1`.
+    async fn mock(
+        State(state): State<Arc<MockState>>,
+        method: axum::http::Method,
+        uri: Uri,
+        headers: HeaderMap,
+        body: String,
+    ) -> Json<serde_json::Value> {
+        let ok = |data: serde_json::Value| Json(json!({ "code": SUCCESS, "data": data }));
+        let endpoint = uri.path_and_query().map(|endpoint| endpoint.as_str()).unwrap_or_default();
+        if endpoint == "/api/v1/timestamp" {
+            state.syncs.fetch_add(1, Ordering::Relaxed);
+            return ok(json!(chrono::Utc::now().timestamp_millis()));
+        }
+        if endpoint == "/api/v2/symbols/BTC-USDT" {
+            return ok(json!({
+                "symbol": "BTC-USDT", "baseCurrency": "BTC", "quoteCurrency": "USDT",
+                "baseIncrement": "0.0001", "priceIncrement": "0.01", "baseMinSize": "0.0001", "minFunds": "0.1"
+            }));
+        }
+
+        let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok()).unwrap_or_default().to_string();
+        assert_eq!(header("KC-API-KEY"), "key");
+        assert_eq!(header("KC-API-KEY-VERSION"), "2");
+        assert_eq!(header("KC-API-PASSPHRASE"), BASE64.encode(hmac_sha256("secret", "passphrase")));
+        let payload = format!("{}{}{}{}", header("KC-API-TIMESTAMP"), method.as_str(), endpoint, body);
+        assert_eq!(header("KC-API-SIGN"), BASE64.encode(hmac_sha256("secret", &payload)));
+        if !state.rejected.swap(true, Ordering::Relaxed) {
+            return Json(json!({ "code": INVALID_TIMESTAMP, "msg": "Invalid KC-API-TIMESTAMP" }));
+        }
+
+        let stop_order = json!({
+            "id": "s1", "symbol": "BTC-USDT", "type": "market", "side": "sell", "size": "0.5",
+            "stop": "loss", "stopPrice": "90", "status": "NEW", "createdAt": 1700000000000i64
+        });
+        match (method.as_str(), endpoint) {
+            ("POST", "/api/v1/stop-order") => {
+                assert!(body.contains(r#""stop":"loss""#));
+                ok(json!({ "orderId": "s1" }))
+            }
+            ("GET", "/api/v1/stop-order/s1") => ok(stop_order),
+            ("DELETE", "/api/v1/stop-order/s1") => ok(json!({ "cancelledOrderIds": ["s1"] })),
+            _ => Json(json!({ "code": ORDER_NOT_FOUND, "msg": "order not exist." })),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_signed_stop_order_against_mock_exchange() {
+        let state = Arc::new(MockState::default());
+        let app = Router::new().fallback(mock).with_state(state.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let credentials = ExchangeCredentials {
+            api_key: "key".to_string(),
+            api_secret: "secret".to_string(),
+            passphrase: Some("passphrase".to_string()),
+        };
+        let exchange = KuCoinExchange::with_base_url(credentials, &url).unwrap();
+
+        // La orden no está entre las normales y se encuentra entre las de activación
+        let order = exchange.place_order("BTC/USDT", OrderSide::Sell, OrderType::StopLoss, Decimal::new(5, 1), Some(Decimal::new(90, 0)))
+            .await
+            .unwrap();
+        assert_eq!(order.id, "s1");
+        assert_eq!(order.order_type, OrderType::StopLoss);
+        assert_eq!(order.status, OrderStatus::New);
+        // La primera petición se rechazó por la marca de tiempo: se resincronizó y reintentó
+        assert_eq!(state.syncs.load(Ordering::Relaxed), 2);
+
+        exchange.cancel_order("BTC/USDT", "s1").await.unwrap();
+        assert!(matches!(exchange.get_order("BTC/USDT", "x").await, Err(ExchangeError::OrderNotFound(_))));
+    }
+
+    fn order(json: serde_json::Value) -> Order {
+        KuCoinExchange::to_order(serde_json::from_value(json).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_order_mapping() {
+        let partial = order(json!({
+            "id": "1", "symbol": "BTC-USDT", "type": "limit", "side": "buy",
+            "price": "100", "size": "2", "dealSize": "0.5", "dealFunds": "50",
+            "fee": "0.05", "isActive": true, "cancelExist": false, "stop": "",
+            "createdAt": 1700000000000i64
+        }));
+        assert_eq!(partial.status, OrderStatus::PartiallyFilled);
+        assert_eq!(partial.order_type, OrderType::Limit);
+        assert_eq!(partial.average_price, Some(Decimal::new(100, 0)));
+
+        let stop = order(json!({
+            "id": "2", "symbol": "BTC-USDT", "type": "market", "side": "sell",
+            "size": "1", "stop": "loss", "stopPrice": "90", "status": "NEW",
+            "createdAt": 1700000000000i64
+        }));
+        assert_eq!(stop.status, OrderStatus::New);
+        assert_eq!(stop.order_type, OrderType::StopLoss);
+        assert_eq!(stop.price, Some(Decimal::new(90, 0)));
+        assert_eq!(stop_direction(OrderType::TakeProfit, OrderSide::Sell), Some("entry"));
+    }
+}
@@ -2,18 +2,69 @@ pub mod types;
 pub mod errors;
 pub mod binance;
 pub mod kucoin;
+pub mod paper;
+pub mod vault;
 
 pub use types::*;
 pub use errors::*;
 pub use binance::BinanceExchange;
 pub use kucoin::KuCoinExchange;
+pub use paper::{PaperConfig, PaperExchange};
+pub use vault::{Vault, VaultError};
 
+use std::str::FromStr;
 use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rust_decimal::Decimal;
+use sha2::Sha256;
+use tracing::{info, warn};
+
+/// Parte del saldo de cotización que se deja sin asignar en las compras para
+/// cubrir la comisión (0,5 %).
+const FEE_BUFFER: Decimal = Decimal::from_parts(5, 0, 0, false, 3);
+
+fn hmac_sha256(secret: &str, message: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC acepta claves de cualquier tamaño");
+    mac.update(message.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Los exchanges devuelven cantidades y precios como texto; vacío es cero.
+fn parse_decimal(value: &str) -> Result<Decimal, ExchangeError> {
+    if value.is_empty() {
+        return Ok(Decimal::ZERO);
+    }
+    Ok(Decimal::from_str(value)?)
+}
+
+fn from_millis(ms: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp_millis(ms).unwrap_or_else(Utc::now)
+}
+
+/// Exchange que puede atender una orden, con su precio y cuánto puede ejecutar.
+struct Candidate {
+    exchange: Arc<dyn Exchange>,
+    price: Decimal,
+    /// Saldo libre del activo que se gasta: base al vender, cotización al comprar
+    available: Decimal,
+    /// Cantidad máxima de activo base que permite ese saldo
+    capacity: Decimal,
+}
+
+/// Cantidad asignada a cada exchange.
+type Allocation = Vec<(Arc<dyn Exchange>, Decimal)>;
 
 pub struct ExchangeManager {
     exchanges: Vec<Arc<dyn Exchange>>,
 }
 
+impl Default for ExchangeManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ExchangeManager {
     pub fn new() -> Self {
         Self {
@@ -21,12 +72,214 @@ impl ExchangeManager {
         }
     }
     
+    /// Registra un exchange; si ya había uno con el mismo nombre lo reemplaza.
     pub fn add_exchange(&mut self, exchange: Arc<dyn Exchange>) {
+        self.exchanges.retain(|existing| existing.name() != exchange.name());
         self.exchanges.push(exchange);
     }
-    
-    pub async fn execute_order(&self, order: OrderRequest) -> Result<Order, ExchangeError> {
-        // Implementar lógica para ejecutar orden en el exchange apropiado
-        todo!()
+
+    pub fn exchange(&self, name: &str) -> Option<Arc<dyn Exchange>> {
+        self.exchanges.iter()
+            .find(|exchange| exchange.name().eq_ignore_ascii_case(name))
+            .cloned()
+    }
+
+    pub fn exchanges(&self) -> &[Arc<dyn Exchange>] {
+        &self.exchanges
+    }
+
+    /// Ejecuta una orden según su política de enrutado. Antes de enviar nada
+    /// se comprueba el saldo de cada exchange; si no alcanza no se coloca
+    /// ninguna parte. Las partes que un exchange rechace quedan en
+    /// `failures` y solo es error si no se ejecutó ninguna.
+    pub async fn execute_order(&self, request: OrderRequest) -> Result<ExecutionReport, ExchangeError> {
+        if request.quantity <= Decimal::ZERO {
+            return Err(ExchangeError::InvalidOrder("la cantidad debe ser positiva".into()));
+        }
+        let (base, quote) = request.assets()?;
+        let venues = match &request.routing {
+            Routing::Venue(name) => vec![self.exchange(name).ok_or_else(|| ExchangeError::ExchangeNotFound(name.clone()))?],
+            Routing::BestPrice | Routing::SplitByBalance => self.exchanges.clone(),
+        };
+        if venues.is_empty() {
+            return Err(ExchangeError::InvalidOrder("no hay exchanges configurados".into()));
+        }
+
+        let mut candidates = Vec::new();
+        let mut last_error = None;
+        for exchange in venues {
+            match Self::candidate(exchange.clone(), &request, &base, &quote).await {
+                Ok(candidate) => candidates.push(candidate),
+                Err(e) => {
+                    warn!("No se pudo cotizar {} en {}: {}", request.symbol, exchange.name(), e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        if candidates.is_empty() {
+            return Err(last_error.unwrap_or(ExchangeError::PriceUnavailable(request.symbol.clone())));
+        }
+        // El mejor precio primero: el ask más bajo al comprar, el bid más alto al vender
+        candidates.sort_by(|a, b| match request.side {
+            OrderSide::Buy => a.price.cmp(&b.price),
+            OrderSide::Sell => b.price.cmp(&a.price),
+        });
+
+        let plan = Self::allocate(&request, &candidates, &base, &quote)?;
+
+        let mut fills = Vec::new();
+        let mut errors = Vec::new();
+        for (exchange, quantity) in plan {
+            match exchange.place_order(&request.symbol, request.side, request.order_type, quantity, request.price).await {
+                Ok(order) => {
+                    info!("Orden {} de {} {} colocada en {}", order.id, quantity, request.symbol, exchange.name());
+                    fills.push(VenueFill { exchange: exchange.name().to_string(), order });
+                }
+                Err(e) => {
+                    warn!("{} rechazó {} {}: {}", exchange.name(), quantity, request.symbol, e);
+                    errors.push((exchange.name().to_string(), quantity, e));
+                }
+            }
+        }
+
+        if fills.is_empty() && !errors.is_empty() {
+            return Err(errors.swap_remove(0).2);
+        }
+        let failures = errors.into_iter()
+            .map(|(exchange, quantity, error)| VenueFailure { exchange, quantity, error: error.to_string() })
+            .collect();
+        Ok(ExecutionReport::new(&request, fills, failures))
+    }
+
+    async fn candidate(exchange: Arc<dyn Exchange>, request: &OrderRequest, base: &str, quote: &str) -> Result<Candidate, ExchangeError> {
+        let price = exchange.get_quote(&request.symbol).await?.price_for(request.side);
+        let asset = match request.side {
+            OrderSide::Buy => quote,
+            OrderSide::Sell => base,
+        };
+        let available = match exchange.get_balance(asset).await {
+            Ok(balance) => balance.free,
+            Err(ExchangeError::AssetNotFound(_)) => Decimal::ZERO,
+            Err(e) => return Err(e),
+        };
+        // Una límite de compra bloquea saldo a su precio, no al de mercado
+        let reference = request.price.unwrap_or(price);
+        let capacity = match request.side {
+            OrderSide::Sell => available,
+            OrderSide::Buy if reference > Decimal::ZERO => available / (reference * (Decimal::ONE + FEE_BUFFER)),
+            OrderSide::Buy => Decimal::ZERO,
+        };
+        Ok(Candidate { exchange, price, available, capacity })
+    }
+
+    /// Reparte la cantidad entre los candidatos, ya ordenados por precio.
+    fn allocate(request: &OrderRequest, candidates: &[Candidate], base: &str, quote: &str) -> Result<Allocation, ExchangeError> {
+        let insufficient = |available: Decimal, price: Decimal| match request.side {
+            OrderSide::Sell => ExchangeError::InsufficientBalance {
+                asset: base.to_string(),
+                available,
+                required: request.quantity,
+            },
+            OrderSide::Buy => ExchangeError::InsufficientBalance {
+                asset: quote.to_string(),
+                available,
+                required: request.quantity * request.price.unwrap_or(price) * (Decimal::ONE + FEE_BUFFER),
+            },
+        };
+
+        match request.routing {
+            Routing::Venue(_) | Routing::BestPrice => candidates.iter()
+                .find(|candidate| candidate.capacity >= request.quantity)
+                .map(|candidate| vec![(candidate.exchange.clone(), request.quantity)])
+                .ok_or_else(|| insufficient(candidates[0].available, candidates[0].price)),
+            Routing::SplitByBalance => {
+                let mut plan = Vec::new();
+                let mut remaining = request.quantity;
+                for candidate in candidates {
+                    if remaining <= Decimal::ZERO {
+                        break;
+                    }
+                    let quantity = remaining.min(candidate.capacity);
+                    if quantity > Decimal::ZERO {
+                        plan.push((candidate.exchange.clone(), quantity));
+                        remaining -= quantity;
+                    }
+                }
+                if remaining > Decimal::ZERO {
+                    let available = candidates.iter().map(|candidate| candidate.available).sum();
+                    return Err(insufficient(available, candidates[0].price));
+                }
+                Ok(plan)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto_api::PriceCache;
+    use crate::models::CryptoPrice;
+
+    fn manager() -> ExchangeManager {
+        let prices = PriceCache::default();
+        prices.set(CryptoPrice {
+            symbol: "BTC".to_string(),
+            price: 1000.0,
+            exchange: "binance".to_string(),
+            timestamp: Utc::now().timestamp(),
+        });
+        // "cheap" tiene menos deslizamiento pero solo 1 BTC
+        let cheap = PaperExchange::new(prices.clone(), PaperConfig { slippage: Decimal::ZERO, ..PaperConfig::default() })
+            .with_name("cheap");
+        cheap.deposit("BTC", Decimal::ONE);
+        cheap.deposit("USDT", Decimal::new(500, 0));
+        let deep = PaperExchange::new(prices, PaperConfig::default()).with_name("deep");
+        deep.deposit("BTC", Decimal::new(5, 0));
+        deep.deposit("USDT", Decimal::new(10_000, 0));
+
+        let mut manager = ExchangeManager::new();
+        manager.add_exchange(Arc::new(cheap));
+        manager.add_exchange(Arc::new(deep));
+        manager
     }
-} 
\ No newline at end of file
+
+    fn request(side: OrderSide, quantity: Decimal, routing: Routing) -> OrderRequest {
+        OrderRequest {
+            symbol: "BTC/USDT".to_string(),
+            side,
+            order_type: OrderType::Market,
+            quantity,
+            price: None,
+            routing,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_order_routing() {
+        let manager = manager();
+
+        // Compra: "cheap" tiene mejor ask pero no USDT para 1 BTC, así que va a "deep"
+        let report = manager.execute_order(request(OrderSide::Buy, Decimal::ONE, Routing::BestPrice)).await.unwrap();
+        assert_eq!(report.fills.len(), 1);
+        assert_eq!(report.fills[0].exchange, "deep");
+
+        // Venta: mejor bid en "cheap", que tiene saldo
+        let report = manager.execute_order(request(OrderSide::Sell, Decimal::ONE, Routing::BestPrice)).await.unwrap();
+        assert_eq!(report.fills[0].exchange, "cheap");
+        assert_eq!(report.average_price, Some(Decimal::new(1000, 0)));
+
+        // Reparto: "cheap" ya no tiene BTC; "deep" tiene 6
+        let report = manager.execute_order(request(OrderSide::Sell, Decimal::new(7, 0), Routing::SplitByBalance)).await;
+        assert!(matches!(report, Err(ExchangeError::InsufficientBalance { .. })));
+
+        let report = manager.execute_order(request(OrderSide::Buy, Decimal::TWO, Routing::SplitByBalance)).await.unwrap();
+        assert_eq!(report.filled_quantity, Decimal::TWO);
+        assert_eq!(report.fills[0].exchange, "cheap");
+        assert_eq!(report.fills[1].exchange, "deep");
+        assert!(report.failures.is_empty());
+
+        let missing = manager.execute_order(request(OrderSide::Buy, Decimal::ONE, Routing::Venue("kraken".into()))).await;
+        assert!(matches!(missing, Err(ExchangeError::ExchangeNotFound(_))));
+    }
+}
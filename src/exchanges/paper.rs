@@ -0,0 +1,396 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use chrono::Utc;
+use rust_decimal::Decimal;
+use rust_decimal::prelude::FromPrimitive;
+use crate::crypto_api::PriceCache;
+use super::{errors::ExchangeError, types::*};
+
+/// Parámetros de simulación del exchange de papel.
+#[derive(Debug, Clone)]
+pub struct PaperConfig {
+    /// Comisión sobre el nominal de cada ejecución (0.001 = 0,1 %)
+    pub fee_rate: Decimal,
+    /// Deslizamiento en contra en las ejecuciones a mercado (0.0005 = 0,05 %)
+    pub slippage: Decimal,
+    /// Activo de cotización de los pares: BTCUSDT, BTC/USDT o BTC-USDT
+    pub quote_asset: String,
+    /// Segundos tras los que un precio del monitor ya no sirve para ejecutar
+    pub max_price_age: i64,
+}
+
+impl Default for PaperConfig {
+    fn default() -> Self {
+        Self {
+            fee_rate: Decimal::new(1, 3),
+            slippage: Decimal::new(5, 4),
+            quote_asset: "USDT".to_string(),
+            max_price_age: 300,
+        }
+    }
+}
+
+/// Separa un par en activo base y de cotización.
+fn split_symbol(symbol: &str, quote_asset: &str) -> Option<(String, String)> {
+    let symbol = symbol.to_uppercase();
+    if let Some((base, quote)) = symbol.split_once(['/', '-']) {
+        return (!base.is_empty() && quote == quote_asset).then(|| (base.to_string(), quote.to_string()));
+    }
+    symbol.strip_suffix(quote_asset)
+        .filter(|base| !base.is_empty())
+        .map(|base| (base.to_string(), quote_asset.to_string()))
+}
+
+#[derive(Default)]
+struct PaperBook {
+    balances: HashMap<String, Balance>,
+    orders: Vec<Order>,
+    /// Fondos bloqueados por cada orden abierta: (activo, cantidad)
+    reserved: HashMap<String, (String, Decimal)>,
+    next_id: u64,
+}
+
+impl PaperBook {
+    fn balance_mut(&mut self, asset: &str) -> &mut Balance {
+        self.balances.entry(asset.to_string()).or_insert_with(|| Balance {
+            asset: asset.to_string(),
+            free: Decimal::ZERO,
+            locked: Decimal::ZERO,
+        })
+    }
+
+    fn reserve(&mut self, order_id: &str, asset: &str, amount: Decimal) -> Result<(), ExchangeError> {
+        let balance = self.balance_mut(asset);
+        if balance.free < amount {
+            return Err(ExchangeError::InsufficientBalance {
+                asset: asset.to_string(),
+                available: balance.free,
+                required: amount,
+            });
+        }
+        balance.free -= amount;
+        balance.locked += amount;
+        self.reserved.insert(order_id.to_string(), (asset.to_string(), amount));
+        Ok(())
+    }
+
+    fn release(&mut self, order_id: &str) {
+        if let Some((asset, amount)) = self.reserved.remove(order_id) {
+            let balance = self.balance_mut(&asset);
+            balance.locked -= amount;
+            balance.free += amount;
+        }
+    }
+
+    /// Ejecuta la orden completa a `price` y mueve los saldos.
+    fn fill(&mut self, order: &mut Order, base: &str, quote: &str, price: Decimal, fee_rate: Decimal) -> Result<(), ExchangeError> {
+        self.release(&order.id);
+        let notional = order.quantity * price;
+        let fee = notional * fee_rate;
+        match order.side {
+            OrderSide::Buy => {
+                let required = notional + fee;
+                let balance = self.balance_mut(quote);
+                if balance.free < required {
+                    return Err(ExchangeError::InsufficientBalance {
+                        asset: quote.to_string(),
+                        available: balance.free,
+                        required,
+                    });
+                }
+                balance.free -= required;
+                self.balance_mut(base).free += order.quantity;
+            }
+            OrderSide::Sell => {
+                let balance = self.balance_mut(base);
+                if balance.free < order.quantity {
+                    return Err(ExchangeError::InsufficientBalance {
+                        asset: base.to_string(),
+                        available: balance.free,
+                        required: order.quantity,
+                    });
+                }
+                balance.free -= order.quantity;
+                self.balance_mut(quote).free += notional - fee;
+            }
+        }
+        order.filled_quantity = order.quantity;
+        order.average_price = Some(price);
+        order.fee = fee;
+        order.status = OrderStatus::Filled;
+        order.updated_at = Utc::now();
+        Ok(())
+    }
+}
+
+/// Exchange simulado en memoria. Ejecuta contra los precios que obtiene el
+/// monitor, con comisión y deslizamiento configurables, y nunca sale a la red.
+/// Las órdenes límite y de activación quedan abiertas hasta que el precio las
+/// alcanza; se revisan cada vez que se consulta el exchange.
+pub struct PaperExchange {
+    name: String,
+    prices: PriceCache,
+    config: PaperConfig,
+    book: Mutex<PaperBook>,
+    /// Arranque de la cuenta; forma parte de los ids para que no se repitan
+    /// entre reinicios
+    session: i64,
+}
+
+impl PaperExchange {
+    pub fn new(prices: PriceCache, config: PaperConfig) -> Self {
+        Self {
+            name: "paper".to_string(),
+            prices,
+            config,
+            book: Mutex::new(PaperBook::default()),
+            session: Utc::now().timestamp(),
+        }
+    }
+
+    /// Cambia el nombre con el que se registra, para tener varias cuentas simuladas.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Acredita saldo disponible en la cuenta simulada.
+    pub fn deposit(&self, asset: &str, amount: Decimal) {
+        self.book.lock().unwrap().balance_mut(&asset.to_uppercase()).free += amount;
+    }
+
+    fn pair(&self, symbol: &str) -> Result<(String, String), ExchangeError> {
+        split_symbol(symbol, &self.config.quote_asset)
+            .ok_or_else(|| ExchangeError::UnsupportedSymbol(symbol.to_string()))
+    }
+
+    fn market_price(&self, base: &str) -> Result<Decimal, ExchangeError> {
+        let price = self.prices.get(base)
+            .filter(|price| Utc::now().timestamp() - price.timestamp <= self.config.max_price_age)
+            .ok_or_else(|| ExchangeError::PriceUnavailable(base.to_string()))?;
+        Decimal::from_f64(price.price).ok_or_else(|| ExchangeError::PriceUnavailable(base.to_string()))
+    }
+
+    fn slipped(&self, price: Decimal, side: OrderSide) -> Decimal {
+        match side {
+            OrderSide::Buy => price * (Decimal::ONE + self.config.slippage),
+            OrderSide::Sell => price * (Decimal::ONE - self.config.slippage),
+        }
+    }
+
+    /// Precio de ejecución de una orden abierta si el mercado la alcanzó.
+    fn execution_price(&self, order: &Order, market: Decimal) -> Option<Decimal> {
+        let target = order.price?;
+        let reached = match (order.order_type, order.side) {
+            (OrderType::Limit, OrderSide::Buy) | (OrderType::TakeProfit, OrderSide::Buy) => market <= target,
+            (OrderType::Limit, OrderSide::Sell) | (OrderType::TakeProfit, OrderSide::Sell) => market >= target,
+            (OrderType::StopLoss, OrderSide::Buy) => market >= target,
+            (OrderType::StopLoss, OrderSide::Sell) => market <= target,
+            _ => false,
+        };
+        if !reached {
+            return None;
+        }
+        // Una límite nunca empeora su precio; las de activación salen a mercado
+        match order.order_type {
+            OrderType::Limit => Some(market),
+            _ => Some(self.slipped(market, order.side)),
+        }
+    }
+
+    fn match_resting(&self, book: &mut PaperBook) {
+        for index in 0..book.orders.len() {
+            if !book.orders[index].status.is_open() {
+                continue;
+            }
+            let mut order = book.orders[index].clone();
+            let Ok((base, quote)) = self.pair(&order.symbol) else { continue };
+            let Some(price) = self.market_price(&base).ok().and_then(|market| self.execution_price(&order, market)) else {
+                continue;
+            };
+            if book.fill(&mut order, &base, &quote, price, self.config.fee_rate).is_err() {
+                order.status = OrderStatus::Rejected;
+                order.updated_at = Utc::now();
+            }
+            book.orders[index] = order;
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Exchange for PaperExchange {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn get_balance(&self, asset: &str) -> Result<Balance, ExchangeError> {
+        let mut book = self.book.lock().unwrap();
+        self.match_resting(&mut book);
+        Ok(book.balance_mut(&asset.to_uppercase()).clone())
+    }
+
+    async fn get_quote(&self, symbol: &str) -> Result<Quote, ExchangeError> {
+        let (base, _) = self.pair(symbol)?;
+        let market = self.market_price(&base)?;
+        Ok(Quote {
+            bid: self.slipped(market, OrderSide::Sell),
+            ask: self.slipped(market, OrderSide::Buy),
+        })
+    }
+
+    async fn place_order(
+        &self,
+        symbol: &str,
+        side: OrderSide,
+        order_type: OrderType,
+        quantity: Decimal,
+        price: Option<Decimal>,
+    ) -> Result<Order, ExchangeError> {
+        let (base, quote) = self.pair(symbol)?;
+        if quantity <= Decimal::ZERO {
+            return Err(ExchangeError::InvalidOrder("la cantidad debe ser mayor que cero".to_string()));
+        }
+        let market = self.market_price(&base)?;
+
+        let mut book = self.book.lock().unwrap();
+        book.next_id += 1;
+        let now = Utc::now();
+        let mut order = Order {
+            id: format!("paper-{}-{}", self.session, book.next_id),
+            symbol: symbol.to_uppercase(),
+            order_type,
+            side,
+            price,
+            quantity,
+            filled_quantity: Decimal::ZERO,
+            average_price: None,
+            fee: Decimal::ZERO,
+            fee_complete: true,
+            status: OrderStatus::New,
+            created_at: now,
+            updated_at: now,
+        };
+
+        match order_type {
+            OrderType::Market => {
+                book.fill(&mut order, &base, &quote, self.slipped(market, side), self.config.fee_rate)?;
+                book.orders.push(order.clone());
+                Ok(order)
+            }
+            OrderType::Limit | OrderType::StopLoss | OrderType::TakeProfit => {
+                let target = match price {
+                    Some(target) if target > Decimal::ZERO => target,
+                    _ => return Err(ExchangeError::InvalidOrder("esta orden necesita un precio positivo".to_string())),
+                };
+                match side {
+                    OrderSide::Buy => {
+                        let margin = Decimal::ONE + self.config.fee_rate + self.config.slippage;
+                        book.reserve(&order.id, &quote, quantity * target * margin)?;
+                    }
+                    OrderSide::Sell => book.reserve(&order.id, &base, quantity)?,
+                }
+                book.orders.push(order.clone());
+                self.match_resting(&mut book);
+                Ok(book.orders.last().cloned().unwrap_or(order))
+            }
+            OrderType::StopLossLimit | OrderType::TakeProfitLimit => Err(ExchangeError::InvalidOrder(
+                "el exchange simulado no admite órdenes de activación con límite".to_string(),
+            )),
+        }
+    }
+
+    async fn cancel_order(&self, symbol: &str, order_id: &str) -> Result<(), ExchangeError> {
+        let mut book = self.book.lock().unwrap();
+        self.match_resting(&mut book);
+        let symbol = symbol.to_uppercase();
+        let index = book.orders.iter()
+            .position(|order| order.id == order_id && order.symbol == symbol)
+            .ok_or_else(|| ExchangeError::OrderNotFound(order_id.to_string()))?;
+        if !book.orders[index].status.is_open() {
+            return Err(ExchangeError::InvalidOrder(format!("la orden {} ya no está abierta", order_id)));
+        }
+        book.release(order_id);
+        book.orders[index].status = OrderStatus::Canceled;
+        book.orders[index].updated_at = Utc::now();
+        Ok(())
+    }
+
+    async fn get_order(&self, symbol: &str, order_id: &str) -> Result<Order, ExchangeError> {
+        let mut book = self.book.lock().unwrap();
+        self.match_resting(&mut book);
+        let symbol = symbol.to_uppercase();
+        book.orders.iter()
+            .find(|order| order.id == order_id && order.symbol == symbol)
+            .cloned()
+            .ok_or_else(|| ExchangeError::OrderNotFound(order_id.to_string()))
+    }
+
+    async fn get_open_orders(&self, symbol: &str) -> Result<Vec<Order>, ExchangeError> {
+        let mut book = self.book.lock().unwrap();
+        self.match_resting(&mut book);
+        let symbol = symbol.to_uppercase();
+        Ok(book.orders.iter()
+            .filter(|order| order.symbol == symbol && order.status.is_open())
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::CryptoPrice;
+
+    fn exchange_at(price: f64) -> (PaperExchange, PriceCache) {
+        let prices = PriceCache::default();
+        set_price(&prices, price);
+        let exchange = PaperExchange::new(prices.clone(), PaperConfig::default());
+        exchange.deposit("USDT", Decimal::new(10_000, 0));
+        (exchange, prices)
+    }
+
+    fn set_price(prices: &PriceCache, price: f64) {
+        prices.set(CryptoPrice {
+            symbol: "BTC".to_string(),
+            price,
+            exchange: "binance".to_string(),
+            timestamp: Utc::now().timestamp(),
+        });
+    }
+
+    #[tokio::test]
+    async fn test_market_order_applies_fee_and_slippage() {
+        let (exchange, _) = exchange_at(1000.0);
+        let order = exchange.place_order("BTCUSDT", OrderSide::Buy, OrderType::Market, Decimal::ONE, None).await.unwrap();
+
+        // 1000 + 0,05 % de deslizamiento, más 0,1 % de comisión
+        assert_eq!(order.status, OrderStatus::Filled);
+        assert_eq!(order.average_price, Some(Decimal::new(10005, 1)));
+        assert_eq!(order.fee, Decimal::new(10005, 4));
+        let usdt = exchange.get_balance("USDT").await.unwrap();
+        assert_eq!(usdt.free, Decimal::new(10_000, 0) - Decimal::new(10005, 1) - Decimal::new(10005, 4));
+        assert_eq!(exchange.get_balance("BTC").await.unwrap().free, Decimal::ONE);
+
+        let oversell = exchange.place_order("BTC/USDT", OrderSide::Sell, OrderType::Market, Decimal::TWO, None).await;
+        assert!(matches!(oversell, Err(ExchangeError::InsufficientBalance { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_limit_order_rests_until_price_reached() {
+        let (exchange, prices) = exchange_at(1000.0);
+        let order = exchange.place_order("BTC-USDT", OrderSide::Buy, OrderType::Limit, Decimal::ONE, Some(Decimal::new(900, 0))).await.unwrap();
+        assert_eq!(order.status, OrderStatus::New);
+        assert!(exchange.get_balance("USDT").await.unwrap().locked > Decimal::ZERO);
+
+        set_price(&prices, 890.0);
+        let filled = exchange.get_order("BTC-USDT", &order.id).await.unwrap();
+        assert_eq!(filled.status, OrderStatus::Filled);
+        assert_eq!(filled.average_price, Some(Decimal::new(890, 0)));
+        assert_eq!(exchange.get_balance("USDT").await.unwrap().locked, Decimal::ZERO);
+
+        let resting = exchange.place_order("BTC-USDT", OrderSide::Sell, OrderType::Limit, Decimal::ONE, Some(Decimal::new(2000, 0))).await.unwrap();
+        exchange.cancel_order("BTC-USDT", &resting.id).await.unwrap();
+        assert!(exchange.get_open_orders("BTC-USDT").await.unwrap().is_empty());
+        assert_eq!(exchange.get_balance("BTC").await.unwrap().free, Decimal::ONE);
+    }
+}
@@ -1,6 +1,7 @@
 use rust_decimal::Decimal;
 use serde::{Deserialize, Serialize};
 use chrono::{DateTime, Utc};
+use super::errors::ExchangeError;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ExchangeCredentials {
@@ -25,6 +26,19 @@ pub enum OrderType {
     TakeProfitLimit,
 }
 
+impl OrderType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderType::Market => "market",
+            OrderType::Limit => "limit",
+            OrderType::StopLoss => "stop_loss",
+            OrderType::StopLossLimit => "stop_loss_limit",
+            OrderType::TakeProfit => "take_profit",
+            OrderType::TakeProfitLimit => "take_profit_limit",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Order {
     pub id: String,
@@ -34,12 +48,21 @@ pub struct Order {
     pub price: Option<Decimal>,
     pub quantity: Decimal,
     pub filled_quantity: Decimal,
+    /// Precio medio de lo ejecutado
+    pub average_price: Option<Decimal>,
+    /// Comisión cobrada, en el activo de cotización. La cobrada en el activo
+    /// base se valora al precio de la ejecución y ya está descontada de
+    /// `filled_quantity`
+    pub fee: Decimal,
+    /// Falso si parte de la comisión no se pudo valorar (p. ej. pagada en
+    /// BNB): `fee` se queda por debajo de la real
+    pub fee_complete: bool,
     pub status: OrderStatus,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub enum OrderStatus {
     New,
     PartiallyFilled,
@@ -49,6 +72,24 @@ pub enum OrderStatus {
     Expired,
 }
 
+impl OrderStatus {
+    /// La orden sigue en el libro y puede ejecutarse o cancelarse.
+    pub fn is_open(&self) -> bool {
+        matches!(self, OrderStatus::New | OrderStatus::PartiallyFilled)
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrderStatus::New => "new",
+            OrderStatus::PartiallyFilled => "partially_filled",
+            OrderStatus::Filled => "filled",
+            OrderStatus::Canceled => "canceled",
+            OrderStatus::Rejected => "rejected",
+            OrderStatus::Expired => "expired",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Balance {
     pub asset: String,
@@ -56,10 +97,171 @@ pub struct Balance {
     pub locked: Decimal,
 }
 
+/// Mejor precio de compra (bid) y de venta (ask) del libro.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct Quote {
+    pub bid: Decimal,
+    pub ask: Decimal,
+}
+
+impl Quote {
+    /// Precio al que se ejecutaría una orden a mercado de ese lado.
+    pub fn price_for(&self, side: OrderSide) -> Decimal {
+        match side {
+            OrderSide::Buy => self.ask,
+            OrderSide::Sell => self.bid,
+        }
+    }
+}
+
+/// Cómo elige `ExchangeManager` dónde ejecutar una orden.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "policy", content = "exchange", rename_all = "snake_case")]
+pub enum Routing {
+    /// Solo en el exchange indicado
+    Venue(String),
+    /// Entera en el exchange con mejor precio que tenga saldo suficiente
+    BestPrice,
+    /// Repartida entre exchanges según su saldo, empezando por el mejor precio
+    SplitByBalance,
+}
+
+/// Orden a ejecutar a través de `ExchangeManager`. El símbolo va como
+/// BASE/QUOTE (p. ej. BTC/USDT); cada exchange lo traduce a su formato.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderRequest {
+    pub symbol: String,
+    pub side: OrderSide,
+    pub order_type: OrderType,
+    pub quantity: Decimal,
+    pub price: Option<Decimal>,
+    pub routing: Routing,
+}
+
+impl OrderRequest {
+    /// Activos base y de cotización del par.
+    pub fn assets(&self) -> Result<(String, String), ExchangeError> {
+        match self.symbol.to_uppercase().split_once(['/', '-']) {
+            Some((base, quote)) if !base.is_empty() && !quote.is_empty() => Ok((base.to_string(), quote.to_string())),
+            _ => Err(ExchangeError::InvalidOrder(format!("el símbolo {} debe tener el formato BASE/QUOTE", self.symbol))),
+        }
+    }
+}
+
+/// Parte de una orden ejecutada en un exchange.
+#[derive(Debug, Clone, Serialize)]
+pub struct VenueFill {
+    pub exchange: String,
+    pub order: Order,
+}
+
+/// Parte de una orden que un exchange no aceptó.
+#[derive(Debug, Clone, Serialize)]
+pub struct VenueFailure {
+    pub exchange: String,
+    pub quantity: Decimal,
+    pub error: String,
+}
+
+/// Resultado consolidado de una `OrderRequest` repartida entre exchanges.
+#[derive(Debug, Clone, Serialize)]
+pub struct ExecutionReport {
+    pub symbol: String,
+    pub side: OrderSide,
+    pub requested_quantity: Decimal,
+    pub filled_quantity: Decimal,
+    /// Precio medio ponderado de lo ejecutado
+    pub average_price: Option<Decimal>,
+    pub total_fee: Decimal,
+    pub fills: Vec<VenueFill>,
+    pub failures: Vec<VenueFailure>,
+}
+
+impl ExecutionReport {
+    pub fn new(request: &OrderRequest, fills: Vec<VenueFill>, failures: Vec<VenueFailure>) -> Self {
+        let filled_quantity: Decimal = fills.iter().map(|fill| fill.order.filled_quantity).sum();
+        let notional: Decimal = fills.iter()
+            .filter_map(|fill| fill.order.average_price.map(|price| price * fill.order.filled_quantity))
+            .sum();
+        Self {
+            symbol: request.symbol.to_uppercase(),
+            side: request.side,
+            requested_quantity: request.quantity,
+            filled_quantity,
+            average_price: (filled_quantity > Decimal::ZERO).then(|| notional / filled_quantity),
+            total_fee: fills.iter().map(|fill| fill.order.fee).sum(),
+            fills,
+            failures,
+        }
+    }
+}
+
+/// Restricciones de precisión y tamaño mínimo de un par, tal como las
+/// publica el exchange.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SymbolRules {
+    pub symbol: String,
+    pub base_asset: String,
+    pub quote_asset: String,
+    /// Incremento de cantidad; cero si no hay restricción
+    pub step_size: Decimal,
+    /// Incremento de precio; cero si no hay restricción
+    pub tick_size: Decimal,
+    pub min_quantity: Decimal,
+    pub min_notional: Decimal,
+}
+
+fn round_down(value: Decimal, step: Decimal) -> Decimal {
+    if step <= Decimal::ZERO {
+        return value.normalize();
+    }
+    ((value / step).floor() * step).normalize()
+}
+
+impl SymbolRules {
+    /// Cantidad truncada al incremento permitido (nunca se redondea hacia arriba).
+    pub fn round_quantity(&self, quantity: Decimal) -> Decimal {
+        round_down(quantity, self.step_size)
+    }
+
+    pub fn round_price(&self, price: Decimal) -> Decimal {
+        if self.tick_size <= Decimal::ZERO {
+            return price.normalize();
+        }
+        ((price / self.tick_size).round() * self.tick_size).normalize()
+    }
+
+    /// Ajusta cantidad y precio a la precisión del par y comprueba los mínimos.
+    pub fn apply(&self, quantity: Decimal, price: Option<Decimal>) -> Result<(Decimal, Option<Decimal>), ExchangeError> {
+        let quantity = self.round_quantity(quantity);
+        if quantity <= Decimal::ZERO || quantity < self.min_quantity {
+            return Err(ExchangeError::InvalidOrder(format!(
+                "la cantidad {} de {} está por debajo del mínimo {}",
+                quantity, self.symbol, self.min_quantity.normalize()
+            )));
+        }
+        let price = price.map(|price| self.round_price(price));
+        if let Some(price) = price {
+            if quantity * price < self.min_notional {
+                return Err(ExchangeError::InvalidOrder(format!(
+                    "el nominal de la orden es menor que el mínimo de {} {}",
+                    self.min_notional.normalize(), self.quote_asset
+                )));
+            }
+        }
+        Ok((quantity, price))
+    }
+}
+
 // Trait común para todos los exchanges
 #[async_trait::async_trait]
 pub trait Exchange: Send + Sync {
+    /// Identificador del exchange (p. ej. "binance", "paper")
+    fn name(&self) -> &str;
+
     async fn get_balance(&self, asset: &str) -> Result<Balance, ExchangeError>;
+
+    async fn get_quote(&self, symbol: &str) -> Result<Quote, ExchangeError>;
     
     async fn place_order(
         &self,
@@ -75,4 +277,4 @@ pub trait Exchange: Send + Sync {
     async fn get_order(&self, symbol: &str, order_id: &str) -> Result<Order, ExchangeError>;
     
     async fn get_open_orders(&self, symbol: &str) -> Result<Vec<Order>, ExchangeError>;
-} 
\ No newline at end of file
+}
@@ -0,0 +1,226 @@
+use std::env;
+use std::sync::Arc;
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use sha2::{Digest, Sha256};
+use tracing::info;
+use crate::db::Database;
+use crate::models::{ExchangeCredentialInfo, SealedCredential};
+use super::{BinanceExchange, Exchange, ExchangeCredentials, ExchangeManager, KuCoinExchange};
+
+/// Exchanges para los que se pueden guardar credenciales.
+pub const SUPPORTED_EXCHANGES: [&str; 2] = ["binance", "kucoin"];
+
+#[derive(Debug)]
+pub enum VaultError {
+    /// Clave maestra mal formada
+    InvalidKey(String),
+    /// Ninguna clave conocida descifra las credenciales, o fueron alteradas
+    Decrypt(String),
+    UnsupportedExchange(String),
+    InvalidCredentials(String),
+    Database(rusqlite::Error),
+}
+
+impl std::error::Error for VaultError {}
+
+impl std::fmt::Display for VaultError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VaultError::InvalidKey(reason) => write!(f, "Clave maestra inválida: {}", reason),
+            VaultError::Decrypt(exchange) => write!(f, "No se pudieron descifrar las credenciales de {}", exchange),
+            VaultError::UnsupportedExchange(exchange) => write!(f, "Exchange no soportado: {}", exchange),
+            VaultError::InvalidCredentials(reason) => write!(f, "Credenciales inválidas: {}", reason),
+            VaultError::Database(e) => write!(f, "Error de base de datos: {}", e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for VaultError {
+    fn from(err: rusqlite::Error) -> Self {
+        VaultError::Database(err)
+    }
+}
+
+struct MasterKey {
+    /// Prefijo del SHA-256 de la clave; se guarda junto a cada registro
+    id: String,
+    cipher: ChaCha20Poly1305,
+}
+
+impl MasterKey {
+    /// Acepta 32 bytes en hexadecimal (64 caracteres) o en base64.
+    fn parse(encoded: &str) -> Result<Self, VaultError> {
+        let encoded = encoded.trim();
+        let bytes = if encoded.len() == 64 {
+            hex::decode(encoded).map_err(|e| VaultError::InvalidKey(e.to_string()))?
+        } else {
+            BASE64.decode(encoded).map_err(|e| VaultError::InvalidKey(e.to_string()))?
+        };
+        if bytes.len() != 32 {
+            return Err(VaultError::InvalidKey(format!("debe tener 32 bytes y tiene {}", bytes.len())));
+        }
+        Ok(Self {
+            id: hex::encode(&Sha256::digest(&bytes)[..4]),
+            cipher: ChaCha20Poly1305::new(Key::from_slice(&bytes)),
+        })
+    }
+}
+
+/// Almacén cifrado de credenciales de exchange. Cada registro se cifra con
+/// ChaCha20-Poly1305 bajo la clave maestra actual, ligado a su usuario y
+/// exchange para que no pueda moverse a otra fila. Las claves anteriores
+/// solo se usan para descifrar y rotar.
+pub struct Vault {
+    current: MasterKey,
+    previous: Vec<MasterKey>,
+}
+
+impl Vault {
+    /// Lee `VAULT_MASTER_KEY` y, para rotar, `VAULT_PREVIOUS_KEYS` separadas
+    /// por comas. Sin clave maestra no hay almacén.
+    pub fn from_env() -> Result<Option<Self>, VaultError> {
+        let current = match env::var("VAULT_MASTER_KEY") {
+            Ok(key) => key,
+            Err(_) => return Ok(None),
+        };
+        let previous = env::var("VAULT_PREVIOUS_KEYS").unwrap_or_default();
+        let previous: Vec<&str> = previous.split(',').filter(|key| !key.trim().is_empty()).collect();
+        Self::new(&current, &previous).map(Some)
+    }
+
+    pub fn new(current: &str, previous: &[&str]) -> Result<Self, VaultError> {
+        Ok(Self {
+            current: MasterKey::parse(current)?,
+            previous: previous.iter().map(|key| MasterKey::parse(key)).collect::<Result<_, _>>()?,
+        })
+    }
+
+    fn associated_data(user_id: i64, exchange: &str) -> Vec<u8> {
+        format!("{}:{}", user_id, exchange).into_bytes()
+    }
+
+    fn seal(&self, user_id: i64, exchange: &str, credentials: &ExchangeCredentials) -> Result<(Vec<u8>, Vec<u8>), VaultError> {
+        let plaintext = serde_json::to_vec(credentials)
+            .map_err(|e| VaultError::InvalidCredentials(e.to_string()))?;
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let aad = Self::associated_data(user_id, exchange);
+        let ciphertext = self.current.cipher
+            .encrypt(&nonce, Payload { msg: &plaintext, aad: &aad })
+            .map_err(|_| VaultError::InvalidCredentials("no se pudieron cifrar".into()))?;
+        Ok((nonce.to_vec(), ciphertext))
+    }
+
+    fn open(&self, sealed: &SealedCredential) -> Result<ExchangeCredentials, VaultError> {
+        let exchange = &sealed.info.exchange;
+        let key = std::iter::once(&self.current)
+            .chain(&self.previous)
+            .find(|key| key.id == sealed.info.key_id)
+            .ok_or_else(|| VaultError::Decrypt(exchange.clone()))?;
+        if sealed.nonce.len() != 12 {
+            return Err(VaultError::Decrypt(exchange.clone()));
+        }
+        let aad = Self::associated_data(sealed.info.user_id, exchange);
+        let plaintext = key.cipher
+            .decrypt(Nonce::from_slice(&sealed.nonce), Payload { msg: &sealed.ciphertext, aad: &aad })
+            .map_err(|_| VaultError::Decrypt(exchange.clone()))?;
+        serde_json::from_slice(&plaintext).map_err(|_| VaultError::Decrypt(exchange.clone()))
+    }
+
+    /// Cifra y guarda las credenciales de un usuario para un exchange.
+    pub fn store(&self, db: &Database, user_id: i64, exchange: &str, credentials: &ExchangeCredentials) -> Result<ExchangeCredentialInfo, VaultError> {
+        let exchange = exchange.trim().to_lowercase();
+        if !SUPPORTED_EXCHANGES.contains(&exchange.as_str()) {
+            return Err(VaultError::UnsupportedExchange(exchange));
+        }
+        if credentials.api_key.trim().is_empty() || credentials.api_secret.trim().is_empty() {
+            return Err(VaultError::InvalidCredentials("faltan la API key o el secret".into()));
+        }
+        if exchange == "kucoin" && credentials.passphrase.as_deref().is_none_or(|p| p.is_empty()) {
+            return Err(VaultError::InvalidCredentials("KuCoin requiere passphrase".into()));
+        }
+
+        let (nonce, ciphertext) = self.seal(user_id, &exchange, credentials)?;
+        let hint = mask(&credentials.api_key);
+        Ok(db.save_exchange_credential(user_id, &exchange, &hint, &self.current.id, &nonce, &ciphertext)?)
+    }
+
+    /// Exchanges reales del usuario, construidos con sus credenciales.
+    pub fn exchanges_for(&self, db: &Database, user_id: i64) -> Result<ExchangeManager, VaultError> {
+        let mut manager = ExchangeManager::new();
+        for sealed in db.get_sealed_credentials(user_id)? {
+            let credentials = self.open(&sealed)?;
+            let exchange: Arc<dyn Exchange> = match sealed.info.exchange.as_str() {
+                "binance" => Arc::new(BinanceExchange::new(credentials)
+                    .map_err(|e| VaultError::InvalidCredentials(e.to_string()))?),
+                "kucoin" => Arc::new(KuCoinExchange::new(credentials)
+                    .map_err(|e| VaultError::InvalidCredentials(e.to_string()))?),
+                other => return Err(VaultError::UnsupportedExchange(other.to_string())),
+            };
+            manager.add_exchange(exchange);
+        }
+        Ok(manager)
+    }
+
+    /// Vuelve a cifrar con la clave actual lo que esté cifrado con una
+    /// anterior. Devuelve cuántos registros se rotaron.
+    pub fn rotate(&self, db: &Database) -> Result<usize, VaultError> {
+        let pending = db.get_credentials_to_rotate(&self.current.id)?;
+        for sealed in &pending {
+            let credentials = self.open(sealed)?;
+            let (nonce, ciphertext) = self.seal(sealed.info.user_id, &sealed.info.exchange, &credentials)?;
+            db.reseal_exchange_credential(sealed.info.id, &self.current.id, &nonce, &ciphertext)?;
+        }
+        if !pending.is_empty() {
+            info!("{} credenciales de exchange recifradas con la clave {}", pending.len(), self.current.id);
+        }
+        Ok(pending.len())
+    }
+}
+
+/// Deja visibles solo los últimos 4 caracteres.
+fn mask(api_key: &str) -> String {
+    let chars: Vec<char> = api_key.trim().chars().collect();
+    let visible: String = chars[chars.len().saturating_sub(4)..].iter().collect();
+    format!("****{}", visible)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::TempDatabase;
+
+    const OLD_KEY: &str = "0101010101010101010101010101010101010101010101010101010101010101";
+    const NEW_KEY: &str = "0202020202020202020202020202020202020202020202020202020202020202";
+
+    #[test]
+    fn test_seal_open_and_rotate() {
+        let db = TempDatabase::new("vault-test");
+        let user_id = db.create_user("trader", "hash", false, None).unwrap();
+        let credentials = ExchangeCredentials {
+            api_key: "key-abcd1234".to_string(),
+            api_secret: "secret".to_string(),
+            passphrase: None,
+        };
+
+        let old = Vault::new(OLD_KEY, &[]).unwrap();
+        let info = old.store(&db, user_id, "Binance", &credentials).unwrap();
+        assert_eq!(info.exchange, "binance");
+        assert_eq!(info.api_key_hint, "****1234");
+
+        // Ligado a su fila: otro usuario no puede descifrarlo
+        let mut sealed = db.get_sealed_credentials(user_id).unwrap().remove(0);
+        assert_eq!(old.open(&sealed).unwrap().api_secret, "secret");
+        sealed.info.user_id += 1;
+        assert!(old.open(&sealed).is_err());
+
+        let rotated = Vault::new(NEW_KEY, &[OLD_KEY]).unwrap();
+        assert_eq!(rotated.rotate(&db).unwrap(), 1);
+        assert_eq!(rotated.rotate(&db).unwrap(), 0);
+        let sealed = db.get_sealed_credentials(user_id).unwrap().remove(0);
+        assert_ne!(sealed.info.key_id, info.key_id);
+        assert!(old.open(&sealed).is_err());
+        assert_eq!(Vault::new(NEW_KEY, &[]).unwrap().open(&sealed).unwrap().api_key, "key-abcd1234");
+    }
+}
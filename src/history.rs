@@ -0,0 +1,507 @@
+use std::collections::HashSet;
+use std::env;
+use std::error::Error;
+use std::time::Duration;
+use serde::Deserialize;
+use tokio::time::sleep;
+use tracing::info;
+use crate::crypto_api::CryptoAPI;
+use crate::db::Database;
+use crate::models::PriceTick;
+
+/// Tramo que se pide en cada consulta del backfill. Hasta 90 días CoinGecko
+/// devuelve un precio por hora; en tramos más largos, uno por día.
+pub const BACKFILL_PAGE: i64 = 90 * 24 * 3600;
+
+/// Pausa entre consultas del backfill para no agotar el límite de la API.
+const BACKFILL_DELAY: u64 = 3;
+
+/// Resolución con la que se guarda el backfill. Los instantes de CoinGecko no
+/// caen en la hora en punto y cambian de una consulta a otra.
+const BACKFILL_GRANULARITY: i64 = 3600;
+
+/// Tramo al que se reducen los precios guardados pasados `raw_days`.
+pub const DOWNSAMPLE_BUCKET: i64 = 3600;
+
+/// Cuánto se conserva el histórico de precios. Los del monitor se guardan
+/// tal cual durante `raw_days`; después queda uno por hora y, pasados
+/// `retention_days`, se borran. Con `retention_days` a cero no se borra nada.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HistoryRetention {
+    pub raw_days: i64,
+    pub retention_days: i64,
+}
+
+impl Default for HistoryRetention {
+    fn default() -> Self {
+        Self { raw_days: 7, retention_days: 365 }
+    }
+}
+
+impl HistoryRetention {
+    /// Lee `PRICE_HISTORY_RAW_DAYS` y `PRICE_HISTORY_RETENTION_DAYS`.
+    pub fn from_env() -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let defaults = Self::default();
+        let retention = Self {
+            raw_days: match env::var("PRICE_HISTORY_RAW_DAYS") {
+                Ok(days) => days.parse()?,
+                Err(_) => defaults.raw_days,
+            },
+            retention_days: match env::var("PRICE_HISTORY_RETENTION_DAYS") {
+                Ok(days) => days.parse()?,
+                Err(_) => defaults.retention_days,
+            },
+        };
+        retention.validate()?;
+        Ok(retention)
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.raw_days < 0 || self.retention_days < 0 {
+            return Err("Los días del histórico de precios no pueden ser negativos".to_string());
+        }
+        if self.retention_days > 0 && self.retention_days < self.raw_days {
+            return Err("PRICE_HISTORY_RETENTION_DAYS no puede ser menor que PRICE_HISTORY_RAW_DAYS".to_string());
+        }
+        Ok(())
+    }
+
+    /// Aplica la retención al histórico con `now` como instante actual.
+    /// Devuelve cuántos precios se borraron.
+    pub fn prune(&self, db: &Database, now: i64) -> rusqlite::Result<usize> {
+        const DAY: i64 = 24 * 3600;
+        let delete_before = (self.retention_days > 0).then(|| now - self.retention_days * DAY);
+        db.prune_price_history(now - self.raw_days * DAY, DOWNSAMPLE_BUCKET, delete_before)
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    DatabaseError(rusqlite::Error),
+    InvalidFormat(String),
+    InvalidLine { line: usize, reason: String },
+    Api(String),
+}
+
+impl std::error::Error for ImportError {}
+
+impl std::fmt::Display for ImportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ImportError::DatabaseError(e) => write!(f, "Error de base de datos: {}", e),
+            ImportError::InvalidFormat(reason) => write!(f, "Formato inválido: {}", reason),
+            ImportError::InvalidLine { line, reason } => write!(f, "Línea {}: {}", line, reason),
+            ImportError::Api(reason) => write!(f, "Error de API: {}", reason),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for ImportError {
+    fn from(err: rusqlite::Error) -> Self {
+        ImportError::DatabaseError(err)
+    }
+}
+
+impl From<serde_json::Error> for ImportError {
+    fn from(err: serde_json::Error) -> Self {
+        ImportError::InvalidFormat(err.to_string())
+    }
+}
+
+/// Formatos de entrada soportados.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImportFormat {
+    /// CSV con cabecera `timestamp,symbol,venue,price,volume` (volumen opcional)
+    Csv,
+    /// Velas exportadas de un exchange (`binance` o `kucoin`), en CSV o JSON
+    Klines,
+    /// Respuesta de `/coins/{id}/market_chart` de CoinGecko
+    MarketChart,
+}
+
+impl std::str::FromStr for ImportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(ImportFormat::Csv),
+            "klines" | "velas" => Ok(ImportFormat::Klines),
+            "coingecko" | "market_chart" => Ok(ImportFormat::MarketChart),
+            _ => Err(format!("Formato desconocido: {} (usa csv, klines o coingecko)", s)),
+        }
+    }
+}
+
+/// Resultado de una importación.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImportSummary {
+    /// Cotizaciones leídas
+    pub parsed: usize,
+    /// Repetidas dentro de la propia entrada (mismo símbolo, fuente e instante)
+    pub duplicates: usize,
+    /// Ya estaban en el histórico
+    pub already_stored: usize,
+    pub inserted: usize,
+}
+
+impl ImportSummary {
+    fn add(&mut self, other: &ImportSummary) {
+        self.parsed += other.parsed;
+        self.duplicates += other.duplicates;
+        self.already_stored += other.already_stored;
+        self.inserted += other.inserted;
+    }
+}
+
+/// Lee un instante en segundos, milisegundos, RFC 3339, `AAAA-MM-DD HH:MM:SS`
+/// o `AAAA-MM-DD` (UTC). Los números por encima de 10^11 se toman como
+/// milisegundos.
+pub fn parse_timestamp(value: &str) -> Option<i64> {
+    let value = value.trim();
+    if let Ok(number) = value.parse::<f64>() {
+        if !number.is_finite() {
+            return None;
+        }
+        return Some(if number.abs() >= 1e11 { (number / 1000.0) as i64 } else { number as i64 });
+    }
+    if let Ok(date) = chrono::DateTime::parse_from_rfc3339(value) {
+        return Some(date.timestamp());
+    }
+    if let Ok(date) = chrono::NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M:%S") {
+        return Some(date.and_utc().timestamp());
+    }
+    chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d").ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .map(|date| date.and_utc().timestamp())
+}
+
+fn parse_price(value: &str, line: usize) -> Result<f64, ImportError> {
+    match value.trim().parse::<f64>() {
+        Ok(price) if price > 0.0 && price.is_finite() => Ok(price),
+        _ => Err(ImportError::InvalidLine { line, reason: format!("precio inválido: {}", value) }),
+    }
+}
+
+fn split_csv(line: &str) -> Vec<&str> {
+    line.split(',').map(|field| field.trim().trim_matches('"')).collect()
+}
+
+/// CSV genérico. La cabecera es obligatoria y las columnas pueden venir en
+/// cualquier orden; `volume` es opcional.
+pub fn parse_csv(text: &str) -> Result<Vec<PriceTick>, ImportError> {
+    let mut lines = text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty());
+    let (_, header) = lines.next().ok_or_else(|| ImportError::InvalidFormat("el fichero está vacío".to_string()))?;
+    let header: Vec<String> = split_csv(header).iter().map(|column| column.to_lowercase()).collect();
+    let column = |name: &str| header.iter().position(|column| column == name);
+    let required = |name: &str| column(name)
+        .ok_or_else(|| ImportError::InvalidFormat(format!("falta la columna {}", name)));
+    let (timestamp, symbol, venue, price) = (required("timestamp")?, required("symbol")?, required("venue")?, required("price")?);
+    let volume = column("volume");
+
+    let mut ticks = Vec::new();
+    for (index, line) in lines {
+        let line_number = index + 1;
+        let fields = split_csv(line);
+        let field = |position: usize| fields.get(position).copied().unwrap_or_default();
+        let missing = |reason: &str| ImportError::InvalidLine { line: line_number, reason: reason.to_string() };
+        if field(symbol).is_empty() {
+            return Err(missing("falta el símbolo"));
+        }
+        if field(venue).is_empty() {
+            return Err(missing("falta la fuente"));
+        }
+        ticks.push(PriceTick {
+            timestamp: parse_timestamp(field(timestamp)).ok_or_else(|| missing("instante inválido"))?,
+            symbol: field(symbol).to_uppercase(),
+            venue: field(venue).to_lowercase(),
+            price: parse_price(field(price), line_number)?,
+            volume: volume.map(field).filter(|value| !value.is_empty())
+                .map(|value| value.parse::<f64>().map_err(|_| missing("volumen inválido")))
+                .transpose()?,
+        });
+    }
+    Ok(ticks)
+}
+
+/// Velas exportadas de un exchange, como JSON (la respuesta de su API) o
+/// como CSV con o sin cabecera. Cada vela se guarda como su precio de cierre.
+/// - `binance`: apertura (ms), apertura, máximo, mínimo, cierre, volumen, cierre (ms), ...
+/// - `kucoin`: apertura (s), apertura, cierre, máximo, mínimo, volumen, ...
+pub fn parse_klines(text: &str, symbol: &str, venue: &str) -> Result<Vec<PriceTick>, ImportError> {
+    let venue = venue.trim().to_lowercase();
+    // Columnas de apertura, cierre, volumen e instante de cierre
+    let (open_time, close, volume, close_time) = match venue.as_str() {
+        "binance" => (0, 4, 5, Some(6)),
+        "kucoin" => (0, 2, 5, None),
+        _ => return Err(ImportError::InvalidFormat(format!("no se conoce el formato de velas de {}", venue))),
+    };
+
+    let rows: Vec<Vec<String>> = if text.trim_start().starts_with(['[', '{']) {
+        let json: serde_json::Value = serde_json::from_str(text)?;
+        // KuCoin envuelve las velas en {"data": [...]}, Binance no
+        let rows = json.get("data").unwrap_or(&json).as_array()
+            .ok_or_else(|| ImportError::InvalidFormat("se esperaba una lista de velas".to_string()))?;
+        rows.iter()
+            .map(|row| row.as_array().map(|fields| fields.iter()
+                .map(|field| field.as_str().map(str::to_string).unwrap_or_else(|| field.to_string()))
+                .collect()))
+            .collect::<Option<Vec<_>>>()
+            .ok_or_else(|| ImportError::InvalidFormat("cada vela debe ser una lista".to_string()))?
+    } else {
+        text.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| split_csv(line).into_iter().map(str::to_string).collect())
+            .collect()
+    };
+
+    let mut ticks = Vec::new();
+    for (index, row) in rows.iter().enumerate() {
+        let line = index + 1;
+        let field = |position: usize| row.get(position).map(String::as_str).unwrap_or_default();
+        // Cabecera del CSV
+        if index == 0 && field(open_time).parse::<f64>().is_err() {
+            continue;
+        }
+        let timestamp = match close_time.and_then(|position| row.get(position)) {
+            // Binance marca el cierre en el último milisegundo de la vela
+            Some(close_time) => parse_timestamp(close_time).map(|timestamp| timestamp + 1),
+            None => parse_timestamp(field(open_time)),
+        };
+        let timestamp = timestamp
+            .ok_or_else(|| ImportError::InvalidLine { line, reason: "instante inválido".to_string() })?;
+        ticks.push(PriceTick {
+            timestamp,
+            symbol: symbol.trim().to_uppercase(),
+            venue: venue.clone(),
+            price: parse_price(field(close), line)?,
+            volume: field(volume).parse::<f64>().ok(),
+        });
+    }
+    Ok(ticks)
+}
+
+/// Respuesta de `/coins/{id}/market_chart` y `/coins/{id}/market_chart/range`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct MarketChart {
+    pub prices: Vec<(f64, f64)>,
+    #[serde(default)]
+    pub total_volumes: Vec<(f64, f64)>,
+}
+
+impl MarketChart {
+    /// Cotizaciones de `symbol` con fuente `coingecko`. El volumen es el de
+    /// las últimas 24 horas en ese instante.
+    pub fn ticks(&self, symbol: &str) -> Vec<PriceTick> {
+        let volumes: std::collections::HashMap<i64, f64> = self.total_volumes.iter()
+            .map(|(ms, volume)| (*ms as i64, *volume))
+            .collect();
+        self.prices.iter()
+            .filter(|(_, price)| *price > 0.0 && price.is_finite())
+            .map(|(ms, price)| PriceTick {
+                timestamp: (ms / 1000.0) as i64,
+                symbol: symbol.trim().to_uppercase(),
+                venue: "coingecko".to_string(),
+                price: *price,
+                volume: volumes.get(&(*ms as i64)).copied(),
+            })
+            .collect()
+    }
+}
+
+pub fn parse_market_chart(text: &str, symbol: &str) -> Result<Vec<PriceTick>, ImportError> {
+    let chart: MarketChart = serde_json::from_str(text)?;
+    Ok(chart.ticks(symbol))
+}
+
+/// Interpreta `text` según `format`. `symbol` y `venue` solo hacen falta en
+/// los formatos que no los traen.
+pub fn parse(format: ImportFormat, text: &str, symbol: Option<&str>, venue: Option<&str>) -> Result<Vec<PriceTick>, ImportError> {
+    fn required<'a>(value: Option<&'a str>, name: &str) -> Result<&'a str, ImportError> {
+        value.filter(|value| !value.trim().is_empty())
+            .ok_or_else(|| ImportError::InvalidFormat(format!("hay que indicar {}", name)))
+    }
+    match format {
+        ImportFormat::Csv => parse_csv(text),
+        ImportFormat::Klines => parse_klines(text, required(symbol, "el símbolo")?, required(venue, "el exchange")?),
+        ImportFormat::MarketChart => parse_market_chart(text, required(symbol, "el símbolo")?),
+    }
+}
+
+/// Guarda las cotizaciones en el histórico. Volver a importar lo mismo no
+/// añade nada: las repetidas en la entrada se descartan y las que ya estaban
+/// guardadas se ignoran.
+pub fn import(db: &Database, ticks: Vec<PriceTick>) -> Result<ImportSummary, ImportError> {
+    let parsed = ticks.len();
+    let mut seen = HashSet::new();
+    let unique: Vec<PriceTick> = ticks.into_iter()
+        .filter(|tick| seen.insert((tick.symbol.clone(), tick.venue.clone(), tick.timestamp)))
+        .collect();
+    let inserted = db.save_price_ticks(&unique)?;
+    Ok(ImportSummary {
+        parsed,
+        duplicates: parsed - unique.len(),
+        already_stored: unique.len() - inserted,
+        inserted,
+    })
+}
+
+/// Descarga de CoinGecko el histórico de `symbol` entre `from` y `to`, en
+/// tramos de `BACKFILL_PAGE`, y lo guarda. Se puede repetir o reanudar sin
+/// duplicar nada.
+pub async fn backfill(api: &CryptoAPI, db: &Database, symbol: &str, from: i64, to: i64) -> Result<ImportSummary, ImportError> {
+    if from >= to {
+        return Err(ImportError::InvalidFormat("el inicio debe ser anterior al final".to_string()));
+    }
+    let mut summary = ImportSummary::default();
+    let mut start = from;
+    while start < to {
+        let end = (start + BACKFILL_PAGE).min(to);
+        if start > from {
+            sleep(Duration::from_secs(BACKFILL_DELAY)).await;
+        }
+        let chart = api.get_market_chart_range(symbol, start, end).await
+            .map_err(|e| ImportError::Api(e.to_string()))?;
+        let page = import(db, page_ticks(chart.ticks(symbol), start, end, end == to))?;
+        info!("Backfill de {}: {} a {}, {} cotizaciones nuevas", symbol, start, end, page.inserted);
+        summary.add(&page);
+        start = end;
+    }
+    Ok(summary)
+}
+
+/// Cotizaciones de un tramo `[start, end)` del backfill (el último incluye
+/// `end`), redondeadas a `BACKFILL_GRANULARITY` para que los bordes de tramos
+/// contiguos y las repeticiones caigan en el mismo instante.
+fn page_ticks(ticks: Vec<PriceTick>, start: i64, end: i64, last: bool) -> Vec<PriceTick> {
+    ticks.into_iter()
+        .filter(|tick| tick.timestamp >= start && (tick.timestamp < end || (last && tick.timestamp == end)))
+        .map(|tick| PriceTick {
+            timestamp: tick.timestamp - tick.timestamp.rem_euclid(BACKFILL_GRANULARITY),
+            ..tick
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::TempDatabase;
+
+    #[test]
+    fn test_parse_timestamp() {
+        assert_eq!(parse_timestamp("1700000000"), Some(1_700_000_000));
+        assert_eq!(parse_timestamp("1700000000000"), Some(1_700_000_000));
+        assert_eq!(parse_timestamp("2023-11-14T22:13:20Z"), Some(1_700_000_000));
+        assert_eq!(parse_timestamp("2023-11-14 22:13:20"), Some(1_700_000_000));
+        assert_eq!(parse_timestamp("2023-11-14"), Some(1_699_920_000));
+        assert_eq!(parse_timestamp("ayer"), None);
+    }
+
+    #[test]
+    fn test_retention_downsamples_and_expires() {
+        let db = TempDatabase::new("history-test");
+        let day = 24 * 3600;
+        let now = 1_700_006_400;
+        let tick = |timestamp: i64| PriceTick {
+            timestamp,
+            symbol: "BTC".to_string(),
+            venue: "binance".to_string(),
+            price: 1.0,
+            volume: None,
+        };
+        // Cada minuto de una hora de hace 10 días, otra de hace 400 y la última hora
+        let ticks: Vec<PriceTick> = [now - 10 * day, now - 400 * day, now - 3600]
+            .iter()
+            .flat_map(|start| (0..60).map(move |minute| start + minute * 60))
+            .map(tick)
+            .collect();
+        db.save_price_ticks(&ticks).unwrap();
+
+        let retention = HistoryRetention::default();
+        assert_eq!(retention.prune(&db, now).unwrap(), 59 + 60);
+        let kept = db.get_price_history("BTC", 0, now).unwrap();
+        assert_eq!(kept.len(), 61);
+        assert_eq!(kept[0].timestamp, now - 10 * day);
+
+        // Sin retención solo se reduce
+        db.save_price_ticks(&[tick(now - 400 * day)]).unwrap();
+        assert_eq!(HistoryRetention { retention_days: 0, ..retention }.prune(&db, now).unwrap(), 0);
+        assert!(HistoryRetention { raw_days: 30, retention_days: 7 }.validate().is_err());
+    }
+
+    #[test]
+    fn test_parse_csv_any_column_order() {
+        let csv = "symbol,timestamp,price,venue,volume\n\
+                   btc,1700000000,36500.5,Binance,12.5\n\
+                   \n\
+                   ETH,2023-11-14T22:14:20Z,2050,kraken,\n";
+        let ticks = parse_csv(csv).unwrap();
+
+        assert_eq!(ticks, vec![
+            PriceTick { timestamp: 1_700_000_000, symbol: "BTC".to_string(), venue: "binance".to_string(), price: 36500.5, volume: Some(12.5) },
+            PriceTick { timestamp: 1_700_000_060, symbol: "ETH".to_string(), venue: "kraken".to_string(), price: 2050.0, volume: None },
+        ]);
+        assert!(matches!(parse_csv("timestamp,symbol,price\n1,BTC,1"), Err(ImportError::InvalidFormat(_))));
+        assert!(matches!(
+            parse_csv("timestamp,symbol,venue,price\n1,BTC,binance,-3"),
+            Err(ImportError::InvalidLine { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_parse_klines() {
+        let binance = r#"[[1700000000000,"36000.0","36600.0","35900.0","36500.0","10.5",1700000059999,"0",1,"0","0","0"]]"#;
+        let ticks = parse_klines(binance, "btc", "Binance").unwrap();
+        assert_eq!(ticks, vec![PriceTick {
+            timestamp: 1_700_000_060,
+            symbol: "BTC".to_string(),
+            venue: "binance".to_string(),
+            price: 36500.0,
+            volume: Some(10.5),
+        }]);
+
+        let kucoin = "time,open,close,high,low,volume,turnover\n1700000000,36000,36500,36600,35900,10.5,383250\n";
+        let ticks = parse_klines(kucoin, "BTC", "kucoin").unwrap();
+        assert_eq!(ticks.len(), 1);
+        assert_eq!((ticks[0].timestamp, ticks[0].price), (1_700_000_000, 36500.0));
+
+        let kucoin_api = r#"{"code":"200000","data":[["1700000000","36000","36500","36600","35900","10.5","383250"]]}"#;
+        assert_eq!(parse_klines(kucoin_api, "BTC", "kucoin").unwrap(), ticks);
+
+        assert!(parse_klines(kucoin, "BTC", "kraken").is_err());
+    }
+
+    #[test]
+    fn test_parse_market_chart() {
+        let json = r#"{"prices":[[1700000000000,36500.0],[1700003600000,36600.0]],
+                       "market_caps":[],"total_volumes":[[1700000000000,1.5e10]]}"#;
+        let ticks = parse_market_chart(json, "btc").unwrap();
+
+        assert_eq!(ticks.len(), 2);
+        assert_eq!(ticks[0].venue, "coingecko");
+        assert_eq!(ticks[0].volume, Some(1.5e10));
+        assert_eq!((ticks[1].timestamp, ticks[1].price, ticks[1].volume), (1_700_003_600, 36600.0, None));
+    }
+
+    #[test]
+    fn test_backfill_pages_do_not_overlap() {
+        let tick = |timestamp: i64| PriceTick {
+            timestamp,
+            symbol: "BTC".to_string(),
+            venue: "coingecko".to_string(),
+            price: 1.0,
+            volume: None,
+        };
+        let timestamps = |ticks: Vec<PriceTick>| ticks.iter().map(|tick| tick.timestamp).collect::<Vec<_>>();
+        let (start, end) = (1_699_999_200, 1_700_006_400);
+
+        // El punto del borde es de la página siguiente, salvo en la última
+        let page = vec![tick(start - 100), tick(start + 1234), tick(end - 60), tick(end)];
+        assert_eq!(timestamps(page_ticks(page.clone(), start, end, false)), vec![start, end - 3600]);
+        assert_eq!(timestamps(page_ticks(page, start, end, true)), vec![start, end - 3600, end]);
+
+        // Dos consultas con instantes desplazados guardan las mismas horas
+        let shifted = vec![tick(start + 17), tick(start + 3600 + 17)];
+        assert_eq!(timestamps(page_ticks(shifted, start, end, false)), vec![start, start + 3600]);
+    }
+}
@@ -1,12 +1,26 @@
 pub mod api;
 pub mod auth;
+pub mod backtest;
+pub mod chart;
 pub mod crypto_api;
 pub mod db;
+pub mod dca;
+#[cfg(feature = "exchanges")]
+pub mod exchanges;
+pub mod history;
 pub mod models;
 pub mod monitor;
 pub mod notify;
+pub mod portfolio;
+pub mod quota;
+pub mod risk;
+#[cfg(feature = "exchanges")]
+pub mod strategy;
 pub mod timer;
+#[cfg(feature = "exchanges")]
+pub mod trading;
 pub mod bot;
+pub mod callback;
 pub mod config;
 
 pub use crate::auth::Auth;
@@ -16,9 +30,11 @@ pub use crate::crypto_api::CryptoAPI;
 pub use crate::monitor::PriceMonitor;
 pub use crate::notify::NotificationService;
 
+use crate::bot::WebhookConfig;
 use dotenv::dotenv;
 use std::env;
 use std::error::Error;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 
 pub struct Config {
@@ -26,17 +42,69 @@ pub struct Config {
     pub coingecko_api_key: String,
     pub telegram_token: String,
     pub check_interval: u64,
+    pub attach_alert_charts: bool,
+    pub api_addr: Option<SocketAddr>,
+    pub telegram_webhook: Option<WebhookConfig>,
+    /// Usuarios de Telegram que se promueven a administrador al arrancar
+    /// mientras no haya ninguno (`ADMIN_TELEGRAM_IDS`)
+    pub admin_telegram_ids: Vec<i64>,
+    /// Si se define, reemplaza al arrancar el modo guardado en la base de datos
+    pub registration_mode: Option<RegistrationMode>,
+    /// Cuánto se conservan los precios que guarda el monitor
+    pub price_history: history::HistoryRetention,
+    /// Cuenta simulada para las acciones automáticas de las alertas
+    #[cfg(feature = "exchanges")]
+    pub trading: trading::TradingConfig,
 }
 
 impl Config {
     pub fn new() -> Result<Self, Box<dyn Error + Send + Sync>> {
         dotenv().ok();
 
+        let telegram_webhook = match env::var("TELEGRAM_WEBHOOK_URL") {
+            Ok(public_url) => Some(WebhookConfig {
+                public_url,
+                secret: env::var("TELEGRAM_WEBHOOK_SECRET")
+                    .map_err(|_| "TELEGRAM_WEBHOOK_SECRET es obligatorio en modo webhook")?,
+            }),
+            Err(_) => None,
+        };
+
+        // En modo webhook la API tiene que estar levantada para recibir los updates
+        let api_port = match env::var("API_PORT") {
+            Ok(port) => Some(port.parse::<u16>()?),
+            Err(_) if telegram_webhook.is_some() => Some(3000),
+            Err(_) => None,
+        };
+        let api_host: IpAddr = env::var("API_HOST")
+            .unwrap_or_else(|_| "127.0.0.1".to_string())
+            .parse()?;
+
         Ok(Self {
             database_url: env::var("DATABASE_URL")?,
             coingecko_api_key: env::var("COINGECKO_API_KEY")?,
             telegram_token: env::var("TELEGRAM_BOT_TOKEN")?,
             check_interval: env::var("CHECK_INTERVAL")?.parse()?,
+            attach_alert_charts: env::var("ATTACH_ALERT_CHARTS")
+                .map(|v| v == "true" || v == "1")
+                .unwrap_or(false),
+            api_addr: api_port.map(|port| SocketAddr::new(api_host, port)),
+            telegram_webhook,
+            admin_telegram_ids: match env::var("ADMIN_TELEGRAM_IDS") {
+                Ok(v) => v.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(|s| s.parse::<i64>())
+                    .collect::<Result<_, _>>()?,
+                Err(_) => Vec::new(),
+            },
+            registration_mode: match env::var("REGISTRATION_MODE") {
+                Ok(mode) => Some(mode.parse()?),
+                Err(_) => None,
+            },
+            price_history: history::HistoryRetention::from_env()?,
+            #[cfg(feature = "exchanges")]
+            trading: trading::TradingConfig::from_env()?,
         })
     }
 }
@@ -47,9 +115,38 @@ pub async fn start_monitor(config: &Config, db: Arc<Database>) -> Result<(), Box
     let monitor = PriceMonitor::new(
         api,
         notification_service,
-        db,
+        db.clone(),
         config.check_interval,
+        config.attach_alert_charts,
+    ).with_history_retention(config.price_history);
+    let dca = dca::DcaScheduler::new(
+        CryptoAPI::new(config.coingecko_api_key.clone()).with_cache(monitor.price_cache()),
+        NotificationService::new(config.telegram_token.clone()),
+        db.clone(),
     );
+    #[cfg(feature = "exchanges")]
+    let (monitor, dca) = {
+        let vault = exchanges::Vault::from_env()?.map(Arc::new);
+        if let Some(vault) = &vault {
+            vault.rotate(&db)?;
+        }
+        let trader = Arc::new(trading::TradeExecutor::new(&config.trading, monitor.price_cache(), db.clone(), vault));
+        let reconciler = trading::OrderReconciler::new(
+            trader.clone(),
+            NotificationService::new(config.telegram_token.clone()),
+            config.trading.order_sync_interval,
+        );
+        tokio::spawn(async move { reconciler.start().await });
+        let strategies = strategy::StrategyEngine::new(
+            trader.clone(),
+            CryptoAPI::new(config.coingecko_api_key.clone()).with_cache(monitor.price_cache()),
+            db.clone(),
+            config.trading.strategy_interval,
+        );
+        tokio::spawn(async move { strategies.start().await });
+        (monitor.with_trader(trader.clone()), dca.with_trader(trader))
+    };
+    tokio::spawn(async move { dca.start().await });
 
     monitor.start().await
 } 
\ No newline at end of file
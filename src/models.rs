@@ -12,9 +12,108 @@ pub struct User {
     pub created_at: i64,
     pub last_login: Option<i64>,
     pub is_active: bool,
+    pub role: UserRole,
+    /// Registrado en modo aprobación y todavía sin aprobar; la cuenta sigue inactiva.
+    pub pending_approval: bool,
+    pub plan: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UserRole {
+    #[default]
+    User,
+    /// Puede ver estadísticas, desactivar cuentas y enviar anuncios.
+    Admin,
+}
+
+impl FromSql for UserRole {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "user" => Ok(UserRole::User),
+            "admin" => Ok(UserRole::Admin),
+            _ => Err(rusqlite::types::FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl ToSql for UserRole {
+    fn to_sql(&self) -> SqliteResult<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(match self {
+            UserRole::User => "user",
+            UserRole::Admin => "admin",
+        }))
+    }
+}
+
+/// Cómo se admiten cuentas nuevas desde el bot y la API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RegistrationMode {
+    #[default]
+    Open,
+    /// Solo con un código de invitación generado por un administrador.
+    Invite,
+    /// La cuenta queda inactiva hasta que un administrador la aprueba.
+    Approval,
+}
+
+impl std::str::FromStr for RegistrationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "open" => Ok(RegistrationMode::Open),
+            "invite" => Ok(RegistrationMode::Invite),
+            "approval" => Ok(RegistrationMode::Approval),
+            other => Err(format!("Modo de registro desconocido: {}", other)),
+        }
+    }
+}
+
+impl RegistrationMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RegistrationMode::Open => "open",
+            RegistrationMode::Invite => "invite",
+            RegistrationMode::Approval => "approval",
+        }
+    }
+}
+
+/// Límites que se aplican a los usuarios de un plan.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Plan {
+    pub name: String,
+    /// Alertas activas a la vez; `None` es sin límite
+    pub max_active_alerts: Option<i64>,
+    /// Segundos mínimos entre evaluaciones de sus alertas; 0 usa el intervalo global
+    pub min_check_interval: i64,
+    pub allowed_kinds: Vec<AlertKind>,
+}
+
+impl Plan {
+    pub const DEFAULT: &'static str = "free";
+
+    pub fn allows(&self, kind: AlertKind) -> bool {
+        self.allowed_kinds.contains(&kind)
+    }
+}
+
+/// Resumen del servicio para `/admin stats` y `GET /admin/stats`.
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminStats {
+    pub users: i64,
+    pub active_users: i64,
+    pub admins: i64,
+    pub linked_chats: i64,
+    pub alerts: i64,
+    pub active_alerts: i64,
+    pub triggered_last_24h: i64,
+    pub alert_targets: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(tag = "type", content = "data")]
 pub enum AlertType {
     Price {
@@ -31,10 +130,43 @@ pub enum AlertType {
         token2: String,
         expected_ratio: f64,
         differential: f64,
+    },
+    /// Valor total del portafolio del dueño frente a `threshold` (USD).
+    PortfolioValue {
+        threshold: f64,
+        condition: AlertCondition,
+    },
+    /// Caída porcentual desde el máximo valor observado. `peak` lo actualiza el monitor.
+    PortfolioDrawdown {
+        max_drawdown: f64,
+        #[serde(default)]
+        peak: f64,
+    },
+    /// Peso de `symbol` en el portafolio fuera de `target_weight` ± `tolerance` (puntos porcentuales).
+    AllocationDrift {
+        symbol: String,
+        target_weight: f64,
+        tolerance: f64,
+    },
+}
+
+impl AlertType {
+    pub fn kind(&self) -> AlertKind {
+        match self {
+            AlertType::Price { .. } => AlertKind::Price,
+            AlertType::Depeg { .. } => AlertKind::Depeg,
+            AlertType::PairDepeg { .. } => AlertKind::PairDepeg,
+            AlertType::PortfolioValue { .. }
+            | AlertType::PortfolioDrawdown { .. }
+            | AlertType::AllocationDrift { .. } => AlertKind::Portfolio,
+        }
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Símbolo con el que se guardan las alertas sobre el portafolio completo.
+pub const PORTFOLIO_SYMBOL: &str = "PORTFOLIO";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PriceAlert {
     pub id: Option<i64>,
     pub user_id: i64,
@@ -45,6 +177,88 @@ pub struct PriceAlert {
     pub is_active: bool,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertKind {
+    Price,
+    Depeg,
+    PairDepeg,
+    Portfolio,
+}
+
+impl AlertKind {
+    pub const ALL: [AlertKind; 4] = [AlertKind::Price, AlertKind::Depeg, AlertKind::PairDepeg, AlertKind::Portfolio];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AlertKind::Price => "price",
+            AlertKind::Depeg => "depeg",
+            AlertKind::PairDepeg => "pair_depeg",
+            AlertKind::Portfolio => "portfolio",
+        }
+    }
+}
+
+impl std::str::FromStr for AlertKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "price" => Ok(AlertKind::Price),
+            "depeg" => Ok(AlertKind::Depeg),
+            "pair_depeg" | "pair" => Ok(AlertKind::PairDepeg),
+            "portfolio" => Ok(AlertKind::Portfolio),
+            other => Err(format!("Tipo de alerta desconocido: {}", other)),
+        }
+    }
+}
+
+/// Una alerta inactiva sin `triggered_at` fue pausada por el usuario.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlertStatus {
+    Active,
+    Paused,
+    Triggered,
+}
+
+impl PriceAlert {
+    pub fn kind(&self) -> AlertKind {
+        self.alert_type.kind()
+    }
+
+    pub fn status(&self) -> AlertStatus {
+        match (self.is_active, self.triggered_at) {
+            (true, _) => AlertStatus::Active,
+            (false, None) => AlertStatus::Paused,
+            (false, Some(_)) => AlertStatus::Triggered,
+        }
+    }
+}
+
+/// Filtro para listar alertas. Los campos en `None` no filtran.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AlertFilter {
+    pub kind: Option<AlertKind>,
+    pub status: Option<AlertStatus>,
+    pub symbol: Option<String>,
+}
+
+impl AlertFilter {
+    pub fn matches(&self, alert: &PriceAlert) -> bool {
+        self.kind.is_none_or(|kind| alert.kind() == kind)
+            && self.status.is_none_or(|status| alert.status() == status)
+            && self.symbol.as_ref().is_none_or(|symbol| {
+                let symbol = symbol.to_uppercase();
+                match &alert.alert_type {
+                    AlertType::PairDepeg { token1, token2, .. } => {
+                        token1.to_uppercase() == symbol || token2.to_uppercase() == symbol
+                    }
+                    _ => alert.symbol.to_uppercase() == symbol,
+                }
+            })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ApiKey {
     pub id: i64,
@@ -56,7 +270,78 @@ pub struct ApiKey {
     pub is_active: bool,
 }
 
+/// Registro de una acción destructiva o administrativa.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditEvent {
+    pub id: Option<i64>,
+    pub user_id: Option<i64>,
+    pub chat_id: Option<i64>,
+    pub action: String,
+    pub target: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TradeSide {
+    Buy,
+    Sell,
+}
+
+impl FromSql for TradeSide {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "buy" => Ok(TradeSide::Buy),
+            "sell" => Ok(TradeSide::Sell),
+            _ => Err(rusqlite::types::FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl ToSql for TradeSide {
+    fn to_sql(&self) -> SqliteResult<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(match self {
+            TradeSide::Buy => "buy",
+            TradeSide::Sell => "sell",
+        }))
+    }
+}
+
+/// Compra o venta registrada por el usuario. `fee` está en la misma moneda
+/// que `price` (USD).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Transaction {
+    pub id: Option<i64>,
+    pub user_id: i64,
+    pub symbol: String,
+    pub side: TradeSide,
+    pub quantity: f64,
+    pub price: f64,
+    pub fee: f64,
+    pub executed_at: i64,
+    pub note: Option<String>,
+}
+
+/// Posición de un usuario en un activo, calculada a costo promedio a partir
+/// de sus transacciones.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Holding {
+    pub user_id: i64,
+    pub symbol: String,
+    pub quantity: f64,
+    /// Costo total de la cantidad que se mantiene, comisiones incluidas
+    pub cost_basis: f64,
+    pub realized_pnl: f64,
+    pub updated_at: i64,
+}
+
+impl Holding {
+    pub fn average_cost(&self) -> Option<f64> {
+        (self.quantity > 0.0).then(|| self.cost_basis / self.quantity)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum AlertCondition {
     Above,
     Below,
@@ -82,6 +367,436 @@ impl ToSql for AlertCondition {
     }
 }
 
+/// Tipo de destino adicional de una alerta.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum TargetKind {
+    /// Grupo donde se creó la alerta; sus administradores la gestionan.
+    Group,
+    /// Canal al que el dueño comparte la alerta.
+    Channel,
+    /// Chat privado de un usuario suscrito.
+    Subscriber,
+}
+
+impl FromSql for TargetKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "group" => Ok(TargetKind::Group),
+            "channel" => Ok(TargetKind::Channel),
+            "subscriber" => Ok(TargetKind::Subscriber),
+            _ => Err(rusqlite::types::FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl ToSql for TargetKind {
+    fn to_sql(&self) -> SqliteResult<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(match self {
+            TargetKind::Group => "group",
+            TargetKind::Channel => "channel",
+            TargetKind::Subscriber => "subscriber",
+        }))
+    }
+}
+
+/// Política de escalado de una alerta crítica: mientras nadie la reconozca se
+/// reenvía cada `interval_secs` y, tras `max_attempts` avisos, se notifica
+/// también al contacto secundario.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EscalationPolicy {
+    pub alert_id: i64,
+    pub interval_secs: i64,
+    pub max_attempts: i64,
+    pub secondary_chat_id: Option<i64>,
+    /// Avisos enviados desde el último disparo
+    pub attempts: i64,
+    pub last_notified_at: Option<i64>,
+    pub escalated_at: Option<i64>,
+}
+
+/// Cómo ejecuta su orden una acción automática.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TradeMode {
+    /// Solo describe la orden que se habría enviado
+    DryRun,
+    /// Contra el exchange simulado
+    Paper,
+    /// Contra los exchanges reales configurados
+    Live,
+}
+
+impl TradeMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TradeMode::DryRun => "dry_run",
+            TradeMode::Paper => "paper",
+            TradeMode::Live => "live",
+        }
+    }
+}
+
+impl std::str::FromStr for TradeMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "dry_run" | "dry" | "dryrun" => Ok(TradeMode::DryRun),
+            "paper" => Ok(TradeMode::Paper),
+            "live" => Ok(TradeMode::Live),
+            other => Err(format!("Modo de ejecución desconocido: {}", other)),
+        }
+    }
+}
+
+impl FromSql for TradeMode {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        value.as_str()?.parse().map_err(|_| rusqlite::types::FromSqlError::InvalidType)
+    }
+}
+
+impl ToSql for TradeMode {
+    fn to_sql(&self) -> SqliteResult<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+/// Orden que se envía cuando la alerta se dispara. El par es el símbolo de la
+/// alerta contra `quote_asset`; sin `limit_offset` la orden es a mercado.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertAction {
+    pub alert_id: i64,
+    pub side: TradeSide,
+    /// Cantidad del activo base
+    pub quantity: f64,
+    pub quote_asset: String,
+    /// Orden límite a este porcentaje del precio de disparo (-1 = 1 % por debajo)
+    pub limit_offset: Option<f64>,
+    pub mode: TradeMode,
+    /// Exchange en el que ejecutar; sin él se usa el de mejor precio
+    pub exchange: Option<String>,
+    pub created_at: i64,
+}
+
+impl AlertAction {
+    /// Solo las alertas sobre el precio de un activo pueden operar ese activo.
+    pub fn supports(alert_type: &AlertType) -> bool {
+        matches!(alert_type, AlertType::Price { .. } | AlertType::Depeg { .. })
+    }
+
+    /// Comprueba que la acción puede asociarse a una alerta de ese tipo.
+    pub fn validate(&self, alert_type: &AlertType) -> Result<(), String> {
+        if !Self::supports(alert_type) {
+            return Err("Solo las alertas de precio y de depeg pueden tener acciones".to_string());
+        }
+        if !self.quantity.is_finite() || self.quantity <= 0.0 {
+            return Err("La cantidad debe ser un número positivo".to_string());
+        }
+        if self.limit_offset.is_some_and(|offset| !offset.is_finite() || offset <= -100.0) {
+            return Err("El desvío del precio límite debe ser mayor que -100%".to_string());
+        }
+        if self.quote_asset.trim().is_empty() {
+            return Err("Falta el activo de cotización".to_string());
+        }
+        if self.mode != TradeMode::DryRun && !cfg!(feature = "exchanges") {
+            return Err("Este servidor no tiene soporte de exchanges; solo está disponible el modo dry_run".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn limit_price(&self, trigger_price: f64) -> Option<f64> {
+        self.limit_offset.map(|offset| trigger_price * (1.0 + offset / 100.0))
+    }
+
+    pub fn describe(&self, symbol: &str) -> String {
+        let side = match self.side {
+            TradeSide::Buy => "comprar",
+            TradeSide::Sell => "vender",
+        };
+        let price = match self.limit_offset {
+            Some(offset) => format!("límite al {:+}% del precio de disparo", offset),
+            None => "a mercado".to_string(),
+        };
+        let venue = self.exchange.as_deref().unwrap_or("mejor precio");
+        format!("{} {} {}/{} {} ({}, {})", side, self.quantity, symbol, self.quote_asset, price, venue, self.mode.as_str())
+    }
+}
+
+/// Límites que se comprueban antes de enviar cualquier orden automática.
+/// Se aplican por usuario y modo; una regla sin valor no se comprueba.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RiskLimits {
+    pub max_order_notional: Option<f64>,
+    pub max_daily_notional: Option<f64>,
+    /// Valor máximo de la posición neta abierta con órdenes automáticas en cada símbolo
+    pub max_position_notional: Option<f64>,
+    pub max_orders_per_minute: Option<i64>,
+    /// Símbolos que se pueden operar; vacío permite todos
+    pub allowed_symbols: Vec<String>,
+}
+
+/// Credenciales de exchange de un usuario, sin secretos: solo se guardan
+/// cifradas y nunca salen de la base de datos en claro.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExchangeCredentialInfo {
+    pub id: i64,
+    pub user_id: i64,
+    pub exchange: String,
+    /// Últimos caracteres de la API key, para reconocerla
+    pub api_key_hint: String,
+    /// Clave maestra con la que están cifradas
+    pub key_id: String,
+    pub created_at: i64,
+}
+
+/// Credenciales cifradas tal como están en la base de datos.
+#[derive(Debug, Clone)]
+pub struct SealedCredential {
+    pub info: ExchangeCredentialInfo,
+    pub nonce: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+}
+
+/// Resultado de una ejecución de la acción de una alerta.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ActionRun {
+    pub id: i64,
+    pub alert_id: i64,
+    pub mode: TradeMode,
+    pub success: bool,
+    pub summary: String,
+    pub executed_at: i64,
+}
+
+/// Orden enviada a un exchange, con el estado que tenía en el último
+/// sondeo. Los estados son los de `exchanges::OrderStatus` en snake_case.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderRecord {
+    pub id: i64,
+    pub user_id: i64,
+    pub mode: TradeMode,
+    pub exchange: String,
+    /// Identificador que le dio el exchange
+    pub exchange_order_id: String,
+    /// Par BASE/QUOTE
+    pub symbol: String,
+    pub side: TradeSide,
+    pub order_type: String,
+    pub price: Option<f64>,
+    pub quantity: f64,
+    pub filled_quantity: f64,
+    pub average_price: Option<f64>,
+    pub fee: f64,
+    pub status: String,
+    /// Quién la envió: `alert:<id>`, `dca:<id>`, `strategy:<id>`, o `external`
+    /// si apareció en el exchange sin pasar por el sistema
+    pub source: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+impl OrderRecord {
+    pub const EXTERNAL: &'static str = "external";
+
+    pub fn is_open(&self) -> bool {
+        matches!(self.status.as_str(), "new" | "partially_filled")
+    }
+}
+
+/// Compra periódica de un importe fijo de un activo (DCA). El par es
+/// `symbol` contra `quote_asset` y las compras son a mercado.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DcaPlan {
+    pub id: i64,
+    pub user_id: i64,
+    pub symbol: String,
+    /// Importe de cada compra en el activo de cotización
+    pub amount: f64,
+    pub quote_asset: String,
+    /// Horario cron en UTC (`0 9 * * 1` = los lunes a las 9:00)
+    pub schedule: String,
+    /// Si el precio supera este techo, la compra de ese turno se salta
+    pub max_price: Option<f64>,
+    pub mode: TradeMode,
+    /// Exchange en el que comprar; sin él se usa el de mejor precio
+    pub exchange: Option<String>,
+    pub active: bool,
+    pub next_run_at: i64,
+    pub last_run_at: Option<i64>,
+    pub created_at: i64,
+}
+
+impl DcaPlan {
+    pub fn describe(&self) -> String {
+        let ceiling = self.max_price
+            .map(|price| format!(", salvo por encima de ${}", price))
+            .unwrap_or_default();
+        let venue = self.exchange.as_deref().unwrap_or("mejor precio");
+        format!(
+            "comprar {} {} de {} según `{}`{} ({}, {})",
+            self.amount, self.quote_asset, self.symbol, self.schedule, ceiling, venue, self.mode.as_str()
+        )
+    }
+}
+
+/// Resultado de un turno de un plan DCA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DcaOutcome {
+    /// Se compró (o en dry_run, se habría comprado)
+    Executed,
+    /// El precio estaba por encima del techo
+    Skipped,
+    Failed,
+}
+
+impl DcaOutcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DcaOutcome::Executed => "executed",
+            DcaOutcome::Skipped => "skipped",
+            DcaOutcome::Failed => "failed",
+        }
+    }
+}
+
+impl FromSql for DcaOutcome {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "executed" => Ok(DcaOutcome::Executed),
+            "skipped" => Ok(DcaOutcome::Skipped),
+            "failed" => Ok(DcaOutcome::Failed),
+            _ => Err(rusqlite::types::FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl ToSql for DcaOutcome {
+    fn to_sql(&self) -> SqliteResult<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+/// Turno ejecutado de un plan DCA.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DcaRun {
+    pub id: i64,
+    pub plan_id: i64,
+    pub mode: TradeMode,
+    pub outcome: DcaOutcome,
+    /// Cotización al llegar el turno
+    pub price: Option<f64>,
+    /// Cantidad comprada
+    pub quantity: Option<f64>,
+    pub summary: String,
+    pub executed_at: i64,
+}
+
+/// Tipo de estrategia automática.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StrategyKind {
+    /// Escalera de órdenes límite entre dos precios
+    Grid,
+}
+
+impl StrategyKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StrategyKind::Grid => "grid",
+        }
+    }
+}
+
+impl FromSql for StrategyKind {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "grid" => Ok(StrategyKind::Grid),
+            _ => Err(rusqlite::types::FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl ToSql for StrategyKind {
+    fn to_sql(&self) -> SqliteResult<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StrategyStatus {
+    Running,
+    /// Se pidió detenerla; el motor cancelará sus órdenes en la próxima pasada
+    Stopping,
+    Stopped,
+    /// No se pudo cargar su estado; requiere revisión manual
+    Failed,
+}
+
+impl StrategyStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StrategyStatus::Running => "running",
+            StrategyStatus::Stopping => "stopping",
+            StrategyStatus::Stopped => "stopped",
+            StrategyStatus::Failed => "failed",
+        }
+    }
+}
+
+impl FromSql for StrategyStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "running" => Ok(StrategyStatus::Running),
+            "stopping" => Ok(StrategyStatus::Stopping),
+            "stopped" => Ok(StrategyStatus::Stopped),
+            "failed" => Ok(StrategyStatus::Failed),
+            _ => Err(rusqlite::types::FromSqlError::InvalidType),
+        }
+    }
+}
+
+impl ToSql for StrategyStatus {
+    fn to_sql(&self) -> SqliteResult<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+/// Estrategia automática de un usuario. `config` son los parámetros que
+/// eligió y `state`, lo que la estrategia necesita para continuar tras un
+/// reinicio; el formato de ambos depende de `kind`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StrategyRecord {
+    pub id: i64,
+    pub user_id: i64,
+    pub kind: StrategyKind,
+    pub mode: TradeMode,
+    pub exchange: String,
+    pub symbol: String,
+    pub quote_asset: String,
+    pub config: serde_json::Value,
+    pub state: serde_json::Value,
+    pub status: StrategyStatus,
+    /// Beneficio cerrado, en el activo de cotización y neto de comisiones
+    pub realized_profit: f64,
+    /// Último error de la estrategia; se borra en cuanto una pasada va bien
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+/// Chat adicional que recibe las notificaciones de una alerta.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertTarget {
+    pub alert_id: i64,
+    pub chat_id: i64,
+    pub kind: TargetKind,
+    pub added_by: i64,
+    pub created_at: i64,
+}
+
 #[derive(Debug, Clone)]
 pub struct CryptoPrice {
     pub symbol: String,
@@ -90,6 +805,33 @@ pub struct CryptoPrice {
     pub timestamp: i64,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PricePoint {
+    pub timestamp: i64,
+    pub price: f64,
+}
+
+/// Cotización del histórico de precios. `venue` es el exchange o la fuente
+/// de la que viene (`binance`, `coingecko`...).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PriceTick {
+    pub timestamp: i64,
+    pub symbol: String,
+    pub venue: String,
+    pub price: f64,
+    #[serde(default)]
+    pub volume: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Candle {
+    pub timestamp: i64,
+    pub open: f64,
+    pub high: f64,
+    pub low: f64,
+    pub close: f64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum UserState {
     Idle,
@@ -113,6 +855,10 @@ pub enum UserState {
         expected_ratio: Option<f64>,
         differential: Option<f64>,
     },
+    /// Esperando el nuevo umbral de una alerta existente.
+    EditingAlert {
+        alert_id: i64,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -139,4 +885,207 @@ pub enum PairAlertStep {
     EnterRatio,
     EnterDifferential,
     Confirm,
-} 
\ No newline at end of file
+} 
+
+impl PriceAlertStep {
+    pub fn previous(&self) -> Option<Self> {
+        match self {
+            PriceAlertStep::SelectSymbol => None,
+            PriceAlertStep::EnterPrice => Some(PriceAlertStep::SelectSymbol),
+            PriceAlertStep::SelectCondition => Some(PriceAlertStep::EnterPrice),
+            PriceAlertStep::Confirm => Some(PriceAlertStep::SelectCondition),
+        }
+    }
+}
+
+impl DepegAlertStep {
+    // El bot fija el precio objetivo en $1 al elegir la stablecoin, así que
+    // volver desde el diferencial lleva directamente a la selección de símbolo.
+    pub fn previous(&self) -> Option<Self> {
+        match self {
+            DepegAlertStep::SelectSymbol => None,
+            DepegAlertStep::EnterTargetPrice => Some(DepegAlertStep::SelectSymbol),
+            DepegAlertStep::EnterDifferential => Some(DepegAlertStep::SelectSymbol),
+            DepegAlertStep::SelectExchanges => Some(DepegAlertStep::EnterDifferential),
+            DepegAlertStep::Confirm => Some(DepegAlertStep::SelectExchanges),
+        }
+    }
+}
+
+impl PairAlertStep {
+    // Los pares se eligen juntos, así que SelectToken2 no se usa en el bot.
+    pub fn previous(&self) -> Option<Self> {
+        match self {
+            PairAlertStep::SelectToken1 => None,
+            PairAlertStep::SelectToken2 => Some(PairAlertStep::SelectToken1),
+            PairAlertStep::EnterRatio => Some(PairAlertStep::SelectToken1),
+            PairAlertStep::EnterDifferential => Some(PairAlertStep::EnterRatio),
+            PairAlertStep::Confirm => Some(PairAlertStep::EnterDifferential),
+        }
+    }
+}
+
+impl UserState {
+    /// Retrocede un paso del asistente descartando los datos ingresados en el
+    /// paso al que se vuelve. Devuelve `None` si ya está en el primer paso.
+    pub fn back(&self) -> Option<UserState> {
+        match self {
+            UserState::Idle | UserState::EditingAlert { .. } => None,
+            UserState::CreatingPriceAlert { step, symbol, target_price, .. } => {
+                let step = step.previous()?;
+                Some(UserState::CreatingPriceAlert {
+                    symbol: match step {
+                        PriceAlertStep::SelectSymbol => None,
+                        _ => symbol.clone(),
+                    },
+                    target_price: match step {
+                        PriceAlertStep::SelectSymbol | PriceAlertStep::EnterPrice => None,
+                        _ => *target_price,
+                    },
+                    condition: None,
+                    step,
+                })
+            }
+            UserState::CreatingDepegAlert { step, symbol, target_price, differential, .. } => {
+                let step = step.previous()?;
+                let at_symbol = matches!(step, DepegAlertStep::SelectSymbol);
+                Some(UserState::CreatingDepegAlert {
+                    symbol: if at_symbol { None } else { symbol.clone() },
+                    target_price: if at_symbol { None } else { *target_price },
+                    differential: match step {
+                        DepegAlertStep::SelectExchanges | DepegAlertStep::Confirm => *differential,
+                        _ => None,
+                    },
+                    exchanges: None,
+                    step,
+                })
+            }
+            UserState::CreatingPairAlert { step, token1, token2, expected_ratio, .. } => {
+                let step = step.previous()?;
+                let at_pair = matches!(step, PairAlertStep::SelectToken1);
+                Some(UserState::CreatingPairAlert {
+                    token1: if at_pair { None } else { token1.clone() },
+                    token2: if at_pair { None } else { token2.clone() },
+                    expected_ratio: match step {
+                        PairAlertStep::EnterDifferential | PairAlertStep::Confirm => *expected_ratio,
+                        _ => None,
+                    },
+                    differential: None,
+                    step,
+                })
+            }
+        }
+    }
+
+    /// Resumen legible del asistente en curso.
+    pub fn describe(&self) -> String {
+        fn or_pending<T: std::fmt::Display>(value: &Option<T>) -> String {
+            value.as_ref().map(|v| v.to_string()).unwrap_or_else(|| "—".to_string())
+        }
+
+        match self {
+            UserState::Idle => "Sin operaciones en curso".to_string(),
+            UserState::CreatingPriceAlert { step, symbol, target_price, condition } => format!(
+                "Creando alerta de precio (paso: {:?})\n\
+                 Símbolo: {}\n\
+                 Precio objetivo: {}\n\
+                 Condición: {}",
+                step,
+                or_pending(symbol),
+                or_pending(target_price),
+                condition.as_ref().map(|c| format!("{:?}", c)).unwrap_or_else(|| "—".to_string()),
+            ),
+            UserState::CreatingDepegAlert { step, symbol, target_price, differential, .. } => format!(
+                "Creando alerta de depeg (paso: {:?})\n\
+                 Stablecoin: {}\n\
+                 Precio objetivo: {}\n\
+                 Diferencial: {}",
+                step,
+                or_pending(symbol),
+                or_pending(target_price),
+                or_pending(differential),
+            ),
+            UserState::CreatingPairAlert { step, token1, token2, expected_ratio, differential } => format!(
+                "Creando alerta de par (paso: {:?})\n\
+                 Par: {}/{}\n\
+                 Ratio esperado: {}\n\
+                 Diferencial: {}",
+                step,
+                or_pending(token1),
+                or_pending(token2),
+                or_pending(expected_ratio),
+                or_pending(differential),
+            ),
+            UserState::EditingAlert { alert_id } => format!(
+                "Editando la alerta #{}: envía el nuevo valor",
+                alert_id
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_back_discards_current_step_data() {
+        let state = UserState::CreatingPriceAlert {
+            step: PriceAlertStep::SelectCondition,
+            symbol: Some("BTC".to_string()),
+            target_price: Some(42000.0),
+            condition: None,
+        };
+
+        let previous = state.back().expect("debería poder retroceder");
+        match &previous {
+            UserState::CreatingPriceAlert { step: PriceAlertStep::EnterPrice, symbol, target_price, .. } => {
+                assert_eq!(symbol.as_deref(), Some("BTC"));
+                assert!(target_price.is_none());
+            }
+            other => panic!("estado inesperado: {:?}", other),
+        }
+
+        let first = previous.back().expect("debería volver a la selección de símbolo");
+        assert!(first.back().is_none());
+    }
+
+    #[test]
+    fn test_alert_filter() {
+        let mut alert = PriceAlert {
+            id: Some(1),
+            user_id: 1,
+            symbol: "ETH/stETH".to_string(),
+            alert_type: AlertType::PairDepeg {
+                token1: "ETH".to_string(),
+                token2: "stETH".to_string(),
+                expected_ratio: 1.0,
+                differential: 0.5,
+            },
+            created_at: 0,
+            triggered_at: None,
+            is_active: false,
+        };
+        assert_eq!(alert.status(), AlertStatus::Paused);
+
+        let filter = AlertFilter {
+            kind: Some(AlertKind::PairDepeg),
+            status: Some(AlertStatus::Paused),
+            symbol: Some("steth".to_string()),
+        };
+        assert!(filter.matches(&alert));
+        assert!(AlertFilter::default().matches(&alert));
+
+        alert.triggered_at = Some(10);
+        assert!(!filter.matches(&alert));
+    }
+
+    #[test]
+    fn test_plan_kinds_round_trip() {
+        for kind in AlertKind::ALL {
+            assert_eq!(kind.as_str().parse::<AlertKind>(), Ok(kind));
+        }
+        assert_eq!("APPROVAL".parse::<RegistrationMode>(), Ok(RegistrationMode::Approval));
+        assert!("cerrado".parse::<RegistrationMode>().is_err());
+    }
+}
@@ -1,30 +1,66 @@
 use crate::{
-    crypto_api::CryptoAPI,
-    models::{CryptoPrice, PriceAlert, AlertType, AlertCondition},
+    chart,
+    crypto_api::{CryptoAPI, PriceCache},
+    models::{CryptoPrice, PriceTick, PriceAlert, AlertType, AlertCondition, AlertKind, AlertAction, TargetKind, TradeMode, PORTFOLIO_SYMBOL},
     notify::NotificationService,
     db::Database,
+    history::HistoryRetention,
+    portfolio,
 };
-use std::{error::Error, sync::Arc, time::Duration};
+#[cfg(feature = "exchanges")]
+use crate::trading::{self, TradeExecutor};
+use std::{collections::HashMap, error::Error, sync::{Arc, Mutex}, time::Duration};
 use tokio::time;
 use tracing::{info, error};
 
+/// Cada cuánto se aplica la retención al histórico de precios.
+const PRUNE_INTERVAL: i64 = 3600;
+
 pub struct PriceMonitor {
     api: CryptoAPI,
     notification_service: NotificationService,
     db: Arc<Database>,
     check_interval: u64,
+    attach_charts: bool,
+    /// Última evaluación de cada usuario cuyo plan limita el intervalo
+    last_checked: Mutex<HashMap<i64, i64>>,
+    history_retention: HistoryRetention,
+    #[cfg(feature = "exchanges")]
+    trader: Option<Arc<TradeExecutor>>,
 }
 
 impl PriceMonitor {
-    pub fn new(api: CryptoAPI, notification_service: NotificationService, db: Arc<Database>, check_interval: u64) -> Self {
+    pub fn new(api: CryptoAPI, notification_service: NotificationService, db: Arc<Database>, check_interval: u64, attach_charts: bool) -> Self {
         Self {
             api,
             notification_service,
             db,
             check_interval,
+            attach_charts,
+            last_checked: Mutex::new(HashMap::new()),
+            history_retention: HistoryRetention::default(),
+            #[cfg(feature = "exchanges")]
+            trader: None,
         }
     }
 
+    pub fn with_history_retention(mut self, retention: HistoryRetention) -> Self {
+        self.history_retention = retention;
+        self
+    }
+
+    /// Habilita las acciones de las alertas en modo `paper` y `live`.
+    #[cfg(feature = "exchanges")]
+    pub fn with_trader(mut self, trader: Arc<TradeExecutor>) -> Self {
+        self.trader = Some(trader);
+        self
+    }
+
+    /// Precios que el monitor va obteniendo en cada ciclo.
+    pub fn price_cache(&self) -> PriceCache {
+        self.api.price_cache()
+    }
+
     pub async fn start(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
         info!("Iniciando monitor de precios...");
         
@@ -35,19 +71,65 @@ impl PriceMonitor {
         }
 
         let mut interval = time::interval(Duration::from_secs(self.check_interval));
+        let mut last_pruned = 0;
 
         loop {
             interval.tick().await;
+            let now = chrono::Utc::now().timestamp();
+            if now - last_pruned >= PRUNE_INTERVAL {
+                last_pruned = now;
+                match self.history_retention.prune(&self.db, now) {
+                    Ok(0) => {}
+                    Ok(deleted) => info!("{} precios antiguos descartados del histórico", deleted),
+                    Err(e) => error!("Error al aplicar la retención del histórico: {}", e),
+                }
+            }
             if let Err(e) = self.check_all_alerts().await {
                 error!("Error al verificar alertas: {}", e);
             }
+            if let Err(e) = self.process_escalations().await {
+                error!("Error al procesar escalados: {}", e);
+            }
+        }
+    }
+
+    /// Descarta las alertas de usuarios cuyo plan exige esperar más entre
+    /// evaluaciones de lo que pasó desde la última.
+    fn due_alerts(&self, alerts: Vec<PriceAlert>) -> Result<Vec<PriceAlert>, Box<dyn Error + Send + Sync>> {
+        let intervals = self.db.get_user_check_intervals()?;
+        if intervals.is_empty() {
+            return Ok(alerts);
+        }
+
+        let now = chrono::Utc::now().timestamp();
+        let mut last_checked = self.last_checked.lock().unwrap();
+        let due: HashMap<i64, bool> = intervals.iter()
+            .map(|(user_id, interval)| {
+                let due = last_checked.get(user_id).is_none_or(|checked| now - checked >= *interval);
+                (*user_id, due)
+            })
+            .collect();
+        for (user_id, _) in due.iter().filter(|(_, due)| **due) {
+            last_checked.insert(*user_id, now);
         }
+
+        Ok(alerts.into_iter()
+            .filter(|alert| due.get(&alert.user_id).copied().unwrap_or(true))
+            .collect())
     }
 
     async fn check_all_alerts(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
         info!("Verificando alertas activas...");
-        let alerts = self.db.get_active_alerts()?;
+        let alerts = self.due_alerts(self.db.get_active_alerts()?)?;
         info!("Encontradas {} alertas activas", alerts.len());
+
+        let (portfolio_alerts, alerts): (Vec<PriceAlert>, Vec<PriceAlert>) = alerts.into_iter()
+            .partition(|alert| alert.kind() == AlertKind::Portfolio);
+        if !portfolio_alerts.is_empty() {
+            if let Err(e) = self.check_portfolio_alerts(portfolio_alerts).await {
+                error!("Error al verificar alertas de portafolio: {}", e);
+            }
+        }
         
         for alert in &alerts {
             match &alert.alert_type {
@@ -56,8 +138,10 @@ impl PriceMonitor {
                         alert.id.unwrap_or(-1), alert.symbol, target_price, condition);
                     
                     if let Ok(price) = self.api.get_price(&alert.symbol).await {
-                        if self.should_trigger_alert(&price, alert) {
-                            if let Err(e) = self.send_alert_notification(alert, &price).await {
+                        self.record_prices(std::slice::from_ref(&price));
+                        if price_triggers(&alert.alert_type, price.price) {
+                            let action_report = self.run_alert_action(alert, &price).await;
+                            if let Err(e) = self.send_alert_notification(alert, &price, action_report.as_deref()).await {
                                 error!("Error al enviar notificación: {}", e);
                             }
                             if let Err(e) = self.db.mark_alert_triggered(alert.id.unwrap()) {
@@ -75,38 +159,29 @@ impl PriceMonitor {
                         differential
                     );
                     
-                    let mut prices = Vec::new();
+                    let mut quotes = Vec::new();
                     for exchange in exchanges {
                         if let Ok(price) = self.api.get_price_from_exchange(&alert.symbol, exchange).await {
-                            prices.push(price.price);
+                            quotes.push(price);
                         }
                     }
-                    
-                    if !prices.is_empty() {
-                        let max_price = prices.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
-                        let min_price = prices.iter().fold(f64::INFINITY, |a, &b| a.min(b));
-                        let deviation = ((max_price - target_price).abs() / target_price) * 100.0;
+                    self.record_prices(&quotes);
+                    let prices: Vec<f64> = quotes.iter().map(|quote| quote.price).collect();
+
+                    if let Some(trigger_price) = depeg_trigger(*target_price, *differential, &prices) {
+                        let crypto_price = CryptoPrice {
+                            symbol: alert.symbol.clone(),
+                            price: trigger_price,
+                            exchange: "multiple".to_string(),
+                            timestamp: chrono::Utc::now().timestamp(),
+                        };
                         
-                        if deviation > *differential {
-                            let trigger_price = if (max_price - target_price).abs() > (min_price - target_price).abs() {
-                                max_price
-                            } else {
-                                min_price
-                            };
-                            
-                            let crypto_price = CryptoPrice {
-                                symbol: alert.symbol.clone(),
-                                price: trigger_price,
-                                exchange: "multiple".to_string(),
-                                timestamp: chrono::Utc::now().timestamp(),
-                            };
-                            
-                            if let Err(e) = self.send_alert_notification(alert, &crypto_price).await {
-                                error!("Error al enviar notificación: {}", e);
-                            }
-                            if let Err(e) = self.db.mark_alert_triggered(alert.id.unwrap()) {
-                                error!("Error al marcar alerta como disparada: {}", e);
-                            }
+                        let action_report = self.run_alert_action(alert, &crypto_price).await;
+                        if let Err(e) = self.send_alert_notification(alert, &crypto_price, action_report.as_deref()).await {
+                            error!("Error al enviar notificación: {}", e);
+                        }
+                        if let Err(e) = self.db.mark_alert_triggered(alert.id.unwrap()) {
+                            error!("Error al marcar alerta como disparada: {}", e);
                         }
                     }
                 },
@@ -114,10 +189,8 @@ impl PriceMonitor {
                     // Implementación del manejo de alertas de par de tokens
                     if let Ok(price1) = self.api.get_price(token1).await {
                         if let Ok(price2) = self.api.get_price(token2).await {
-                            let current_ratio = price1.price / price2.price;
-                            let deviation = ((current_ratio - expected_ratio) / expected_ratio).abs() * 100.0;
-                            
-                            if deviation > *differential {
+                            self.record_prices(&[price1.clone(), price2.clone()]);
+                            if let Some(current_ratio) = pair_trigger(*expected_ratio, *differential, price1.price, price2.price) {
                                 let crypto_price = CryptoPrice {
                                     symbol: format!("{}/{}", token1, token2),
                                     price: current_ratio,
@@ -125,7 +198,7 @@ impl PriceMonitor {
                                     timestamp: chrono::Utc::now().timestamp(),
                                 };
                                 
-                                if let Err(e) = self.send_alert_notification(alert, &crypto_price).await {
+                                if let Err(e) = self.send_alert_notification(alert, &crypto_price, None).await {
                                     error!("Error al enviar notificación: {}", e);
                                 }
                                 if let Err(e) = self.db.mark_alert_triggered(alert.id.unwrap()) {
@@ -135,31 +208,154 @@ impl PriceMonitor {
                         }
                     }
                 }
+                // Separadas arriba
+                AlertType::PortfolioValue { .. }
+                | AlertType::PortfolioDrawdown { .. }
+                | AlertType::AllocationDrift { .. } => {}
             }
         }
 
         Ok(())
     }
 
-    fn should_trigger_alert(&self, price: &CryptoPrice, alert: &PriceAlert) -> bool {
-        match &alert.alert_type {
-            AlertType::Price { target_price, condition } => match condition {
-                AlertCondition::Above => price.price > *target_price,
-                AlertCondition::Below => price.price < *target_price,
-            },
-            AlertType::Depeg { target_price, differential: diff, .. } => {
-                let deviation = ((price.price - target_price) / target_price).abs() * 100.0;
-                deviation > *diff
-            },
-            AlertType::PairDepeg { .. } => false, // Se maneja en check_pair_depeg
+    /// Valora los portafolios de los dueños de las alertas con una única
+    /// consulta de precios por ciclo y evalúa cada alerta contra el suyo.
+    async fn check_portfolio_alerts(&self, alerts: Vec<PriceAlert>) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let mut holdings = HashMap::new();
+        for alert in &alerts {
+            if let std::collections::hash_map::Entry::Vacant(entry) = holdings.entry(alert.user_id) {
+                entry.insert(self.db.get_holdings(alert.user_id)?);
+            }
         }
+
+        let mut symbols: Vec<String> = holdings.values()
+            .flat_map(|user_holdings| portfolio::open_symbols(user_holdings))
+            .collect();
+        symbols.sort();
+        symbols.dedup();
+        if symbols.is_empty() {
+            return Ok(());
+        }
+        let prices = self.api.get_prices(&symbols).await?;
+        let now = chrono::Utc::now().timestamp();
+        let quotes: Vec<CryptoPrice> = prices.iter()
+            .map(|(symbol, price)| CryptoPrice { symbol: symbol.clone(), price: *price, exchange: "coingecko".to_string(), timestamp: now })
+            .collect();
+        self.record_prices(&quotes);
+
+        let valuations: HashMap<i64, portfolio::PortfolioValuation> = holdings.into_iter()
+            .map(|(user_id, user_holdings)| (user_id, portfolio::value_at(user_holdings, &prices)))
+            .collect();
+
+        for mut alert in alerts {
+            let (Some(alert_id), Some(valuation)) = (alert.id, valuations.get(&alert.user_id)) else {
+                continue;
+            };
+            info!("Evaluando alerta de portafolio: ID={}, Usuario={}, Valor=${:.2}", alert_id, alert.user_id, valuation.total_value);
+
+            let previous_peak = match alert.alert_type {
+                AlertType::PortfolioDrawdown { peak, .. } => Some(peak),
+                _ => None,
+            };
+            let observed = portfolio::check_alert(&mut alert.alert_type, valuation);
+            if let AlertType::PortfolioDrawdown { peak, .. } = alert.alert_type {
+                if previous_peak != Some(peak) {
+                    if let Err(e) = self.db.update_alert_type(alert_id, &alert.alert_type) {
+                        error!("Error al guardar el máximo del portafolio de la alerta {}: {}", alert_id, e);
+                    }
+                }
+            }
+
+            if let Some(observed) = observed {
+                let crypto_price = CryptoPrice {
+                    symbol: alert.symbol.clone(),
+                    price: observed,
+                    exchange: "portfolio".to_string(),
+                    timestamp: chrono::Utc::now().timestamp(),
+                };
+                if let Err(e) = self.send_alert_notification(&alert, &crypto_price, None).await {
+                    error!("Error al enviar notificación: {}", e);
+                }
+                if let Err(e) = self.db.mark_alert_triggered(alert_id) {
+                    error!("Error al marcar alerta como disparada: {}", e);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Guarda en el histórico los precios obtenidos en el ciclo.
+    fn record_prices(&self, prices: &[CryptoPrice]) {
+        let ticks: Vec<PriceTick> = prices.iter()
+            .map(|price| PriceTick {
+                timestamp: price.timestamp,
+                symbol: price.symbol.to_uppercase(),
+                venue: price.exchange.clone(),
+                price: price.price,
+                volume: None,
+            })
+            .collect();
+        if let Err(e) = self.db.save_price_ticks(&ticks) {
+            error!("Error al guardar el histórico de precios: {}", e);
+        }
+    }
+
+    /// Ejecuta la acción automática de la alerta, si tiene, y guarda el
+    /// resultado. Devuelve el texto que acompaña a la notificación.
+    async fn run_alert_action(&self, alert: &PriceAlert, price: &CryptoPrice) -> Option<String> {
+        let alert_id = alert.id?;
+        let action = match self.db.get_alert_action(alert_id) {
+            Ok(action) => action?,
+            Err(e) => {
+                error!("Error al leer la acción de la alerta {}: {}", alert_id, e);
+                return None;
+            }
+        };
+
+        let outcome = match action.mode {
+            TradeMode::DryRun => {
+                let mut summary = format!("Se habría enviado: {}", action.describe(&alert.symbol));
+                if let Some(limit) = action.limit_price(price.price) {
+                    summary.push_str(&format!("\nPrecio límite: ${:.4}", limit));
+                }
+                Ok(summary)
+            }
+            TradeMode::Paper | TradeMode::Live => self.submit_action(alert.user_id, &action, &alert.symbol, price.price).await,
+        };
+        let (success, summary) = match outcome {
+            Ok(summary) => (true, summary),
+            Err(e) => {
+                error!("Falló la acción de la alerta {}: {}", alert_id, e);
+                (false, e.to_string())
+            }
+        };
+        if let Err(e) = self.db.record_action_run(alert_id, action.mode, success, &summary) {
+            error!("Error al guardar la ejecución de la acción de la alerta {}: {}", alert_id, e);
+        }
+
+        let icon = if success { "🤖" } else { "⚠️" };
+        Some(format!("{} Acción automática ({}):\n{}", icon, action.mode.as_str(), summary))
+    }
+
+    #[cfg(feature = "exchanges")]
+    async fn submit_action(&self, user_id: i64, action: &AlertAction, symbol: &str, trigger_price: f64) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let trader = self.trader.as_ref().ok_or("la operativa automática no está configurada")?;
+        let report = trader.execute(user_id, action, symbol, trigger_price).await?;
+        Ok(trading::summarize(&report))
+    }
+
+    #[cfg(not(feature = "exchanges"))]
+    async fn submit_action(&self, _user_id: i64, _action: &AlertAction, _symbol: &str, _trigger_price: f64) -> Result<String, Box<dyn Error + Send + Sync>> {
+        Err("este servidor se compiló sin soporte de exchanges".into())
     }
 
-    async fn send_alert_notification(&self, alert: &PriceAlert, price: &CryptoPrice) -> Result<(), Box<dyn Error + Send + Sync>> {
-        let user = self.db.get_user_telegram_chat_id(alert.user_id)?
-            .ok_or_else(|| format!("No se encontró telegram_chat_id para el usuario {}", alert.user_id))?;
+    async fn send_alert_notification(&self, alert: &PriceAlert, price: &CryptoPrice, action_report: Option<&str>) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let recipients = self.alert_recipients(alert)?;
+        if recipients.is_empty() {
+            return Err(format!("La alerta {:?} no tiene chats a los que notificar", alert.id).into());
+        }
 
-        let message = match &alert.alert_type {
+        let mut message = match &alert.alert_type {
             AlertType::Price { target_price, condition } => {
                 format!(
                     "🚨 ¡Alerta de Precio!\n\n\
@@ -195,10 +391,142 @@ impl PriceMonitor {
                     expected_ratio,
                     differential
                 )
+            },
+            AlertType::PortfolioValue { threshold, condition } => {
+                format!(
+                    "🚨 ¡Alerta de Portafolio!\n\n\
+                     Valor Actual: ${:.2}\n\
+                     Valor Objetivo: ${:.2}\n\
+                     Condición: {:?}",
+                    price.price, threshold, condition
+                )
+            },
+            AlertType::PortfolioDrawdown { max_drawdown, peak } => {
+                format!(
+                    "🚨 ¡Caída del Portafolio!\n\n\
+                     Caída desde el máximo: {:.2}%\n\
+                     Máximo registrado: ${:.2}\n\
+                     Caída permitida: {:.2}%",
+                    price.price, peak, max_drawdown
+                )
+            },
+            AlertType::AllocationDrift { symbol, target_weight, tolerance } => {
+                format!(
+                    "🚨 ¡Desvío de Asignación!\n\n\
+                     Símbolo: {}\n\
+                     Peso Actual: {:.2}%\n\
+                     Peso Objetivo: {:.2}% (±{:.2} puntos)",
+                    symbol, price.price, target_weight, tolerance
+                )
+            }
+        };
+
+        if let Some(report) = action_report {
+            message.push_str("\n\n");
+            message.push_str(report);
+        }
+
+        // El valor del portafolio no tiene serie de precios que graficar
+        let chart_png = if self.attach_charts && alert.symbol != PORTFOLIO_SYMBOL {
+            match chart::alert_chart(&self.api, alert, Some(price)).await {
+                Ok(png) => Some(png),
+                Err(e) => {
+                    error!("No se pudo generar el gráfico de la alerta: {}", e);
+                    None
+                }
             }
+        } else {
+            None
         };
 
-        self.notification_service.send_alert(user, &message).await
+        // Un chat que falla (bot expulsado de un grupo, suscriptor que bloqueó
+        // el bot) no debe impedir que el resto reciba la alerta
+        let mut delivered = 0;
+        for (chat_id, can_manage) in &recipients {
+            let actions = alert.id.map(|id| self.notification_service.alert_actions(*chat_id, id, *can_manage));
+            let result = match &chart_png {
+                Some(png) => self.notification_service.send_alert_with_chart(*chat_id, &message, png.clone(), actions).await,
+                None => self.notification_service.send_alert(*chat_id, &message, actions).await,
+            };
+            match result {
+                Ok(_) => delivered += 1,
+                Err(e) => error!("No se pudo notificar la alerta {:?} al chat {}: {}", alert.id, chat_id, e),
+            }
+        }
+
+        if delivered == 0 {
+            return Err(format!("No se pudo entregar la alerta {:?} a ningún chat", alert.id).into());
+        }
+        Ok(())
+    }
+
+    /// Reenvía las alertas disparadas que siguen sin reconocer y, agotados los
+    /// intentos de la política, avisa también al contacto secundario.
+    async fn process_escalations(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let due = self.db.get_due_escalations(chrono::Utc::now().timestamp())?;
+        for policy in due {
+            let alert = match self.db.get_alert(policy.alert_id)? {
+                Some(alert) => alert,
+                None => continue,
+            };
+
+            let attempt = policy.attempts + 1;
+            let escalate = policy.escalated_at.is_none() && policy.attempts >= policy.max_attempts;
+            let message = format!(
+                "⏰ Recordatorio #{}: la alerta #{} ({}) sigue sin reconocer.\n\
+                 Pulsa ✅ Reconocer para detener los avisos.",
+                attempt, policy.alert_id, alert.symbol
+            );
+            info!("Reenviando alerta {} sin reconocer (aviso {})", policy.alert_id, attempt);
+
+            let mut recipients = self.alert_recipients(&alert)?;
+            if let Some(secondary) = policy.secondary_chat_id {
+                if escalate || policy.escalated_at.is_some() {
+                    recipients.retain(|(chat_id, _)| *chat_id != secondary);
+                    let escalation = format!(
+                        "🚨 Escalado: la alerta #{} ({}) lleva {} avisos sin reconocer.",
+                        policy.alert_id, alert.symbol, policy.attempts
+                    );
+                    let actions = self.notification_service.escalation_actions(secondary, policy.alert_id);
+                    if let Err(e) = self.notification_service.send_alert(secondary, &escalation, Some(actions)).await {
+                        error!("No se pudo escalar la alerta {} al chat {}: {}", policy.alert_id, secondary, e);
+                    }
+                }
+            }
+
+            for (chat_id, can_manage) in &recipients {
+                let actions = self.notification_service.alert_actions(*chat_id, policy.alert_id, *can_manage);
+                if let Err(e) = self.notification_service.send_alert(*chat_id, &message, Some(actions)).await {
+                    error!("No se pudo reenviar la alerta {} al chat {}: {}", policy.alert_id, chat_id, e);
+                }
+            }
+
+            self.db.record_escalation_attempt(policy.alert_id, escalate && policy.secondary_chat_id.is_some())?;
+        }
+        Ok(())
+    }
+
+    /// Chats que reciben la alerta: sus destinos adicionales y, salvo que la
+    /// alerta pertenezca a un grupo, el chat privado del dueño. El booleano
+    /// indica si el chat puede gestionar la alerta desde la notificación.
+    fn alert_recipients(&self, alert: &PriceAlert) -> Result<Vec<(i64, bool)>, Box<dyn Error + Send + Sync>> {
+        let targets = match alert.id {
+            Some(id) => self.db.get_alert_targets(id)?,
+            None => Vec::new(),
+        };
+
+        let mut recipients: Vec<(i64, bool)> = Vec::new();
+        if !targets.iter().any(|target| target.kind == TargetKind::Group) {
+            if let Some(chat_id) = self.db.get_user_telegram_chat_id(alert.user_id)? {
+                recipients.push((chat_id, true));
+            }
+        }
+        for target in targets {
+            if !recipients.iter().any(|(chat_id, _)| *chat_id == target.chat_id) {
+                recipients.push((target.chat_id, target.kind != TargetKind::Subscriber));
+            }
+        }
+        Ok(recipients)
     }
 
     async fn check_depeg_alert(&self, alert: &PriceAlert) -> Result<bool, Box<dyn Error + Send + Sync>> {
@@ -212,17 +540,7 @@ impl PriceMonitor {
                 }
             }
 
-            if prices.is_empty() {
-                return Ok(false);
-            }
-
-            // Calcular desviación máxima
-            let max_price = prices.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
-            let _min_price = prices.iter().fold(f64::INFINITY, |a, &b| a.min(b));
-            
-            let deviation = ((max_price - target_price).abs() / target_price) * 100.0;
-            
-            Ok(deviation > *diff)
+            Ok(depeg_trigger(*target_price, *diff, &prices).is_some())
         } else {
             Ok(false)
         }
@@ -233,12 +551,56 @@ impl PriceMonitor {
             let price1 = self.api.get_price(token1).await?;
             let price2 = self.api.get_price(token2).await?;
             
-            let current_ratio = price1.price / price2.price;
-            let deviation = ((current_ratio - expected_ratio) / expected_ratio).abs() * 100.0;
-            
-            Ok(deviation > *differential)
+            Ok(pair_trigger(*expected_ratio, *differential, price1.price, price2.price).is_some())
         } else {
             Ok(false)
         }
     }
-} 
\ No newline at end of file
+}
+
+/// Si una alerta de precio, o de depeg con un único precio, se cumple con
+/// `price`. El resto de tipos se evalúan con `depeg_trigger`, `pair_trigger`
+/// y `portfolio::check_alert`.
+pub fn price_triggers(alert_type: &AlertType, price: f64) -> bool {
+    match alert_type {
+        AlertType::Price { target_price, condition } => match condition {
+            AlertCondition::Above => price > *target_price,
+            AlertCondition::Below => price < *target_price,
+        },
+        AlertType::Depeg { target_price, differential: diff, .. } => {
+            let deviation = ((price - target_price) / target_price).abs() * 100.0;
+            deviation > *diff
+        },
+        AlertType::PairDepeg { .. } => false, // Se maneja con pair_trigger
+        AlertType::PortfolioValue { .. }
+        | AlertType::PortfolioDrawdown { .. }
+        | AlertType::AllocationDrift { .. } => false, // Se maneja en check_portfolio_alerts
+    }
+}
+
+/// Evalúa una alerta de depeg con los precios de sus exchanges. Devuelve el
+/// precio más alejado del objetivo si la desviación supera `differential` (%).
+pub fn depeg_trigger(target_price: f64, differential: f64, prices: &[f64]) -> Option<f64> {
+    if prices.is_empty() {
+        return None;
+    }
+    let max_price = prices.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
+    let min_price = prices.iter().fold(f64::INFINITY, |a, &b| a.min(b));
+    let deviation = ((max_price - target_price).abs() / target_price) * 100.0;
+    if deviation <= differential {
+        return None;
+    }
+    if (max_price - target_price).abs() > (min_price - target_price).abs() {
+        Some(max_price)
+    } else {
+        Some(min_price)
+    }
+}
+
+/// Evalúa una alerta de par. Devuelve el ratio `price1 / price2` si se aleja
+/// de `expected_ratio` más de `differential` (%).
+pub fn pair_trigger(expected_ratio: f64, differential: f64, price1: f64, price2: f64) -> Option<f64> {
+    let current_ratio = price1 / price2;
+    let deviation = ((current_ratio - expected_ratio) / expected_ratio).abs() * 100.0;
+    (deviation > differential).then_some(current_ratio)
+}
@@ -1,9 +1,13 @@
 use std::error::Error;
-use teloxide::{prelude::*, types::ChatId};
+use std::time::Duration;
+use teloxide::{prelude::*, types::{ChatId, InputFile, InlineKeyboardButton, InlineKeyboardMarkup}};
 use tracing::{info, error, debug};
+use crate::callback::{CallbackAction, CallbackCodec};
+use crate::models::User;
 
 pub struct NotificationService {
     bot: Bot,
+    callbacks: CallbackCodec,
 }
 
 impl NotificationService {
@@ -11,16 +15,47 @@ impl NotificationService {
         info!("Inicializando NotificationService con token: {}...", &token[..8]);
         Self {
             bot: Bot::new(token),
+            callbacks: CallbackCodec::from_env(),
         }
     }
 
-    pub async fn send_alert(&self, user_id: i64, message: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+    /// Botones de una notificación de alerta. Los chats que no pueden
+    /// gestionar la alerta (suscriptores) solo reciben el del gráfico.
+    pub fn alert_actions(&self, chat_id: i64, alert_id: i64, can_manage: bool) -> InlineKeyboardMarkup {
+        let button = |label: &str, action: CallbackAction| {
+            InlineKeyboardButton::callback(label.to_string(), self.callbacks.encode(chat_id, &action))
+        };
+
+        let mut rows = Vec::new();
+        if can_manage {
+            rows.push(vec![
+                button("😴 Posponer 1h", CallbackAction::SnoozeAlert(alert_id)),
+                button("🔁 Rearmar", CallbackAction::RearmAlert(alert_id)),
+            ]);
+            rows.push(vec![
+                button("✅ Reconocer", CallbackAction::AcknowledgeAlert(alert_id)),
+                button("🗑 Eliminar", CallbackAction::DeleteAlert(alert_id)),
+            ]);
+        }
+        rows.push(vec![button("📈 Ver gráfico", CallbackAction::AlertChart(alert_id))]);
+        InlineKeyboardMarkup::new(rows)
+    }
+
+    /// Botones para el contacto secundario de un escalado: solo puede reconocer.
+    pub fn escalation_actions(&self, chat_id: i64, alert_id: i64) -> InlineKeyboardMarkup {
+        InlineKeyboardMarkup::new([[InlineKeyboardButton::callback(
+            "✅ Reconocer",
+            self.callbacks.encode(chat_id, &CallbackAction::AcknowledgeAlert(alert_id)),
+        )]])
+    }
+
+    pub async fn send_alert(&self, user_id: i64, message: &str, actions: Option<InlineKeyboardMarkup>) -> Result<(), Box<dyn Error + Send + Sync>> {
         info!("Preparando envío de notificación");
         debug!("Usuario ID: {}", user_id);
         debug!("Mensaje: {}", message);
         
-        // Verificar que el user_id es válido
-        if user_id <= 0 {
+        // Verificar que el user_id es válido (grupos y canales usan IDs negativos)
+        if user_id == 0 {
             error!("ID de usuario inválido: {}", user_id);
             return Err("ID de usuario inválido".into());
         }
@@ -30,7 +65,11 @@ impl NotificationService {
         info!("Intentando enviar mensaje a chat_id: {}", user_id);
 
         // Intentar enviar el mensaje
-        match self.bot.send_message(chat_id, message).await {
+        let mut request = self.bot.send_message(chat_id, message);
+        if let Some(actions) = actions {
+            request = request.reply_markup(actions);
+        }
+        match request.await {
             Ok(message) => {
                 info!("Notificación enviada exitosamente");
                 debug!("Message ID: {}", message.id);
@@ -46,6 +85,37 @@ impl NotificationService {
         }
     }
 
+    pub async fn send_alert_with_chart(&self, user_id: i64, message: &str, chart_png: Vec<u8>, actions: Option<InlineKeyboardMarkup>) -> Result<(), Box<dyn Error + Send + Sync>> {
+        info!("Preparando envío de notificación con gráfico");
+        debug!("Usuario ID: {}", user_id);
+
+        if user_id == 0 {
+            error!("ID de usuario inválido: {}", user_id);
+            return Err("ID de usuario inválido".into());
+        }
+
+        let photo = InputFile::memory(chart_png).file_name("chart.png");
+        let mut request = self.bot.send_photo(ChatId(user_id), photo).caption(message);
+        if let Some(actions) = actions {
+            request = request.reply_markup(actions);
+        }
+        match request.await {
+            Ok(message) => {
+                info!("Notificación con gráfico enviada exitosamente");
+                debug!("Message ID: {}", message.id);
+                Ok(())
+            }
+            Err(e) => {
+                error!("Error al enviar notificación con gráfico a {}: {}", user_id, e);
+                Err(Box::new(e))
+            }
+        }
+    }
+
+    pub async fn broadcast(&self, chat_ids: &[i64], message: &str) -> (usize, usize) {
+        broadcast(&self.bot, chat_ids, message).await
+    }
+
     // Método para verificar el estado del bot
     pub async fn verify_bot(&self) -> Result<(), Box<dyn Error + Send + Sync>> {
         info!("Verificando estado del bot");
@@ -60,4 +130,36 @@ impl NotificationService {
             }
         }
     }
-} 
\ No newline at end of file
+}
+
+/// Pausa entre mensajes de un anuncio para no superar el límite de Telegram
+/// (unos 30 mensajes por segundo).
+const BROADCAST_DELAY: Duration = Duration::from_millis(50);
+
+/// Envía un anuncio a cada chat y devuelve cuántos se entregaron y cuántos fallaron.
+pub async fn broadcast(bot: &Bot, chat_ids: &[i64], message: &str) -> (usize, usize) {
+    info!("Enviando anuncio a {} chats", chat_ids.len());
+    let (mut sent, mut failed) = (0, 0);
+    for &chat_id in chat_ids {
+        match bot.send_message(ChatId(chat_id), message).await {
+            Ok(_) => sent += 1,
+            Err(e) => {
+                error!("No se pudo enviar el anuncio a {}: {}", chat_id, e);
+                failed += 1;
+            }
+        }
+        tokio::time::sleep(BROADCAST_DELAY).await;
+    }
+    (sent, failed)
+}
+
+/// Avisa a los administradores de una solicitud de registro pendiente.
+pub async fn notify_pending_registration(bot: &Bot, admin_chat_ids: &[i64], user: &User) {
+    let message = format!(
+        "🆕 {} pidió registrarse (#{}).\n\
+         Aprobar: /admin approve #{}\n\
+         Rechazar: /admin reject #{}",
+        user.username, user.id, user.id, user.id
+    );
+    broadcast(bot, admin_chat_ids, &message).await;
+}
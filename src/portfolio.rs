@@ -0,0 +1,426 @@
+use std::collections::HashMap;
+use serde::Serialize;
+use tracing::error;
+use crate::config::CONFIG;
+use crate::crypto_api::CryptoAPI;
+use crate::db::Database;
+use crate::models::{AlertCondition, AlertType, Holding, TradeSide, Transaction};
+
+/// Por debajo de esto una cantidad se considera cero (redondeo de f64).
+const QUANTITY_EPSILON: f64 = 1e-9;
+
+#[derive(Debug)]
+pub enum PortfolioError {
+    DatabaseError(rusqlite::Error),
+    UnsupportedSymbol(String),
+    InvalidTrade(String),
+    InvalidAlert(String),
+    InsufficientHoldings { symbol: String, held: f64, requested: f64 },
+    NotFound,
+}
+
+impl std::error::Error for PortfolioError {}
+
+impl std::fmt::Display for PortfolioError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PortfolioError::DatabaseError(e) => write!(f, "Error de base de datos: {}", e),
+            PortfolioError::UnsupportedSymbol(symbol) => write!(f, "Símbolo no soportado: {}", symbol),
+            PortfolioError::InvalidTrade(reason) => write!(f, "Operación inválida: {}", reason),
+            PortfolioError::InvalidAlert(reason) => write!(f, "Alerta inválida: {}", reason),
+            PortfolioError::InsufficientHoldings { symbol, held, requested } => write!(
+                f,
+                "No tienes suficiente {}: tienes {} y la venta es de {}",
+                symbol, held, requested
+            ),
+            PortfolioError::NotFound => write!(f, "La transacción no existe"),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for PortfolioError {
+    fn from(err: rusqlite::Error) -> Self {
+        PortfolioError::DatabaseError(err)
+    }
+}
+
+/// Recalcula la posición de un activo a costo promedio. Las transacciones
+/// deben venir en orden cronológico; vender más de lo que se tiene es error.
+pub fn replay(user_id: i64, symbol: &str, transactions: &[Transaction]) -> Result<Holding, PortfolioError> {
+    let mut holding = Holding {
+        user_id,
+        symbol: symbol.to_string(),
+        quantity: 0.0,
+        cost_basis: 0.0,
+        realized_pnl: 0.0,
+        updated_at: chrono::Utc::now().timestamp(),
+    };
+
+    for transaction in transactions {
+        match transaction.side {
+            TradeSide::Buy => {
+                holding.quantity += transaction.quantity;
+                holding.cost_basis += transaction.quantity * transaction.price + transaction.fee;
+            }
+            TradeSide::Sell => {
+                if transaction.quantity > holding.quantity + QUANTITY_EPSILON {
+                    return Err(PortfolioError::InsufficientHoldings {
+                        symbol: symbol.to_string(),
+                        held: holding.quantity,
+                        requested: transaction.quantity,
+                    });
+                }
+                let sold_cost = holding.cost_basis * transaction.quantity / holding.quantity;
+                let proceeds = transaction.quantity * transaction.price - transaction.fee;
+                holding.realized_pnl += proceeds - sold_cost;
+                holding.cost_basis -= sold_cost;
+                holding.quantity -= transaction.quantity;
+            }
+        }
+        if holding.quantity.abs() < QUANTITY_EPSILON {
+            holding.quantity = 0.0;
+            holding.cost_basis = 0.0;
+        }
+    }
+    Ok(holding)
+}
+
+fn validate(transaction: &Transaction) -> Result<(), PortfolioError> {
+    if !CONFIG.cryptocurrencies.contains_key(&transaction.symbol) {
+        return Err(PortfolioError::UnsupportedSymbol(transaction.symbol.clone()));
+    }
+    if !(transaction.quantity > 0.0 && transaction.quantity.is_finite()) {
+        return Err(PortfolioError::InvalidTrade("la cantidad debe ser mayor que cero".to_string()));
+    }
+    if !(transaction.price >= 0.0 && transaction.price.is_finite()) {
+        return Err(PortfolioError::InvalidTrade("el precio no puede ser negativo".to_string()));
+    }
+    if !(transaction.fee >= 0.0 && transaction.fee.is_finite()) {
+        return Err(PortfolioError::InvalidTrade("la comisión no puede ser negativa".to_string()));
+    }
+    Ok(())
+}
+
+pub struct Portfolio<'a> {
+    db: &'a Database,
+}
+
+impl<'a> Portfolio<'a> {
+    pub fn new(db: &'a Database) -> Self {
+        Self { db }
+    }
+
+    /// Registra una transacción nueva y devuelve su ID y la posición resultante.
+    pub fn record(&self, transaction: &Transaction) -> Result<(i64, Holding), PortfolioError> {
+        validate(transaction)?;
+        let mut transactions = self.db.get_transactions(transaction.user_id, Some(&transaction.symbol))?;
+        transactions.push(transaction.clone());
+        transactions.sort_by_key(|t| t.executed_at);
+
+        let holding = replay(transaction.user_id, &transaction.symbol, &transactions)?;
+        let id = self.db.record_transaction(transaction, &holding)?;
+        Ok((id, holding))
+    }
+
+    /// Corrige una transacción del usuario. El símbolo no se puede cambiar.
+    pub fn update(&self, user_id: i64, transaction_id: i64, transaction: &Transaction) -> Result<Holding, PortfolioError> {
+        let existing = self.owned(user_id, transaction_id)?;
+        if existing.symbol != transaction.symbol {
+            return Err(PortfolioError::InvalidTrade(
+                "no se puede cambiar el símbolo; elimina la transacción y regístrala de nuevo".to_string(),
+            ));
+        }
+        let updated = Transaction { id: Some(transaction_id), user_id, ..transaction.clone() };
+        validate(&updated)?;
+
+        let mut transactions = self.db.get_transactions(user_id, Some(&updated.symbol))?;
+        for t in transactions.iter_mut().filter(|t| t.id == Some(transaction_id)) {
+            *t = updated.clone();
+        }
+        transactions.sort_by_key(|t| t.executed_at);
+
+        let holding = replay(user_id, &updated.symbol, &transactions)?;
+        self.db.record_transaction(&updated, &holding)?;
+        Ok(holding)
+    }
+
+    /// Borra una transacción si la posición sigue siendo coherente sin ella
+    /// (p. ej. no se puede borrar una compra que respalda una venta posterior).
+    pub fn delete(&self, user_id: i64, transaction_id: i64) -> Result<Holding, PortfolioError> {
+        let existing = self.owned(user_id, transaction_id)?;
+        let transactions: Vec<Transaction> = self.db.get_transactions(user_id, Some(&existing.symbol))?
+            .into_iter()
+            .filter(|t| t.id != Some(transaction_id))
+            .collect();
+
+        let holding = replay(user_id, &existing.symbol, &transactions)?;
+        self.db.delete_transaction(transaction_id, &holding)?;
+        Ok(holding)
+    }
+
+    fn owned(&self, user_id: i64, transaction_id: i64) -> Result<Transaction, PortfolioError> {
+        match self.db.get_transaction(transaction_id)? {
+            Some(transaction) if transaction.user_id == user_id => Ok(transaction),
+            _ => Err(PortfolioError::NotFound),
+        }
+    }
+}
+
+/// Posición valorada a precio de mercado. Sin cotización, los campos de
+/// mercado quedan en `None`.
+#[derive(Debug, Clone, Serialize)]
+pub struct PositionValue {
+    pub symbol: String,
+    pub quantity: f64,
+    pub average_cost: Option<f64>,
+    pub cost_basis: f64,
+    pub price: Option<f64>,
+    pub market_value: Option<f64>,
+    pub unrealized_pnl: Option<f64>,
+    pub realized_pnl: f64,
+    /// Porcentaje del valor total
+    pub weight: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PortfolioValuation {
+    pub positions: Vec<PositionValue>,
+    /// Suma de las posiciones con cotización
+    pub total_value: f64,
+    pub total_cost: f64,
+    pub unrealized_pnl: f64,
+    pub realized_pnl: f64,
+}
+
+impl PortfolioValuation {
+    /// Hay posiciones abiertas y todas tienen cotización.
+    pub fn is_complete(&self) -> bool {
+        let mut open = self.positions.iter().filter(|p| p.quantity > 0.0).peekable();
+        open.peek().is_some() && open.all(|p| p.price.is_some())
+    }
+
+    /// Peso (%) de `symbol`; cero si no está en el portafolio.
+    pub fn weight(&self, symbol: &str) -> f64 {
+        self.positions.iter()
+            .find(|p| p.symbol.eq_ignore_ascii_case(symbol))
+            .and_then(|p| p.weight)
+            .unwrap_or(0.0)
+    }
+}
+
+/// Símbolos con cantidad abierta, los únicos que hace falta cotizar.
+pub fn open_symbols(holdings: &[Holding]) -> Vec<String> {
+    holdings.iter()
+        .filter(|h| h.quantity > 0.0)
+        .map(|h| h.symbol.clone())
+        .collect()
+}
+
+/// Valora las posiciones con una foto de precios ya obtenida.
+pub fn value_at(holdings: Vec<Holding>, prices: &HashMap<String, f64>) -> PortfolioValuation {
+    let mut positions: Vec<PositionValue> = holdings.into_iter()
+        .map(|holding| {
+            let price = if holding.quantity > 0.0 {
+                prices.get(&holding.symbol.to_uppercase()).copied()
+            } else {
+                None
+            };
+            let market_value = price.map(|price| price * holding.quantity);
+            PositionValue {
+                average_cost: holding.average_cost(),
+                unrealized_pnl: market_value.map(|value| value - holding.cost_basis),
+                symbol: holding.symbol,
+                quantity: holding.quantity,
+                cost_basis: holding.cost_basis,
+                price,
+                market_value,
+                realized_pnl: holding.realized_pnl,
+                weight: None,
+            }
+        })
+        .collect();
+
+    let priced = || positions.iter().filter(|p| p.market_value.is_some());
+    let total_value: f64 = priced().filter_map(|p| p.market_value).sum();
+    let total_cost = priced().map(|p| p.cost_basis).sum();
+    let unrealized_pnl = priced().filter_map(|p| p.unrealized_pnl).sum();
+    let realized_pnl = positions.iter().map(|p| p.realized_pnl).sum();
+
+    if total_value > 0.0 {
+        for position in &mut positions {
+            position.weight = position.market_value.map(|value| value / total_value * 100.0);
+        }
+    }
+
+    PortfolioValuation { positions, total_value, total_cost, unrealized_pnl, realized_pnl }
+}
+
+pub async fn valuation(api: &CryptoAPI, holdings: Vec<Holding>) -> PortfolioValuation {
+    let symbols = open_symbols(&holdings);
+    let prices = if symbols.is_empty() {
+        HashMap::new()
+    } else {
+        match api.get_prices(&symbols).await {
+            Ok(prices) => prices,
+            Err(e) => {
+                error!("No se pudo cotizar el portafolio: {}", e);
+                HashMap::new()
+            }
+        }
+    };
+    value_at(holdings, &prices)
+}
+
+/// Comprueba los parámetros de una alerta de portafolio antes de guardarla.
+pub fn validate_alert(alert_type: &AlertType) -> Result<(), PortfolioError> {
+    let invalid = |reason: &str| Err(PortfolioError::InvalidAlert(reason.to_string()));
+    match alert_type {
+        AlertType::PortfolioValue { threshold, .. } => {
+            if !(*threshold > 0.0 && threshold.is_finite()) {
+                return invalid("el valor objetivo debe ser mayor que cero");
+            }
+        }
+        AlertType::PortfolioDrawdown { max_drawdown, .. } => {
+            if !(*max_drawdown > 0.0 && *max_drawdown < 100.0) {
+                return invalid("la caída máxima debe estar entre 0 y 100%");
+            }
+        }
+        AlertType::AllocationDrift { symbol, target_weight, tolerance } => {
+            if !CONFIG.cryptocurrencies.contains_key(symbol) {
+                return Err(PortfolioError::UnsupportedSymbol(symbol.clone()));
+            }
+            if !(0.0..=100.0).contains(target_weight) {
+                return invalid("el peso objetivo debe estar entre 0 y 100%");
+            }
+            if !(*tolerance > 0.0 && *tolerance < 100.0) {
+                return invalid("la tolerancia debe estar entre 0 y 100 puntos");
+            }
+        }
+        _ => return invalid("no es una alerta de portafolio"),
+    }
+    Ok(())
+}
+
+/// Las alertas de caída nuevas miden desde el valor actual del portafolio.
+pub async fn seed_peak(api: &CryptoAPI, db: &Database, user_id: i64, alert_type: &mut AlertType) -> Result<(), PortfolioError> {
+    if let AlertType::PortfolioDrawdown { peak, .. } = alert_type {
+        let valuation = valuation(api, db.get_holdings(user_id)?).await;
+        if valuation.is_complete() {
+            *peak = valuation.total_value;
+        }
+    }
+    Ok(())
+}
+
+/// Evalúa una alerta de portafolio. Devuelve el valor observado (USD para
+/// `PortfolioValue`, % para las demás) si debe dispararse. En
+/// `PortfolioDrawdown` actualiza el máximo; el llamador debe persistirlo.
+/// Con una valoración incompleta no se evalúa nada.
+pub fn check_alert(alert_type: &mut AlertType, valuation: &PortfolioValuation) -> Option<f64> {
+    if !valuation.is_complete() {
+        return None;
+    }
+    let total = valuation.total_value;
+    match alert_type {
+        AlertType::PortfolioValue { threshold, condition } => {
+            let crossed = match condition {
+                AlertCondition::Above => total > *threshold,
+                AlertCondition::Below => total < *threshold,
+            };
+            crossed.then_some(total)
+        }
+        AlertType::PortfolioDrawdown { max_drawdown, peak } => {
+            if total > *peak {
+                *peak = total;
+            }
+            let drawdown = (*peak - total) / *peak * 100.0;
+            (drawdown > *max_drawdown).then_some(drawdown)
+        }
+        AlertType::AllocationDrift { symbol, target_weight, tolerance } => {
+            let weight = valuation.weight(symbol);
+            ((weight - *target_weight).abs() > *tolerance).then_some(weight)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(side: TradeSide, quantity: f64, price: f64, fee: f64, executed_at: i64) -> Transaction {
+        Transaction {
+            id: None,
+            user_id: 1,
+            symbol: "BTC".to_string(),
+            side,
+            quantity,
+            price,
+            fee,
+            executed_at,
+            note: None,
+        }
+    }
+
+    #[test]
+    fn test_replay_average_cost() {
+        let transactions = vec![
+            trade(TradeSide::Buy, 1.0, 100.0, 0.0, 1),
+            trade(TradeSide::Buy, 1.0, 200.0, 2.0, 2),
+            trade(TradeSide::Sell, 1.0, 300.0, 1.0, 3),
+        ];
+        let holding = replay(1, "BTC", &transactions).unwrap();
+        // Costo promedio 151: se venden 299 netos contra 151 de costo
+        assert_eq!(holding.quantity, 1.0);
+        assert!((holding.cost_basis - 151.0).abs() < 1e-9);
+        assert!((holding.realized_pnl - 148.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_replay_rejects_overselling() {
+        let transactions = vec![
+            trade(TradeSide::Buy, 1.0, 100.0, 0.0, 1),
+            trade(TradeSide::Sell, 1.5, 100.0, 0.0, 2),
+        ];
+        assert!(matches!(
+            replay(1, "BTC", &transactions),
+            Err(PortfolioError::InsufficientHoldings { .. })
+        ));
+    }
+
+    fn holding(symbol: &str, quantity: f64, cost_basis: f64) -> Holding {
+        Holding {
+            user_id: 1,
+            symbol: symbol.to_string(),
+            quantity,
+            cost_basis,
+            realized_pnl: 0.0,
+            updated_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_portfolio_alerts() {
+        let holdings = vec![holding("BTC", 1.0, 100.0), holding("ETH", 10.0, 100.0)];
+        let prices = HashMap::from([("BTC".to_string(), 300.0), ("ETH".to_string(), 10.0)]);
+        let valuation = value_at(holdings.clone(), &prices);
+        assert_eq!(valuation.total_value, 400.0);
+        assert_eq!(valuation.weight("btc"), 75.0);
+
+        let mut value = AlertType::PortfolioValue { threshold: 350.0, condition: AlertCondition::Above };
+        assert_eq!(check_alert(&mut value, &valuation), Some(400.0));
+
+        let mut drift = AlertType::AllocationDrift { symbol: "BTC".to_string(), target_weight: 60.0, tolerance: 10.0 };
+        assert_eq!(check_alert(&mut drift, &valuation), Some(75.0));
+
+        // El primer tick fija el máximo; una caída del 50 % supera el 20 %
+        let mut drawdown = AlertType::PortfolioDrawdown { max_drawdown: 20.0, peak: 0.0 };
+        assert_eq!(check_alert(&mut drawdown, &valuation), None);
+        let fallen = value_at(holdings.clone(), &HashMap::from([("BTC".to_string(), 100.0), ("ETH".to_string(), 10.0)]));
+        assert_eq!(check_alert(&mut drawdown, &fallen), Some(50.0));
+
+        // Sin cotización de ETH no se evalúa
+        let partial = value_at(holdings, &HashMap::from([("BTC".to_string(), 1.0)]));
+        assert_eq!(check_alert(&mut value, &partial), None);
+    }
+}
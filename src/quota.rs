@@ -0,0 +1,85 @@
+use crate::db::Database;
+use crate::models::{AlertKind, Plan, PriceAlert, UserRole};
+
+#[derive(Debug)]
+pub enum QuotaError {
+    DatabaseError(rusqlite::Error),
+    KindNotAllowed { plan: String, kind: AlertKind },
+    TooManyAlerts { plan: String, max: i64 },
+}
+
+impl std::error::Error for QuotaError {}
+
+impl std::fmt::Display for QuotaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuotaError::DatabaseError(e) => write!(f, "Error de base de datos: {}", e),
+            QuotaError::KindNotAllowed { plan, kind } => write!(
+                f,
+                "Tu plan '{}' no permite alertas de tipo {}",
+                plan,
+                kind_label(*kind)
+            ),
+            QuotaError::TooManyAlerts { plan, max } => write!(
+                f,
+                "Tu plan '{}' permite hasta {} alertas activas. Pausa o elimina alguna para crear otra.",
+                plan,
+                max
+            ),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for QuotaError {
+    fn from(err: rusqlite::Error) -> Self {
+        QuotaError::DatabaseError(err)
+    }
+}
+
+fn kind_label(kind: AlertKind) -> &'static str {
+    match kind {
+        AlertKind::Price => "precio",
+        AlertKind::Depeg => "depeg",
+        AlertKind::PairDepeg => "par",
+        AlertKind::Portfolio => "portafolio",
+    }
+}
+
+/// Plan efectivo del usuario; si el suyo ya no existe se usa el plan por defecto.
+pub fn user_plan(db: &Database, user_id: i64) -> Result<Option<Plan>, QuotaError> {
+    match db.get_user_plan(user_id)? {
+        Some(plan) => Ok(Some(plan)),
+        None => Ok(db.get_plan(Plan::DEFAULT)?),
+    }
+}
+
+/// Comprueba que `user_id` puede tener una alerta activa más de tipo `kind`.
+/// Los administradores no tienen cuota.
+pub fn check_new_alert(db: &Database, user_id: i64, kind: AlertKind) -> Result<(), QuotaError> {
+    if db.get_user(user_id)?.is_some_and(|user| user.role == UserRole::Admin) {
+        return Ok(());
+    }
+    let plan = match user_plan(db, user_id)? {
+        Some(plan) => plan,
+        None => return Ok(()),
+    };
+
+    if !plan.allows(kind) {
+        return Err(QuotaError::KindNotAllowed { plan: plan.name, kind });
+    }
+    if let Some(max) = plan.max_active_alerts {
+        if db.count_active_alerts(user_id)? >= max {
+            return Err(QuotaError::TooManyAlerts { plan: plan.name, max });
+        }
+    }
+    Ok(())
+}
+
+/// Reanudar, rearmar o posponer una alerta inactiva vuelve a contarla en la
+/// cuota de su dueño.
+pub fn check_reactivation(db: &Database, alert: &PriceAlert) -> Result<(), QuotaError> {
+    if alert.is_active {
+        return Ok(());
+    }
+    check_new_alert(db, alert.user_id, alert.kind())
+}
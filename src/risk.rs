@@ -0,0 +1,144 @@
+use crate::models::{RiskLimits, TradeSide};
+
+/// Orden automática a punto de enviarse, valorada en la moneda de cotización.
+#[derive(Debug, Clone)]
+pub struct ProposedOrder {
+    /// Activo base, p. ej. BTC
+    pub symbol: String,
+    pub side: TradeSide,
+    pub quantity: f64,
+    pub price: f64,
+}
+
+impl ProposedOrder {
+    pub fn notional(&self) -> f64 {
+        self.quantity * self.price
+    }
+}
+
+/// Órdenes automáticas ya enviadas por el usuario en el mismo modo.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TradingActivity {
+    /// Nominal enviado desde las 00:00 UTC
+    pub daily_notional: f64,
+    pub orders_last_minute: i64,
+    /// Compras menos ventas del símbolo de la orden
+    pub net_position: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RiskViolation {
+    Halted,
+    SymbolNotAllowed(String),
+    OrderNotional { notional: f64, limit: f64 },
+    DailyNotional { used: f64, notional: f64, limit: f64 },
+    Position { position: f64, limit: f64 },
+    RateLimit { orders: i64, limit: i64 },
+}
+
+impl std::error::Error for RiskViolation {}
+
+impl std::fmt::Display for RiskViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RiskViolation::Halted => write!(f, "La operativa automática está detenida por un administrador"),
+            RiskViolation::SymbolNotAllowed(symbol) => write!(f, "{} no está entre los símbolos permitidos", symbol),
+            RiskViolation::OrderNotional { notional, limit } => write!(
+                f, "La orden de ${:.2} supera el máximo por orden de ${:.2}", notional, limit
+            ),
+            RiskViolation::DailyNotional { used, notional, limit } => write!(
+                f, "Con esta orden de ${:.2} se superaría el máximo diario de ${:.2} (usado hoy: ${:.2})", notional, limit, used
+            ),
+            RiskViolation::Position { position, limit } => write!(
+                f, "La posición quedaría en ${:.2}, por encima del máximo de ${:.2}", position, limit
+            ),
+            RiskViolation::RateLimit { orders, limit } => write!(
+                f, "Ya se enviaron {} órdenes en el último minuto (máximo {})", orders, limit
+            ),
+        }
+    }
+}
+
+/// Comprueba una orden contra los límites configurados. La posición se
+/// valora al precio de la propia orden.
+pub fn check(limits: &RiskLimits, halted: bool, order: &ProposedOrder, activity: &TradingActivity) -> Result<(), RiskViolation> {
+    if halted {
+        return Err(RiskViolation::Halted);
+    }
+    if !limits.allowed_symbols.is_empty()
+        && !limits.allowed_symbols.iter().any(|symbol| symbol.eq_ignore_ascii_case(&order.symbol))
+    {
+        return Err(RiskViolation::SymbolNotAllowed(order.symbol.clone()));
+    }
+
+    let notional = order.notional();
+    if let Some(limit) = limits.max_order_notional {
+        if notional > limit {
+            return Err(RiskViolation::OrderNotional { notional, limit });
+        }
+    }
+    if let Some(limit) = limits.max_daily_notional {
+        if activity.daily_notional + notional > limit {
+            return Err(RiskViolation::DailyNotional { used: activity.daily_notional, notional, limit });
+        }
+    }
+    if let Some(limit) = limits.max_position_notional {
+        let quantity = match order.side {
+            TradeSide::Buy => activity.net_position + order.quantity,
+            TradeSide::Sell => activity.net_position - order.quantity,
+        };
+        let position = quantity.abs() * order.price;
+        // Una orden que reduce la posición siempre se permite
+        if position > limit && quantity.abs() > activity.net_position.abs() {
+            return Err(RiskViolation::Position { position, limit });
+        }
+    }
+    if let Some(limit) = limits.max_orders_per_minute {
+        if activity.orders_last_minute >= limit {
+            return Err(RiskViolation::RateLimit { orders: activity.orders_last_minute, limit });
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_risk_limits() {
+        let limits = RiskLimits {
+            max_order_notional: Some(1000.0),
+            max_daily_notional: Some(2500.0),
+            max_position_notional: Some(3000.0),
+            max_orders_per_minute: Some(2),
+            allowed_symbols: vec!["BTC".to_string(), "ETH".to_string()],
+        };
+        let order = ProposedOrder { symbol: "eth".to_string(), side: TradeSide::Buy, quantity: 0.4, price: 2000.0 };
+        let activity = TradingActivity::default();
+
+        assert_eq!(check(&limits, false, &order, &activity), Ok(()));
+        assert_eq!(check(&limits, true, &order, &activity), Err(RiskViolation::Halted));
+        assert!(matches!(
+            check(&limits, false, &ProposedOrder { symbol: "DOGE".to_string(), ..order.clone() }, &activity),
+            Err(RiskViolation::SymbolNotAllowed(_))
+        ));
+        assert!(matches!(
+            check(&limits, false, &ProposedOrder { quantity: 1.0, ..order.clone() }, &activity),
+            Err(RiskViolation::OrderNotional { .. })
+        ));
+        assert!(matches!(
+            check(&limits, false, &order, &TradingActivity { daily_notional: 2000.0, ..activity.clone() }),
+            Err(RiskViolation::DailyNotional { .. })
+        ));
+        assert!(matches!(
+            check(&limits, false, &order, &TradingActivity { orders_last_minute: 2, ..activity.clone() }),
+            Err(RiskViolation::RateLimit { .. })
+        ));
+
+        // Con 1.3 ETH comprados la compra se pasa del tope, pero vender lo reduce
+        let long = TradingActivity { net_position: 1.3, ..activity };
+        assert!(matches!(check(&limits, false, &order, &long), Err(RiskViolation::Position { .. })));
+        assert_eq!(check(&limits, false, &ProposedOrder { side: TradeSide::Sell, ..order }, &long), Ok(()));
+    }
+}
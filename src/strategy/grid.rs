@@ -0,0 +1,394 @@
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use crate::exchanges::{ExchangeError, Order, OrderRequest, OrderSide, OrderStatus, OrderType, Routing};
+use crate::models::{StrategyRecord, TradeSide};
+use crate::trading::TradeError;
+use super::{Strategy, StrategyContext};
+
+/// Máximo de tramos de una rejilla.
+pub const MAX_GRIDS: usize = 100;
+
+/// Parámetros de una rejilla: `grids` tramos iguales entre `lower` y `upper`,
+/// con `capital` en el activo de cotización repartido entre ellos.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GridConfig {
+    pub lower: f64,
+    pub upper: f64,
+    pub grids: usize,
+    pub capital: f64,
+}
+
+impl GridConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.lower > 0.0 && self.lower.is_finite()) {
+            return Err("El precio inferior debe ser mayor que cero".to_string());
+        }
+        if !(self.upper > self.lower && self.upper.is_finite()) {
+            return Err("El precio superior debe ser mayor que el inferior".to_string());
+        }
+        if !(2..=MAX_GRIDS).contains(&self.grids) {
+            return Err(format!("La rejilla debe tener entre 2 y {} tramos", MAX_GRIDS));
+        }
+        if !(self.capital > 0.0 && self.capital.is_finite()) {
+            return Err("El capital debe ser mayor que cero".to_string());
+        }
+        Ok(())
+    }
+
+    /// Precios de los `grids + 1` niveles, de menor a mayor.
+    pub fn levels(&self) -> Vec<f64> {
+        let step = (self.upper - self.lower) / self.grids as f64;
+        (0..=self.grids).map(|level| self.lower + step * level as f64).collect()
+    }
+}
+
+/// Tramo entre dos niveles consecutivos. Siempre tiene una orden: compra en
+/// el nivel inferior mientras no tiene base y venta en el superior cuando la
+/// tiene.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GridSlot {
+    pub side: TradeSide,
+    /// Orden en el exchange; sin ella se coloca en la próxima pasada
+    pub order_id: Option<String>,
+    /// Lo que costó la base que respalda la venta, comisión incluida
+    pub cost: f64,
+    /// Base de la orden si no es la de la rejilla: lo que quedó tras una
+    /// ejecución parcial
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quantity: Option<f64>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct GridState {
+    /// Base de cada orden; se fija al arrancar con el precio de ese momento
+    pub quantity: f64,
+    /// Un tramo por elemento, de abajo arriba; vacío hasta la primera pasada
+    pub slots: Vec<GridSlot>,
+    pub realized_profit: f64,
+    /// Ventas que cerraron un ciclo de compra y venta
+    pub round_trips: u32,
+}
+
+/// Rejilla de órdenes límite: compra en cada nivel por debajo del precio y,
+/// cuando una compra se ejecuta, pone la venta un nivel más arriba (y al
+/// revés). El beneficio sale de cada ciclo completo.
+pub struct GridStrategy {
+    config: GridConfig,
+    state: GridState,
+}
+
+impl GridStrategy {
+    pub fn new(config: GridConfig) -> Self {
+        Self { config, state: GridState::default() }
+    }
+
+    pub fn from_record(record: &StrategyRecord) -> Result<Self, serde_json::Error> {
+        let config = serde_json::from_value(record.config.clone())?;
+        let state = match &record.state {
+            serde_json::Value::Null => GridState::default(),
+            state => serde_json::from_value(state.clone())?,
+        };
+        Ok(Self { config, state })
+    }
+
+    pub fn grid_state(&self) -> &GridState {
+        &self.state
+    }
+
+    /// Reparte los tramos según el precio de arranque: los que están por
+    /// debajo, o contienen el precio, esperan para comprar; los de arriba
+    /// venden base que se compra al empezar.
+    fn layout(&self, price: f64) -> GridState {
+        let levels = self.config.levels();
+        GridState {
+            quantity: self.config.capital / (self.config.grids as f64 * price),
+            slots: (0..self.config.grids)
+                .map(|slot| GridSlot {
+                    side: if levels[slot] < price { TradeSide::Buy } else { TradeSide::Sell },
+                    order_id: None,
+                    cost: 0.0,
+                    quantity: None,
+                })
+                .collect(),
+            realized_profit: 0.0,
+            round_trips: 0,
+        }
+    }
+
+    /// Precio de la orden de un tramo.
+    fn price(&self, slot: usize) -> f64 {
+        let levels = self.config.levels();
+        match self.state.slots[slot].side {
+            TradeSide::Buy => levels[slot],
+            TradeSide::Sell => levels[slot + 1],
+        }
+    }
+
+    /// Base de la orden de un tramo.
+    fn quantity(&self, slot: usize) -> f64 {
+        self.state.slots[slot].quantity.unwrap_or(self.state.quantity)
+    }
+
+    /// Anota la ejecución completa de la orden de un tramo y le da la vuelta.
+    /// `notional` es lo ejecutado por su precio medio. Devuelve el beneficio
+    /// si era una venta.
+    fn filled(&mut self, slot: usize, notional: f64, fee: f64) -> Option<f64> {
+        let slot = &mut self.state.slots[slot];
+        slot.order_id = None;
+        match slot.side {
+            TradeSide::Buy => {
+                slot.side = TradeSide::Sell;
+                slot.cost = notional + fee;
+                None
+            }
+            TradeSide::Sell => {
+                let profit = notional - fee - slot.cost;
+                slot.side = TradeSide::Buy;
+                slot.cost = 0.0;
+                slot.quantity = None;
+                self.state.realized_profit += profit;
+                self.state.round_trips += 1;
+                Some(profit)
+            }
+        }
+    }
+
+    /// Anota una orden que se cerró tras ejecutar solo `filled` de base. Una
+    /// compra pasa a vender lo comprado; una venta sigue con lo que le queda.
+    /// Devuelve el beneficio de la parte vendida.
+    fn partially_filled(&mut self, slot: usize, filled: f64, notional: f64, fee: f64) -> Option<f64> {
+        let held = self.quantity(slot);
+        let slot = &mut self.state.slots[slot];
+        slot.order_id = None;
+        match slot.side {
+            TradeSide::Buy => {
+                slot.side = TradeSide::Sell;
+                slot.cost = notional + fee;
+                slot.quantity = Some(filled);
+                None
+            }
+            TradeSide::Sell => {
+                let cost = slot.cost * filled / held;
+                let profit = notional - fee - cost;
+                slot.cost -= cost;
+                slot.quantity = Some(held - filled);
+                self.state.realized_profit += profit;
+                Some(profit)
+            }
+        }
+    }
+
+    fn pair(ctx: &StrategyContext<'_>) -> String {
+        format!("{}/{}", ctx.record.symbol, ctx.record.quote_asset)
+    }
+
+    /// Primera pasada: reparte los tramos y compra a mercado la base de las ventas.
+    async fn open(&mut self, ctx: &StrategyContext<'_>, price: f64) -> Result<(), TradeError> {
+        let mut state = self.layout(price);
+        let sells = state.slots.iter().filter(|slot| slot.side == TradeSide::Sell).count();
+        if sells > 0 {
+            let request = OrderRequest {
+                symbol: Self::pair(ctx),
+                side: OrderSide::Buy,
+                order_type: OrderType::Market,
+                quantity: decimal(state.quantity * sells as f64)?,
+                price: None,
+                routing: Routing::Venue(ctx.record.exchange.clone()),
+            };
+            let report = ctx.trader.submit(ctx.record.user_id, ctx.record.mode, request, price, &ctx.source()).await?;
+            let notional = (report.average_price.unwrap_or_default() * report.filled_quantity).to_f64().unwrap_or_default();
+            let cost = (notional + report.total_fee.to_f64().unwrap_or_default()) / sells as f64;
+            for slot in state.slots.iter_mut().filter(|slot| slot.side == TradeSide::Sell) {
+                slot.cost = cost;
+            }
+        }
+        info!("Estrategia {}: rejilla de {} tramos abierta con {} {} por orden", ctx.record.id, state.slots.len(), state.quantity, ctx.record.symbol);
+        self.state = state;
+        Ok(())
+    }
+
+    async fn place(&mut self, ctx: &StrategyContext<'_>, slot: usize) -> Result<(), TradeError> {
+        let price = self.price(slot);
+        let request = OrderRequest {
+            symbol: Self::pair(ctx),
+            side: match self.state.slots[slot].side {
+                TradeSide::Buy => OrderSide::Buy,
+                TradeSide::Sell => OrderSide::Sell,
+            },
+            order_type: OrderType::Limit,
+            quantity: decimal(self.quantity(slot))?,
+            price: Some(decimal(price)?),
+            routing: Routing::Venue(ctx.record.exchange.clone()),
+        };
+        let report = ctx.trader.submit(ctx.record.user_id, ctx.record.mode, request, price, &ctx.source()).await?;
+        // Con un exchange concreto hay una sola parte
+        self.state.slots[slot].order_id = report.fills.first().map(|fill| fill.order.id.clone());
+        Ok(())
+    }
+}
+
+fn decimal(value: f64) -> Result<Decimal, ExchangeError> {
+    Decimal::from_f64(value)
+        .map(|value| value.round_dp(8))
+        .filter(|value| *value > Decimal::ZERO)
+        .ok_or_else(|| ExchangeError::InvalidOrder(format!("valor inválido para la rejilla: {}", value)))
+}
+
+/// Nominal y comisión de una orden ejecutada.
+fn fill_amounts(order: &Order) -> (f64, f64) {
+    let notional = order.average_price.unwrap_or_default() * order.filled_quantity;
+    (notional.to_f64().unwrap_or_default(), order.fee.to_f64().unwrap_or_default())
+}
+
+#[async_trait]
+impl Strategy for GridStrategy {
+    async fn tick(&mut self, ctx: &StrategyContext<'_>, price: f64) -> Result<(), TradeError> {
+        if self.state.slots.is_empty() {
+            self.open(ctx, price).await?;
+        }
+
+        let exchange = ctx.trader.exchange(ctx.record.user_id, ctx.record.mode, &ctx.record.exchange)?;
+        let pair = Self::pair(ctx);
+        for slot in 0..self.state.slots.len() {
+            let Some(order_id) = self.state.slots[slot].order_id.clone() else {
+                continue;
+            };
+            match exchange.get_order(&pair, &order_id).await {
+                Ok(order) if order.status == OrderStatus::Filled => {
+                    if !order.fee_complete {
+                        warn!("Estrategia {}: la comisión de la orden {} se pagó en otro activo y no cuenta en el beneficio", ctx.record.id, order_id);
+                    }
+                    let (notional, fee) = fill_amounts(&order);
+                    if let Some(profit) = self.filled(slot, notional, fee) {
+                        info!("Estrategia {}: ciclo cerrado en el tramo {} con {:.4} {}", ctx.record.id, slot, profit, ctx.record.quote_asset);
+                    }
+                }
+                Ok(order) if order.status.is_open() => {}
+                // Cancelada fuera del sistema, caducada o perdida al reiniciar
+                // la cuenta simulada: se vuelve a colocar
+                Ok(order) => {
+                    warn!("Estrategia {}: la orden {} terminó como {}; se repone", ctx.record.id, order_id, order.status.as_str());
+                    let filled = order.filled_quantity.to_f64().unwrap_or_default();
+                    if filled > 0.0 {
+                        let (notional, fee) = fill_amounts(&order);
+                        self.partially_filled(slot, filled, notional, fee);
+                    } else {
+                        self.state.slots[slot].order_id = None;
+                    }
+                }
+                Err(ExchangeError::OrderNotFound(_)) => {
+                    warn!("Estrategia {}: la orden {} ya no existe; se repone", ctx.record.id, order_id);
+                    self.state.slots[slot].order_id = None;
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        for slot in 0..self.state.slots.len() {
+            if self.state.slots[slot].order_id.is_none() {
+                self.place(ctx, slot).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn stop(&mut self, ctx: &StrategyContext<'_>) -> Result<(), TradeError> {
+        let exchange = ctx.trader.exchange(ctx.record.user_id, ctx.record.mode, &ctx.record.exchange)?;
+        let pair = Self::pair(ctx);
+        for slot in self.state.slots.iter_mut() {
+            let Some(order_id) = &slot.order_id else {
+                continue;
+            };
+            match exchange.cancel_order(&pair, order_id).await {
+                Ok(()) | Err(ExchangeError::OrderNotFound(_)) => slot.order_id = None,
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    fn state(&self) -> serde_json::Value {
+        serde_json::to_value(&self.state).unwrap_or_default()
+    }
+
+    fn realized_profit(&self) -> f64 {
+        self.state.realized_profit
+    }
+
+    fn base_holdings(&self) -> f64 {
+        (0..self.state.slots.len())
+            .filter(|slot| self.state.slots[*slot].side == TradeSide::Sell)
+            .map(|slot| self.quantity(slot))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> GridConfig {
+        GridConfig { lower: 90.0, upper: 110.0, grids: 4, capital: 400.0 }
+    }
+
+    #[test]
+    fn test_grid_layout_and_round_trip() {
+        let mut grid = GridStrategy::new(config());
+        assert_eq!(grid.config.levels(), vec![90.0, 95.0, 100.0, 105.0, 110.0]);
+
+        // A 100 los dos tramos de abajo compran y los de arriba venden
+        grid.state = grid.layout(100.0);
+        assert_eq!(grid.state.quantity, 1.0);
+        let sides: Vec<TradeSide> = grid.state.slots.iter().map(|slot| slot.side).collect();
+        assert_eq!(sides, vec![TradeSide::Buy, TradeSide::Buy, TradeSide::Sell, TradeSide::Sell]);
+        assert_eq!(grid.price(1), 95.0);
+        assert_eq!(grid.price(2), 105.0);
+
+        // Compra a 95 y la venta se pone un nivel más arriba
+        assert_eq!(grid.filled(1, 95.0, 0.1), None);
+        assert_eq!(grid.state.slots[1].side, TradeSide::Sell);
+        assert_eq!(grid.price(1), 100.0);
+
+        let profit = grid.filled(1, 100.0, 0.1).unwrap();
+        assert!((profit - 4.8).abs() < 1e-9);
+        assert_eq!(grid.state.slots[1].side, TradeSide::Buy);
+        assert_eq!(grid.state.round_trips, 1);
+        assert!((grid.realized_profit() - 4.8).abs() < 1e-9);
+        assert_eq!(grid.base_holdings(), 2.0);
+    }
+
+    #[test]
+    fn test_grid_partial_fill_reopens_with_filled_quantity() {
+        let mut grid = GridStrategy::new(config());
+        grid.state = grid.layout(100.0);
+
+        // Compra cancelada tras ejecutar 0.4: vende solo lo comprado
+        assert_eq!(grid.partially_filled(1, 0.4, 38.0, 0.04), None);
+        assert_eq!(grid.state.slots[1].side, TradeSide::Sell);
+        assert_eq!(grid.quantity(1), 0.4);
+        assert!((grid.base_holdings() - 2.4).abs() < 1e-9);
+
+        // Venta cancelada tras ejecutar 0.1 de 0.4: sigue con 0.3
+        let profit = grid.partially_filled(1, 0.1, 10.0, 0.01).unwrap();
+        assert!((profit - (10.0 - 0.01 - 38.04 / 4.0)).abs() < 1e-9);
+        assert!((grid.quantity(1) - 0.3).abs() < 1e-9);
+        assert_eq!(grid.state.slots[1].side, TradeSide::Sell);
+
+        // Al venderse el resto, el tramo vuelve a comprar la base completa
+        grid.filled(1, 30.0, 0.03);
+        assert_eq!(grid.state.slots[1].side, TradeSide::Buy);
+        assert_eq!(grid.quantity(1), 1.0);
+    }
+
+    #[test]
+    fn test_grid_config_validation() {
+        assert!(config().validate().is_ok());
+        assert!(GridConfig { upper: 80.0, ..config() }.validate().is_err());
+        assert!(GridConfig { grids: 1, ..config() }.validate().is_err());
+        assert!(GridConfig { grids: MAX_GRIDS + 1, ..config() }.validate().is_err());
+        assert!(GridConfig { capital: 0.0, ..config() }.validate().is_err());
+    }
+}
@@ -0,0 +1,197 @@
+pub mod grid;
+
+pub use grid::{GridConfig, GridStrategy};
+
+use std::sync::Arc;
+use async_trait::async_trait;
+use rust_decimal::Decimal;
+use rust_decimal::prelude::FromPrimitive;
+use tracing::{error, info, warn};
+use crate::crypto_api::CryptoAPI;
+use crate::db::Database;
+use crate::config::CONFIG;
+use crate::models::{StrategyKind, StrategyRecord, StrategyStatus, TradeMode};
+use crate::timer::Timer;
+use crate::trading::{TradeError, TradeExecutor};
+
+/// Estrategia que opera sola a través de `TradeExecutor`, de modo que sus
+/// órdenes pasan por los límites de riesgo y quedan en la tabla de órdenes.
+/// Todo lo que necesita para continuar tras un reinicio va en `state`.
+#[async_trait]
+pub trait Strategy: Send {
+    /// Una pasada con el activo base cotizando a `price`: revisa sus órdenes
+    /// y coloca las que falten. Si falla a medias, lo ya hecho debe quedar
+    /// reflejado en `state`.
+    async fn tick(&mut self, ctx: &StrategyContext<'_>, price: f64) -> Result<(), TradeError>;
+
+    /// Cancela las órdenes que tenga abiertas.
+    async fn stop(&mut self, ctx: &StrategyContext<'_>) -> Result<(), TradeError>;
+
+    fn state(&self) -> serde_json::Value;
+
+    fn realized_profit(&self) -> f64;
+
+    /// Activo base que la estrategia tiene comprado según `state`.
+    fn base_holdings(&self) -> f64;
+}
+
+/// Lo que una estrategia necesita en cada pasada.
+pub struct StrategyContext<'a> {
+    pub trader: &'a TradeExecutor,
+    pub record: &'a StrategyRecord,
+}
+
+impl StrategyContext<'_> {
+    /// Valor de `source` de las órdenes de la estrategia.
+    pub fn source(&self) -> String {
+        format!("strategy:{}", self.record.id)
+    }
+}
+
+/// Comprueba una rejilla nueva y prepara su registro. El motor coloca las
+/// órdenes en su próxima pasada. En papel el exchange es siempre el simulado.
+pub fn new_grid(
+    user_id: i64,
+    mode: TradeMode,
+    exchange: Option<String>,
+    symbol: &str,
+    quote_asset: &str,
+    config: GridConfig,
+) -> Result<StrategyRecord, String> {
+    config.validate()?;
+    let symbol = symbol.trim().to_uppercase();
+    if !CONFIG.cryptocurrencies.contains_key(&symbol) {
+        return Err(format!("Símbolo no soportado: {}", symbol));
+    }
+    let exchange = match (mode, exchange) {
+        (TradeMode::Paper, _) => "paper".to_string(),
+        (TradeMode::Live, Some(exchange)) if !exchange.trim().is_empty() => exchange.trim().to_lowercase(),
+        (TradeMode::Live, _) => return Err("En modo live hay que indicar el exchange".to_string()),
+        (TradeMode::DryRun, _) => return Err("Las estrategias solo funcionan en modo paper o live".to_string()),
+    };
+    let now = chrono::Utc::now().timestamp();
+    Ok(StrategyRecord {
+        id: 0,
+        user_id,
+        kind: StrategyKind::Grid,
+        mode,
+        exchange,
+        symbol,
+        quote_asset: quote_asset.trim().to_uppercase(),
+        config: serde_json::to_value(&config).map_err(|e| e.to_string())?,
+        state: serde_json::Value::Null,
+        status: StrategyStatus::Running,
+        realized_profit: 0.0,
+        last_error: None,
+        created_at: now,
+        updated_at: now,
+    })
+}
+
+/// Reconstruye una estrategia a partir de lo guardado.
+pub fn load(record: &StrategyRecord) -> Result<Box<dyn Strategy>, serde_json::Error> {
+    match record.kind {
+        StrategyKind::Grid => Ok(Box::new(GridStrategy::from_record(record)?)),
+    }
+}
+
+/// Ejecuta en segundo plano las estrategias en marcha y guarda su estado
+/// tras cada pasada.
+pub struct StrategyEngine {
+    trader: Arc<TradeExecutor>,
+    api: CryptoAPI,
+    db: Arc<Database>,
+    interval: u64,
+}
+
+impl StrategyEngine {
+    pub fn new(trader: Arc<TradeExecutor>, api: CryptoAPI, db: Arc<Database>, interval: u64) -> Self {
+        Self { trader, api, db, interval }
+    }
+
+    pub async fn start(&self) {
+        self.restore_paper_holdings();
+        info!("Ejecutando estrategias cada {} segundos", self.interval);
+        Timer::new(self.interval).start(|| self.run()).await;
+    }
+
+    /// Las cuentas simuladas viven en memoria y arrancan solo con
+    /// `PAPER_BALANCES`: a cada usuario se le devuelve la base que respaldan
+    /// las ventas de sus estrategias en papel para que sigan donde lo dejaron.
+    fn restore_paper_holdings(&self) {
+        let records = match self.db.get_active_strategies() {
+            Ok(records) => records,
+            Err(e) => {
+                error!("Error al leer las estrategias activas: {}", e);
+                return;
+            }
+        };
+        for record in records.iter().filter(|record| record.mode == TradeMode::Paper) {
+            let Ok(strategy) = load(record) else {
+                continue;
+            };
+            let Some(amount) = Decimal::from_f64(strategy.base_holdings()).filter(|amount| *amount > Decimal::ZERO) else {
+                continue;
+            };
+            self.trader.deposit_paper(record.user_id, &record.symbol, amount);
+            info!("Estrategia {}: {} {} repuestos en la cuenta simulada", record.id, amount, record.symbol);
+        }
+    }
+
+    async fn run(&self) {
+        let records = match self.db.get_active_strategies() {
+            Ok(records) => records,
+            Err(e) => {
+                error!("Error al leer las estrategias activas: {}", e);
+                return;
+            }
+        };
+        for mut record in records {
+            if let Err(e) = self.step(&mut record).await {
+                error!("Error al guardar la estrategia {}: {}", record.id, e);
+            }
+        }
+    }
+
+    async fn step(&self, record: &mut StrategyRecord) -> Result<(), rusqlite::Error> {
+        let mut strategy = match load(record) {
+            Ok(strategy) => strategy,
+            Err(e) => {
+                error!("No se pudo cargar la estrategia {}: {}", record.id, e);
+                record.status = StrategyStatus::Failed;
+                record.last_error = Some(format!("Estado ilegible: {}", e));
+                return self.db.update_strategy(record);
+            }
+        };
+
+        let snapshot = record.clone();
+        let ctx = StrategyContext { trader: &self.trader, record: &snapshot };
+        let outcome = match record.status {
+            StrategyStatus::Stopping => strategy.stop(&ctx).await,
+            _ => match self.api.get_price(&record.symbol).await {
+                Ok(price) => strategy.tick(&ctx, price.price).await,
+                Err(e) => {
+                    warn!("Estrategia {}: no se pudo obtener el precio de {}: {}", record.id, record.symbol, e);
+                    record.last_error = Some(format!("No se pudo obtener el precio de {}: {}", record.symbol, e));
+                    return self.db.update_strategy(record);
+                }
+            },
+        };
+        match outcome {
+            Ok(()) => {
+                record.last_error = None;
+                if record.status == StrategyStatus::Stopping {
+                    info!("Estrategia {} detenida", record.id);
+                    record.status = StrategyStatus::Stopped;
+                }
+            }
+            Err(e) => {
+                warn!("Estrategia {}: {}", record.id, e);
+                record.last_error = Some(e.to_string());
+            }
+        }
+        record.state = strategy.state();
+        record.realized_profit = strategy.realized_profit();
+        self.db.update_strategy(record)
+    }
+}
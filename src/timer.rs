@@ -1,4 +1,5 @@
 use std::time::Duration;
+use chrono::{DateTime, Datelike, Days, Duration as ChronoDuration, TimeZone, Timelike, Utc};
 use tokio::time;
 
 pub struct Timer {
@@ -23,4 +24,163 @@ impl Timer {
             task().await;
         }
     }
-} 
\ No newline at end of file
+} 
+/// Horario al estilo cron en UTC: `minuto hora día-del-mes mes día-de-la-semana`.
+/// Cada campo admite `*`, valores, listas (`1,15`), rangos (`1-5`) y pasos
+/// (`*/15`, `0-30/10`); el domingo es 0 o 7. También acepta `@hourly`,
+/// `@daily`, `@weekly` y `@monthly`.
+///
+/// Como en cron, si se restringen tanto el día del mes como el de la semana
+/// basta con que coincida uno de los dos.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    any_day: bool,
+    any_weekday: bool,
+}
+
+impl Schedule {
+    /// Primer instante del horario estrictamente posterior a `after`, o `None`
+    /// si no hay ninguno en los próximos años (p. ej. `0 0 30 2 *`).
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let limit = after + ChronoDuration::days(366 * 5);
+        let mut t = after.with_second(0)?.with_nanosecond(0)? + ChronoDuration::minutes(1);
+        while t <= limit {
+            if !has(self.months, t.month()) {
+                let (year, month) = if t.month() == 12 { (t.year() + 1, 1) } else { (t.year(), t.month() + 1) };
+                t = Utc.with_ymd_and_hms(year, month, 1, 0, 0, 0).single()?;
+            } else if !self.day_matches(&t) {
+                t = (t.date_naive() + Days::new(1)).and_hms_opt(0, 0, 0)?.and_utc();
+            } else if !has(self.hours, t.hour()) {
+                t = t.with_minute(0)? + ChronoDuration::hours(1);
+            } else if !has(self.minutes, t.minute()) {
+                t += ChronoDuration::minutes(1);
+            } else {
+                return Some(t);
+            }
+        }
+        None
+    }
+
+    fn day_matches(&self, t: &DateTime<Utc>) -> bool {
+        let day = has(self.days, t.day());
+        let weekday = has(self.weekdays, t.weekday().num_days_from_sunday());
+        match (self.any_day, self.any_weekday) {
+            (false, false) => day || weekday,
+            _ => day && weekday,
+        }
+    }
+}
+
+impl std::str::FromStr for Schedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expression = match s.trim() {
+            "@hourly" => "0 * * * *",
+            "@daily" => "0 0 * * *",
+            "@weekly" => "0 0 * * 0",
+            "@monthly" => "0 0 1 * *",
+            other => other,
+        };
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minutes, hours, days, months, weekdays] = fields[..] else {
+            return Err(format!("El horario debe tener 5 campos (minuto hora día mes día-semana): {}", s.trim()));
+        };
+
+        let mut weekday_bits = parse_field(weekdays, 0, 7)?;
+        // El 7 también es domingo
+        if has(weekday_bits, 7) {
+            weekday_bits |= 1;
+        }
+        Ok(Self {
+            minutes: parse_field(minutes, 0, 59)?,
+            hours: parse_field(hours, 0, 23)?,
+            days: parse_field(days, 1, 31)?,
+            months: parse_field(months, 1, 12)?,
+            weekdays: weekday_bits,
+            any_day: days == "*",
+            any_weekday: weekdays == "*",
+        })
+    }
+}
+
+fn has(bits: u64, value: u32) -> bool {
+    bits & (1 << value) != 0
+}
+
+/// Valores de un campo como máscara de bits.
+fn parse_field(field: &str, min: u32, max: u32) -> Result<u64, String> {
+    let invalid = || format!("Campo de horario inválido: {} (valores de {} a {})", field, min, max);
+    let number = |value: &str| value.parse::<u32>().ok().filter(|n| (min..=max).contains(n)).ok_or_else(invalid);
+
+    let mut bits = 0;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0).ok_or_else(invalid)?),
+            None => (part, 1),
+        };
+        let (start, end) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((start, end)) => (number(start)?, number(end)?),
+                // `5/10` va de 5 al máximo
+                None if step > 1 => (number(range)?, max),
+                None => {
+                    let value = number(range)?;
+                    (value, value)
+                }
+            },
+        };
+        if start > end {
+            return Err(invalid());
+        }
+        for value in (start..=end).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_schedule_next_after() {
+        // Lunes a las 9:00; el 2026-10-18 es domingo
+        let weekly: Schedule = "0 9 * * 1".parse().unwrap();
+        assert_eq!(weekly.next_after(at(2026, 10, 18, 12, 0)), Some(at(2026, 10, 19, 9, 0)));
+        assert_eq!(weekly.next_after(at(2026, 10, 19, 9, 0)), Some(at(2026, 10, 26, 9, 0)));
+
+        let quarter: Schedule = "*/15 * * * *".parse().unwrap();
+        assert_eq!(quarter.next_after(at(2026, 10, 18, 23, 50)), Some(at(2026, 10, 19, 0, 0)));
+
+        let monthly: Schedule = "@monthly".parse().unwrap();
+        assert_eq!(monthly.next_after(at(2026, 12, 5, 0, 0)), Some(at(2027, 1, 1, 0, 0)));
+
+        // Día 1 o cualquier domingo
+        let either: Schedule = "0 0 1 * 0".parse().unwrap();
+        assert_eq!(either.next_after(at(2026, 10, 19, 0, 0)), Some(at(2026, 10, 25, 0, 0)));
+
+        let never: Schedule = "0 0 30 2 *".parse().unwrap();
+        assert_eq!(never.next_after(at(2026, 1, 1, 0, 0)), None);
+    }
+
+    #[test]
+    fn test_schedule_rejects_invalid_fields() {
+        assert!("0 9 * *".parse::<Schedule>().is_err());
+        assert!("60 * * * *".parse::<Schedule>().is_err());
+        assert!("0 9-5 * * *".parse::<Schedule>().is_err());
+        assert!("*/0 * * * *".parse::<Schedule>().is_err());
+        assert!("0 9 * * 1-5".parse::<Schedule>().is_ok());
+    }
+}
@@ -0,0 +1,803 @@
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use rust_decimal::Decimal;
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use tokio::time;
+use tracing::{error, info, warn};
+use crate::crypto_api::PriceCache;
+use crate::db::Database;
+use crate::exchanges::{
+    Exchange, ExchangeError, ExchangeManager, ExecutionReport, Order, OrderRequest, OrderSide, OrderType,
+    PaperConfig, PaperExchange, Routing, Vault, VaultError,
+};
+use crate::models::{AlertAction, AuditEvent, DcaPlan, OrderRecord, TradeMode, TradeSide};
+use crate::notify::NotificationService;
+use crate::risk::{self, ProposedOrder, RiskViolation};
+
+/// Saldo con el que arranca la cuenta simulada si no se define `PAPER_BALANCES`.
+const DEFAULT_PAPER_BALANCE: &str = "USDT:10000";
+
+/// Segundos entre sondeos de las órdenes abiertas si no se define `ORDER_SYNC_INTERVAL`.
+const DEFAULT_ORDER_SYNC_INTERVAL: u64 = 30;
+
+/// Segundos entre pasadas del motor de estrategias si no se define `STRATEGY_INTERVAL`.
+const DEFAULT_STRATEGY_INTERVAL: u64 = 30;
+
+/// Configuración de la operativa automática.
+#[derive(Debug, Clone)]
+pub struct TradingConfig {
+    /// Saldo inicial de la cuenta simulada (`PAPER_BALANCES=USDT:10000,BTC:0.5`)
+    pub paper_balances: Vec<(String, Decimal)>,
+    /// Comisión de la cuenta simulada (`PAPER_FEE_RATE`, 0.001 = 0,1 %)
+    pub paper_fee_rate: Decimal,
+    /// Deslizamiento de la cuenta simulada en órdenes a mercado (`PAPER_SLIPPAGE`)
+    pub paper_slippage: Decimal,
+    /// Segundos entre sondeos de las órdenes abiertas (`ORDER_SYNC_INTERVAL`)
+    pub order_sync_interval: u64,
+    /// Segundos entre pasadas del motor de estrategias (`STRATEGY_INTERVAL`)
+    pub strategy_interval: u64,
+}
+
+impl TradingConfig {
+    pub fn from_env() -> Result<Self, Box<dyn Error + Send + Sync>> {
+        Self::from_vars(|name| env::var(name).ok())
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Result<Self, Box<dyn Error + Send + Sync>> {
+        let balances = var("PAPER_BALANCES").unwrap_or_else(|| DEFAULT_PAPER_BALANCE.to_string());
+        let paper_balances = balances.split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (asset, amount) = entry.split_once(':')
+                    .ok_or_else(|| format!("PAPER_BALANCES: se esperaba ACTIVO:cantidad y llegó {}", entry))?;
+                Ok((asset.trim().to_uppercase(), Decimal::from_str(amount.trim())?))
+            })
+            .collect::<Result<Vec<_>, Box<dyn Error + Send + Sync>>>()?;
+
+        // Tasas entre 0 y 1; sin definir, las de `PaperConfig::default()`
+        let defaults = PaperConfig::default();
+        let rate = |name: &str, default: Decimal| -> Result<Decimal, Box<dyn Error + Send + Sync>> {
+            let Some(value) = var(name) else {
+                return Ok(default);
+            };
+            let rate = Decimal::from_str(value.trim())?;
+            if rate < Decimal::ZERO || rate >= Decimal::ONE {
+                return Err(format!("{}: se esperaba una tasa entre 0 y 1 y llegó {}", name, value).into());
+            }
+            Ok(rate)
+        };
+        let paper_fee_rate = rate("PAPER_FEE_RATE", defaults.fee_rate)?;
+        let paper_slippage = rate("PAPER_SLIPPAGE", defaults.slippage)?;
+
+        let order_sync_interval = match var("ORDER_SYNC_INTERVAL") {
+            Some(secs) => secs.parse()?,
+            None => DEFAULT_ORDER_SYNC_INTERVAL,
+        };
+
+        let strategy_interval = match var("STRATEGY_INTERVAL") {
+            Some(secs) => secs.parse()?,
+            None => DEFAULT_STRATEGY_INTERVAL,
+        };
+
+        Ok(Self { paper_balances, paper_fee_rate, paper_slippage, order_sync_interval, strategy_interval })
+    }
+
+    /// Parámetros del exchange simulado.
+    pub fn paper_config(&self) -> PaperConfig {
+        PaperConfig {
+            fee_rate: self.paper_fee_rate,
+            slippage: self.paper_slippage,
+            ..PaperConfig::default()
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum TradeError {
+    Risk(RiskViolation),
+    Exchange(ExchangeError),
+    Vault(VaultError),
+    /// El usuario no conectó ningún exchange real
+    NoLiveExchanges,
+    Database(rusqlite::Error),
+}
+
+impl std::error::Error for TradeError {}
+
+impl std::fmt::Display for TradeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TradeError::Risk(violation) => write!(f, "Orden bloqueada por los límites de riesgo: {}", violation),
+            TradeError::Exchange(e) => write!(f, "{}", e),
+            TradeError::Vault(e) => write!(f, "{}", e),
+            TradeError::NoLiveExchanges => write!(f, "No tienes credenciales de ningún exchange para operar en real"),
+            TradeError::Database(e) => write!(f, "Error de base de datos: {}", e),
+        }
+    }
+}
+
+impl From<RiskViolation> for TradeError {
+    fn from(violation: RiskViolation) -> Self {
+        TradeError::Risk(violation)
+    }
+}
+
+impl From<ExchangeError> for TradeError {
+    fn from(err: ExchangeError) -> Self {
+        TradeError::Exchange(err)
+    }
+}
+
+impl From<VaultError> for TradeError {
+    fn from(err: VaultError) -> Self {
+        TradeError::Vault(err)
+    }
+}
+
+impl From<rusqlite::Error> for TradeError {
+    fn from(err: rusqlite::Error) -> Self {
+        TradeError::Database(err)
+    }
+}
+
+/// Envía las órdenes de las acciones automáticas de las alertas. El modo
+/// `paper` usa una cuenta simulada por usuario alimentada con los precios del
+/// monitor; el modo `live`, los exchanges que el usuario conectó en el
+/// almacén de credenciales.
+///
+/// Toda orden pasa antes por los límites de riesgo y el interruptor general
+/// guardados en la base de datos.
+pub struct TradeExecutor {
+    db: Arc<Database>,
+    vault: Option<Arc<Vault>>,
+    prices: PriceCache,
+    paper_config: PaperConfig,
+    paper_balances: Vec<(String, Decimal)>,
+    /// Cuenta simulada de cada usuario, abierta con `PAPER_BALANCES` la
+    /// primera vez que opera en papel
+    paper: Mutex<HashMap<i64, Arc<PaperExchange>>>,
+}
+
+impl TradeExecutor {
+    pub fn new(config: &TradingConfig, prices: PriceCache, db: Arc<Database>, vault: Option<Arc<Vault>>) -> Self {
+        if vault.is_none() {
+            info!("Sin VAULT_MASTER_KEY: solo se ejecutarán acciones en modo paper");
+        }
+        // La cuenta simulada vive en memoria: lo que quedó abierto se perdió
+        match db.expire_open_orders(TradeMode::Paper) {
+            Ok(0) => {}
+            Ok(expired) => info!("{} órdenes simuladas abiertas se dan por caducadas tras el reinicio", expired),
+            Err(e) => error!("No se pudieron cerrar las órdenes simuladas anteriores: {}", e),
+        }
+
+        Self {
+            db,
+            vault,
+            prices,
+            paper_config: config.paper_config(),
+            paper_balances: config.paper_balances.clone(),
+            paper: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn paper_account(&self, user_id: i64) -> Arc<PaperExchange> {
+        self.paper.lock().unwrap()
+            .entry(user_id)
+            .or_insert_with(|| {
+                let account = PaperExchange::new(self.prices.clone(), self.paper_config.clone());
+                for (asset, amount) in &self.paper_balances {
+                    account.deposit(asset, *amount);
+                }
+                Arc::new(account)
+            })
+            .clone()
+    }
+
+    fn paper_exchanges(&self, user_id: i64) -> ExchangeManager {
+        let mut manager = ExchangeManager::new();
+        manager.add_exchange(self.paper_account(user_id));
+        manager
+    }
+
+    /// Acredita saldo en la cuenta simulada de `user_id`.
+    pub fn deposit_paper(&self, user_id: i64, asset: &str, amount: Decimal) {
+        self.paper_account(user_id).deposit(asset, amount);
+    }
+
+    /// Orden que corresponde a la acción cuando la alerta salta a `trigger_price`.
+    pub fn order_request(action: &AlertAction, symbol: &str, trigger_price: f64) -> Result<OrderRequest, ExchangeError> {
+        let quantity = Decimal::from_f64(action.quantity)
+            .filter(|quantity| *quantity > Decimal::ZERO)
+            .ok_or_else(|| ExchangeError::InvalidOrder(format!("cantidad inválida: {}", action.quantity)))?;
+        let price = match action.limit_price(trigger_price) {
+            Some(limit) => Some(
+                Decimal::from_f64(limit)
+                    .filter(|price| *price > Decimal::ZERO)
+                    .ok_or_else(|| ExchangeError::InvalidOrder(format!("precio límite inválido: {}", limit)))?
+                    .round_dp(8),
+            ),
+            None => None,
+        };
+        Ok(OrderRequest {
+            symbol: format!("{}/{}", symbol.to_uppercase(), action.quote_asset.to_uppercase()),
+            side: match action.side {
+                TradeSide::Buy => OrderSide::Buy,
+                TradeSide::Sell => OrderSide::Sell,
+            },
+            order_type: if price.is_some() { OrderType::Limit } else { OrderType::Market },
+            quantity,
+            price,
+            routing: routing(action.mode, action.exchange.as_deref()),
+        })
+    }
+
+    pub async fn execute(&self, user_id: i64, action: &AlertAction, symbol: &str, trigger_price: f64) -> Result<ExecutionReport, TradeError> {
+        let request = Self::order_request(action, symbol, trigger_price)?;
+        info!("Acción de la alerta {}: {:?} {} {} ({})", action.alert_id, request.side, request.quantity, request.symbol, action.mode.as_str());
+        let source = format!("alert:{}", action.alert_id);
+        self.submit(user_id, action.mode, request, trigger_price, &source).await
+    }
+
+    /// Compra a mercado `plan.amount` en el activo de cotización, valorado a
+    /// `price`, para un turno de un plan DCA.
+    pub async fn execute_dca(&self, plan: &DcaPlan, price: f64) -> Result<ExecutionReport, TradeError> {
+        let quantity = Decimal::from_f64(plan.amount / price)
+            .map(|quantity| quantity.round_dp(8))
+            .filter(|quantity| *quantity > Decimal::ZERO)
+            .ok_or_else(|| ExchangeError::InvalidOrder(format!("importe inválido: {} a ${}", plan.amount, price)))?;
+        let request = OrderRequest {
+            symbol: format!("{}/{}", plan.symbol.to_uppercase(), plan.quote_asset.to_uppercase()),
+            side: OrderSide::Buy,
+            order_type: OrderType::Market,
+            quantity,
+            price: None,
+            routing: routing(plan.mode, plan.exchange.as_deref()),
+        };
+        info!("Plan DCA {}: compra de {} {} ({})", plan.id, request.quantity, request.symbol, plan.mode.as_str());
+        let source = format!("dca:{}", plan.id);
+        self.submit(plan.user_id, plan.mode, request, price, &source).await
+    }
+
+    /// Exchanges reales que el usuario conectó.
+    fn live_exchanges(&self, user_id: i64) -> Result<ExchangeManager, TradeError> {
+        let vault = self.vault.as_ref().ok_or(TradeError::NoLiveExchanges)?;
+        let manager = vault.exchanges_for(&self.db, user_id)?;
+        if manager.exchanges().is_empty() {
+            return Err(TradeError::NoLiveExchanges);
+        }
+        Ok(manager)
+    }
+
+    /// Exchange `name` de la cuenta de `user_id` en ese modo, para seguir o
+    /// cancelar órdenes ya enviadas.
+    pub fn exchange(&self, user_id: i64, mode: TradeMode, name: &str) -> Result<Arc<dyn Exchange>, TradeError> {
+        let exchange = match mode {
+            TradeMode::Live => self.live_exchanges(user_id)?.exchange(name),
+            TradeMode::Paper => self.paper_exchanges(user_id).exchange(name),
+            TradeMode::DryRun => None,
+        };
+        exchange.ok_or_else(|| ExchangeError::ExchangeNotFound(name.to_string()).into())
+    }
+
+    /// Envía una orden en nombre de `user_id` tras comprobar los límites de
+    /// riesgo. `reference_price` valora las órdenes a mercado y `source`
+    /// queda guardado con cada orden colocada.
+    pub async fn submit(&self, user_id: i64, mode: TradeMode, request: OrderRequest, reference_price: f64, source: &str) -> Result<ExecutionReport, TradeError> {
+        let manager = match mode {
+            TradeMode::Live => self.live_exchanges(user_id)?,
+            TradeMode::Paper => self.paper_exchanges(user_id),
+            TradeMode::DryRun => return Err(ExchangeError::InvalidOrder("en modo simulación no se envían órdenes".into()).into()),
+        };
+
+        let (base, _) = request.assets()?;
+        let side = trade_side(request.side);
+        let order = ProposedOrder {
+            symbol: base,
+            side,
+            quantity: request.quantity.to_f64().unwrap_or_default(),
+            price: request.price.and_then(|price| price.to_f64()).unwrap_or(reference_price),
+        };
+        let reservation = self.reserve_risk(user_id, mode, &order)?;
+
+        let report = match manager.execute_order(request).await {
+            Ok(report) => report,
+            Err(e) => {
+                self.db.settle_trade_reservation(reservation, &[])?;
+                return Err(e.into());
+            }
+        };
+        // Se anota lo colocado, no solo lo ejecutado: una límite abierta
+        // cuenta para los límites desde que se envía y, al cerrarse, se
+        // queda en lo ejecutado
+        let mut placed = Vec::new();
+        for fill in &report.fills {
+            let record = order_record(user_id, mode, &fill.exchange, &report.symbol, source, &fill.order);
+            let id = self.db.save_order(&record)?;
+            let quantity = if record.is_open() { record.quantity } else { record.filled_quantity };
+            placed.push((id, quantity, quantity * order.price));
+        }
+        self.db.settle_trade_reservation(reservation, &placed)?;
+        Ok(report)
+    }
+
+    /// Comprueba los límites de riesgo y aparta la orden en el registro en
+    /// un solo paso. Devuelve el id de la reserva.
+    fn reserve_risk(&self, user_id: i64, mode: TradeMode, order: &ProposedOrder) -> Result<i64, TradeError> {
+        let limits = self.db.get_risk_limits()?;
+        let halted = self.db.is_trading_halted()?;
+        let checked = self.db.reserve_trade(user_id, mode, order, |activity| {
+            risk::check(&limits, halted, order, activity).map_err(TradeError::from)
+        });
+        if let Err(TradeError::Risk(violation)) = &checked {
+            warn!("Orden de {} rechazada por riesgo: {:?} {} {} ({})", user_id, order.side, order.quantity, order.symbol, violation);
+            let event = AuditEvent {
+                id: None,
+                user_id: Some(user_id),
+                chat_id: None,
+                action: "trading.risk.reject".to_string(),
+                target: Some(format!("{} {:?} {} {}: {}", mode.as_str(), order.side, order.quantity, order.symbol, violation)),
+                created_at: chrono::Utc::now().timestamp(),
+            };
+            self.db.record_audit_event(&event)?;
+        }
+        checked
+    }
+
+    /// Consulta en los exchanges las órdenes que seguían abiertas y guarda su
+    /// nuevo estado. En real, además, busca órdenes abiertas que el sistema no
+    /// envió en los pares en los que el usuario ha operado. Devuelve los
+    /// cambios que hay que notificar.
+    pub async fn reconcile(&self) -> Result<Vec<OrderEvent>, TradeError> {
+        let mut events = Vec::new();
+
+        let open = self.db.get_open_orders()?;
+        for account in open.chunk_by(|a, b| a.user_id == b.user_id && a.mode == b.mode) {
+            let (user_id, mode) = (account[0].user_id, account[0].mode);
+            let manager = match mode {
+                TradeMode::Paper => self.paper_exchanges(user_id),
+                TradeMode::Live => match self.live_exchanges(user_id) {
+                    Ok(manager) => manager,
+                    Err(e) => {
+                        warn!("No se pueden sincronizar las órdenes de {}: {}", user_id, e);
+                        continue;
+                    }
+                },
+                TradeMode::DryRun => continue,
+            };
+
+            for record in account {
+                // Sin el exchange (p. ej. borró las credenciales) no hay a quién preguntar
+                let Some(exchange) = manager.exchange(&record.exchange) else {
+                    continue;
+                };
+                let updated = match exchange.get_order(&record.symbol, &record.exchange_order_id).await {
+                    Ok(order) => order_record(user_id, mode, &record.exchange, &record.symbol, &record.source, &order),
+                    Err(ExchangeError::OrderNotFound(_)) => OrderRecord {
+                        status: "expired".to_string(),
+                        updated_at: chrono::Utc::now().timestamp(),
+                        ..record.clone()
+                    },
+                    Err(e) => {
+                        warn!("No se pudo consultar la orden {} en {}: {}", record.exchange_order_id, record.exchange, e);
+                        continue;
+                    }
+                };
+                if updated.status == record.status && updated.filled_quantity == record.filled_quantity {
+                    continue;
+                }
+                let id = self.db.save_order(&updated)?;
+                if !updated.is_open() {
+                    self.db.settle_trade(id, updated.filled_quantity)?;
+                }
+                info!("Orden {} en {}: {} -> {}", record.exchange_order_id, record.exchange, record.status, updated.status);
+                events.extend(OrderEvent::between(record, OrderRecord { id, ..updated }));
+            }
+        }
+
+        // En papel solo opera el sistema
+        let markets = self.db.get_traded_markets(TradeMode::Live)?;
+        for account in markets.chunk_by(|a, b| a.0 == b.0) {
+            let user_id = account[0].0;
+            let manager = match self.live_exchanges(user_id) {
+                Ok(manager) => manager,
+                Err(e) => {
+                    warn!("No se pueden revisar las órdenes abiertas de {}: {}", user_id, e);
+                    continue;
+                }
+            };
+            for (_, exchange_name, symbol) in account {
+                let Some(exchange) = manager.exchange(exchange_name) else {
+                    continue;
+                };
+                let orders = match exchange.get_open_orders(symbol).await {
+                    Ok(orders) => orders,
+                    Err(e) => {
+                        warn!("No se pudieron listar las órdenes abiertas de {} en {}: {}", symbol, exchange_name, e);
+                        continue;
+                    }
+                };
+                for order in orders {
+                    if self.db.order_exists(user_id, TradeMode::Live, exchange_name, &order.id)? {
+                        continue;
+                    }
+                    let mut record = order_record(user_id, TradeMode::Live, exchange_name, symbol, OrderRecord::EXTERNAL, &order);
+                    record.id = self.db.save_order(&record)?;
+                    info!("Orden {} de {} en {} enviada fuera del sistema", order.id, user_id, exchange_name);
+                    events.push(OrderEvent::Detected(record));
+                }
+            }
+        }
+
+        Ok(events)
+    }
+}
+
+/// En papel solo hay un exchange, así que el elegido no aplica.
+fn routing(mode: TradeMode, exchange: Option<&str>) -> Routing {
+    match (exchange, mode) {
+        (Some(exchange), TradeMode::Live) => Routing::Venue(exchange.to_string()),
+        _ => Routing::BestPrice,
+    }
+}
+
+fn trade_side(side: OrderSide) -> TradeSide {
+    match side {
+        OrderSide::Buy => TradeSide::Buy,
+        OrderSide::Sell => TradeSide::Sell,
+    }
+}
+
+/// Copia de `order` para guardar. `symbol` es el par tal como se le pide al
+/// exchange, no como él lo devuelve.
+fn order_record(user_id: i64, mode: TradeMode, exchange: &str, symbol: &str, source: &str, order: &Order) -> OrderRecord {
+    OrderRecord {
+        id: 0,
+        user_id,
+        mode,
+        exchange: exchange.to_string(),
+        exchange_order_id: order.id.clone(),
+        symbol: symbol.to_string(),
+        side: trade_side(order.side),
+        order_type: order.order_type.as_str().to_string(),
+        price: order.price.and_then(|price| price.to_f64()),
+        quantity: order.quantity.to_f64().unwrap_or_default(),
+        filled_quantity: order.filled_quantity.to_f64().unwrap_or_default(),
+        average_price: order.average_price.and_then(|price| price.to_f64()),
+        fee: order.fee.to_f64().unwrap_or_default(),
+        status: order.status.as_str().to_string(),
+        source: source.to_string(),
+        created_at: order.created_at.timestamp(),
+        updated_at: order.updated_at.timestamp(),
+    }
+}
+
+/// Cambio en una orden que se avisa a su dueño.
+#[derive(Debug, Clone)]
+pub enum OrderEvent {
+    /// Se ejecutó `quantity` más desde el último sondeo
+    Filled { order: OrderRecord, quantity: f64 },
+    /// Se cerró sin completarse: cancelada, caducada o rechazada
+    Closed(OrderRecord),
+    /// Orden abierta en el exchange que no envió el sistema
+    Detected(OrderRecord),
+}
+
+impl OrderEvent {
+    /// Eventos entre dos sondeos de la misma orden.
+    pub fn between(previous: &OrderRecord, current: OrderRecord) -> Vec<OrderEvent> {
+        let mut events = Vec::new();
+        let filled = current.filled_quantity - previous.filled_quantity;
+        let closed = !current.is_open() && current.status != "filled";
+        if filled > 0.0 {
+            events.push(OrderEvent::Filled { order: current.clone(), quantity: filled });
+        }
+        if closed {
+            events.push(OrderEvent::Closed(current));
+        }
+        events
+    }
+
+    pub fn order(&self) -> &OrderRecord {
+        match self {
+            OrderEvent::Filled { order, .. } | OrderEvent::Closed(order) | OrderEvent::Detected(order) => order,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        let order = self.order();
+        let side = match order.side {
+            TradeSide::Buy => "compra",
+            TradeSide::Sell => "venta",
+        };
+        let header = format!("orden {} de {} de {} {} en {} ({})", order.exchange_order_id, side, order.quantity, order.symbol, order.exchange, order.mode.as_str());
+        match self {
+            OrderEvent::Filled { quantity, .. } => {
+                let progress = if order.is_open() {
+                    format!("ejecutado {} de {}", order.filled_quantity, order.quantity)
+                } else {
+                    "completada".to_string()
+                };
+                format!(
+                    "✅ Ejecución en la {}: {} a ${:.4} de media, {}",
+                    header, quantity, order.average_price.unwrap_or_default(), progress
+                )
+            }
+            OrderEvent::Closed(_) => {
+                let reason = match order.status.as_str() {
+                    "canceled" => "se canceló fuera del sistema",
+                    "rejected" => "fue rechazada por el exchange",
+                    _ => "caducó",
+                };
+                format!("❌ La {} {} (ejecutado {})", header, reason, order.filled_quantity)
+            }
+            OrderEvent::Detected(_) => {
+                let price = order.price.map(|price| format!(" a ${}", price)).unwrap_or_default();
+                format!("👀 Nueva {}{} que no envió el sistema", header, price)
+            }
+        }
+    }
+}
+
+/// Mantiene al día la tabla de órdenes en segundo plano y avisa por Telegram
+/// de las ejecuciones y de los cambios hechos fuera del sistema.
+pub struct OrderReconciler {
+    trader: Arc<TradeExecutor>,
+    notification_service: NotificationService,
+    interval: u64,
+}
+
+impl OrderReconciler {
+    pub fn new(trader: Arc<TradeExecutor>, notification_service: NotificationService, interval: u64) -> Self {
+        Self { trader, notification_service, interval }
+    }
+
+    pub async fn start(&self) {
+        info!("Sincronizando órdenes cada {} segundos", self.interval);
+        let mut interval = time::interval(Duration::from_secs(self.interval));
+        loop {
+            interval.tick().await;
+            match self.trader.reconcile().await {
+                Ok(events) => {
+                    for event in &events {
+                        self.notify(event).await;
+                    }
+                }
+                Err(e) => error!("Error al sincronizar órdenes: {}", e),
+            }
+        }
+    }
+
+    async fn notify(&self, event: &OrderEvent) {
+        let user_id = event.order().user_id;
+        match self.trader.db.get_user_telegram_chat_id(user_id) {
+            Ok(Some(chat_id)) => {
+                if let Err(e) = self.notification_service.send_alert(chat_id, &event.message(), None).await {
+                    error!("No se pudo avisar a {} del cambio en una orden: {}", user_id, e);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => error!("Error al buscar el chat de {}: {}", user_id, e),
+        }
+    }
+}
+
+/// Resumen legible de una ejecución para la notificación.
+pub fn summarize(report: &ExecutionReport) -> String {
+    let mut lines = Vec::new();
+    if report.filled_quantity > Decimal::ZERO {
+        let verb = match report.side {
+            OrderSide::Buy => "Comprados",
+            OrderSide::Sell => "Vendidos",
+        };
+        let venues: Vec<&str> = report.fills.iter()
+            .filter(|fill| fill.order.filled_quantity > Decimal::ZERO)
+            .map(|fill| fill.exchange.as_str())
+            .collect();
+        lines.push(format!(
+            "{} {} {} a ${} de media en {} (comisión {}{})",
+            verb,
+            report.filled_quantity.normalize(),
+            report.symbol,
+            report.average_price.unwrap_or_default().round_dp(4),
+            venues.join(", "),
+            report.total_fee.round_dp(4).normalize(),
+            if report.fills.iter().all(|fill| fill.order.fee_complete) { "" } else { ", sin la pagada en otros activos" },
+        ));
+    }
+    for fill in report.fills.iter().filter(|fill| fill.order.status.is_open()) {
+        lines.push(format!(
+            "Orden {} abierta en {}: {} a ${}",
+            fill.order.id,
+            fill.exchange,
+            (fill.order.quantity - fill.order.filled_quantity).normalize(),
+            fill.order.price.unwrap_or_default().normalize(),
+        ));
+    }
+    for failure in &report.failures {
+        lines.push(format!("{} rechazó {}: {}", failure.exchange, failure.quantity.normalize(), failure.error));
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::TempDatabase;
+    use crate::models::RiskLimits;
+
+    #[test]
+    fn test_order_request_from_action() {
+        let mut action = AlertAction {
+            alert_id: 1,
+            side: TradeSide::Buy,
+            quantity: 0.5,
+            quote_asset: "usdt".to_string(),
+            limit_offset: Some(-1.0),
+            mode: TradeMode::Paper,
+            exchange: Some("binance".to_string()),
+            created_at: 0,
+        };
+
+        let request = TradeExecutor::order_request(&action, "eth", 2000.0).unwrap();
+        assert_eq!(request.symbol, "ETH/USDT");
+        assert_eq!(request.order_type, OrderType::Limit);
+        assert_eq!(request.price, Some(Decimal::new(1980, 0)));
+        assert_eq!(request.quantity, Decimal::new(5, 1));
+        assert_eq!(request.routing, Routing::BestPrice);
+
+        action.mode = TradeMode::Live;
+        action.limit_offset = None;
+        let request = TradeExecutor::order_request(&action, "ETH", 2000.0).unwrap();
+        assert_eq!(request.order_type, OrderType::Market);
+        assert_eq!(request.price, None);
+        assert_eq!(request.routing, Routing::Venue("binance".to_string()));
+
+        action.quantity = 0.0;
+        assert!(TradeExecutor::order_request(&action, "ETH", 2000.0).is_err());
+    }
+
+    #[test]
+    fn test_order_events_between_polls() {
+        let previous = OrderRecord {
+            id: 1,
+            user_id: 1,
+            mode: TradeMode::Live,
+            exchange: "binance".to_string(),
+            exchange_order_id: "42".to_string(),
+            symbol: "ETH/USDT".to_string(),
+            side: TradeSide::Buy,
+            order_type: "limit".to_string(),
+            price: Some(2000.0),
+            quantity: 1.0,
+            filled_quantity: 0.0,
+            average_price: None,
+            fee: 0.0,
+            status: "new".to_string(),
+            source: "alert:7".to_string(),
+            created_at: 0,
+            updated_at: 0,
+        };
+
+        let partial = OrderRecord { filled_quantity: 0.4, status: "partially_filled".to_string(), ..previous.clone() };
+        let events = OrderEvent::between(&previous, partial.clone());
+        assert!(matches!(events.as_slice(), [OrderEvent::Filled { quantity, .. }] if (*quantity - 0.4).abs() < 1e-9));
+
+        let canceled = OrderRecord { status: "canceled".to_string(), ..partial.clone() };
+        let events = OrderEvent::between(&partial, canceled);
+        assert!(matches!(events.as_slice(), [OrderEvent::Closed(_)]));
+
+        let filled = OrderRecord { filled_quantity: 1.0, status: "filled".to_string(), ..partial.clone() };
+        let events = OrderEvent::between(&partial, filled);
+        assert!(matches!(events.as_slice(), [OrderEvent::Filled { .. }]));
+        assert!(events[0].message().contains("completada"));
+    }
+
+    #[tokio::test]
+    async fn test_risk_ledger_releases_unfilled_orders() {
+        let db = TempDatabase::new("trading-test");
+        let user_id = db.create_user("trader", "hash", false, None).unwrap();
+        db.set_risk_limits(&RiskLimits { max_daily_notional: Some(1500.0), ..RiskLimits::default() }).unwrap();
+
+        let prices = PriceCache::default();
+        prices.set(crate::models::CryptoPrice {
+            symbol: "BTC".to_string(),
+            price: 1000.0,
+            exchange: "binance".to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+        });
+        let config = TradingConfig::from_vars(|_| None).unwrap();
+        let executor = TradeExecutor::new(&config, prices, db.shared(), None);
+        let limit = OrderRequest {
+            symbol: "BTC/USDT".to_string(),
+            side: OrderSide::Buy,
+            order_type: OrderType::Limit,
+            quantity: Decimal::ONE,
+            price: Some(Decimal::new(900, 0)),
+            routing: Routing::BestPrice,
+        };
+
+        // La límite abierta ocupa el máximo diario hasta que se cierra
+        let report = executor.submit(user_id, TradeMode::Paper, limit.clone(), 1000.0, "test").await.unwrap();
+        assert_eq!(db.get_trading_activity(user_id, TradeMode::Paper, "BTC").unwrap().daily_notional, 900.0);
+        let rejected = executor.submit(user_id, TradeMode::Paper, limit.clone(), 1000.0, "test").await;
+        assert!(matches!(rejected, Err(TradeError::Risk(RiskViolation::DailyNotional { .. }))));
+
+        let fill = &report.fills[0];
+        let exchange = executor.exchange(user_id, TradeMode::Paper, &fill.exchange).unwrap();
+        exchange.cancel_order(&report.symbol, &fill.order.id).await.unwrap();
+        executor.reconcile().await.unwrap();
+
+        let activity = db.get_trading_activity(user_id, TradeMode::Paper, "BTC").unwrap();
+        assert_eq!((activity.daily_notional, activity.net_position), (0.0, 0.0));
+        assert!(executor.submit(user_id, TradeMode::Paper, limit, 1000.0, "test").await.is_ok());
+
+        // Al reiniciar, la límite que quedó abierta caduca y deja de contar
+        let _restarted = TradeExecutor::new(&config, PriceCache::default(), db.shared(), None);
+        assert_eq!(db.get_trading_activity(user_id, TradeMode::Paper, "BTC").unwrap().daily_notional, 0.0);
+    }
+
+    #[tokio::test]
+    async fn test_paper_accounts_are_per_user() {
+        let db = TempDatabase::new("paper-accounts-test");
+        let first_user = db.create_user("first", "hash", false, None).unwrap();
+        let second_user = db.create_user("second", "hash", false, None).unwrap();
+        let prices = PriceCache::default();
+        prices.set(crate::models::CryptoPrice {
+            symbol: "BTC".to_string(),
+            price: 1000.0,
+            exchange: "binance".to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+        });
+        let config = TradingConfig::from_vars(|_| None).unwrap();
+        let executor = TradeExecutor::new(&config, prices, db.shared(), None);
+        let buy = OrderRequest {
+            symbol: "BTC/USDT".to_string(),
+            side: OrderSide::Buy,
+            order_type: OrderType::Market,
+            quantity: Decimal::new(9, 0),
+            price: None,
+            routing: Routing::BestPrice,
+        };
+
+        // Lo que gasta un usuario no sale de la cuenta de otro
+        executor.submit(first_user, TradeMode::Paper, buy.clone(), 1000.0, "test").await.unwrap();
+        executor.deposit_paper(first_user, "BTC", Decimal::ONE);
+        let first = executor.exchange(first_user, TradeMode::Paper, "paper").unwrap();
+        let second = executor.exchange(second_user, TradeMode::Paper, "paper").unwrap();
+        assert_eq!(first.get_balance("BTC").await.unwrap().free, Decimal::new(10, 0));
+        assert_eq!(second.get_balance("USDT").await.unwrap().free, Decimal::new(10_000, 0));
+        assert_eq!(second.get_balance("BTC").await.unwrap().free, Decimal::ZERO);
+        assert!(executor.submit(second_user, TradeMode::Paper, buy, 1000.0, "test").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_paper_fee_and_slippage_from_env() {
+        let vars = std::collections::HashMap::from([("PAPER_FEE_RATE", "0.002"), ("PAPER_SLIPPAGE", "0.01")]);
+        let config = TradingConfig::from_vars(|name| vars.get(name).map(|value| value.to_string())).unwrap();
+        assert_eq!(config.paper_fee_rate, Decimal::new(2, 3));
+        assert_eq!(config.paper_slippage, Decimal::new(1, 2));
+
+        let prices = PriceCache::default();
+        prices.set(crate::models::CryptoPrice {
+            symbol: "BTC".to_string(),
+            price: 1000.0,
+            exchange: "binance".to_string(),
+            timestamp: chrono::Utc::now().timestamp(),
+        });
+        let exchange = PaperExchange::new(prices, config.paper_config());
+        exchange.deposit("USDT", Decimal::new(10_000, 0));
+        let order = exchange.place_order("BTCUSDT", OrderSide::Buy, OrderType::Market, Decimal::ONE, None).await.unwrap();
+        // 1000 + 1 % de deslizamiento, más 0,2 % de comisión
+        assert_eq!(order.average_price, Some(Decimal::new(1010, 0)));
+        assert_eq!(order.fee, Decimal::new(202, 2));
+
+        let defaults = TradingConfig::from_vars(|_| None).unwrap();
+        assert_eq!(defaults.paper_fee_rate, PaperConfig::default().fee_rate);
+        assert!(TradingConfig::from_vars(|name| (name == "PAPER_SLIPPAGE").then(|| "1.5".to_string())).is_err());
+    }
+}